@@ -1,10 +1,12 @@
 use std::borrow::Cow;
 use std::sync::RwLock;
+use std::time::Duration;
 
 use http::Request;
 use reqwest::{Response, StatusCode};
 
 mod http;
+pub mod srt;
 pub mod v1;
 
 #[derive(Debug)]
@@ -15,14 +17,27 @@ pub struct Client {
 }
 
 impl Client {
+    /// The default timeout for establishing a connection to the server.
+    pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+    /// The default timeout for a whole request, including reading the response body.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Creates a new `Client` with the default timeouts.
     pub fn new<T>(base_url: T) -> Self
     where
         T: Into<Cow<'static, str>>,
     {
-        Self {
+        Self::builder(base_url).build()
+    }
+
+    pub fn builder<T>(base_url: T) -> ClientBuilder
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        ClientBuilder {
             base_url: base_url.into(),
-            client: reqwest::Client::new(),
-            auth: RwLock::new(None),
+            connect_timeout: Some(Self::DEFAULT_CONNECT_TIMEOUT),
+            timeout: Some(Self::DEFAULT_TIMEOUT),
         }
     }
 
@@ -43,14 +58,71 @@ impl Client {
     }
 }
 
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    base_url: Cow<'static, str>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+}
+
+impl ClientBuilder {
+    /// Sets the timeout for establishing a connection. `None` waits forever.
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the timeout for a whole request, including reading the response body. `None` waits
+    /// forever.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Builds the [`Client`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend cannot be initialized, like [`reqwest::Client::new`].
+    pub fn build(self) -> Client {
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+
+        Client {
+            base_url: self.base_url,
+            client: builder.build().expect("failed to build HTTP client"),
+            auth: RwLock::new(None),
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error(transparent)]
-    Http(#[from] reqwest::Error),
+    Http(reqwest::Error),
+    /// The server didn't respond within the connect or request timeout. The request may be
+    /// retried.
+    #[error("request timed out")]
+    Timeout(#[source] reqwest::Error),
     #[error(transparent)]
     Json(#[from] serde_json::Error),
     #[error("bad status: {0}")]
     BadStatus(StatusCode),
 }
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout(err)
+        } else {
+            Self::Http(err)
+        }
+    }
+}
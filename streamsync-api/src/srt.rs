@@ -0,0 +1,78 @@
+//! Helpers for connecting to the SRT server.
+
+use std::fmt::Write;
+
+/// A builder for a stream id in the standard `#!::key=value,...` format.
+///
+/// See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#appendix-B.1
+#[derive(Clone, Debug, Default)]
+pub struct StreamIdBuilder {
+    keys: Vec<(&'static str, String)>,
+}
+
+impl StreamIdBuilder {
+    /// Creates a new `StreamIdBuilder` without any keys.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the user name (`u`).
+    pub fn user(self, val: &str) -> Self {
+        self.set("u", val)
+    }
+
+    /// Sets the resource id (`r`).
+    pub fn resource(self, val: &str) -> Self {
+        self.set("r", val)
+    }
+
+    /// Sets the session id (`s`).
+    pub fn session(self, val: &str) -> Self {
+        self.set("s", val)
+    }
+
+    /// Sets the mode (`m`), e.g. `request` or `publish`.
+    pub fn mode(self, val: &str) -> Self {
+        self.set("m", val)
+    }
+
+    fn set(mut self, key: &'static str, val: &str) -> Self {
+        self.keys.retain(|(k, _)| *k != key);
+        self.keys.push((key, val.to_owned()));
+        self
+    }
+
+    /// Builds the stream id.
+    ///
+    /// Returns an error if no keys were set or a value contains a `,` or `=`, which cannot be
+    /// represented in the stream id.
+    pub fn build(self) -> Result<String, StreamIdError> {
+        if self.keys.is_empty() {
+            return Err(StreamIdError::Empty);
+        }
+
+        let mut s = String::from("#!::");
+        for (index, (key, val)) in self.keys.iter().enumerate() {
+            if val.contains([',', '=']) {
+                return Err(StreamIdError::InvalidValue(key));
+            }
+
+            if index != 0 {
+                s.push(',');
+            }
+
+            let _ = write!(s, "{}={}", key, val);
+        }
+
+        Ok(s)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StreamIdError {
+    #[error("stream id has no keys")]
+    Empty,
+    #[error("value of stream id key '{0}' contains ',' or '='")]
+    InvalidValue(&'static str),
+}
@@ -2,6 +2,11 @@ use proc_macro::TokenStream;
 
 mod packet;
 
+/// Derives `IsPacket` for a control packet.
+///
+/// The struct must have a `header` field. All other fields are encoded in declaration order.
+/// Fields whose name starts with an underscore are reserved and always encoded as their
+/// `Default` value (zero).
 #[proc_macro_derive(Packet)]
 pub fn packet(input: TokenStream) -> TokenStream {
     packet::packet(input)
@@ -28,8 +28,10 @@ pub(crate) fn packet(input: TokenStream) -> TokenStream {
 
     let decode_body_fn = expand_decode_body_impl(&pkt_fields);
     let encode_body_fn = expand_encode_body_impl(&pkt_fields);
+    let body_size_hint_fn = expand_body_size_hint_impl(&pkt_fields);
 
     let downcast_fn = expand_downcast_impl(&pkt_fields);
+    let peek_fn = expand_peek_impl(&pkt_fields);
     let upcast_fn = expand_upcast_impl(&pkt_fields);
 
     let expanded = quote! {
@@ -37,12 +39,14 @@ pub(crate) fn packet(input: TokenStream) -> TokenStream {
         impl #ident {
             #decode_body_fn
             #encode_body_fn
+            #body_size_hint_fn
         }
 
         impl crate::srt::IsPacket for #ident {
             type Error = crate::srt::Error;
 
             #downcast_fn
+            #peek_fn
             #upcast_fn
         }
     };
@@ -91,9 +95,18 @@ fn expand_encode_body_impl(pkt_fields: &PacketFields) -> TokenStream2 {
         .iter()
         .map(|field| {
             let ident = field.ident.clone().unwrap();
+            let ty = field.ty.clone();
 
-            quote! {
-                self.#ident.encode(&mut writer)?;
+            // Fields starting with an underscore are reserved and always encoded as zero,
+            // regardless of their decoded value.
+            if ident.to_string().starts_with('_') {
+                quote! {
+                    <#ty as ::core::default::Default>::default().encode(&mut writer)?;
+                }
+            } else {
+                quote! {
+                    self.#ident.encode(&mut writer)?;
+                }
             }
         })
         .collect();
@@ -109,6 +122,27 @@ fn expand_encode_body_impl(pkt_fields: &PacketFields) -> TokenStream2 {
     }
 }
 
+fn expand_body_size_hint_impl(pkt_fields: &PacketFields) -> TokenStream2 {
+    let fn_impl: TokenStream2 = pkt_fields
+        .fields
+        .iter()
+        .map(|field| {
+            let ident = field.ident.clone().unwrap();
+
+            quote! {
+                + crate::proto::Encode::size_hint(&self.#ident)
+            }
+        })
+        .collect();
+
+    quote! {
+        /// Returns the expected size of the encoded body, excluding the header.
+        fn body_size_hint(&self) -> usize {
+            0 #fn_impl
+        }
+    }
+}
+
 fn expand_downcast_impl(pkt_fields: &PacketFields) -> TokenStream2 {
     assert!(pkt_fields.header.is_some());
 
@@ -120,12 +154,23 @@ fn expand_downcast_impl(pkt_fields: &PacketFields) -> TokenStream2 {
     }
 }
 
+fn expand_peek_impl(pkt_fields: &PacketFields) -> TokenStream2 {
+    assert!(pkt_fields.header.is_some());
+
+    quote! {
+        fn peek(packet: &crate::srt::Packet) -> Result<Self, Self::Error> {
+            let header = packet.header.try_into()?;
+            Self::decode_body(&packet.body[..], header)
+        }
+    }
+}
+
 fn expand_upcast_impl(pkt_fields: &PacketFields) -> TokenStream2 {
     assert!(pkt_fields.header.is_some());
 
     quote! {
         fn upcast(self) -> crate::srt::Packet {
-            let mut body = Vec::new();
+            let mut body = Vec::with_capacity(self.body_size_hint());
             self.encode_body(&mut body).unwrap();
 
             crate::srt::Packet {
@@ -1,6 +1,8 @@
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
 
 use clap::Parser;
+use streamsync_api::srt::{StreamIdBuilder, StreamIdError};
 use streamsync_api::v1::Session;
 use streamsync_api::Client;
 
@@ -22,6 +24,10 @@ pub struct Args {
     pub resource: String,
     #[arg(long, short = 't')]
     pub token: String,
+
+    /// Record the stream as MPEG-TS to the given path while playing.
+    #[arg(long)]
+    pub record: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -43,7 +49,10 @@ async fn main() {
         session_id: res.session_id,
     };
 
-    ffplay(&opts);
+    match args.record {
+        Some(path) => ffplay_record(&opts, &path),
+        None => ffplay(&opts),
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -55,19 +64,53 @@ pub struct SrtOptions {
 }
 
 impl SrtOptions {
-    pub fn address(&self) -> String {
-        format!(
-            "srt://{}:{}?streamid=#!::m=request,r={},s={}",
-            self.host, self.port, self.resource_id, self.session_id
-        )
+    pub fn address(&self) -> Result<String, StreamIdError> {
+        let stream_id = StreamIdBuilder::new()
+            .mode("request")
+            .resource(&self.resource_id)
+            .session(&self.session_id)
+            .build()?;
+
+        Ok(format!(
+            "srt://{}:{}?streamid={}",
+            self.host, self.port, stream_id
+        ))
     }
 }
 
 pub fn ffplay(opts: &SrtOptions) {
-    let addr = opts.address();
+    let addr = opts.address().unwrap();
 
     let mut cmd = Command::new("ffplay");
     cmd.args(["-fflags", "nobuffer", "-flags", "low_delay", &addr]);
 
     cmd.spawn().unwrap().wait().unwrap();
 }
+
+/// Plays the stream while recording it to `path`.
+///
+/// A session can only be used for a single SRT connection, so the stream is received once by
+/// `ffmpeg`, which writes it to `path` and pipes a copy to `ffplay`.
+pub fn ffplay_record(opts: &SrtOptions, path: &Path) {
+    let addr = opts.address().unwrap();
+
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg
+        .args(["-fflags", "nobuffer", "-i", &addr])
+        .args(["-map", "0", "-c", "copy", "-f", "mpegts"])
+        .arg(path)
+        .args(["-map", "0", "-c", "copy", "-f", "mpegts", "-"])
+        .stdout(Stdio::piped());
+
+    let mut ffmpeg = ffmpeg.spawn().unwrap();
+    let stdout = ffmpeg.stdout.take().unwrap();
+
+    let mut cmd = Command::new("ffplay");
+    cmd.args(["-fflags", "nobuffer", "-flags", "low_delay", "-"])
+        .stdin(stdout);
+
+    cmd.spawn().unwrap().wait().unwrap();
+
+    // ffmpeg exits once ffplay closes the pipe.
+    ffmpeg.wait().unwrap();
+}
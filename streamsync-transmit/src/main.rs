@@ -1,11 +1,13 @@
 mod listen;
 mod stream;
 
-use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 use clap::Parser;
 use stream::LiveTransmission;
+use streamsync_api::srt::{StreamIdBuilder, StreamIdError};
 use streamsync_api::{v1, Client};
+use tokio::sync::mpsc;
 
 #[derive(Clone, Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -37,6 +39,25 @@ pub struct Args {
     /// input address instead of transmitting it.
     #[arg(long, short = 'l')]
     pub listen: bool,
+
+    /// The number of times to reconnect after the transmission failed, before giving up.
+    #[arg(long, default_value_t = 5)]
+    pub max_retries: u32,
+}
+
+/// The delay before the first reconnect. It doubles with every consecutive failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A transmission that ran for this long resets the number of retries.
+const STABLE_DURATION: Duration = Duration::from_secs(60);
+
+#[derive(Copy, Clone, Debug)]
+pub enum Event {
+    /// The transmission ended.
+    Exited { success: bool },
+    /// The process received SIGINT.
+    Shutdown,
 }
 
 #[tokio::main]
@@ -49,25 +70,74 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     ));
     client.authorize(args.token);
 
-    let (tx, rx) = mpsc::channel::<()>();
-
-    let res = v1::Session::create(&client, args.resource).await?;
-
-    let opts = SrtOptions {
-        host: args.host,
-        port: args.srt_port,
-        resource_id: res.resource_id,
-        session_id: res.session_id,
-    };
-
     if args.listen {
         unimplemented!()
     }
 
-    LiveTransmission::new(&opts, &args.input).run(tx);
-
-    let _ = rx.recv();
-    Ok(())
+    let (tx, mut rx) = mpsc::unbounded_channel::<Event>();
+
+    let shutdown = tx.clone();
+    tokio::task::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            let _ = shutdown.send(Event::Shutdown);
+        }
+    });
+
+    let mut retries = 0;
+    loop {
+        let started = Instant::now();
+
+        match v1::Session::create(&client, args.resource.clone()).await {
+            Ok(res) => {
+                // A SIGINT while creating the session is not received by ffmpeg, which would
+                // keep running after we exit.
+                if let Ok(Event::Shutdown) = rx.try_recv() {
+                    return Ok(());
+                }
+
+                let opts = SrtOptions {
+                    host: args.host.clone(),
+                    port: args.srt_port,
+                    resource_id: res.resource_id,
+                    session_id: res.session_id,
+                };
+
+                LiveTransmission::new(&opts.address()?, &args.input).run(tx.clone());
+
+                match rx.recv().await {
+                    Some(Event::Exited { success: false }) => (),
+                    _ => return Ok(()),
+                }
+            }
+            Err(err) => eprintln!("failed to create session: {}", err),
+        }
+
+        if started.elapsed() >= STABLE_DURATION {
+            retries = 0;
+        }
+
+        if retries >= args.max_retries {
+            return Err(format!("transmission failed after {} retries", retries).into());
+        }
+
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(retries))
+            .min(MAX_BACKOFF);
+        retries += 1;
+
+        eprintln!(
+            "reconnecting in {}s (attempt {}/{})",
+            backoff.as_secs(),
+            retries,
+            args.max_retries
+        );
+
+        // The ffmpeg child also receives SIGINT and exits, so a shutdown usually arrives
+        // while waiting here.
+        if let Ok(Some(Event::Shutdown)) = tokio::time::timeout(backoff, rx.recv()).await {
+            return Ok(());
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -79,10 +149,16 @@ pub struct SrtOptions {
 }
 
 impl SrtOptions {
-    pub fn address(&self) -> String {
-        format!(
-            "srt://{}:{}?streamid=#!::m=publish,r={},s={}",
-            self.host, self.port, self.resource_id, self.session_id
-        )
+    pub fn address(&self) -> Result<String, StreamIdError> {
+        let stream_id = StreamIdBuilder::new()
+            .mode("publish")
+            .resource(&self.resource_id)
+            .session(&self.session_id)
+            .build()?;
+
+        Ok(format!(
+            "srt://{}:{}?streamid={}",
+            self.host, self.port, stream_id
+        ))
     }
 }
@@ -1,35 +1,42 @@
 use std::process::{Command, Stdio};
-use std::sync::mpsc;
+use tokio::sync::mpsc;
 
-use crate::SrtOptions;
+use crate::Event;
 
 pub struct LiveTransmission {
     command: Command,
 }
 
 impl LiveTransmission {
-    pub fn new(opts: &SrtOptions, source: &str) -> Self {
-        let addr = opts.address();
-
+    /// Creates a new `LiveTransmission` sending `source` to the SRT url `addr`.
+    pub fn new(addr: &str, source: &str) -> Self {
         let mut command = Command::new("ffmpeg");
-        command.args(["-re", "-i", source, "-f", "mpegts", &addr]);
+        command.args(["-re", "-i", source, "-f", "mpegts", addr]);
         // command.stdout(Stdio::null());
 
         Self { command }
     }
 
-    pub fn run(self, tx: mpsc::Sender<()>) {
+    /// Runs the transmission in a new thread. Sends [`Event::Exited`] to `tx` once it ended.
+    pub fn run(self, tx: mpsc::UnboundedSender<Event>) {
         let mut cmd = self.command;
 
         std::thread::spawn(move || {
-            let mut child = cmd.spawn().expect("failed to execute ffmpeg command");
-            let status = child.wait().expect("failed to execute ffmpeg command");
-
-            if !status.success() {
-                eprintln!("ffmpeg command failed to execute");
-            }
-
-            let _ = tx.send(());
+            let status = cmd.spawn().and_then(|mut child| child.wait());
+
+            let success = match status {
+                Ok(status) if status.success() => true,
+                Ok(status) => {
+                    eprintln!("ffmpeg command failed: {}", status);
+                    false
+                }
+                Err(err) => {
+                    eprintln!("failed to execute ffmpeg command: {}", err);
+                    false
+                }
+            };
+
+            let _ = tx.send(Event::Exited { success });
         });
     }
 }
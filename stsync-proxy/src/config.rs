@@ -1,19 +1,46 @@
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::Read;
 use std::net::SocketAddr;
-use std::path::Path;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use hyper::http::uri::Scheme;
+use hyper::Uri;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use toml::Value;
+use tracing_subscriber::EnvFilter;
 
+use crate::http;
+use crate::session::buffer::{self, PublisherPolicy};
 use crate::srt;
+use crate::srt::access_log::AccessLogTarget;
+use crate::srt::config::{ModeDuration, PeerAddressCheck};
+use crate::srt::cookie::SynCookies;
+use crate::srt::loss::LossConfig;
+use crate::srt::queue::QueueKind;
+use crate::srt::Header;
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub log: Log,
     pub srt: Srt,
     pub http: Http,
+    #[serde(default)]
+    pub session: Session,
 }
 
 impl Config {
+    /// The prefix of environment variables that override values of the config file.
+    pub const ENV_PREFIX: &'static str = "STSYNC_";
+
+    /// Reads the config file at `path`, overlaid with the environment variables prefixed with
+    /// [`ENV_PREFIX`]. See [`overlay_env`] for the mapping of variables to keys.
+    ///
+    /// [`ENV_PREFIX`]: Self::ENV_PREFIX
     pub fn from_file<P>(path: P) -> Result<Self, Box<dyn std::error::Error>>
     where
         P: AsRef<Path>,
@@ -23,14 +50,436 @@ impl Config {
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
 
-        Ok(toml::from_slice(&buf)?)
+        let vars = env_vars(std::env::vars_os())?;
+
+        let config: Self = if vars.is_empty() {
+            toml::from_slice(&buf)?
+        } else {
+            let mut value = toml::from_slice(&buf)?;
+            overlay_env(&mut value, vars.clone())?;
+            let config: Self = value.try_into()?;
+
+            check_env(
+                &Value::try_from(&config)?,
+                vars.iter().map(|(key, _)| key.as_str()),
+            )?;
+            config
+        };
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Returns this `Config` with every unset option replaced by the value in effect, as resolved
+    /// by the server. Disabled options are set to `0`.
+    pub fn resolved(&self) -> Self {
+        let secs = |duration: Option<Duration>| Some(duration.map_or(0, |d| d.as_secs()));
+
+        let srt = srt::Config::from(self.srt.clone());
+        let idle_timeout = srt.idle_timeout;
+        let keepalive = srt.keepalive;
+        let http = http::Config::from(self.http.clone());
+
+        Self {
+            log: Log {
+                format: self.log.format,
+                level: self.log.level.clone(),
+            },
+            srt: Srt {
+                recv_buffer: Some(srt.recv_buffer.unwrap_or(0)),
+                send_buffer: Some(srt.send_buffer.unwrap_or(0)),
+                initial_window: Some(srt.initial_window),
+                congestion_threshold: Some(srt.congestion_threshold.unwrap_or(0.0)),
+                max_latency: Some(srt.max_latency),
+                adaptive_latency: Some(srt.adaptive_latency.unwrap_or(0)),
+                max_bandwidth: Some(srt.max_bandwidth.unwrap_or(0)),
+                nak_delay: Some(srt.nak_delay),
+                ack_interval: Some(srt.ack_interval.as_millis() as u64),
+                idle_timeout: ModeSecs {
+                    publish: secs(idle_timeout.publish),
+                    request: secs(idle_timeout.request),
+                },
+                keepalive: ModeSecs {
+                    publish: secs(keepalive.publish),
+                    request: secs(keepalive.request),
+                },
+                watchdog_timeout: secs(srt.watchdog_timeout),
+                reject_threshold: Some(srt.reject_threshold),
+                syn_cookie_lifetime: secs(srt.syn_cookie_lifetime),
+                ..self.srt.clone()
+            },
+            http: Http {
+                max_sessions: Some(http.max_sessions),
+                body_timeout: Some(http.body_timeout.as_secs()),
+                max_body_size: Some(http.max_body_size),
+                ..self.http.clone()
+            },
+            session: Session {
+                channel_capacity: Some(
+                    self.session
+                        .channel_capacity
+                        .unwrap_or(buffer::Config::DEFAULT_CHANNEL_CAPACITY),
+                ),
+                ..self.session.clone()
+            },
+        }
+    }
+
+    /// Checks that all values are within their valid ranges.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(level) = &self.log.level {
+            if EnvFilter::try_new(level).is_err() {
+                return Err(ConfigError::LogLevel);
+            }
+        }
+
+        let srt = &self.srt;
+
+        if srt.mtu < Srt::MIN_MTU {
+            return Err(ConfigError::Mtu(srt.mtu));
+        }
+
+        if !Srt::LATENCY.contains(&srt.latency) {
+            return Err(ConfigError::Latency(srt.latency));
+        }
+
+        if let Some(max_latency) = srt.max_latency {
+            if !Srt::LATENCY.contains(&max_latency) || max_latency < srt.latency {
+                return Err(ConfigError::MaxLatency(max_latency));
+            }
+        }
+
+        if let Some(latency) = srt.adaptive_latency.filter(|latency| *latency != 0) {
+            if !Srt::LATENCY.contains(&latency) {
+                return Err(ConfigError::AdaptiveLatency(latency));
+            }
+        }
+
+        if srt
+            .nak_delay
+            .is_some_and(|delay| !(0.0..=1.0).contains(&delay))
+        {
+            return Err(ConfigError::NakDelay);
+        }
+
+        if let Some(interval) = srt.ack_interval {
+            if !Srt::ACK_INTERVAL.contains(&interval) {
+                return Err(ConfigError::AckInterval(interval));
+            }
+        }
+
+        if srt.flow_window == 0 {
+            return Err(ConfigError::FlowWindow);
+        }
+
+        if srt.workers == Some(0) {
+            return Err(ConfigError::Workers);
+        }
+
+        if let Some(lifetime) = srt.syn_cookie_lifetime {
+            if lifetime > SynCookies::MAX_LIFETIME.as_secs() {
+                return Err(ConfigError::SynCookieLifetime(lifetime));
+            }
+        }
+
+        if srt
+            .passphrase
+            .as_ref()
+            .is_some_and(|passphrase| !Srt::PASSPHRASE.contains(&passphrase.len()))
+        {
+            return Err(ConfigError::Passphrase);
+        }
+
+        if self.http.body_timeout == Some(0) {
+            return Err(ConfigError::BodyTimeout);
+        }
+
+        if self.http.max_body_size == Some(0) {
+            return Err(ConfigError::MaxBodySize);
+        }
+
+        if let Some(capacity) = self.session.channel_capacity {
+            if !(1..=buffer::Config::MAX_CHANNEL_CAPACITY).contains(&capacity) {
+                return Err(ConfigError::ChannelCapacity(capacity));
+            }
+        }
+
+        if let Some(url) = &self.session.auth_callback {
+            if url
+                .parse::<Uri>()
+                .ok()
+                .and_then(|uri| uri.scheme().cloned())
+                != Some(Scheme::HTTP)
+            {
+                return Err(ConfigError::AuthCallback);
+            }
+        }
+
+        Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Returns the variables of `vars` prefixed with [`Config::ENV_PREFIX`]. Other variables are
+/// ignored, even if they are not valid unicode.
+fn env_vars<I>(vars: I) -> Result<Vec<(String, String)>, EnvError>
+where
+    I: IntoIterator<Item = (OsString, OsString)>,
+{
+    let mut env = Vec::new();
+    for (key, val) in vars {
+        if !key
+            .as_encoded_bytes()
+            .starts_with(Config::ENV_PREFIX.as_bytes())
+        {
+            continue;
+        }
+
+        let key = key
+            .into_string()
+            .map_err(|key| EnvError::NotUnicode(key.to_string_lossy().into_owned()))?;
+        let val = val
+            .into_string()
+            .map_err(|_| EnvError::NotUnicode(key.clone()))?;
+        env.push((key, val));
+    }
+
+    Ok(env)
+}
+
+/// Overlays the environment variables `vars` prefixed with [`Config::ENV_PREFIX`] onto the
+/// parsed config file `value`. Other variables are ignored.
+///
+/// Nested keys are separated by `__` and dashes are written as `_`, e.g. `srt.max-latency`
+/// is set by `STSYNC_SRT__MAX_LATENCY`. Values are parsed as TOML values and fall back to
+/// strings, so `STSYNC_SRT__BIND=0.0.0.0:9999` does not require quotes. A value that has the
+/// wrong type for its key is used as a string as well, e.g. the all-digit
+/// `STSYNC_HTTP__ADMIN_TOKEN=123456`.
+fn overlay_env<I>(value: &mut Value, vars: I) -> Result<(), EnvError>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    for (key, val) in vars {
+        let Some(path) = env_path(&key) else {
+            continue;
+        };
+
+        if path.iter().any(String::is_empty) {
+            return Err(EnvError::Key(key));
+        }
+
+        let typed = toml::from_str::<toml::value::Table>(&format!("value = {}", val))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| Value::String(val.clone()));
+
+        let is_str = typed.is_str();
+        let mut overlaid = value.clone();
+        insert_env(&mut overlaid, &key, &path, typed)?;
+
+        if !is_str && overlaid.clone().try_into::<Config>().is_err() {
+            let mut string = value.clone();
+            insert_env(&mut string, &key, &path, Value::String(val))?;
+            if string.clone().try_into::<Config>().is_ok() {
+                overlaid = string;
+            }
+        }
+
+        *value = overlaid;
+    }
+
+    Ok(())
+}
+
+/// Inserts `val` at `path` into `value`, creating missing tables.
+fn insert_env(value: &mut Value, key: &str, path: &[String], val: Value) -> Result<(), EnvError> {
+    let (name, parents) = path.split_last().unwrap();
+
+    let mut table = value;
+    for parent in parents {
+        table = match table {
+            Value::Table(table) => table
+                .entry(parent.clone())
+                .or_insert_with(|| Value::Table(Default::default())),
+            _ => return Err(EnvError::Key(key.to_owned())),
+        };
+    }
+
+    let table = match table {
+        Value::Table(table) => table,
+        _ => return Err(EnvError::Key(key.to_owned())),
+    };
+
+    // Whole tables cannot be replaced, e.g. by `STSYNC_SRT=1`.
+    if table.get(name).is_some_and(Value::is_table) {
+        return Err(EnvError::Key(key.to_owned()));
+    }
+
+    table.insert(name.clone(), val);
+    Ok(())
+}
+
+/// Checks that every variable of `keys` refers to a value of the deserialized and serialized
+/// again [`Config`] `value`. Variables with misspelled keys are otherwise silently ignored
+/// when deserializing.
+fn check_env<'a, I>(value: &Value, keys: I) -> Result<(), EnvError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    for key in keys {
+        let Some(path) = env_path(key) else {
+            continue;
+        };
+
+        if path
+            .iter()
+            .try_fold(value, |value, name| value.get(name.as_str()))
+            .is_none()
+        {
+            return Err(EnvError::Key(key.to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the path of the config value that the environment variable `key` refers to, or
+/// `None` if `key` is not prefixed with [`Config::ENV_PREFIX`].
+fn env_path(key: &str) -> Option<Vec<String>> {
+    let path = key.strip_prefix(Config::ENV_PREFIX)?;
+
+    Some(
+        path.split("__")
+            .map(|name| name.to_lowercase().replace('_', "-"))
+            .collect(),
+    )
+}
+
+/// An invalid environment variable overriding a value of the [`Config`].
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum EnvError {
+    #[error("environment variable {0} does not refer to a config value")]
+    Key(String),
+    #[error("environment variable {0} is not valid unicode")]
+    NotUnicode(String),
+}
+
+/// An invalid value in the [`Config`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Error)]
+pub enum ConfigError {
+    #[error("log.level is not a valid filter")]
+    LogLevel,
+    #[error("srt.mtu must be at least {} bytes, got {0}", Srt::MIN_MTU)]
+    Mtu(u32),
+    #[error(
+        "srt.latency must be between {} and {} ms, got {0}",
+        Srt::LATENCY.start(),
+        Srt::LATENCY.end()
+    )]
+    Latency(u16),
+    #[error(
+        "srt.max-latency must be between srt.latency and {} ms, got {0}",
+        Srt::LATENCY.end()
+    )]
+    MaxLatency(u16),
+    #[error(
+        "srt.adaptive-latency must be between {} and {} ms, got {0}",
+        Srt::LATENCY.start(),
+        Srt::LATENCY.end()
+    )]
+    AdaptiveLatency(u16),
+    #[error("srt.nak-delay must be between 0.0 and 1.0")]
+    NakDelay,
+    #[error(
+        "srt.ack-interval must be between {} and {} ms, got {0}",
+        Srt::ACK_INTERVAL.start(),
+        Srt::ACK_INTERVAL.end()
+    )]
+    AckInterval(u64),
+    #[error("srt.flow-window must not be 0")]
+    FlowWindow,
+    #[error("srt.workers must not be 0")]
+    Workers,
+    #[error(
+        "srt.syn-cookie-lifetime must be at most {} seconds, got {0}",
+        SynCookies::MAX_LIFETIME.as_secs()
+    )]
+    SynCookieLifetime(u64),
+    #[error(
+        "srt.passphrase must be between {} and {} bytes long",
+        Srt::PASSPHRASE.start(),
+        Srt::PASSPHRASE.end()
+    )]
+    Passphrase,
+    #[error("http.body-timeout must not be 0")]
+    BodyTimeout,
+    #[error("http.max-body-size must not be 0")]
+    MaxBodySize,
+    #[error(
+        "session.channel-capacity must be between 1 and {} segments, got {0}",
+        buffer::Config::MAX_CHANNEL_CAPACITY
+    )]
+    ChannelCapacity(usize),
+    #[error("session.auth-callback must be an http:// URL")]
+    AuthCallback,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Log {
+    #[serde(default)]
+    pub format: LogFormat,
+    /// The filter of log events in the `RUST_LOG` syntax. `None` uses the `RUST_LOG` environment
+    /// variable.
+    pub level: Option<String>,
+}
+
+/// The output format of the logger.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable output.
+    #[default]
+    Pretty,
+    /// One JSON object per line.
+    Json,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Http {
     pub enabled: bool,
     pub bind: SocketAddr,
+    #[serde(rename = "max-sessions")]
+    pub max_sessions: Option<usize>,
+    #[serde(rename = "allowed-origins", default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(rename = "body-timeout")]
+    pub body_timeout: Option<u64>,
+    #[serde(rename = "max-body-size")]
+    pub max_body_size: Option<usize>,
+    #[serde(rename = "admin-token")]
+    pub admin_token: Option<String>,
+}
+
+impl From<Http> for http::Config {
+    fn from(src: Http) -> Self {
+        Self {
+            max_sessions: src.max_sessions.unwrap_or(64),
+            allowed_origins: src.allowed_origins,
+            body_timeout: Duration::from_secs(src.body_timeout.unwrap_or(10)),
+            max_body_size: src.max_body_size.unwrap_or(65536),
+            // An empty token would authorize every request with an empty bearer token.
+            admin_token: src.admin_token.filter(|token| !token.is_empty()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "channel-capacity")]
+    pub channel_capacity: Option<usize>,
+    #[serde(default)]
+    pub backpressure: bool,
+    #[serde(rename = "auth-callback")]
+    pub auth_callback: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -38,28 +487,415 @@ pub struct Srt {
     pub enabled: bool,
     pub bind: SocketAddr,
     pub workers: Option<usize>,
+    #[serde(rename = "worker-sharding", default)]
+    pub worker_sharding: bool,
 
-    pub rcvbuf: usize,
-    pub sndbuf: usize,
+    #[serde(rename = "recv-buffer", alias = "rcvbuf", default)]
+    pub recv_buffer: Option<usize>,
+    #[serde(rename = "send-buffer", alias = "sndbuf", default)]
+    pub send_buffer: Option<usize>,
 
     pub mtu: u32,
     #[serde(rename = "flow-window")]
     pub flow_window: u32,
     pub buffer: u32,
+    #[serde(rename = "initial-window")]
+    pub initial_window: Option<u32>,
+    #[serde(rename = "congestion-threshold")]
+    pub congestion_threshold: Option<f64>,
     pub latency: u16,
+    #[serde(rename = "max-latency")]
+    pub max_latency: Option<u16>,
+    #[serde(rename = "adaptive-latency")]
+    pub adaptive_latency: Option<u16>,
+    #[serde(rename = "max-bandwidth")]
+    pub max_bandwidth: Option<u64>,
+    #[serde(rename = "nak-delay")]
+    pub nak_delay: Option<f64>,
+    #[serde(rename = "ack-interval")]
+    pub ack_interval: Option<u64>,
+    #[serde(rename = "idle-timeout", default)]
+    pub idle_timeout: ModeSecs,
+    #[serde(default)]
+    pub keepalive: ModeSecs,
+    #[serde(rename = "watchdog-timeout")]
+    pub watchdog_timeout: Option<u64>,
+    #[serde(rename = "reject-threshold")]
+    pub reject_threshold: Option<u32>,
+    #[serde(rename = "syn-cookie-lifetime")]
+    pub syn_cookie_lifetime: Option<u64>,
+    #[serde(rename = "start-from-keyframe", default)]
+    pub start_from_keyframe: bool,
+    #[serde(rename = "publisher-policy", default)]
+    pub publisher_policy: PublisherPolicy,
+    #[serde(rename = "reorder-queue", default)]
+    pub reorder_queue: QueueKind,
+    #[serde(rename = "peer-address-check", default)]
+    pub peer_address_check: PeerAddressCheck,
+    #[serde(rename = "access-log")]
+    pub access_log: Option<PathBuf>,
+    #[serde(default)]
+    pub debug: bool,
+    #[serde(default)]
+    pub loss: Option<LossConfig>,
+    pub passphrase: Option<String>,
+}
+
+impl Srt {
+    /// The valid range of `latency` in milliseconds.
+    pub const LATENCY: RangeInclusive<u16> = 20..=10_000;
+    /// The valid range of the length of `passphrase` in bytes.
+    pub const PASSPHRASE: RangeInclusive<usize> = 10..=79;
+    /// The valid range of `ack_interval` in milliseconds. Connections don't tick more often than
+    /// every 10 ms.
+    pub const ACK_INTERVAL: RangeInclusive<u64> = 10..=1000;
+    /// The smallest `mtu` in bytes that fits the IPv6, UDP and SRT headers and one byte of
+    /// payload.
+    pub const MIN_MTU: u32 = (40 + 8 + Header::SIZE + 1) as u32;
 }
 
 impl From<Srt> for srt::Config {
     fn from(src: Srt) -> Self {
         Self {
             workers: src.workers,
+            worker_sharding: src.worker_sharding,
             mtu: src.mtu,
             flow_window: src.flow_window,
             bind: src.bind,
             buffer: src.buffer,
-            rcvbuf: src.rcvbuf,
-            sndbuf: src.sndbuf,
+            initial_window: src.initial_window.unwrap_or(32),
+            // A value of 0 disables congestion warnings.
+            congestion_threshold: Some(src.congestion_threshold.unwrap_or(0.9))
+                .filter(|threshold| *threshold > 0.0),
+            // A value of 0 falls back to the system default.
+            recv_buffer: src.recv_buffer.filter(|size| *size != 0),
+            send_buffer: src.send_buffer.filter(|size| *size != 0),
             latency: src.latency,
+            max_latency: src.max_latency.unwrap_or(*Srt::LATENCY.end()),
+            // A value of 0 disables adaptive latency.
+            adaptive_latency: src.adaptive_latency.filter(|latency| *latency != 0),
+            // A value of 0 disables the limit.
+            max_bandwidth: src.max_bandwidth.filter(|rate| *rate != 0),
+            nak_delay: src.nak_delay.unwrap_or(0.25),
+            ack_interval: Duration::from_millis(src.ack_interval.unwrap_or(10)),
+            idle_timeout: src.idle_timeout.resolve(srt::Config::DEFAULT_IDLE_TIMEOUT),
+            keepalive: src.keepalive.resolve(srt::Config::DEFAULT_KEEPALIVE),
+            // A value of 0 disables the watchdog.
+            watchdog_timeout: match src.watchdog_timeout {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => Some(Duration::from_secs(10)),
+            },
+            reject_threshold: src.reject_threshold.unwrap_or(1),
+            // A value of 0 disables the expiry.
+            syn_cookie_lifetime: match src.syn_cookie_lifetime {
+                Some(0) => None,
+                Some(secs) => Some(Duration::from_secs(secs)),
+                None => Some(Duration::from_secs(60)),
+            },
+            reorder_queue: src.reorder_queue,
+            peer_address_check: src.peer_address_check,
+            access_log: src.access_log.map(AccessLogTarget::from_path),
+            debug: src.debug,
+            loss: src.loss,
+            passphrase: src.passphrase,
+        }
+    }
+}
+
+/// A duration in seconds per connection mode, e.g. the idle timeout. Unset values use the
+/// default duration, `0` disables it.
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModeSecs {
+    pub publish: Option<u64>,
+    pub request: Option<u64>,
+}
+
+impl ModeSecs {
+    /// Converts the seconds into a [`ModeDuration`], using `default` for unset values.
+    fn resolve(self, default: Duration) -> ModeDuration {
+        let duration = |secs: Option<u64>| match secs {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(default),
+        };
+
+        ModeDuration::new(duration(self.publish), duration(self.request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use toml::Value;
+
+    use crate::session::buffer;
+
+    use super::{check_env, env_vars, overlay_env, Config, ConfigError, EnvError};
+
+    fn config() -> Config {
+        toml::from_str(include_str!("../config.toml")).unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(config().validate(), Ok(()));
+
+        let mut config = self::config();
+        config.log.level = Some("stsync_proxy=debug,info".to_owned());
+        assert_eq!(config.validate(), Ok(()));
+        config.log.level = Some("stsync_proxy=verbose".to_owned());
+        assert_eq!(config.validate(), Err(ConfigError::LogLevel));
+
+        let mut config = self::config();
+        config.srt.mtu = 64;
+        assert_eq!(config.validate(), Err(ConfigError::Mtu(64)));
+        config.srt.mtu = 65;
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = self::config();
+        config.srt.latency = 0;
+        assert_eq!(config.validate(), Err(ConfigError::Latency(0)));
+        config.srt.latency = 10_001;
+        assert_eq!(config.validate(), Err(ConfigError::Latency(10_001)));
+
+        let mut config = self::config();
+        config.srt.max_latency = Some(config.srt.latency - 1);
+        let max_latency = config.srt.max_latency.unwrap();
+        assert_eq!(config.validate(), Err(ConfigError::MaxLatency(max_latency)));
+        config.srt.max_latency = Some(10_001);
+        assert_eq!(config.validate(), Err(ConfigError::MaxLatency(10_001)));
+
+        let mut config = self::config();
+        config.srt.adaptive_latency = Some(0);
+        assert_eq!(config.validate(), Ok(()));
+        config.srt.adaptive_latency = Some(10);
+        assert_eq!(config.validate(), Err(ConfigError::AdaptiveLatency(10)));
+
+        let mut config = self::config();
+        config.srt.ack_interval = Some(5);
+        assert_eq!(config.validate(), Err(ConfigError::AckInterval(5)));
+
+        let mut config = self::config();
+        config.srt.nak_delay = Some(1.5);
+        assert_eq!(config.validate(), Err(ConfigError::NakDelay));
+
+        let mut config = self::config();
+        config.srt.flow_window = 0;
+        assert_eq!(config.validate(), Err(ConfigError::FlowWindow));
+
+        let mut config = self::config();
+        config.srt.workers = Some(0);
+        assert_eq!(config.validate(), Err(ConfigError::Workers));
+
+        let mut config = self::config();
+        config.srt.syn_cookie_lifetime = Some(256);
+        assert_eq!(config.validate(), Err(ConfigError::SynCookieLifetime(256)));
+
+        let mut config = self::config();
+        config.srt.passphrase = Some("too short".to_owned());
+        assert_eq!(config.validate(), Err(ConfigError::Passphrase));
+        config.srt.passphrase = Some("long enough".to_owned());
+        assert_eq!(config.validate(), Ok(()));
+
+        let mut config = self::config();
+        config.http.body_timeout = Some(0);
+        assert_eq!(config.validate(), Err(ConfigError::BodyTimeout));
+
+        let mut config = self::config();
+        config.http.max_body_size = Some(0);
+        assert_eq!(config.validate(), Err(ConfigError::MaxBodySize));
+
+        let mut config = self::config();
+        config.session.channel_capacity = Some(0);
+        assert_eq!(config.validate(), Err(ConfigError::ChannelCapacity(0)));
+        config.session.channel_capacity = Some(buffer::Config::MAX_CHANNEL_CAPACITY);
+        assert_eq!(config.validate(), Ok(()));
+        for capacity in [buffer::Config::MAX_CHANNEL_CAPACITY + 1, usize::MAX] {
+            config.session.channel_capacity = Some(capacity);
+            assert_eq!(
+                config.validate(),
+                Err(ConfigError::ChannelCapacity(capacity))
+            );
         }
+
+        let mut config = self::config();
+        config.session.auth_callback = Some("http://127.0.0.1:8080/authorize".to_owned());
+        assert_eq!(config.validate(), Ok(()));
+        for url in ["https://127.0.0.1/authorize", "/authorize", "not a url"] {
+            config.session.auth_callback = Some(url.to_owned());
+            assert_eq!(config.validate(), Err(ConfigError::AuthCallback));
+        }
+    }
+
+    #[test]
+    fn test_resolved() {
+        let config: Config = toml::from_str(
+            r#"
+            [srt]
+            enabled = true
+            bind = "0.0.0.0:9999"
+            mtu = 1500
+            flow-window = 8192
+            buffer = 8192
+            latency = 120
+            watchdog-timeout = 0
+
+            [http]
+            enabled = true
+            bind = "0.0.0.0:9998"
+            "#,
+        )
+        .unwrap();
+
+        let resolved = config.resolved();
+        assert_eq!(resolved.validate(), Ok(()));
+        assert_eq!(resolved.srt.nak_delay, Some(0.25));
+        assert_eq!(resolved.srt.ack_interval, Some(10));
+        assert_eq!(resolved.srt.max_latency, Some(10_000));
+        assert_eq!(resolved.srt.idle_timeout.publish, Some(15));
+        assert_eq!(resolved.srt.watchdog_timeout, Some(0));
+        assert_eq!(resolved.srt.adaptive_latency, Some(0));
+        assert_eq!(resolved.http.max_body_size, Some(65536));
+        assert_eq!(resolved.session.channel_capacity, Some(1024));
+
+        // The defaults appear in the printed config.
+        let printed = Value::try_from(&resolved).unwrap().to_string();
+        for line in [
+            "nak-delay = 0.25",
+            "syn-cookie-lifetime = 60",
+            "body-timeout = 10",
+            "channel-capacity = 1024",
+        ] {
+            assert!(printed.contains(line), "missing {:?} in {}", line, printed);
+        }
+
+        // Resolving is idempotent, the resolved config is equivalent to the original one.
+        let again = resolved.resolved();
+        assert_eq!(
+            Value::try_from(&again).unwrap(),
+            Value::try_from(&resolved).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_buffer_aliases() {
+        let config: Config = toml::from_str(
+            &include_str!("../config.toml")
+                .replace("recv-buffer = 500_000", "rcvbuf = 400_000")
+                .replace("# send-buffer = 0", "sndbuf = 300_000"),
+        )
+        .unwrap();
+        assert_eq!(config.srt.recv_buffer, Some(400_000));
+        assert_eq!(config.srt.send_buffer, Some(300_000));
+    }
+
+    #[test]
+    fn test_overlay_env() {
+        let vars = |vars: &[(&str, &str)]| {
+            vars.iter()
+                .map(|(key, val)| (key.to_string(), val.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut value: Value = toml::from_str(include_str!("../config.toml")).unwrap();
+        overlay_env(
+            &mut value,
+            vars(&[
+                ("STSYNC_SRT__LATENCY", "200"),
+                ("STSYNC_SRT__MAX_LATENCY", "400"),
+                ("STSYNC_SRT__BIND", "127.0.0.1:4000"),
+                ("STSYNC_SRT__KEEPALIVE__PUBLISH", "0"),
+                ("STSYNC_HTTP__ENABLED", "false"),
+                ("STSYNC_HTTP__ADMIN_TOKEN", "123456"),
+                ("STSYNC_HTTP__ALLOWED_ORIGINS", r#"["https://example.com"]"#),
+                (
+                    "STSYNC_SESSION__AUTH_CALLBACK",
+                    "http://127.0.0.1/authorize",
+                ),
+                ("PATH", "/usr/bin"),
+            ]),
+        )
+        .unwrap();
+
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(config.validate(), Ok(()));
+        let keys = [
+            "STSYNC_SRT__LATENCY",
+            "STSYNC_SRT__MAX_LATENCY",
+            "STSYNC_SRT__KEEPALIVE__PUBLISH",
+            "STSYNC_SESSION__AUTH_CALLBACK",
+            "PATH",
+        ];
+        assert_eq!(check_env(&Value::try_from(&config).unwrap(), keys), Ok(()));
+        assert_eq!(config.srt.latency, 200);
+        assert_eq!(config.srt.max_latency, Some(400));
+        assert_eq!(config.srt.bind, "127.0.0.1:4000".parse().unwrap());
+        assert_eq!(config.srt.keepalive.publish, Some(0));
+        assert!(!config.http.enabled);
+        // Only a string is valid for the token.
+        assert_eq!(config.http.admin_token.as_deref(), Some("123456"));
+        assert_eq!(config.http.allowed_origins, ["https://example.com"]);
+        assert_eq!(
+            config.session.auth_callback.as_deref(),
+            Some("http://127.0.0.1/authorize")
+        );
+
+        for key in [
+            "STSYNC_SRT",
+            "STSYNC_SRT__LATENCY__MIN",
+            "STSYNC_SRT____LATENCY",
+        ] {
+            let mut value: Value = toml::from_str(include_str!("../config.toml")).unwrap();
+            assert_eq!(
+                overlay_env(&mut value, vars(&[(key, "1")])),
+                Err(EnvError::Key(key.to_owned()))
+            );
+        }
+
+        // Values of the wrong type that are no valid strings either are kept.
+        let mut value: Value = toml::from_str(include_str!("../config.toml")).unwrap();
+        overlay_env(&mut value, vars(&[("STSYNC_SRT__LATENCY", "[1]")])).unwrap();
+        assert!(value["srt"]["latency"].is_array());
+        assert!(value.try_into::<Config>().is_err());
+
+        // Misspelled keys are ignored when deserializing.
+        let key = "STSYNC_SRT__LATENCI";
+        let mut value: Value = toml::from_str(include_str!("../config.toml")).unwrap();
+        overlay_env(&mut value, vars(&[(key, "200")])).unwrap();
+        let config: Config = value.try_into().unwrap();
+        assert_eq!(
+            check_env(&Value::try_from(&config).unwrap(), [key]),
+            Err(EnvError::Key(key.to_owned()))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_env_vars() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let invalid = || OsString::from_vec(vec![0xff]);
+
+        let vars = env_vars([
+            ("STSYNC_SRT__LATENCY".into(), "200".into()),
+            (invalid(), "1".into()),
+            ("PATH".into(), invalid()),
+        ])
+        .unwrap();
+        assert_eq!(vars, [("STSYNC_SRT__LATENCY".to_owned(), "200".to_owned())]);
+
+        assert_eq!(
+            env_vars([("STSYNC_SRT__BIND".into(), invalid())]),
+            Err(EnvError::NotUnicode("STSYNC_SRT__BIND".to_owned()))
+        );
+
+        let mut key = b"STSYNC_SRT__".to_vec();
+        key.push(0xff);
+        assert_eq!(
+            env_vars([(OsString::from_vec(key), "1".into())]),
+            Err(EnvError::NotUnicode("STSYNC_SRT__\u{FFFD}".to_owned()))
+        );
     }
 }
@@ -1,19 +1,37 @@
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
+use parking_lot::RwLock;
+use rand::rngs::OsRng;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 
 use crate::session::ResourceId;
 
 #[derive(Debug)]
 pub struct Database {
-    pub streams: HashMap<ResourceId, Stream>,
+    /// The file the streams are loaded from and written back to.
+    path: PathBuf,
+    streams: RwLock<HashMap<ResourceId, Stream>>,
+    /// Held while the database file is written, so that concurrent writes don't overwrite a
+    /// newer snapshot with an older one.
+    save: Mutex<()>,
 }
 
 impl Database {
     pub fn new() -> Self {
-        let mut file = File::open("config.json").unwrap();
+        Self::open("config.json")
+    }
+
+    /// Opens the database stored in the file at `path`.
+    pub fn open<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::open(path.as_ref()).unwrap();
 
         let mut buf = Vec::new();
         file.read_to_end(&mut buf).unwrap();
@@ -25,13 +43,145 @@ impl Database {
             streams.insert(id, stream);
         }
 
-        Self { streams }
+        Self {
+            path: path.as_ref().to_owned(),
+            streams: RwLock::new(streams),
+            save: Mutex::new(()),
+        }
+    }
+
+    /// Returns the stream `id` if `token` is its current token.
+    pub fn authorize(&self, id: &ResourceId, token: Option<&[u8]>) -> Result<Stream, AuthError> {
+        let streams = self.streams.read();
+
+        let stream = streams.get(id).ok_or(AuthError::NotFound)?;
+        match token {
+            Some(token) if token == stream.token.as_bytes() => Ok(stream.clone()),
+            _ => Err(AuthError::InvalidToken),
+        }
+    }
+
+    /// Replaces the token of the stream `id` with a new random token and returns it. The old
+    /// token is rejected from now on.
+    ///
+    /// The new token is written back to the database file, so it also survives a restart.
+    /// Writing the file can fail, the new token is in effect regardless.
+    pub async fn rotate_token(&self, id: &ResourceId) -> Option<(String, io::Result<()>)> {
+        let _save = self.save.lock().await;
+
+        // Only hold the lock while updating the token, not while writing the file.
+        let (token, streams) = {
+            let mut streams = self.streams.write();
+
+            let stream = streams.get_mut(id)?;
+            stream.token = (0..Stream::TOKEN_LEN)
+                .map(|_| format!("{:02x}", OsRng.gen::<u8>()))
+                .collect();
+
+            let token = stream.token.clone();
+            (token, streams.values().cloned().collect())
+        };
+
+        let path = self.path.clone();
+        let res = tokio::task::spawn_blocking(move || save(&path, streams))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+        Some((token, res))
     }
 }
 
+/// Writes `streams` to the database file at `path`. The file is replaced atomically so a failed
+/// write never leaves a truncated file.
+fn save(path: &Path, mut streams: Vec<Stream>) -> io::Result<()> {
+    streams.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let buf = serde_json::to_vec_pretty(&streams)?;
+
+    let mut tmp = path.to_owned().into_os_string();
+    tmp.push(".tmp");
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+
+    std::fs::rename(tmp, path)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    /// The stream does not exist.
+    NotFound,
+    /// The token is missing or not the current token of the stream.
+    InvalidToken,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Stream {
     pub id: String,
     pub name: String,
     pub token: String,
 }
+
+impl Stream {
+    /// The number of random bytes in a generated token.
+    const TOKEN_LEN: usize = 32;
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use crate::session::ResourceId;
+
+    use super::{AuthError, Database};
+
+    /// A database file that is removed once dropped.
+    pub struct TempFile(pub PathBuf);
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotate_token() {
+        let file = TempFile(std::env::temp_dir().join(format!(
+            "stsync-proxy-test-rotate-token-{}.json",
+            std::process::id()
+        )));
+        std::fs::write(&file.0, r#"[{"id":"1","name":"test","token":"old"}]"#).unwrap();
+
+        let db = Database::open(&file.0);
+        let id = ResourceId(1);
+
+        assert!(db.authorize(&id, Some(b"old")).is_ok());
+        assert_eq!(
+            db.authorize(&id, None).unwrap_err(),
+            AuthError::InvalidToken
+        );
+        assert_eq!(
+            db.authorize(&ResourceId(2), Some(b"old")).unwrap_err(),
+            AuthError::NotFound
+        );
+
+        let (token, res) = db.rotate_token(&id).await.unwrap();
+        res.unwrap();
+        assert_eq!(token.len(), 64);
+
+        // The old token is revoked immediately.
+        assert_eq!(
+            db.authorize(&id, Some(b"old")).unwrap_err(),
+            AuthError::InvalidToken
+        );
+        assert!(db.authorize(&id, Some(token.as_bytes())).is_ok());
+
+        // The new token is persisted.
+        let db = Database::open(&file.0);
+        assert!(db.authorize(&id, Some(token.as_bytes())).is_ok());
+        assert!(db.authorize(&id, Some(b"old")).is_err());
+
+        assert!(db.rotate_token(&ResourceId(2)).await.is_none());
+    }
+}
@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// The resolved settings of the HTTP API.
+#[derive(Clone, Debug, Serialize)]
+pub struct Config {
+    /// The maximum number of sessions per resource that can be created over the HTTP API.
+    pub max_sessions: usize,
+    /// The origins allowed to make cross-origin requests to the HTTP API. Empty disables CORS.
+    pub allowed_origins: Vec<String>,
+    /// The time a client has to send the body of a request to the HTTP API.
+    pub body_timeout: Duration,
+    /// The maximum size of a request body in bytes.
+    pub max_body_size: usize,
+    /// The bearer token required for administrative requests. `None` disables them.
+    #[serde(skip)]
+    pub admin_token: Option<String>,
+}
@@ -0,0 +1,25 @@
+use hyper::{Body, Method, Response};
+
+use crate::http::Context;
+
+/// Liveness probe. Always succeeds while the HTTP server is running.
+pub(super) async fn healthz(ctx: Context) -> Response<Body> {
+    if ctx.request.method() != Method::GET {
+        return Response::builder().status(405).body(Body::empty()).unwrap();
+    }
+
+    Response::builder().status(200).body(Body::empty()).unwrap()
+}
+
+/// Readiness probe. Succeeds once the SRT server is bound and its workers are running.
+pub(super) async fn readyz(ctx: Context) -> Response<Body> {
+    if ctx.request.method() != Method::GET {
+        return Response::builder().status(405).body(Body::empty()).unwrap();
+    }
+
+    if ctx.state.srt.is_ready() {
+        Response::builder().status(200).body(Body::empty()).unwrap()
+    } else {
+        Response::builder().status(503).body(Body::empty()).unwrap()
+    }
+}
@@ -1,13 +1,23 @@
-use std::fmt::Write;
+use std::fmt::{self, Display, Formatter, Write};
 
 use hyper::{Body, Response};
 
 use crate::http::Context;
+use crate::session::SessionManager;
+use crate::srt::SrtVersion;
 
 pub(super) async fn metrics(ctx: Context) -> Response<Body> {
     let mut string = String::new();
-    let guard = ctx.state.srt.conn_metrics.lock();
-    let iter = guard.iter();
+
+    // Copy the connection metrics so the lock is not held while formatting.
+    let connections: Vec<_> = ctx
+        .state
+        .srt
+        .conn_metrics
+        .lock()
+        .iter()
+        .map(|(id, metrics)| (*id, metrics.snapshot()))
+        .collect();
 
     writeln!(
         string,
@@ -38,7 +48,56 @@ pub(super) async fn metrics(ctx: Context) -> Response<Body> {
         .unwrap();
     }
 
-    for (id, metrics) in iter {
+    let closed = &ctx.state.srt.metrics.connections_closed;
+    for (reason, counter) in [
+        ("idle_timeout", &closed.idle_timeout),
+        ("peer_shutdown", &closed.peer_shutdown),
+        ("end_of_stream", &closed.end_of_stream),
+        ("server_shutdown", &closed.server_shutdown),
+        ("watchdog", &closed.watchdog),
+        ("operator", &closed.operator),
+        ("error", &closed.error),
+    ] {
+        writeln!(
+            string,
+            "srt_connections_closed_total{{reason=\"{}\"}} {}",
+            reason, counter
+        )
+        .unwrap();
+    }
+
+    for (code, counter) in ctx.state.srt.metrics.handshake_rejections.iter() {
+        writeln!(
+            string,
+            "srt_handshake_rejections_total{{reason=\"{}\"}} {}",
+            code.rejection_reason().unwrap(),
+            counter
+        )
+        .unwrap();
+    }
+
+    writeln!(
+        string,
+        "srt_datagrams_truncated_total {}",
+        ctx.state.srt.metrics.datagrams_truncated
+    )
+    .unwrap();
+
+    writeln!(
+        string,
+        "srt_handshake_invalid_mode_total {}",
+        ctx.state.srt.metrics.handshake_invalid_mode
+    )
+    .unwrap();
+
+    writeln!(
+        string,
+        "srt_sessions_rejected_total {}",
+        ctx.state.srt.metrics.sessions_rejected
+    )
+    .unwrap();
+
+    for (id, metrics) in connections {
         let id = id.server_socket_id.0;
 
         writeln!(
@@ -138,6 +197,118 @@ pub(super) async fn metrics(ctx: Context) -> Response<Body> {
             id, metrics.rtt_variance
         )
         .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_decrypt_failures{{id=\"{}\"}} {}",
+            id, metrics.decrypt_failures
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_oversized_packets{{id=\"{}\"}} {}",
+            id, metrics.oversized_packets
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_pacing_interval{{id=\"{}\"}} {}",
+            id, metrics.pacing_interval
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_peer_errors{{id=\"{}\"}} {}",
+            id, metrics.peer_errors
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_max_send_rate{{id=\"{}\"}} {}",
+            id, metrics.max_send_rate
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_send_rate{{id=\"{}\"}} {}",
+            id, metrics.send_rate
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_latency_ms{{id=\"{}\"}} {}",
+            id, metrics.latency
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_effective_latency_ms{{id=\"{}\"}} {}",
+            id, metrics.effective_latency
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_reorder_queue_len{{id=\"{}\"}} {}",
+            id, metrics.reorder_queue_len
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_reorder_oldest_age_ms{{id=\"{}\"}} {}",
+            id, metrics.reorder_oldest_age
+        )
+        .unwrap();
+
+        writeln!(
+            string,
+            "srt_connection_peer_version{{id=\"{}\",version=\"{}\"}} 1",
+            id,
+            SrtVersion(metrics.peer_version as u32)
+        )
+        .unwrap();
+    }
+
+    for stats in ctx.state.srt.session_manager.stats() {
+        match &stats.host {
+            Some(host) => writeln!(
+                string,
+                "stream_ingest_bitrate{{resource=\"{}\",host=\"{}\"}} {}",
+                stats.resource_id,
+                LabelValue(host),
+                stats.ingest_bitrate
+            ),
+            None => writeln!(
+                string,
+                "stream_ingest_bitrate{{resource=\"{}\"}} {}",
+                stats.resource_id, stats.ingest_bitrate
+            ),
+        }
+        .unwrap();
+
+        match stats.host {
+            Some(host) => writeln!(
+                string,
+                "stream_lagged_segments_total{{resource=\"{}\",host=\"{}\"}} {}",
+                stats.resource_id,
+                LabelValue(&host),
+                stats.lagged_segments
+            ),
+            None => writeln!(
+                string,
+                "stream_lagged_segments_total{{resource=\"{}\"}} {}",
+                stats.resource_id, stats.lagged_segments
+            ),
+        }
+        .unwrap();
     }
 
     Response::builder()
@@ -145,3 +316,33 @@ pub(super) async fn metrics(ctx: Context) -> Response<Body> {
         .body(Body::from(string))
         .unwrap()
 }
+
+/// A label value escaped for the Prometheus text format. Label values are peer controlled, e.g.
+/// the host of the stream id.
+struct LabelValue<'a>(&'a str);
+
+impl Display for LabelValue<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LabelValue;
+
+    #[test]
+    fn test_label_value() {
+        assert_eq!(LabelValue("example.com").to_string(), "example.com");
+        assert_eq!(LabelValue("a\"}\\\nb").to_string(), "a\\\"}\\\\\\nb");
+    }
+}
@@ -1,21 +1,35 @@
+mod config;
+mod health;
 mod metrics;
 mod v1;
 
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use hyper::body::HttpBody;
 use hyper::header::{
     ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
-    ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, ORIGIN,
+    ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, CONTENT_LENGTH, ORIGIN, VARY,
 };
-use hyper::http::HeaderValue;
+use hyper::http::{HeaderMap, HeaderValue};
 use hyper::service::service_fn;
 use hyper::{server::conn::Http, Response};
 use hyper::{Body, Request};
 use tokio::net::TcpListener;
 
+use crate::database::AuthError;
+use crate::session::ResourceId;
 use crate::state::State;
 
+pub use config::Config;
+
 pub async fn serve(state: State) {
     let socket = TcpListener::bind("0.0.0.0:9998").await.unwrap();
+    accept(socket, state).await
+}
 
+/// Serves the HTTP API to all connections accepted from `socket`.
+async fn accept(socket: TcpListener, state: State) {
     loop {
         let (stream, _) = socket.accept().await.unwrap();
 
@@ -24,7 +38,8 @@ pub async fn serve(state: State) {
             let service = service_fn(move |req| {
                 tracing::debug!("HEAD {}", req.uri());
 
-                let origin = req.headers().get(ORIGIN).cloned();
+                let origin =
+                    allowed_origin(req.headers().get(ORIGIN), &state.config.allowed_origins);
 
                 let mut ctx = Context {
                     state: state.clone(),
@@ -36,28 +51,14 @@ pub async fn serve(state: State) {
                     let mut resp = match ctx.path.take() {
                         Some(path) if path == "v1" => v1::route(ctx).await,
                         Some(path) if path == "metrics" => metrics::metrics(ctx).await,
+                        Some(path) if path == "healthz" => health::healthz(ctx).await,
+                        Some(path) if path == "readyz" => health::readyz(ctx).await,
                         _ => Response::builder()
                             .status(404)
                             .body(Body::from("Not Found"))
                             .unwrap(),
                     };
-                    resp.headers_mut().append(
-                        ACCESS_CONTROL_ALLOW_METHODS,
-                        HeaderValue::from_static("POST"),
-                    );
-                    resp.headers_mut().append(
-                        ACCESS_CONTROL_ALLOW_CREDENTIALS,
-                        HeaderValue::from_static("true"),
-                    );
-                    resp.headers_mut().append(
-                        ACCESS_CONTROL_ALLOW_HEADERS,
-                        HeaderValue::from_static("content-type, authorization"),
-                    );
-
-                    if let Some(origin) = origin {
-                        resp.headers_mut()
-                            .append(ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
-                    }
+                    cors_headers(resp.headers_mut(), origin);
 
                     Ok::<_, hyper::Error>(resp)
                 }
@@ -70,6 +71,90 @@ pub async fn serve(state: State) {
     }
 }
 
+/// Returns the `origin` of a request if it is one of the `allowed` origins.
+fn allowed_origin(origin: Option<&HeaderValue>, allowed: &[String]) -> Option<HeaderValue> {
+    let origin = origin?;
+
+    allowed
+        .iter()
+        .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+        .then(|| origin.clone())
+}
+
+/// Appends the CORS headers allowing requests from `origin` to `headers`. `None` only appends
+/// the `Vary` header, the request is not allowed.
+fn cors_headers(headers: &mut HeaderMap, origin: Option<HeaderValue>) {
+    // The response depends on the origin of the request, even if it is not allowed. Otherwise
+    // caches may serve a response without CORS headers to an allowed origin.
+    headers.append(VARY, HeaderValue::from_static("origin"));
+
+    let origin = match origin {
+        Some(origin) => origin,
+        None => return,
+    };
+
+    headers.append(ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+    headers.append(
+        ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("GET, POST, DELETE, OPTIONS"),
+    );
+    headers.append(
+        ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        HeaderValue::from_static("true"),
+    );
+    headers.append(
+        ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("content-type, authorization"),
+    );
+}
+
+/// Reads the whole `body` with the given `headers` into memory. Returns the error response if
+/// the body is larger than `max_size` (413) or not received within `timeout` (408).
+async fn read_body(
+    headers: &HeaderMap,
+    body: &mut Body,
+    max_size: usize,
+    timeout: Duration,
+) -> Result<Bytes, Response<Body>> {
+    let status = |code| {
+        Response::builder()
+            .status(code)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // Reject bodies that announce their size upfront without reading them.
+    let content_length = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    if content_length.is_some_and(|len| len > max_size as u64) {
+        return Err(status(413));
+    }
+
+    let read = async {
+        let mut buf = BytesMut::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.map_err(|err| {
+                tracing::debug!("Failed to read request body: {}", err);
+                status(400)
+            })?;
+
+            if buf.len() + chunk.len() > max_size {
+                return Err(status(413));
+            }
+
+            buf.extend_from_slice(&chunk);
+        }
+
+        Ok(buf.freeze())
+    };
+
+    match tokio::time::timeout(timeout, read).await {
+        Ok(res) => res,
+        Err(_) => Err(status(408)),
+    }
+}
+
 struct Context {
     pub request: Request<Body>,
     path: Path,
@@ -77,12 +162,68 @@ struct Context {
 }
 
 impl Context {
+    /// Reads the request body into memory, so that handlers don't wait on slow clients. The body
+    /// of the request is replaced by the buffered body. Returns the error response if the body is
+    /// too large or too slow.
+    pub async fn buffer_body(&mut self) -> Result<(), Response<Body>> {
+        let (parts, mut body) = std::mem::take(&mut self.request).into_parts();
+        let res = read_body(
+            &parts.headers,
+            &mut body,
+            self.state.config.max_body_size,
+            self.state.config.body_timeout,
+        )
+        .await;
+
+        self.request = Request::from_parts(parts, Body::empty());
+        *self.request.body_mut() = Body::from(res?);
+        Ok(())
+    }
+
     pub fn authorization(&self) -> Option<&[u8]> {
         match self.request.headers().get(AUTHORIZATION) {
             Some(token) => token.as_bytes().strip_prefix(b"Bearer "),
             None => None,
         }
     }
+
+    /// Returns `true` if the bearer token of the request is the admin token.
+    pub fn is_admin(&self) -> bool {
+        match (&self.state.config.admin_token, self.authorization()) {
+            (Some(admin_token), Some(token)) => token == admin_token.as_bytes(),
+            _ => false,
+        }
+    }
+
+    /// Checks the bearer token of the request against the admin token. Returns the error response
+    /// if the request is not authorized, or if no admin token is configured.
+    pub fn authorize_admin(&self) -> Option<Response<Body>> {
+        let Some(admin_token) = &self.state.config.admin_token else {
+            return Some(Response::builder().status(403).body(Body::empty()).unwrap());
+        };
+
+        match self.authorization() {
+            Some(token) if token == admin_token.as_bytes() => None,
+            _ => Some(Response::builder().status(401).body(Body::empty()).unwrap()),
+        }
+    }
+
+    /// Checks the bearer token of the request against the current token of the stream `id`.
+    /// Returns the error response if the request is not authorized.
+    ///
+    /// The token is only checked once here, so a request that was authorized before the token
+    /// was rotated still completes.
+    pub fn authorize_stream(&self, id: &ResourceId) -> Option<Response<Body>> {
+        match self.state.db.authorize(id, self.authorization()) {
+            Ok(_) => None,
+            Err(AuthError::NotFound) => {
+                Some(Response::builder().status(404).body(Body::empty()).unwrap())
+            }
+            Err(AuthError::InvalidToken) => {
+                Some(Response::builder().status(401).body(Body::empty()).unwrap())
+            }
+        }
+    }
 }
 
 struct Path {
@@ -112,3 +253,219 @@ impl Path {
         }
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use hyper::header::{
+        ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_METHODS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_LENGTH, VARY,
+    };
+    use hyper::http::{HeaderMap, HeaderValue};
+    use hyper::{Body, Client, Method, Request, Response};
+    use tokio::net::TcpListener;
+
+    use crate::database::tests::TempFile;
+    use crate::database::Database;
+    use crate::session::buffer::BufferSessionManager;
+    use crate::srt::config::tests::config;
+    use crate::state::State;
+
+    use super::{accept, allowed_origin, cors_headers, read_body, Config};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Returns the HTTP settings used by the tests.
+    pub fn http_config() -> Config {
+        Config {
+            max_sessions: 2,
+            allowed_origins: Vec::new(),
+            body_timeout: TIMEOUT,
+            max_body_size: 1024,
+            admin_token: Some("admin".to_owned()),
+        }
+    }
+
+    /// Creates a temporary database file named after `name` containing the JSON `streams`.
+    pub fn database(name: &str, streams: &str) -> (TempFile, Database) {
+        let file = TempFile(std::env::temp_dir().join(format!(
+            "stsync-proxy-test-{}-{}.json",
+            name,
+            std::process::id()
+        )));
+        std::fs::write(&file.0, streams).unwrap();
+
+        let db = Database::open(&file.0);
+        (file, db)
+    }
+
+    /// Serves the HTTP API for `state` on a local port. Returns the address of the API.
+    pub async fn spawn(state: State) -> SocketAddr {
+        let socket = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::task::spawn(accept(socket, state));
+        addr
+    }
+
+    /// Sends a request with an empty body to `uri` on the HTTP API at `addr`, using `token` as
+    /// the bearer token.
+    pub async fn request(
+        addr: SocketAddr,
+        method: Method,
+        uri: &str,
+        token: Option<&str>,
+    ) -> Response<Body> {
+        let mut req = Request::builder()
+            .method(method)
+            .uri(format!("http://{}{}", addr, uri));
+        if let Some(token) = token {
+            req = req.header("Authorization", format!("Bearer {}", token));
+        }
+
+        Client::new()
+            .request(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// Returns the body of `resp`.
+    pub async fn body(resp: Response<Body>) -> String {
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_allowed_origin() {
+        let allowed = [
+            "https://example.com".to_owned(),
+            "http://localhost:8080".to_owned(),
+        ];
+        let origin = |s| HeaderValue::from_static(s);
+
+        for s in ["https://example.com", "http://localhost:8080"] {
+            assert_eq!(allowed_origin(Some(&origin(s)), &allowed), Some(origin(s)));
+        }
+
+        for s in [
+            "https://evil.example",
+            "http://example.com",
+            "https://example.com.evil.example",
+            "null",
+        ] {
+            assert_eq!(allowed_origin(Some(&origin(s)), &allowed), None);
+        }
+
+        assert_eq!(allowed_origin(None, &allowed), None);
+
+        // No origin is allowed by default.
+        assert_eq!(
+            allowed_origin(Some(&origin("https://example.com")), &[]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cors_headers() {
+        let mut headers = HeaderMap::new();
+        cors_headers(
+            &mut headers,
+            Some(HeaderValue::from_static("https://example.com")),
+        );
+
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "https://example.com");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, POST, DELETE, OPTIONS"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(headers[VARY], "origin");
+
+        // Responses to other origins only vary by the origin.
+        let mut headers = HeaderMap::new();
+        cors_headers(&mut headers, None);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers[VARY], "origin");
+    }
+
+    #[tokio::test]
+    async fn test_read_body() {
+        let headers = HeaderMap::new();
+
+        let mut body = Body::from("hello");
+        let buf = read_body(&headers, &mut body, 5, TIMEOUT).await.unwrap();
+        assert_eq!(buf, "hello");
+
+        let mut body = Body::empty();
+        let buf = read_body(&headers, &mut body, 5, TIMEOUT).await.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_read_body_too_large() {
+        let headers = HeaderMap::new();
+
+        let (mut tx, mut body) = Body::channel();
+        tokio::task::spawn(async move {
+            for _ in 0..3 {
+                tx.send_data(Bytes::from_static(b"hello")).await.unwrap();
+            }
+        });
+        let resp = read_body(&headers, &mut body, 12, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(resp.status(), 413);
+
+        // An announced size is rejected without reading the body.
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_static("13"));
+        let (_tx, mut body) = Body::channel();
+        let resp = read_body(&headers, &mut body, 12, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(resp.status(), 413);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_read_body_timeout() {
+        let headers = HeaderMap::new();
+
+        // The client sends part of the body and then stalls.
+        let (mut tx, mut body) = Body::channel();
+        tx.send_data(Bytes::from_static(b"hello")).await.unwrap();
+        let resp = read_body(&headers, &mut body, 1024, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert_eq!(resp.status(), 408);
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn test_session_limit() {
+        let (_file, db) = database(
+            "session-limit",
+            r#"[{"id":"1","name":"test","token":"token"}]"#,
+        );
+        let srt =
+            crate::srt::state::State::new(BufferSessionManager::new(Default::default()), config());
+        let state = State::with_database(srt, http_config(), db);
+        let addr = spawn(state.clone()).await;
+
+        let uri = "/v1/streams/1/sessions";
+        let create = || request(addr, Method::POST, uri, Some("token"));
+
+        assert_eq!(create().await.status(), 201);
+        assert_eq!(create().await.status(), 201);
+        assert_eq!(state.srt.metrics.sessions_rejected.get(), 0);
+
+        assert_eq!(create().await.status(), 429);
+        assert_eq!(state.srt.metrics.sessions_rejected.get(), 1);
+
+        let resp = request(addr, Method::GET, "/metrics", None).await;
+        assert_eq!(resp.status(), 200);
+        let body = body(resp).await;
+        assert!(body.lines().any(|l| l == "srt_sessions_rejected_total 1"));
+    }
+}
@@ -0,0 +1,415 @@
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Method, Response};
+
+use crate::http::Context;
+use crate::srt::Control;
+
+pub(super) async fn route(mut ctx: Context) -> Response<Body> {
+    match ctx.path.take() {
+        Some(path) => match path.parse::<u32>() {
+            Ok(id) => match ctx.path.take() {
+                None => match *ctx.request.method() {
+                    Method::GET => get_connection(ctx, id).await,
+                    Method::DELETE => delete_connection(ctx, id).await,
+                    _ => Response::builder().status(405).body(Body::empty()).unwrap(),
+                },
+                // Connection commands are only meant for debugging.
+                Some(_) if !ctx.state.srt.config.debug => {
+                    Response::builder().status(404).body(Body::empty()).unwrap()
+                }
+                Some(p) if p == "retransmit" => match ctx.request.method() {
+                    &Method::POST => retransmit(ctx, id).await,
+                    _ => Response::builder().status(405).body(Body::empty()).unwrap(),
+                },
+                _ => Response::builder().status(404).body(Body::empty()).unwrap(),
+            },
+            Err(_) => Response::builder()
+                .status(400)
+                .body(Body::from("Failed to parse id"))
+                .unwrap(),
+        },
+        None => match ctx.request.method() {
+            &Method::GET => list_connections(ctx).await,
+            _ => Response::builder().status(405).body(Body::empty()).unwrap(),
+        },
+    }
+}
+
+/// `GET /v1/connections`
+///
+/// Returns the id, mode and resource of the active connections as JSON. Only the connections
+/// of the streams that the bearer token of the request is authorized for are returned, or all
+/// connections if the request is authorized with the admin token.
+async fn list_connections(ctx: Context) -> Response<Body> {
+    let token = match ctx.authorization() {
+        Some(token) => token,
+        None => return Response::builder().status(401).body(Body::empty()).unwrap(),
+    };
+
+    let mut connections: Vec<_> = ctx
+        .state
+        .srt
+        .conn_metrics
+        .lock()
+        .iter()
+        .filter_map(|(id, metrics)| {
+            let resource_id = (*metrics.resource_id.lock())?;
+            Some((*id, metrics.snapshot().mode, resource_id))
+        })
+        .collect();
+    if !ctx.is_admin() {
+        connections
+            .retain(|(_, _, resource_id)| ctx.state.db.authorize(resource_id, Some(token)).is_ok());
+    }
+    connections.sort_by_key(|(id, _, _)| id.server_socket_id);
+
+    let body: Vec<_> = connections
+        .into_iter()
+        .map(|(id, mode, resource_id)| {
+            serde_json::json!({
+                "id": id.to_string(),
+                "server_socket_id": id.server_socket_id.0,
+                "client_socket_id": id.client_socket_id.0,
+                "addr": id.addr.to_string(),
+                "mode": mode,
+                "resource_id": resource_id.to_string(),
+            })
+        })
+        .collect();
+
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::Value::from(body).to_string()))
+        .unwrap()
+}
+
+/// `GET /v1/connections/{server_socket_id}`
+///
+/// Returns a snapshot of the metrics of the connection as JSON. The request must be authorized
+/// with the bearer token of the stream of the connection, or with the admin token.
+async fn get_connection(ctx: Context, id: u32) -> Response<Body> {
+    let token = match ctx.authorization() {
+        Some(token) => token,
+        None => return Response::builder().status(401).body(Body::empty()).unwrap(),
+    };
+
+    let handle = match ctx.state.srt.pool.find_server_id(id) {
+        Some(handle) => handle,
+        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
+    };
+
+    // The connection may have been closed in the meantime.
+    let (snapshot, resource_id) = match ctx.state.srt.conn_metrics.lock().get(&handle.id) {
+        Some(metrics) => (metrics.snapshot(), *metrics.resource_id.lock()),
+        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
+    };
+
+    // Like in the list, connections of other streams don't exist for the request.
+    let authorized = ctx.is_admin()
+        || resource_id.is_some_and(|id| ctx.state.db.authorize(&id, Some(token)).is_ok());
+    if !authorized {
+        return Response::builder().status(404).body(Body::empty()).unwrap();
+    }
+
+    let mut body = serde_json::to_value(snapshot).unwrap();
+    body["id"] = handle.id.to_string().into();
+
+    Response::builder()
+        .status(200)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `DELETE /v1/connections/{server_socket_id}`
+///
+/// Closes the connection. The peer receives a shutdown. The request must be authorized with
+/// the admin token.
+async fn delete_connection(ctx: Context, id: u32) -> Response<Body> {
+    // Authorize first, so that unauthorized requests don't learn which connections exist.
+    if let Some(resp) = ctx.authorize_admin() {
+        return resp;
+    }
+
+    let handle = match ctx.state.srt.pool.find_server_id(id) {
+        Some(handle) => handle,
+        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
+    };
+
+    if handle.control(Control::Close) {
+        Response::builder().status(202).body(Body::empty()).unwrap()
+    } else {
+        Response::builder().status(503).body(Body::empty()).unwrap()
+    }
+}
+
+/// `POST /v1/connections/{server_socket_id}/retransmit?first={seq}&last={seq}`
+///
+/// Retransmits the given range of packets to the peer. The request must be authorized with the
+/// admin token.
+async fn retransmit(ctx: Context, id: u32) -> Response<Body> {
+    if let Some(resp) = ctx.authorize_admin() {
+        return resp;
+    }
+
+    let query = ctx.request.uri().query().unwrap_or_default();
+
+    let control = match (param(query, "first"), param(query, "last")) {
+        (Some(first), Some(last)) => Control::retransmit(first, last),
+        _ => None,
+    };
+
+    let control = match control {
+        Some(control) => control,
+        None => {
+            return Response::builder()
+                .status(400)
+                .body(Body::from(format!(
+                    "Expected a range of at most {} packets",
+                    Control::MAX_RETRANSMIT
+                )))
+                .unwrap()
+        }
+    };
+
+    let handle = match ctx.state.srt.pool.find_server_id(id) {
+        Some(handle) => handle,
+        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
+    };
+
+    if handle.control(control) {
+        Response::builder().status(202).body(Body::empty()).unwrap()
+    } else {
+        Response::builder().status(503).body(Body::empty()).unwrap()
+    }
+}
+
+/// Returns the value of the query parameter `key`.
+fn param(query: &str, key: &str) -> Option<u32> {
+    query
+        .split('&')
+        .find_map(|pair| match pair.split_once('=') {
+            Some((k, v)) if k == key => v.parse().ok(),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    use hyper::{Method, StatusCode};
+    use tokio::task::JoinHandle;
+
+    use crate::database::tests::TempFile;
+    use crate::http::tests::{body, database, http_config, request, spawn};
+    use crate::session::buffer::{BufferSessionManager, SessionKey};
+    use crate::session::{ResourceId, SessionId};
+    use crate::srt::config::tests::config;
+    use crate::srt::config::Config;
+    use crate::srt::server::tests::{unused_addr, Peer};
+    use crate::srt::server::Server;
+    use crate::state::State;
+
+    /// A SRT server with a publisher for each of the streams `1` and `2`, and the HTTP API for
+    /// it. The publisher of stream `n` uses the socket id `n`.
+    struct Env {
+        state: State,
+        addr: SocketAddr,
+        server: JoinHandle<()>,
+        peers: Vec<Peer>,
+        _file: TempFile,
+    }
+
+    impl Env {
+        async fn new(name: &str, srt: Config) -> Self {
+            let (file, db) = database(
+                name,
+                r#"[{"id":"1","name":"one","token":"one"},{"id":"2","name":"two","token":"two"}]"#,
+            );
+
+            let srt = Config {
+                bind: unused_addr(),
+                ..srt
+            };
+            let bind = srt.bind;
+            let server = Server::new(BufferSessionManager::new(Default::default()), srt).unwrap();
+            for id in [1, 2] {
+                server.state.session_manager.registry.insert(SessionKey {
+                    resource_id: ResourceId(id),
+                    session_id: SessionId(id),
+                    expires: Instant::now() + Duration::from_secs(60),
+                });
+            }
+
+            let state = State::with_database(server.state.clone(), http_config(), db);
+            let server = tokio::task::spawn(async move {
+                server.await.unwrap();
+            });
+
+            let mut peers = Vec::new();
+            for id in [1, 2] {
+                let peer = Peer::new(bind, id).await;
+                peer.connect(&format!("#!::m=publish,r={},s={}", id, id))
+                    .await;
+                peers.push(peer);
+            }
+
+            let addr = spawn(state.clone()).await;
+            Self {
+                state,
+                addr,
+                server,
+                peers,
+                _file: file,
+            }
+        }
+
+        async fn request(&self, method: Method, uri: &str, token: Option<&str>) -> StatusCode {
+            request(self.addr, method, uri, token).await.status()
+        }
+
+        /// Closes all connections and stops the server.
+        async fn close(self) {
+            // Connections borrow from the workers and must be closed before the server is
+            // dropped.
+            for peer in &self.peers {
+                peer.shutdown().await;
+            }
+            tokio::time::timeout(Duration::from_secs(2), async {
+                while self.state.srt.pool.len() != 0 {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("connections were not closed");
+
+            self.server.abort();
+            let _ = self.server.await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_connection() {
+        let env = Env::new("get-connection", config()).await;
+
+        assert_eq!(
+            env.request(Method::GET, "/v1/connections/1", None).await,
+            401
+        );
+
+        // Connections of other streams are hidden.
+        for token in ["two", "invalid"] {
+            assert_eq!(
+                env.request(Method::GET, "/v1/connections/1", Some(token))
+                    .await,
+                404
+            );
+        }
+
+        for token in ["one", "admin"] {
+            let resp = request(env.addr, Method::GET, "/v1/connections/1", Some(token)).await;
+            assert_eq!(resp.status(), 200);
+            let body: serde_json::Value = serde_json::from_str(&body(resp).await).unwrap();
+            assert_eq!(body["mode"], "publish");
+        }
+
+        env.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_retransmit() {
+        let uri = "/v1/connections/1/retransmit?first=0&last=0";
+
+        // Connection commands don't exist outside of debug mode.
+        let env = Env::new("retransmit-no-debug", config()).await;
+        assert_eq!(env.request(Method::POST, uri, Some("admin")).await, 404);
+        env.close().await;
+
+        let env = Env::new(
+            "retransmit",
+            Config {
+                debug: true,
+                ..config()
+            },
+        )
+        .await;
+
+        for token in [None, Some("one"), Some("invalid")] {
+            assert_eq!(env.request(Method::POST, uri, token).await, 401);
+        }
+        assert_eq!(env.request(Method::POST, uri, Some("admin")).await, 202);
+
+        env.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_list_connections() {
+        let env = Env::new("list-connections", config()).await;
+
+        assert_eq!(env.request(Method::GET, "/v1/connections", None).await, 401);
+
+        let list = |token| async move {
+            let resp = request(env.addr, Method::GET, "/v1/connections", Some(token)).await;
+            assert_eq!(resp.status(), 200);
+            let body: Vec<serde_json::Value> = serde_json::from_str(&body(resp).await).unwrap();
+            body.into_iter()
+                .map(|conn| conn["resource_id"].as_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(list("one").await, ["1"]);
+        assert_eq!(list("two").await, ["2"]);
+        assert!(list("invalid").await.is_empty());
+        assert_eq!(list("admin").await, ["1", "2"]);
+
+        env.close().await;
+    }
+
+    #[tokio::test]
+    async fn test_delete_connection() {
+        let env = Env::new("delete-connection", config()).await;
+
+        // Only the admin token may close connections, even those of the own stream.
+        for token in [None, Some("one")] {
+            assert_eq!(
+                env.request(Method::DELETE, "/v1/connections/1", token)
+                    .await,
+                401
+            );
+        }
+        assert_eq!(
+            env.request(Method::DELETE, "/v1/connections/3", Some("admin"))
+                .await,
+            404
+        );
+
+        assert_eq!(
+            env.request(Method::DELETE, "/v1/connections/1", Some("admin"))
+                .await,
+            202
+        );
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while env.state.srt.pool.len() != 1 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("connection was not closed");
+
+        assert_eq!(
+            env.request(Method::GET, "/v1/connections/1", Some("admin"))
+                .await,
+            404
+        );
+        assert_eq!(
+            env.request(Method::GET, "/v1/connections/2", Some("admin"))
+                .await,
+            200
+        );
+
+        env.close().await;
+    }
+}
@@ -1,3 +1,4 @@
+mod connections;
 mod streams;
 
 use hyper::{Body, Response};
@@ -5,7 +6,12 @@ use hyper::{Body, Response};
 use super::Context;
 
 pub(super) async fn route(mut ctx: Context) -> Response<Body> {
+    if let Err(resp) = ctx.buffer_body().await {
+        return resp;
+    }
+
     match ctx.path.take() {
+        Some(path) if path == "connections" => connections::route(ctx).await,
         Some(path) if path == "streams" => streams::route(ctx).await,
         _ => Response::builder().status(404).body(Body::empty()).unwrap(),
     }
@@ -10,6 +10,11 @@ pub(super) async fn route(mut ctx: Context) -> Response<Body> {
         Some(path) => match path.parse::<ResourceId>() {
             Ok(id) => match ctx.path.take() {
                 Some(p) if p == "sessions" => sessions::route(ctx, id).await,
+                Some(p) if p == "token" => match *ctx.request.method() {
+                    Method::POST => rotate_token(ctx, id).await,
+                    Method::OPTIONS => Response::builder().status(204).body(Body::empty()).unwrap(),
+                    _ => Response::builder().status(405).body(Body::empty()).unwrap(),
+                },
                 _ => Response::builder().status(404).body(Body::empty()).unwrap(),
             },
             Err(_) => Response::builder()
@@ -24,6 +29,35 @@ pub(super) async fn route(mut ctx: Context) -> Response<Body> {
     }
 }
 
+/// `POST /v1/streams/{id}/token`
+///
+/// Replaces the token of the stream with a new random token, revoking the current token. The
+/// request must be authorized with the admin token, so that a leaked stream token cannot be
+/// used to lock out the owner of the stream.
+async fn rotate_token(ctx: Context, id: ResourceId) -> Response<Body> {
+    if let Some(resp) = ctx.authorize_admin() {
+        return resp;
+    }
+
+    let token = match ctx.state.db.rotate_token(&id).await {
+        Some((token, Ok(()))) => token,
+        Some((token, Err(err))) => {
+            tracing::error!("Failed to persist token of stream {}: {}", id, err);
+            token
+        }
+        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
+    };
+
+    tracing::info!("Rotated token of stream {}", id);
+
+    let body = format!("{{\"token\":\"{}\"}}", token);
+
+    Response::builder()
+        .status(201)
+        .body(Body::from(body))
+        .unwrap()
+}
+
 async fn get_streams(_ctx: Context) -> Response<Body> {
     Response::builder().status(501).body(Body::empty()).unwrap()
 }
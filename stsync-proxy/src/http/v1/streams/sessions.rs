@@ -36,16 +36,8 @@ async fn list(ctx: Context, id: ResourceId) -> Response<Body> {
 }
 
 async fn create(ctx: Context, id: ResourceId) -> Response<Body> {
-    let stream = match ctx.state.db.streams.get(&id) {
-        Some(stream) => stream,
-        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
-    };
-
-    if !match ctx.authorization() {
-        Some(token) => token == stream.token.as_bytes(),
-        None => false,
-    } {
-        return Response::builder().status(401).body(Body::empty()).unwrap();
+    if let Some(resp) = ctx.authorize_stream(&id) {
+        return resp;
     }
 
     let expires = Instant::now() + Duration::from_secs(60 * 60 * 24);
@@ -58,7 +50,21 @@ async fn create(ctx: Context, id: ResourceId) -> Response<Body> {
         session_id,
     };
 
-    ctx.state.srt.session_manager.registry.insert(key);
+    if !ctx
+        .state
+        .srt
+        .session_manager
+        .registry
+        .try_insert(key, ctx.state.config.max_sessions)
+    {
+        tracing::debug!("Too many sessions for resource {}", resource_id);
+        ctx.state.srt.metrics.sessions_rejected.inc();
+
+        return Response::builder()
+            .status(429)
+            .body(Body::from("Too many sessions"))
+            .unwrap();
+    }
 
     let body = format!(
         "{{\"resource_id\":\"{}\",\"session_id\":\"{}\"}}",
@@ -76,16 +82,8 @@ async fn get(ctx: Context, id: ResourceId, session_id: SessionId) -> Response<Bo
 }
 
 async fn delete(ctx: Context, id: ResourceId, session_id: SessionId) -> Response<Body> {
-    let stream = match ctx.state.db.streams.get(&id) {
-        Some(stream) => stream,
-        None => return Response::builder().status(404).body(Body::empty()).unwrap(),
-    };
-
-    if !match ctx.authorization() {
-        Some(token) => token == stream.token.as_bytes(),
-        None => false,
-    } {
-        return Response::builder().status(401).body(Body::empty()).unwrap();
+    if let Some(resp) = ctx.authorize_stream(&id) {
+        return resp;
     }
 
     Response::builder().status(501).body(Body::empty()).unwrap()
@@ -0,0 +1,251 @@
+//! Log output
+use std::fmt::{self, Debug, Formatter};
+
+use log::{Log, Metadata, Record};
+use parking_lot::RwLock;
+use pretty_env_logger::env_logger::Logger;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+use crate::config::LogFormat;
+
+/// The environment variable used as the filter if none is configured.
+const FILTER_ENV: &str = "RUST_LOG";
+
+/// Installs the global logger using the given output `format` and `filter`. The `RUST_LOG`
+/// environment variable is used if `filter` is `None`. Returns a [`LogHandle`] to change the
+/// filter of the installed logger.
+///
+/// # Panics
+///
+/// Panics if a global logger was already installed.
+pub fn init(format: LogFormat, filter: Option<&str>) -> LogHandle {
+    match format {
+        LogFormat::Pretty => {
+            let logger: &'static PrettyLogger = Box::leak(Box::new(PrettyLogger {
+                inner: RwLock::new(pretty(filter)),
+            }));
+            log::set_logger(logger).expect("logger already initialized");
+            log::set_max_level(logger.inner.read().filter());
+
+            LogHandle::Pretty(logger)
+        }
+        LogFormat::Json => {
+            let (subscriber, handle) = json(std::io::stdout, env_filter(filter));
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("logger already initialized");
+
+            LogHandle::Json(handle)
+        }
+    }
+}
+
+/// A handle to change the filter of the installed logger.
+#[derive(Clone)]
+pub enum LogHandle {
+    Pretty(&'static PrettyLogger),
+    Json(reload::Handle<EnvFilter, Registry>),
+}
+
+impl LogHandle {
+    /// Replaces the filter of the logger. The `RUST_LOG` environment variable is used if
+    /// `filter` is `None`.
+    pub fn set_filter(&self, filter: Option<&str>) {
+        match self {
+            Self::Pretty(logger) => {
+                let inner = pretty(filter);
+                log::set_max_level(inner.filter());
+                *logger.inner.write() = inner;
+            }
+            Self::Json(handle) => {
+                if let Err(err) = handle.reload(env_filter(filter)) {
+                    tracing::error!("Failed to change the log filter: {}", err);
+                }
+            }
+        }
+    }
+}
+
+impl Debug for LogHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pretty(_) => f.write_str("Pretty"),
+            Self::Json(_) => f.write_str("Json"),
+        }
+    }
+}
+
+/// A human-readable [`Log`] implementation whose filter can be replaced while it is installed.
+pub struct PrettyLogger {
+    inner: RwLock<Logger>,
+}
+
+impl Log for PrettyLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.inner.read().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        self.inner.read().log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.read().flush();
+    }
+}
+
+/// Returns the human-readable logger using `filter`, or the `RUST_LOG` environment variable if
+/// `filter` is `None`.
+fn pretty(filter: Option<&str>) -> Logger {
+    let mut builder = pretty_env_logger::formatted_builder();
+
+    match filter {
+        Some(filter) => {
+            builder.parse_filters(filter);
+        }
+        None => {
+            if let Ok(filter) = std::env::var(FILTER_ENV) {
+                builder.parse_filters(&filter);
+            }
+        }
+    }
+
+    builder.build()
+}
+
+/// Returns the [`EnvFilter`] for `filter`, or the `RUST_LOG` environment variable if `filter`
+/// is `None`. `filter` must have been validated before.
+fn env_filter(filter: Option<&str>) -> EnvFilter {
+    match filter {
+        Some(filter) => EnvFilter::new(filter),
+        None => EnvFilter::from_env(FILTER_ENV),
+    }
+}
+
+/// Returns a [`Subscriber`] that writes one JSON object per event passing `filter` to `writer`.
+/// The fields of all entered spans are included in the `spans` list. The filter can be replaced
+/// using the returned [`Handle`].
+///
+/// [`Handle`]: reload::Handle
+fn json<W>(
+    writer: W,
+    filter: EnvFilter,
+) -> (
+    impl Subscriber + Send + Sync,
+    reload::Handle<EnvFilter, Registry>,
+)
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let subscriber = Registry::default().with(filter).with(
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_writer(writer),
+    );
+
+    (subscriber, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{field, span, Level};
+    use tracing_subscriber::fmt::MakeWriter;
+    use tracing_subscriber::EnvFilter;
+
+    use super::LogHandle;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Buffer {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn test_json() {
+        let buf = Buffer::default();
+
+        let (subscriber, _) = super::json(buf.clone(), EnvFilter::new("trace"));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = span!(
+                Level::ERROR,
+                "Connection",
+                connection_id = "[::1]:9000:1:2",
+                resource_id = field::Empty,
+            );
+            let _enter = span.enter();
+            span.record("resource_id", 1234);
+
+            tracing::error!("first");
+            tracing::error!(value = 5, "second");
+        });
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+
+        let line = &lines[0];
+        assert_eq!(line["level"], "ERROR");
+        assert_eq!(line["fields"]["message"], "first");
+        assert_eq!(line["spans"][0]["name"], "Connection");
+        assert_eq!(line["spans"][0]["connection_id"], "[::1]:9000:1:2");
+        assert_eq!(line["spans"][0]["resource_id"], 1234);
+
+        assert_eq!(lines[1]["fields"]["value"], 5);
+    }
+
+    #[test]
+    fn test_json_set_filter() {
+        let buf = Buffer::default();
+
+        let (subscriber, handle) = super::json(buf.clone(), EnvFilter::new("info"));
+        let handle = LogHandle::Json(handle);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::debug!("hidden");
+            tracing::info!("first");
+
+            handle.set_filter(Some("debug"));
+            tracing::debug!("second");
+
+            handle.set_filter(Some("warn"));
+            tracing::info!("hidden");
+            tracing::warn!("third");
+        });
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let messages: Vec<_> = output
+            .lines()
+            .map(|line| {
+                let line: serde_json::Value = serde_json::from_str(line).unwrap();
+                line["fields"]["message"].as_str().unwrap().to_owned()
+            })
+            .collect();
+        assert_eq!(messages, ["first", "second", "third"]);
+    }
+}
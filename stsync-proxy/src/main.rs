@@ -4,9 +4,15 @@
 // We only import log to remove trace and debug levels at compile time.
 use log as _;
 
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
 use clap::Parser;
-use config::Config;
+use config::{Config, LogFormat};
+use logger::LogHandle;
 use ragequit::SHUTDOWN;
+use session::auth::HttpAuthenticator;
 use session::buffer::BufferSessionManager;
 use srt::server::Server;
 use state::State;
@@ -15,11 +21,14 @@ use tokio::runtime::Builder;
 mod config;
 mod database;
 mod http;
+mod logger;
 mod metrics;
 mod proto;
+mod reload;
 mod session;
 mod srt;
 mod state;
+mod ts;
 mod utils;
 
 #[derive(Debug, Parser)]
@@ -27,32 +36,105 @@ mod utils;
 pub struct Args {
     #[clap(short, long, value_name = "FILE", default_value = "config.toml")]
     config: String,
+    /// Validate the config file and print the effective settings without starting the server.
+    #[clap(long)]
+    check: bool,
 }
 
-fn main() {
-    ragequit::init();
-    pretty_env_logger::init();
-
+fn main() -> ExitCode {
     let args = Args::parse();
 
+    if args.check {
+        return check(&args.config);
+    }
+
+    ragequit::init();
+
     let config = match Config::from_file(&args.config) {
         Ok(config) => config,
         Err(err) => {
+            logger::init(LogFormat::default(), None);
             tracing::error!("Failed to load config file: {}", err);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
+    let logger = logger::init(config.log.format, config.log.level.as_deref());
+
     let rt = Builder::new_multi_thread().enable_all().build().unwrap();
 
-    rt.block_on(async_main(config));
+    rt.block_on(async_main(config, PathBuf::from(args.config), logger));
+    ExitCode::SUCCESS
+}
+
+/// Loads and validates the config file at `path`, then prints the effective settings, including
+/// all defaults. Nothing is bound or started.
+fn check(path: &str) -> ExitCode {
+    let config = match Config::from_file(path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut config = config.resolved();
+
+    // Don't leak the secrets, e.g. into CI logs.
+    let redact = |secret: &mut Option<String>| {
+        if secret.is_some() {
+            *secret = Some(String::from("<redacted>"));
+        }
+    };
+    redact(&mut config.srt.passphrase);
+    redact(&mut config.http.admin_token);
+
+    // Convert to a `Value` first, it orders tables after plain values as required by TOML.
+    match toml::Value::try_from(&config) {
+        Ok(value) => {
+            println!("{}: OK", path);
+            println!();
+            print!("{}", value);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-async fn async_main(config: Config) {
-    let manager = BufferSessionManager::new();
+async fn async_main(config: Config, path: PathBuf, logger: LogHandle) {
+    let manager_config = session::buffer::Config {
+        start_from_keyframe: config.srt.start_from_keyframe,
+        publisher_policy: config.srt.publisher_policy,
+        channel_capacity: config
+            .session
+            .channel_capacity
+            .unwrap_or(session::buffer::Config::DEFAULT_CHANNEL_CAPACITY),
+        backpressure: config.session.backpressure,
+    };
+
+    let manager = match &config.session.auth_callback {
+        // The URL was already validated.
+        Some(url) => BufferSessionManager::with_authenticator(
+            manager_config,
+            HttpAuthenticator::new(url.parse().unwrap()),
+        ),
+        None => BufferSessionManager::new(manager_config),
+    };
+
+    tokio::task::spawn(manager.clone().cleanup(Duration::from_secs(60)));
 
     let server = Server::new(manager, config.srt.clone()).unwrap();
-    let state = State::new(server.state.clone());
+    let state = State::new(server.state.clone(), config.http.clone().into());
+
+    tokio::task::spawn(reload::reload_on_sighup(
+        path,
+        server.state.clone(),
+        logger,
+        config.log.level.clone(),
+    ));
 
     if config.srt.enabled {
         tokio::task::spawn(async move {
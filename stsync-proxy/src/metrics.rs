@@ -1,19 +1,24 @@
 use std::fmt::{self, Display, Formatter};
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 /// An increasing counter.
 ///
+/// A `Counter` is a lock-free 64-bit value, even on 32-bit platforms. It wraps around on overflow,
+/// which takes centuries at any realistic rate.
+///
 /// Note that all operations on `Counter` correspond to [`Relaxed`] atomic operations. The value
 /// must not be relied upon for exact correctness.
+///
+/// [`Relaxed`]: Ordering::Relaxed
 #[derive(Debug, Default)]
 #[repr(transparent)]
-pub struct Counter(AtomicUsize);
+pub struct Counter(AtomicU64);
 
 impl Counter {
     /// Creates a new `Counter` initialized to `0`.
     #[inline]
     pub const fn new() -> Self {
-        Self(AtomicUsize::new(0))
+        Self(AtomicU64::new(0))
     }
 
     /// Adds `n` to the `Counter`.
@@ -21,7 +26,7 @@ impl Counter {
     /// Note that this corresponds to a relaxed atomic operation.
     #[inline]
     pub fn add(&self, n: usize) {
-        self.0.fetch_add(n, Ordering::Relaxed);
+        self.0.fetch_add(n as u64, Ordering::Relaxed);
     }
 
     /// Increments the `Counter` by `1`.
@@ -36,7 +41,7 @@ impl Counter {
     ///
     /// Note that this corresponds to a relaxed atomic load.
     #[inline]
-    pub fn get(&self) -> usize {
+    pub fn get(&self) -> u64 {
         self.0.load(Ordering::Relaxed)
     }
 }
@@ -98,3 +103,34 @@ impl Display for Gauge {
         self.get().fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::Counter;
+
+    #[test]
+    fn test_counter_concurrent() {
+        let counter = Arc::new(Counter::new());
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = counter.clone();
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        counter.inc();
+                        counter.add(2);
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(counter.get(), 4 * 10_000 * 3);
+    }
+}
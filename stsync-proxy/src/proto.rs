@@ -106,6 +106,40 @@ impl Decode for Vec<u8> {
     }
 }
 
+impl<const N: usize> Encode for [u8; N] {
+    type Error = io::Error;
+
+    fn encode<W>(&self, mut writer: W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        writer.write_all(self)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> usize {
+        N
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    type Error = io::Error;
+
+    /// Decodes exactly `N` bytes. Nothing is consumed if `bytes` contains less than `N` bytes.
+    fn decode<B>(bytes: &mut B) -> Result<Self, Self::Error>
+    where
+        B: Buf,
+    {
+        if bytes.remaining() < N {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        let mut buf = [0; N];
+        bytes.reader().read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
 /// A transparent wrapper around `T` used to directly manipulate bits.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[repr(transparent)]
@@ -372,7 +406,38 @@ impl Decode for bytes::Bytes {
 
 #[cfg(test)]
 mod tests {
-    use super::{Bits, U16, U32, U8};
+    use std::io;
+
+    use bytes::Buf;
+
+    use super::{Bits, Decode, Encode, U16, U32, U8};
+
+    #[test]
+    fn test_array() {
+        let array = [1, 2, 3, 4];
+        let buf = array.encode_to_vec().unwrap();
+        assert_eq!(buf, [1, 2, 3, 4]);
+        assert_eq!(<[u8; 4]>::decode(&mut buf.as_slice()).unwrap(), array);
+
+        let mut array = [0; 1528];
+        for (index, byte) in array.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let buf = array.encode_to_vec().unwrap();
+        assert_eq!(buf.len(), 1528);
+
+        // The array can span multiple chunks and only `N` bytes are consumed.
+        let (front, back) = buf.split_at(1000);
+        let mut bytes = front.chain(back).chain([5, 6].as_slice());
+        assert_eq!(<[u8; 1528]>::decode(&mut bytes).unwrap(), array);
+        assert_eq!(bytes.remaining(), 2);
+
+        // Nothing is consumed if the buffer is too short.
+        let mut slice = [1, 2, 3].as_slice();
+        let err = <[u8; 4]>::decode(&mut slice).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(slice, [1, 2, 3]);
+    }
 
     #[test]
     fn test_bits_be() {
@@ -0,0 +1,134 @@
+//! Reloading of the config file on SIGHUP.
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::logger::LogHandle;
+use crate::session::SessionManager;
+use crate::srt::config::Config as SrtConfig;
+use crate::srt::state::State;
+
+/// Reloads the config file at `path` every time the process receives SIGHUP and applies the
+/// settings that can be changed at runtime to `state` and `logger`. Existing connections are
+/// kept open. `level` is the log filter the logger was installed with.
+///
+/// An invalid config file is logged and leaves the current settings untouched.
+#[cfg(unix)]
+pub async fn reload_on_sighup<S>(
+    path: PathBuf,
+    state: State<S>,
+    logger: LogHandle,
+    mut level: Option<String>,
+) where
+    S: SessionManager,
+{
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut signal = match signal(SignalKind::hangup()) {
+        Ok(signal) => signal,
+        Err(err) => {
+            tracing::error!("Failed to install SIGHUP handler: {}", err);
+            return;
+        }
+    };
+
+    while signal.recv().await.is_some() {
+        tracing::info!("Received SIGHUP, reloading config file {}", path.display());
+
+        match Config::from_file(&path) {
+            Ok(config) => reload(config, &state, &logger, &mut level),
+            Err(err) => tracing::error!("Failed to reload config file: {}", err),
+        }
+    }
+}
+
+/// Config reloading is only supported on unix platforms.
+#[cfg(not(unix))]
+pub async fn reload_on_sighup<S>(
+    _path: PathBuf,
+    _state: State<S>,
+    _logger: LogHandle,
+    _level: Option<String>,
+) where
+    S: SessionManager,
+{
+}
+
+/// Applies the settings of `config` that can be changed at runtime to `state` and `logger`.
+/// `level` is the current log filter and is updated if it changed.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn reload<S>(config: Config, state: &State<S>, logger: &LogHandle, level: &mut Option<String>)
+where
+    S: SessionManager,
+{
+    if config.log.level != *level {
+        tracing::info!(
+            "Changed log.level from {:?} to {:?}",
+            level,
+            config.log.level
+        );
+
+        logger.set_filter(config.log.level.as_deref());
+        *level = config.log.level;
+    }
+
+    state.reload(&SrtConfig::from(config.srt));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tracing_subscriber::{reload, EnvFilter};
+
+    use crate::config::Config;
+    use crate::logger::LogHandle;
+    use crate::session::buffer::BufferSessionManager;
+    use crate::srt::config::Config as SrtConfig;
+    use crate::srt::state::State;
+
+    use super::reload;
+
+    fn config() -> Config {
+        toml::from_str(include_str!("../config.toml")).unwrap()
+    }
+
+    #[test]
+    fn test_reload() {
+        let state = State::new(
+            BufferSessionManager::new(Default::default()),
+            SrtConfig::from(config().srt),
+        );
+        let (_filter, handle) = reload::Layer::new(EnvFilter::new("info"));
+        let logger = LogHandle::Json(handle.clone());
+        let mut level = Some("info".to_owned());
+
+        let mut config = config();
+        config.log.level = Some("stsync_proxy=debug".to_owned());
+        config.srt.bind = "127.0.0.1:1".parse().unwrap();
+        config.srt.latency = 200;
+        config.srt.ack_interval = Some(50);
+        config.srt.idle_timeout.publish = Some(30);
+        config.srt.keepalive.request = Some(0);
+        reload(config, &state, &logger, &mut level);
+
+        assert_eq!(level.as_deref(), Some("stsync_proxy=debug"));
+        assert_eq!(
+            handle.with_current(|filter| filter.to_string()).unwrap(),
+            "stsync_proxy=debug"
+        );
+
+        let runtime = *state.runtime.read();
+        assert_eq!(runtime.latency, 200);
+        assert_eq!(runtime.ack_interval, Duration::from_millis(50));
+        assert_eq!(runtime.idle_timeout.publish, Some(Duration::from_secs(30)));
+        assert_eq!(runtime.keepalive.request, None);
+
+        // The bind address requires a restart.
+        assert_ne!(state.config.bind, "127.0.0.1:1".parse().unwrap());
+
+        // Removing the filter from the config file falls back to `RUST_LOG`.
+        reload(self::config(), &state, &logger, &mut level);
+        assert_eq!(level, None);
+        assert_eq!(state.runtime.read().ack_interval, Duration::from_millis(10));
+    }
+}
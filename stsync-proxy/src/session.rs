@@ -1,13 +1,16 @@
+pub mod auth;
 pub mod buffer;
-pub mod file;
 
+use std::convert::Infallible;
 use std::fmt::{self, Display, Formatter};
+use std::io;
 use std::num::ParseIntError;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll};
 
 use bytes::Bytes;
+use futures::future::BoxFuture;
 use futures::{Sink, Stream};
 use serde::{Deserialize, Serialize};
 use snowflaked::Snowflake;
@@ -19,6 +22,8 @@ pub enum Error {
     InvalidResourceId,
     #[error("invalid credentials")]
     InvalidCredentials,
+    #[error("resource busy")]
+    ResourceBusy,
     #[error("server error")]
     ServerError,
 }
@@ -31,6 +36,26 @@ impl Error {
     pub fn is_invalid_credentials(&self) -> bool {
         matches!(self, Self::InvalidCredentials)
     }
+
+    pub fn is_resource_busy(&self) -> bool {
+        matches!(self, Self::ResourceBusy)
+    }
+}
+
+/// An error writing to the [`Sink`] of a published stream.
+#[derive(Debug, Error)]
+pub enum SessionError {
+    /// The stream was closed and accepts no more segments.
+    #[error("stream closed")]
+    Closed,
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<Infallible> for SessionError {
+    fn from(err: Infallible) -> Self {
+        match err {}
+    }
 }
 
 /// A producer and consumer for transport streams.
@@ -39,22 +64,98 @@ impl Error {
 /// streams. A `SessionManager` is designed to be shareable between different stream
 /// implementations.
 pub trait SessionManager: Send + Sync + 'static {
-    type Sink: Sink<Bytes> + Send + Sync + Unpin + 'static;
+    type Sink: Sink<Bytes, Error: Into<SessionError>> + Send + Sync + Unpin + 'static;
     type Stream: Stream<Item = Bytes> + Send + Sync + Unpin + 'static;
 
+    /// Authorizes a peer to `mode` the stream with the given `resource_id` using its
+    /// `session_id`. This is called before [`publish`] or [`request`], which assume that the peer
+    /// is authorized.
+    ///
+    /// [`publish`]: Self::publish
+    /// [`request`]: Self::request
+    fn authorize(
+        &self,
+        host: Option<&str>,
+        resource_id: Option<ResourceId>,
+        session_id: Option<SessionId>,
+        mode: Mode,
+    ) -> BoxFuture<'static, Result<(), Error>>;
+
     /// Requests a new [`LiveSink`] to the stream with the given `resource_id`.
+    ///
+    /// The optional `host` selects the namespace of the `resource_id`. The same `resource_id`
+    /// under different hosts refers to different streams.
     fn publish(
         &self,
+        host: Option<&str>,
         resource_id: Option<ResourceId>,
-        session_id: Option<SessionId>,
     ) -> Result<LiveSink<Self::Sink>, Error>;
 
     /// Requests a new [`LiveStream`] of the stream with the given `resource_id`.
+    ///
+    /// See [`publish`] for the meaning of `host`.
+    ///
+    /// [`publish`]: Self::publish
     fn request(
         &self,
+        host: Option<&str>,
         resource_id: Option<ResourceId>,
-        session_id: Option<SessionId>,
     ) -> Result<LiveStream<Self::Stream>, Error>;
+
+    /// Returns the [`StreamStats`] of all active streams.
+    fn stats(&self) -> Vec<StreamStats> {
+        Vec::new()
+    }
+
+    /// Returns `true` if publishers of the stream with the given `resource_id` are held back
+    /// until the requesting streams caught up. A publisher waiting for its [`Sink`] is then not
+    /// considered stuck.
+    fn is_backpressured(&self, host: Option<&str>, resource_id: ResourceId) -> bool {
+        let _ = (host, resource_id);
+        false
+    }
+
+    /// Called when the peer of a connection publishing or requesting `resource_id` reports an
+    /// error with the application defined error `code`. The connection is not closed.
+    fn on_peer_error(&self, resource_id: ResourceId, code: u32) {
+        let _ = (resource_id, code);
+    }
+}
+
+/// What a peer wants to do with a stream.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mode {
+    /// The peer wants to view the stream.
+    Request,
+    /// The peer wants to publish the stream.
+    Publish,
+}
+
+impl Mode {
+    /// Returns the name of the mode as used in the StreamId, e.g. `"publish"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::Publish => "publish",
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Statistics of a single stream.
+#[derive(Clone, Debug)]
+pub struct StreamStats {
+    pub host: Option<String>,
+    pub resource_id: ResourceId,
+    /// The bitrate of the published stream in bits per second.
+    pub ingest_bitrate: u64,
+    /// The number of segments skipped by requesting streams that fell behind the stream.
+    pub lagged_segments: u64,
 }
 
 /// A unique identifier for stream.
@@ -134,6 +235,12 @@ where
         }
     }
 
+    /// Returns the [`ResourceId`] of this `LiveStream`.
+    #[inline]
+    pub fn resource_id(&self) -> ResourceId {
+        self.resource_id
+    }
+
     /// Create a pin projection of `self.stream`.
     #[inline]
     fn stream(self: Pin<&mut Self>) -> Pin<&mut S> {
@@ -0,0 +1,191 @@
+//! Authorization of sessions by external services.
+use std::fmt::Debug;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Client, Method, Request, StatusCode, Uri};
+
+use super::{Error, Mode, ResourceId, SessionId};
+
+/// A policy that decides whether a peer may publish or request a stream.
+///
+/// [`SessionManager`] implementations can delegate [`authorize`] to an `Authenticator`, which
+/// allows the decision to be made by an external service, e.g. a database.
+///
+/// [`SessionManager`]: super::SessionManager
+/// [`authorize`]: super::SessionManager::authorize
+pub trait Authenticator: Debug + Send + Sync + 'static {
+    /// Authorizes the peer with `session_id` to `mode` the stream with the given `resource_id` on
+    /// `host`.
+    fn authorize(
+        &self,
+        host: Option<&str>,
+        resource_id: ResourceId,
+        session_id: SessionId,
+        mode: Mode,
+    ) -> BoxFuture<'static, Result<(), Error>>;
+}
+
+/// An [`Authenticator`] that asks an HTTP service.
+///
+/// Every session is sent to the configured URL as a `POST` request with a JSON body:
+///
+/// ```json
+/// { "host": "example.com", "resource_id": "1", "session_id": "2a", "mode": "publish" }
+/// ```
+///
+/// The ids are hex-encoded, the same as in the StreamId. `host` is `null` if the StreamId has no
+/// host. The response status decides:
+///
+/// | Status               | Result                       |
+/// | -------------------- | ---------------------------- |
+/// | `2xx`                | Accepted                     |
+/// | `401`, `403`         | [`Error::InvalidCredentials`] |
+/// | `404`                | [`Error::InvalidResourceId`]  |
+/// | `409`                | [`Error::ResourceBusy`]       |
+/// | Others, or no answer | [`Error::ServerError`]        |
+#[derive(Clone, Debug)]
+pub struct HttpAuthenticator {
+    client: Client<HttpConnector>,
+    uri: Uri,
+}
+
+impl HttpAuthenticator {
+    /// The maximum time to wait for a response. The peer is still waiting for the handshake to
+    /// complete.
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Creates a new `HttpAuthenticator` that sends requests to `uri`.
+    pub fn new(uri: Uri) -> Self {
+        Self {
+            client: Client::new(),
+            uri,
+        }
+    }
+}
+
+impl Authenticator for HttpAuthenticator {
+    fn authorize(
+        &self,
+        host: Option<&str>,
+        resource_id: ResourceId,
+        session_id: SessionId,
+        mode: Mode,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let body = serde_json::json!({
+            "host": host,
+            "resource_id": resource_id.to_string(),
+            "session_id": session_id.to_string(),
+            "mode": mode.as_str(),
+        });
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(self.uri.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        let fut = self.client.request(req);
+        Box::pin(async move {
+            let resp = match tokio::time::timeout(Self::TIMEOUT, fut).await {
+                Ok(Ok(resp)) => resp,
+                Ok(Err(err)) => {
+                    tracing::warn!("Failed to authorize session {}: {}", session_id, err);
+                    return Err(Error::ServerError);
+                }
+                Err(_) => {
+                    tracing::warn!("Failed to authorize session {}: timed out", session_id);
+                    return Err(Error::ServerError);
+                }
+            };
+
+            match resp.status() {
+                status if status.is_success() => Ok(()),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::InvalidCredentials),
+                StatusCode::NOT_FOUND => Err(Error::InvalidResourceId),
+                StatusCode::CONFLICT => Err(Error::ResourceBusy),
+                status => {
+                    tracing::warn!(
+                        "Failed to authorize session {}: unexpected status {}",
+                        session_id,
+                        status
+                    );
+                    Err(Error::ServerError)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+
+    use crate::session::{Error, Mode, ResourceId, SessionId};
+
+    use super::{Authenticator, HttpAuthenticator};
+
+    /// Answers with the status code in the session id, after checking the request body.
+    async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body["host"], "example.com");
+        assert_eq!(body["resource_id"], "2a");
+        assert_eq!(body["mode"], "publish");
+
+        let status = u16::from_str_radix(body["session_id"].as_str().unwrap(), 16).unwrap();
+        let resp = Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap();
+        Ok(resp)
+    }
+
+    #[tokio::test]
+    async fn test_http_authenticator() {
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let server = Server::bind(&addr).serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(handle))
+        }));
+        let uri = format!("http://{}/authorize", server.local_addr());
+        tokio::task::spawn(server);
+
+        let auth = HttpAuthenticator::new(uri.parse().unwrap());
+        let authorize = |status: StatusCode| {
+            let session_id = SessionId(status.as_u16().into());
+            auth.authorize(
+                Some("example.com"),
+                ResourceId(42),
+                session_id,
+                Mode::Publish,
+            )
+        };
+
+        assert!(authorize(StatusCode::OK).await.is_ok());
+        assert!(authorize(StatusCode::NO_CONTENT).await.is_ok());
+        assert!(matches!(
+            authorize(StatusCode::FORBIDDEN).await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert!(matches!(
+            authorize(StatusCode::NOT_FOUND).await,
+            Err(Error::InvalidResourceId)
+        ));
+        assert!(matches!(
+            authorize(StatusCode::CONFLICT).await,
+            Err(Error::ResourceBusy)
+        ));
+        assert!(matches!(
+            authorize(StatusCode::INTERNAL_SERVER_ERROR).await,
+            Err(Error::ServerError)
+        ));
+    }
+}
@@ -1,21 +1,32 @@
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::convert::Infallible;
+use std::collections::{HashMap, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll};
-use std::time::Instant;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
+use futures::future::{self, BoxFuture};
 use futures::{Sink, Stream, StreamExt};
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use snowflaked::sync::Generator;
 use tokio::sync::broadcast;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 use tokio_stream::wrappers::BroadcastStream;
 
-use super::{Error, LiveSink, LiveStream, ResourceId, SessionId, SessionManager};
+use crate::metrics::Counter;
+use crate::ts::KeyframeDetector;
+
+use super::auth::Authenticator;
+use super::{
+    Error, LiveSink, LiveStream, Mode, ResourceId, SessionError, SessionId, SessionManager,
+    StreamStats,
+};
 
 #[derive(Clone, Debug)]
 pub struct BufferSessionManager(Arc<Inner>);
@@ -31,56 +42,129 @@ impl Deref for BufferSessionManager {
 #[derive(Debug)]
 pub struct Inner {
     resource_id: Generator,
-    streams: Mutex<HashMap<ResourceId, broadcast::Sender<Bytes>>>,
+    streams: Mutex<HashMap<StreamKey, Arc<Channel>>>,
     pub registry: SessionRegistry,
+    /// Authorizes sessions instead of the `registry` if set.
+    authenticator: Option<Box<dyn Authenticator>>,
+    config: Config,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        // Nothing can request the streams anymore.
+        for channel in self.streams.get_mut().unwrap().values() {
+            channel.closed.store(true, Ordering::Release);
+        }
+    }
 }
 
 impl BufferSessionManager {
-    pub fn new() -> Self {
+    /// Creates a new `BufferSessionManager` that authorizes sessions using its
+    /// [`SessionRegistry`].
+    pub fn new(config: Config) -> Self {
+        Self::with_authenticator_opt(config, None)
+    }
+
+    /// Creates a new `BufferSessionManager` that authorizes sessions using `authenticator`
+    /// instead of its [`SessionRegistry`].
+    pub fn with_authenticator<A>(config: Config, authenticator: A) -> Self
+    where
+        A: Authenticator,
+    {
+        Self::with_authenticator_opt(config, Some(Box::new(authenticator)))
+    }
+
+    fn with_authenticator_opt(
+        config: Config,
+        authenticator: Option<Box<dyn Authenticator>>,
+    ) -> Self {
         Self(Arc::new(Inner {
             resource_id: Generator::new(0),
             streams: Default::default(),
             registry: SessionRegistry::new(),
+            authenticator,
+            config,
         }))
     }
+
+    /// Periodically removes expired sessions from the [`SessionRegistry`] and the streams
+    /// that are no longer used. This future never completes.
+    pub async fn cleanup(self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            let count = self.registry.reap();
+            if count != 0 {
+                tracing::info!("Reaped {} expired sessions", count);
+            }
+
+            let count = self.reap_channels();
+            if count != 0 {
+                tracing::debug!("Reaped {} unused streams", count);
+            }
+        }
+    }
+
+    /// Removes the channels of all streams without publishers and requesting streams. Returns
+    /// the number of removed channels.
+    fn reap_channels(&self) -> usize {
+        let mut streams = self.streams.lock().unwrap();
+        let len = streams.len();
+
+        // New references to a channel are only created while holding the lock.
+        streams.retain(|_, channel| Arc::strong_count(channel) > 1);
+        len - streams.len()
+    }
+
+    fn channel(&self, key: StreamKey) -> Arc<Channel> {
+        let mut streams = self.streams.lock().unwrap();
+
+        streams
+            .entry(key)
+            .or_insert_with(|| Arc::new(Channel::new(&self.config)))
+            .clone()
+    }
 }
 
 impl SessionManager for BufferSessionManager {
     type Sink = BufferSink;
     type Stream = BufferStream;
 
-    fn request(
+    fn authorize(
         &self,
+        host: Option<&str>,
         resource_id: Option<ResourceId>,
         session_id: Option<SessionId>,
-    ) -> Result<LiveStream<Self::Stream>, Error> {
-        let resource_id = resource_id.ok_or(Error::InvalidResourceId)?;
-        let session_id = session_id.ok_or(Error::InvalidCredentials)?;
+        mode: Mode,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let (resource_id, session_id) = match (resource_id, session_id) {
+            (Some(resource_id), Some(session_id)) => (resource_id, session_id),
+            (None, _) => return Box::pin(future::ready(Err(Error::InvalidResourceId))),
+            (_, None) => return Box::pin(future::ready(Err(Error::InvalidCredentials))),
+        };
 
-        match self.registry.remove(resource_id, session_id) {
-            Some(key) => {
-                if key.session_id != session_id || key.is_expired() {
-                    tracing::debug!("Rejecting due to invalid or expired key");
-                    return Err(Error::InvalidCredentials);
-                }
-            }
-            None => return Err(Error::InvalidCredentials),
+        match &self.authenticator {
+            Some(authenticator) => authenticator.authorize(host, resource_id, session_id, mode),
+            None => self.registry.authorize(host, resource_id, session_id, mode),
         }
+    }
 
-        let mut streams = self.streams.lock().unwrap();
-
-        let rx = match streams.get(&resource_id) {
-            Some(rx) => rx.subscribe(),
-            None => {
-                let (tx, rx) = broadcast::channel(1024);
+    fn request(
+        &self,
+        host: Option<&str>,
+        resource_id: Option<ResourceId>,
+    ) -> Result<LiveStream<Self::Stream>, Error> {
+        let resource_id = resource_id.ok_or(Error::InvalidResourceId)?;
 
-                streams.insert(resource_id, tx);
-                rx
-            }
-        };
+        let channel = self.channel(StreamKey::new(host, resource_id));
+        let (backlog, rx) = channel.subscribe();
 
         let stream = BufferStream {
-            stream: BroadcastStream::new(rx.resubscribe()),
+            backlog: backlog.into(),
+            stream: rx,
+            channel: StreamChannel(channel),
         };
 
         Ok(LiveStream::new(resource_id, stream))
@@ -88,52 +172,447 @@ impl SessionManager for BufferSessionManager {
 
     fn publish(
         &self,
+        host: Option<&str>,
         resource_id: Option<ResourceId>,
-        session_id: Option<SessionId>,
     ) -> Result<LiveSink<Self::Sink>, Error> {
         let resource_id = resource_id.ok_or(Error::InvalidResourceId)?;
-        let session_id = session_id.ok_or(Error::InvalidCredentials)?;
 
-        match self.registry.remove(resource_id, session_id) {
-            Some(key) => {
-                if key.session_id != session_id || key.is_expired() {
-                    tracing::debug!("Rejecting due to invalid or expired key");
-                    return Err(Error::InvalidCredentials);
+        // Attaches to the existing stream if one exists.
+        let channel = self.channel(StreamKey::new(host, resource_id));
+
+        match self.config.publisher_policy {
+            PublisherPolicy::Attach => {
+                channel.publishers.fetch_add(1, Ordering::AcqRel);
+            }
+            PublisherPolicy::Reject => {
+                if channel
+                    .publishers
+                    .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    tracing::debug!("Rejecting publisher for busy resource {}", resource_id);
+                    return Err(Error::ResourceBusy);
                 }
             }
-            None => return Err(Error::InvalidCredentials),
         }
 
-        let mut streams = self.streams.lock().unwrap();
+        Ok(LiveSink::new(resource_id, BufferSink { channel }))
+    }
+
+    fn is_backpressured(&self, host: Option<&str>, resource_id: ResourceId) -> bool {
+        if !self.config.backpressure {
+            return false;
+        }
+
+        let streams = self.streams.lock().unwrap();
+        streams
+            .get(&StreamKey::new(host, resource_id))
+            .is_some_and(|channel| channel.is_congested())
+    }
+
+    fn stats(&self) -> Vec<StreamStats> {
+        let now = Instant::now();
+        let streams = self.streams.lock().unwrap();
+
+        streams
+            .iter()
+            .filter_map(|(key, channel)| {
+                let mut bitrate = channel.bitrate.lock();
+
+                // Skip streams that have gone idle.
+                if bitrate.is_idle(now) {
+                    return None;
+                }
+
+                Some(StreamStats {
+                    host: key.host.clone(),
+                    resource_id: key.resource_id,
+                    ingest_bitrate: bitrate.get(now),
+                    lagged_segments: channel.lagged.get(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The configuration of a [`BufferSessionManager`].
+#[derive(Copy, Clone, Debug)]
+pub struct Config {
+    /// Whether to retain the last group of pictures of every stream, so that new requesting
+    /// streams start from its keyframe instead of the current segment.
+    pub start_from_keyframe: bool,
+    /// What happens when a publisher connects to a resource that is already published.
+    pub publisher_policy: PublisherPolicy,
+    /// The number of segments buffered for every stream. A requesting stream that falls further
+    /// behind skips the oldest segments. Must be between `1` and [`MAX_CHANNEL_CAPACITY`].
+    ///
+    /// [`MAX_CHANNEL_CAPACITY`]: Self::MAX_CHANNEL_CAPACITY
+    pub channel_capacity: usize,
+    /// Whether publishers wait while the slowest requesting stream is close to falling behind,
+    /// instead of making it skip segments.
+    pub backpressure: bool,
+}
+
+impl Config {
+    pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+    /// The largest supported channel capacity. The broadcast channel allocates all slots upfront
+    /// and panics on capacities above `usize::MAX / 2`.
+    pub const MAX_CHANNEL_CAPACITY: usize = 1 << 18;
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            start_from_keyframe: false,
+            publisher_policy: PublisherPolicy::default(),
+            channel_capacity: Self::DEFAULT_CHANNEL_CAPACITY,
+            backpressure: false,
+        }
+    }
+}
+
+/// The handling of multiple publishers for the same stream.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublisherPolicy {
+    /// All publishers write into the same stream.
+    #[default]
+    Attach,
+    /// Publishers are rejected with [`Error::ResourceBusy`] while the stream has a publisher.
+    Reject,
+}
+
+/// The key of a stream in the [`BufferSessionManager`].
+///
+/// Streams are namespaced by the optional host (the `h` key of the StreamId). A missing host is
+/// its own namespace. Hosts are compared case-insensitively and without a trailing dot, like DNS
+/// names.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct StreamKey {
+    host: Option<String>,
+    resource_id: ResourceId,
+}
+
+impl StreamKey {
+    fn new(host: Option<&str>, resource_id: ResourceId) -> Self {
+        Self {
+            host: host.map(|host| {
+                let host = host.strip_suffix('.').unwrap_or(host);
+                host.to_ascii_lowercase()
+            }),
+            resource_id,
+        }
+    }
+}
+
+/// The channel of a single stream.
+///
+/// Segments are sent directly to the requesting stream while there is only one. Once a second
+/// stream subscribes, all streams are moved to a broadcast channel.
+#[derive(Debug)]
+struct Channel {
+    tx: broadcast::Sender<Bytes>,
+    route: parking_lot::Mutex<Route>,
+    capacity: usize,
+    /// The number of active [`BufferSink`]s.
+    publishers: AtomicUsize,
+    /// Whether the [`BufferSessionManager`] was dropped. Publishers then fail with
+    /// [`SessionError::Closed`].
+    closed: AtomicBool,
+    /// The segments since the last keyframe, `None` if keyframes are not retained.
+    gop: Option<parking_lot::Mutex<Gop>>,
+    bitrate: parking_lot::Mutex<Bitrate>,
+    /// The number of segments skipped by requesting streams that fell behind.
+    lagged: Counter,
+    backpressure: bool,
+    /// The publishers waiting for the requesting streams to catch up.
+    waiters: parking_lot::Mutex<Vec<Waker>>,
+    has_waiters: AtomicBool,
+}
+
+impl Channel {
+    fn new(config: &Config) -> Self {
+        let (tx, _) = broadcast::channel(config.channel_capacity);
+
+        Self {
+            tx,
+            route: parking_lot::Mutex::new(Route::default()),
+            capacity: config.channel_capacity,
+            publishers: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            gop: config
+                .start_from_keyframe
+                .then(|| parking_lot::Mutex::new(Gop::new(config.channel_capacity))),
+            bitrate: parking_lot::Mutex::new(Bitrate::new(Instant::now())),
+            lagged: Counter::new(),
+            backpressure: config.backpressure,
+            waiters: parking_lot::Mutex::new(Vec::new()),
+            has_waiters: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of segments that the slowest requesting stream has not received yet.
+    fn pending(&self) -> usize {
+        let route = self.route.lock();
+        let single = route
+            .single
+            .as_ref()
+            .map_or(0, |(tx, _)| tx.max_capacity() - tx.capacity());
+
+        single.max(self.tx.len())
+    }
+
+    /// Returns `true` if the slowest requesting stream is close to falling behind.
+    fn is_congested(&self) -> bool {
+        // Leave some headroom for segments sent by other publishers in the meantime.
+        let high_watermark = (self.capacity * 3 / 4).max(1);
+        self.pending() >= high_watermark
+    }
+
+    /// Polls whether a publisher can send the next segment without the slowest requesting
+    /// stream falling behind. Always ready if backpressure is disabled.
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if !self.backpressure || !self.is_congested() {
+            return Poll::Ready(());
+        }
 
-        let tx = match streams.get(&resource_id) {
-            // Attach to existing stream.
-            Some(tx) => tx.clone(),
+        let mut waiters = self.waiters.lock();
+        if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        self.has_waiters.store(true, Ordering::Release);
+        drop(waiters);
+
+        // The streams may have caught up before the waker was registered.
+        if self.is_congested() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    /// Wakes the waiting publishers once the requesting streams caught up. Called after a
+    /// stream received a segment or was dropped.
+    fn wake_publishers(&self) {
+        if !self.has_waiters.load(Ordering::Acquire) || self.is_congested() {
+            return;
+        }
+
+        let waiters = std::mem::take(&mut *self.waiters.lock());
+        self.has_waiters.store(false, Ordering::Release);
+
+        for waker in waiters {
+            waker.wake();
+        }
+    }
+
+    fn send(&self, buf: Bytes) {
+        self.bitrate.lock().add(Instant::now(), buf.len());
+
+        // Hold the lock while sending so no subscriber misses or duplicates a segment.
+        let mut gop = self.gop.as_ref().map(|gop| gop.lock());
+        if let Some(gop) = &mut gop {
+            gop.push(buf.clone());
+        }
+
+        let mut route = self.route.lock();
+        match &route.single {
+            Some((tx, _)) => match tx.try_send(buf) {
+                Ok(()) => (),
+                // Unlike the broadcast channel this drops the newest segment.
+                Err(TrySendError::Full(_)) => self.lagged.inc(),
+                Err(TrySendError::Closed(_)) => route.single = None,
+            },
             None => {
-                let (tx, _) = broadcast::channel(1024);
-                streams.insert(resource_id, tx.clone());
-                tx
+                let _ = self.tx.send(buf);
+            }
+        }
+    }
+
+    /// Subscribes to the channel, returning the retained segments that precede the first
+    /// segment of the receiver.
+    fn subscribe(&self) -> (Vec<Bytes>, Receiver) {
+        let gop = self.gop.as_ref().map(|gop| gop.lock());
+        let backlog = gop.map(|gop| gop.segments.clone()).unwrap_or_default();
+
+        let mut route = self.route.lock();
+
+        let rx = match route.single.take() {
+            Some((tx, promoted)) if !tx.is_closed() => {
+                // Promote the single stream. Dropping the sender ends its receiver after the
+                // remaining segments, it then continues with the promoted receiver.
+                *promoted.lock() = Some(self.tx.subscribe());
+                drop(tx);
+
+                Receiver::Broadcast(BroadcastStream::new(self.tx.subscribe()))
+            }
+            // Promoted receivers that were not taken yet count as well, they are dropped
+            // together with their stream.
+            _ if self.tx.receiver_count() > 0 => {
+                Receiver::Broadcast(BroadcastStream::new(self.tx.subscribe()))
+            }
+            _ => {
+                let (tx, rx) = mpsc::channel(self.capacity);
+                let promoted = Promoted::default();
+                route.single = Some((tx, promoted.clone()));
+
+                Receiver::Single(rx, promoted)
             }
         };
 
-        Ok(LiveSink::new(resource_id, BufferSink { tx }))
+        (backlog, rx)
+    }
+}
+
+/// Where the segments of a [`Channel`] are sent to.
+#[derive(Debug, Default)]
+struct Route {
+    /// The sender of the only requesting stream, `None` if the broadcast channel is used.
+    single: Option<(mpsc::Sender<Bytes>, Promoted)>,
+}
+
+/// The broadcast receiver of a single stream once it was promoted, until the stream takes it.
+///
+/// Owned by the stream rather than the [`Route`], so the receiver is never replaced by
+/// later streams and is dropped when the stream is.
+type Promoted = Arc<parking_lot::Mutex<Option<broadcast::Receiver<Bytes>>>>;
+
+#[derive(Debug)]
+enum Receiver {
+    Single(mpsc::Receiver<Bytes>, Promoted),
+    Broadcast(BroadcastStream<Bytes>),
+}
+
+/// A windowed bitrate.
+///
+/// Bytes are accounted in buckets of one second. The bitrate is the average over the last
+/// [`WINDOW`] completed buckets, the current bucket is not included. Buckets older than the
+/// window expire, resetting the bitrate of idle streams to 0.
+///
+/// [`WINDOW`]: Self::WINDOW
+#[derive(Debug)]
+struct Bitrate {
+    epoch: Instant,
+    /// The index of the current bucket in seconds since `epoch`.
+    current: u64,
+    buckets: [u64; Self::WINDOW + 1],
+}
+
+impl Bitrate {
+    /// The number of seconds the bitrate is averaged over.
+    const WINDOW: usize = 5;
+
+    fn new(now: Instant) -> Self {
+        Self {
+            epoch: now,
+            current: 0,
+            buckets: [0; Self::WINDOW + 1],
+        }
+    }
+
+    /// Accounts `len` bytes received at `now`.
+    fn add(&mut self, now: Instant, len: usize) {
+        let index = self.advance(now);
+        self.buckets[index] += len as u64;
+    }
+
+    /// Returns the bitrate at `now` in bits per second.
+    fn get(&mut self, now: Instant) -> u64 {
+        let index = self.advance(now);
+
+        let bytes: u64 = self
+            .buckets
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .map(|(_, bytes)| bytes)
+            .sum();
+
+        bytes * 8 / Self::WINDOW as u64
+    }
+
+    /// Returns `true` if no bytes were received within the window before `now`.
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.advance(now);
+        self.buckets.iter().all(|bytes| *bytes == 0)
+    }
+
+    /// Moves the current bucket to `now`, clearing all expired buckets. Returns the index of the
+    /// current bucket.
+    fn advance(&mut self, now: Instant) -> usize {
+        let secs = now.saturating_duration_since(self.epoch).as_secs();
+        let len = self.buckets.len() as u64;
+
+        // Clear all buckets skipped since the last update, but at most once.
+        let skipped = secs.saturating_sub(self.current).min(len);
+        for i in 1..=skipped {
+            self.buckets[((self.current + i) % len) as usize] = 0;
+        }
+
+        self.current = self.current.max(secs);
+        (self.current % len) as usize
+    }
+}
+
+/// The segments of the current group of pictures, starting with a keyframe.
+#[derive(Debug)]
+struct Gop {
+    segments: Vec<Bytes>,
+    /// The capacity of the [`Channel`].
+    capacity: usize,
+    keyframes: KeyframeDetector,
+}
+
+impl Gop {
+    fn new(capacity: usize) -> Self {
+        Self {
+            segments: Vec::new(),
+            capacity,
+            keyframes: KeyframeDetector::new(),
+        }
+    }
+
+    /// Pushes a new segment. Segments are only retained once a keyframe was seen.
+    fn push(&mut self, buf: Bytes) {
+        if self.keyframes.is_keyframe(&buf) {
+            self.segments.clear();
+        } else if self.segments.is_empty() {
+            return;
+        }
+
+        // Drop the group if it grows too long, it would overflow new subscribers anyways.
+        if self.segments.len() >= self.capacity {
+            self.segments.clear();
+            return;
+        }
+
+        self.segments.push(buf);
     }
 }
 
 #[derive(Debug)]
 pub struct BufferSink {
-    tx: broadcast::Sender<Bytes>,
+    channel: Arc<Channel>,
 }
 
 impl Sink<Bytes> for BufferSink {
-    type Error = Infallible;
+    type Error = SessionError;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
+        if self.channel.closed.load(Ordering::Acquire) {
+            return Poll::Ready(Err(SessionError::Closed));
+        }
+
+        self.channel.poll_ready(cx).map(Ok)
     }
 
     fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
-        let _ = self.tx.send(item);
+        if self.channel.closed.load(Ordering::Acquire) {
+            return Err(SessionError::Closed);
+        }
+
+        self.channel.send(item);
         Ok(())
     }
 
@@ -146,24 +625,85 @@ impl Sink<Bytes> for BufferSink {
     }
 }
 
+impl Drop for BufferSink {
+    fn drop(&mut self) {
+        self.channel.publishers.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
 #[derive(Debug)]
 pub struct BufferStream {
-    stream: BroadcastStream<Bytes>,
+    /// Retained segments that are yielded before the live stream.
+    backlog: VecDeque<Bytes>,
+    stream: Receiver,
+    /// Must be dropped after `stream` so that the segments the stream didn't receive are
+    /// released before the publishers are woken.
+    channel: StreamChannel,
 }
 
 impl Stream for BufferStream {
     type Item = Bytes;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        match self.stream.poll_next_unpin(cx) {
-            Poll::Ready(Some(Ok(bytes))) => Poll::Ready(Some(bytes)),
-            Poll::Ready(Some(Err(_))) => Poll::Ready(None),
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
+        if let Some(buf) = self.backlog.pop_front() {
+            return Poll::Ready(Some(buf));
+        }
+
+        loop {
+            let stream = match &mut self.stream {
+                Receiver::Single(rx, promoted) => match rx.poll_recv(cx) {
+                    Poll::Ready(Some(bytes)) => {
+                        self.channel.wake_publishers();
+                        return Poll::Ready(Some(bytes));
+                    }
+                    // The sender was dropped when the stream was promoted.
+                    Poll::Ready(None) => match promoted.lock().take() {
+                        Some(rx) => BroadcastStream::new(rx),
+                        None => return Poll::Ready(None),
+                    },
+                    Poll::Pending => return Poll::Pending,
+                },
+                Receiver::Broadcast(stream) => match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Ok(bytes))) => {
+                        self.channel.wake_publishers();
+                        return Poll::Ready(Some(bytes));
+                    }
+                    // The stream fell behind and the oldest segments were overwritten. Skip them
+                    // and continue with the oldest segment still in the channel.
+                    Poll::Ready(Some(Err(BroadcastStreamRecvError::Lagged(skipped)))) => {
+                        tracing::debug!("Stream lagged behind by {} segments", skipped);
+                        self.channel.lagged.add(skipped as usize);
+                        continue;
+                    }
+                    Poll::Ready(None) => return Poll::Ready(None),
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+
+            self.stream = Receiver::Broadcast(stream);
         }
     }
 }
 
+/// The [`Channel`] of a [`BufferStream`]. Wakes the waiting publishers once the stream is
+/// dropped.
+#[derive(Debug)]
+struct StreamChannel(Arc<Channel>);
+
+impl Deref for StreamChannel {
+    type Target = Channel;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for StreamChannel {
+    fn drop(&mut self) {
+        self.0.wake_publishers();
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SessionRegistry {
     /// ResourceId => SessionId, Expires
@@ -171,18 +711,61 @@ pub struct SessionRegistry {
 }
 
 impl SessionRegistry {
+    /// The maximum number of sessions per resource. Once reached, inserting a new session evicts
+    /// the oldest session of the resource.
+    pub const MAX_SESSIONS: usize = 1024;
+
     pub fn new() -> Self {
         Self::default()
     }
 
     pub fn insert(&self, key: SessionKey) {
         let mut inner = self.inner.write();
-        match inner.get_mut(&key.resource_id) {
-            Some(vec) => vec.push(key),
-            None => {
-                inner.insert(key.resource_id, vec![key]);
-            }
+        Self::push(inner.entry(key.resource_id).or_default(), key);
+    }
+
+    /// Inserts `key` unless its resource already has `limit` sessions that are not expired.
+    /// Returns `false` if the session was not inserted.
+    pub fn try_insert(&self, key: SessionKey, limit: usize) -> bool {
+        let mut inner = self.inner.write();
+        let keys = inner.entry(key.resource_id).or_default();
+
+        // Expired sessions are only removed by `reap`, they don't count towards the limit.
+        if keys.iter().filter(|key| !key.is_expired()).count() >= limit {
+            return false;
+        }
+
+        Self::push(keys, key);
+        true
+    }
+
+    fn push(keys: &mut Vec<SessionKey>, key: SessionKey) {
+        if keys.len() >= Self::MAX_SESSIONS {
+            tracing::debug!(
+                "Too many sessions for resource {}, evicting oldest",
+                key.resource_id
+            );
+
+            keys.remove(0);
         }
+
+        keys.push(key);
+    }
+
+    /// Removes all expired sessions. Returns the number of removed sessions.
+    pub fn reap(&self) -> usize {
+        let mut inner = self.inner.write();
+        let mut count = 0;
+
+        inner.retain(|_, keys| {
+            let len = keys.len();
+            keys.retain(|key| !key.is_expired());
+            count += len - keys.len();
+
+            !keys.is_empty()
+        });
+
+        count
     }
 
     pub fn get(&self, resource_id: ResourceId, session_id: SessionId) -> Option<SessionKey> {
@@ -215,6 +798,30 @@ impl SessionRegistry {
     }
 }
 
+/// Sessions are single use, they are removed once authorized.
+/// Sessions are issued per resource over the HTTP API, which serves all hosts. A session is valid
+/// for the resource on every host.
+impl Authenticator for SessionRegistry {
+    fn authorize(
+        &self,
+        _host: Option<&str>,
+        resource_id: ResourceId,
+        session_id: SessionId,
+        _mode: Mode,
+    ) -> BoxFuture<'static, Result<(), Error>> {
+        let res = match self.remove(resource_id, session_id) {
+            Some(key) if !key.is_expired() => Ok(()),
+            Some(_) => {
+                tracing::debug!("Rejecting due to expired key");
+                Err(Error::InvalidCredentials)
+            }
+            None => Err(Error::InvalidCredentials),
+        };
+
+        Box::pin(future::ready(res))
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct SessionKey {
     pub resource_id: ResourceId,
@@ -253,3 +860,566 @@ impl Borrow<ResourceId> for SessionKey {
 }
 
 impl Eq for SessionKey {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use futures::{SinkExt, StreamExt};
+
+    use crate::session::{Error, Mode, ResourceId, SessionError, SessionId, SessionManager};
+    use crate::ts::tests::{packet, tables, VIDEO_PID};
+
+    use super::{
+        Bitrate, BufferSessionManager, Config, PublisherPolicy, SessionKey, SessionRegistry,
+        StreamKey,
+    };
+
+    fn session(manager: &BufferSessionManager, resource_id: ResourceId, id: u64) -> SessionId {
+        let session_id = SessionId(id);
+        manager.registry.insert(SessionKey {
+            resource_id,
+            session_id,
+            expires: Instant::now() + Duration::from_secs(60),
+        });
+
+        session_id
+    }
+
+    #[tokio::test]
+    async fn test_authorize() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+        let authorize = |resource_id, session_id| {
+            manager.authorize(None, resource_id, session_id, Mode::Publish)
+        };
+
+        let sid = session(&manager, resource_id, 1);
+        assert!(authorize(Some(resource_id), Some(sid)).await.is_ok());
+        // Sessions are single use.
+        assert!(matches!(
+            authorize(Some(resource_id), Some(sid)).await,
+            Err(Error::InvalidCredentials)
+        ));
+
+        let sid = session(&manager, resource_id, 2);
+        assert!(matches!(
+            authorize(Some(ResourceId(2)), Some(sid)).await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert!(matches!(
+            authorize(None, Some(sid)).await,
+            Err(Error::InvalidResourceId)
+        ));
+        assert!(matches!(
+            authorize(Some(resource_id), None).await,
+            Err(Error::InvalidCredentials)
+        ));
+
+        manager.registry.insert(SessionKey {
+            resource_id,
+            session_id: SessionId(3),
+            expires: Instant::now() - Duration::from_secs(1),
+        });
+        assert!(matches!(
+            authorize(Some(resource_id), Some(SessionId(3))).await,
+            Err(Error::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sink_closed() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+
+        drop(manager);
+        let err = sink.send(Bytes::from_static(b"hello")).await.unwrap_err();
+        assert!(matches!(err, SessionError::Closed));
+    }
+
+    #[tokio::test]
+    async fn test_host_namespaces() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager
+            .publish(Some("a.example"), Some(resource_id))
+            .unwrap();
+
+        let mut stream_a = manager
+            .request(Some("a.example"), Some(resource_id))
+            .unwrap();
+
+        let mut stream_b = manager
+            .request(Some("b.example"), Some(resource_id))
+            .unwrap();
+
+        let mut stream_none = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+
+        assert_eq!(stream_a.next().await.unwrap(), "hello");
+        assert!(futures::poll!(stream_b.next()).is_pending());
+        assert!(futures::poll!(stream_none.next()).is_pending());
+    }
+
+    #[tokio::test]
+    async fn test_host_normalized() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager
+            .publish(Some("A.Example."), Some(resource_id))
+            .unwrap();
+
+        let mut streams: Vec<_> = ["a.example", "A.EXAMPLE", "a.example."]
+            .into_iter()
+            .map(|host| manager.request(Some(host), Some(resource_id)).unwrap())
+            .collect();
+
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+
+        for stream in &mut streams {
+            assert_eq!(stream.next().await.unwrap(), "hello");
+        }
+
+        let stats = manager.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].host.as_deref(), Some("a.example"));
+    }
+
+    #[test]
+    fn test_publisher_policy_reject() {
+        let manager = BufferSessionManager::new(Config {
+            publisher_policy: PublisherPolicy::Reject,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let err = manager.publish(None, Some(resource_id)).unwrap_err();
+        assert!(err.is_resource_busy());
+
+        // Other hosts are separate streams.
+        manager
+            .publish(Some("a.example"), Some(resource_id))
+            .unwrap();
+
+        // The resource is free again once the first publisher is gone.
+        drop(sink);
+        manager.publish(None, Some(resource_id)).unwrap();
+    }
+
+    #[test]
+    fn test_publisher_policy_attach() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let _sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        manager.publish(None, Some(resource_id)).unwrap();
+    }
+
+    /// Creates a segment of a single video packet.
+    fn segment(keyframe: bool, id: u8) -> Bytes {
+        let mut buf = packet(VIDEO_PID, true, keyframe);
+        buf[6] = id;
+        buf.to_vec().into()
+    }
+
+    #[tokio::test]
+    async fn test_start_from_keyframe() {
+        let manager = BufferSessionManager::new(Config {
+            start_from_keyframe: true,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        sink.send(tables().into()).await.unwrap();
+        sink.send(segment(false, 1)).await.unwrap();
+        sink.send(segment(true, 2)).await.unwrap();
+        sink.send(segment(false, 3)).await.unwrap();
+
+        let mut stream = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(segment(false, 4)).await.unwrap();
+
+        assert_eq!(stream.next().await.unwrap(), segment(true, 2));
+        assert_eq!(stream.next().await.unwrap(), segment(false, 3));
+        assert_eq!(stream.next().await.unwrap(), segment(false, 4));
+
+        // A new keyframe starts a new group.
+        sink.send(segment(true, 5)).await.unwrap();
+
+        let mut stream = manager.request(None, Some(resource_id)).unwrap();
+        assert_eq!(stream.next().await.unwrap(), segment(true, 5));
+        assert!(futures::poll!(stream.next()).is_pending());
+    }
+
+    #[tokio::test]
+    async fn test_stream_lagged() {
+        let manager = BufferSessionManager::new(Config {
+            channel_capacity: 16,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream = manager.request(None, Some(resource_id)).unwrap();
+
+        // A second stream moves both to the broadcast channel.
+        let _other = manager.request(None, Some(resource_id)).unwrap();
+
+        // Overflow the channel by 10 segments.
+        let len = 16 + 10;
+        for id in 0..len as u64 {
+            sink.send(Bytes::from(id.to_be_bytes().to_vec()))
+                .await
+                .unwrap();
+        }
+
+        // The stream skips the overwritten segments instead of ending.
+        assert_eq!(stream.next().await.unwrap(), 10u64.to_be_bytes().as_slice());
+        assert_eq!(manager.stats()[0].lagged_segments, 10);
+
+        for id in 11..len as u64 {
+            assert_eq!(stream.next().await.unwrap(), id.to_be_bytes().as_slice());
+        }
+
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        assert_eq!(stream.next().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_stream_lagged_single() {
+        let manager = BufferSessionManager::new(Config {
+            channel_capacity: 16,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream = manager.request(None, Some(resource_id)).unwrap();
+
+        for id in 0..16 + 10u64 {
+            sink.send(Bytes::from(id.to_be_bytes().to_vec()))
+                .await
+                .unwrap();
+        }
+
+        // The newest segments are dropped.
+        for id in 0..16u64 {
+            assert_eq!(stream.next().await.unwrap(), id.to_be_bytes().as_slice());
+        }
+        assert_eq!(manager.stats()[0].lagged_segments, 10);
+
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        assert_eq!(stream.next().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let manager = BufferSessionManager::new(Config {
+            channel_capacity: 4,
+            backpressure: true,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream_a = manager.request(None, Some(resource_id)).unwrap();
+        let mut stream_b = manager.request(None, Some(resource_id)).unwrap();
+
+        for _ in 0..3 {
+            sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        }
+
+        let mut ready = futures::future::poll_fn(|cx| sink.poll_ready_unpin(cx));
+        assert!(futures::poll!(&mut ready).is_pending());
+
+        // Only the slowest stream counts.
+        for _ in 0..3 {
+            stream_a.next().await.unwrap();
+        }
+        assert!(futures::poll!(&mut ready).is_pending());
+
+        stream_b.next().await.unwrap();
+        assert!(futures::poll!(&mut ready).is_ready());
+
+        // Dropping a stalled stream releases the publisher.
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        let mut ready = futures::future::poll_fn(|cx| sink.poll_ready_unpin(cx));
+        assert!(futures::poll!(&mut ready).is_pending());
+        drop(stream_b);
+        assert!(futures::poll!(&mut ready).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_promoted_dropped() {
+        let manager = BufferSessionManager::new(Config {
+            channel_capacity: 4,
+            backpressure: true,
+            ..Default::default()
+        });
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let stream_a = manager.request(None, Some(resource_id)).unwrap();
+        let mut stream_b = manager.request(None, Some(resource_id)).unwrap();
+
+        for _ in 0..3 {
+            sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        }
+
+        // The promoted stream is dropped before it took its receiver. The receiver must not
+        // hold back the publisher once the remaining stream caught up.
+        drop(stream_a);
+        for _ in 0..3 {
+            stream_b.next().await.unwrap();
+        }
+
+        let channel = manager.channel(StreamKey::new(None, resource_id));
+        assert_eq!(channel.pending(), 0);
+
+        let mut ready = futures::future::poll_fn(|cx| sink.poll_ready_unpin(cx));
+        assert!(futures::poll!(&mut ready).is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_stream_promote() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream_a = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(Bytes::from_static(b"a")).await.unwrap();
+        sink.send(Bytes::from_static(b"b")).await.unwrap();
+
+        let mut stream_b = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(Bytes::from_static(b"c")).await.unwrap();
+
+        // The first stream sees every segment exactly once.
+        assert_eq!(stream_a.next().await.unwrap(), "a");
+        assert_eq!(stream_a.next().await.unwrap(), "b");
+        assert_eq!(stream_a.next().await.unwrap(), "c");
+        assert!(futures::poll!(stream_a.next()).is_pending());
+
+        assert_eq!(stream_b.next().await.unwrap(), "c");
+        assert!(futures::poll!(stream_b.next()).is_pending());
+
+        // Once all streams are gone the next stream uses the direct route again.
+        drop(stream_a);
+        drop(stream_b);
+
+        let mut stream_c = manager.request(None, Some(resource_id)).unwrap();
+
+        let channel = manager.channel(StreamKey::new(None, resource_id));
+        assert!(channel.route.lock().single.is_some());
+
+        sink.send(Bytes::from_static(b"d")).await.unwrap();
+        assert_eq!(stream_c.next().await.unwrap(), "d");
+    }
+
+    #[tokio::test]
+    async fn test_stream_promote_undrained() {
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream_a = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(Bytes::from_static(b"a")).await.unwrap();
+
+        // The second stream promotes the first one and is gone before the first one drained
+        // the direct route.
+        drop(manager.request(None, Some(resource_id)).unwrap());
+
+        let mut stream_c = manager.request(None, Some(resource_id)).unwrap();
+
+        sink.send(Bytes::from_static(b"b")).await.unwrap();
+
+        assert_eq!(stream_a.next().await.unwrap(), "a");
+        assert_eq!(stream_a.next().await.unwrap(), "b");
+        assert!(futures::poll!(stream_a.next()).is_pending());
+
+        assert_eq!(stream_c.next().await.unwrap(), "b");
+    }
+
+    /// Measures the segment rate of a stream with a single requesting stream.
+    ///
+    /// Run with `cargo test --release bench_single_subscriber -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_single_subscriber() {
+        const COUNT: usize = 1 << 22;
+        const BATCH: usize = 512;
+
+        let manager = BufferSessionManager::new(Config::default());
+        let resource_id = ResourceId(1);
+
+        let mut sink = manager.publish(None, Some(resource_id)).unwrap();
+
+        let mut stream = manager.request(None, Some(resource_id)).unwrap();
+
+        let buf = Bytes::from_static(&[0; 1316]);
+
+        let now = Instant::now();
+        for _ in 0..COUNT / BATCH {
+            for _ in 0..BATCH {
+                sink.feed(buf.clone()).await.unwrap();
+            }
+
+            for _ in 0..BATCH {
+                stream.next().await.unwrap();
+            }
+        }
+        let elapsed = now.elapsed();
+
+        println!("{:.0} segments/s", COUNT as f64 / elapsed.as_secs_f64());
+    }
+
+    #[test]
+    fn test_reap_channels() {
+        let manager = BufferSessionManager::new(Config::default());
+
+        let sink = manager
+            .publish(Some("a.example.com"), Some(ResourceId(1)))
+            .unwrap();
+        let stream = manager
+            .request(Some("b.example.com"), Some(ResourceId(1)))
+            .unwrap();
+        drop(manager.request(Some("c.example.com"), Some(ResourceId(1))));
+        assert_eq!(manager.streams.lock().unwrap().len(), 3);
+
+        assert_eq!(manager.reap_channels(), 1);
+        drop(sink);
+        drop(stream);
+        assert_eq!(manager.reap_channels(), 2);
+        assert!(manager.streams.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_registry_reap() {
+        let registry = SessionRegistry::new();
+        let now = Instant::now();
+
+        for (id, expires) in [(1, now), (2, now + Duration::from_secs(60)), (3, now)] {
+            registry.insert(SessionKey {
+                resource_id: ResourceId(1),
+                session_id: SessionId(id),
+                expires,
+            });
+        }
+
+        registry.insert(SessionKey {
+            resource_id: ResourceId(2),
+            session_id: SessionId(4),
+            expires: now,
+        });
+
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert_eq!(registry.reap(), 3);
+        assert!(registry.get(ResourceId(1), SessionId(1)).is_none());
+        assert!(registry.get(ResourceId(1), SessionId(2)).is_some());
+        assert!(registry.inner.read().get(&ResourceId(2)).is_none());
+        assert_eq!(registry.reap(), 0);
+    }
+
+    #[test]
+    fn test_registry_max_sessions() {
+        let registry = SessionRegistry::new();
+        let expires = Instant::now() + Duration::from_secs(60);
+
+        for id in 0..=SessionRegistry::MAX_SESSIONS as u64 {
+            registry.insert(SessionKey {
+                resource_id: ResourceId(1),
+                session_id: SessionId(id),
+                expires,
+            });
+        }
+
+        let len = registry.inner.read().get(&ResourceId(1)).unwrap().len();
+        assert_eq!(len, SessionRegistry::MAX_SESSIONS);
+        assert!(registry.get(ResourceId(1), SessionId(0)).is_none());
+        assert!(registry.get(ResourceId(1), SessionId(1)).is_some());
+    }
+
+    #[test]
+    fn test_registry_try_insert() {
+        let registry = SessionRegistry::new();
+        let now = Instant::now();
+
+        let key = |id, expires| SessionKey {
+            resource_id: ResourceId(1),
+            session_id: SessionId(id),
+            expires,
+        };
+
+        assert!(registry.try_insert(key(1, now), 2));
+        for id in 2..4 {
+            assert!(registry.try_insert(key(id, now + Duration::from_secs(60)), 2));
+        }
+        assert!(!registry.try_insert(key(4, now + Duration::from_secs(60)), 2));
+        assert!(registry.get(ResourceId(1), SessionId(4)).is_none());
+
+        // The limit is per resource.
+        assert!(registry.try_insert(
+            SessionKey {
+                resource_id: ResourceId(2),
+                session_id: SessionId(1),
+                expires: now + Duration::from_secs(60),
+            },
+            2
+        ));
+
+        // Removed sessions free up the limit.
+        registry.remove(ResourceId(1), SessionId(2));
+        assert!(registry.try_insert(key(4, now + Duration::from_secs(60)), 2));
+        assert!(!registry.try_insert(key(5, now + Duration::from_secs(60)), 2));
+    }
+
+    #[test]
+    fn test_bitrate() {
+        let start = Instant::now();
+        let mut bitrate = Bitrate::new(start);
+        assert!(bitrate.is_idle(start));
+
+        // 1000 bytes per second over the window.
+        for sec in 0..Bitrate::WINDOW as u64 {
+            let now = start + Duration::from_secs(sec);
+            bitrate.add(now, 500);
+            bitrate.add(now + Duration::from_millis(500), 500);
+        }
+
+        // The current bucket is not included.
+        let now = start + Duration::from_secs(Bitrate::WINDOW as u64);
+        bitrate.add(now, 1_000_000);
+        assert_eq!(bitrate.get(now), 8000);
+        assert!(!bitrate.is_idle(now));
+
+        // Oldest bucket expired.
+        let now = now + Duration::from_secs(1);
+        assert_eq!(bitrate.get(now), (4000 + 1_000_000) * 8 / 5);
+
+        // The accounting resets after the stream goes idle.
+        let now = now + Duration::from_secs(60);
+        assert_eq!(bitrate.get(now), 0);
+        assert!(bitrate.is_idle(now));
+    }
+}
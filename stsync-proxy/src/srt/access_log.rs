@@ -0,0 +1,197 @@
+//! A JSON-lines log of closed connections.
+use std::fs::OpenOptions;
+use std::io::{self, LineWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+use serde::{Deserialize, Serialize};
+
+/// The destination of the [`AccessLog`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessLogTarget {
+    Stdout,
+    /// A file the log is appended to. It is created if it doesn't exist.
+    File(PathBuf),
+}
+
+impl AccessLogTarget {
+    /// Returns the target for the path given in the config file. `-` is stdout.
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        if path == Path::new("-") {
+            Self::Stdout
+        } else {
+            Self::File(path.to_owned())
+        }
+    }
+}
+
+/// A log with one JSON object per closed connection.
+///
+/// Entries are written by a separate thread, so closing a connection never blocks the runtime
+/// on a slow disk. The remaining entries are written when the `AccessLog` is dropped.
+pub struct AccessLog {
+    tx: Option<SyncSender<Vec<u8>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl AccessLog {
+    /// The number of entries waiting for the writer thread. Further entries are dropped.
+    const CAPACITY: usize = 1024;
+
+    /// Opens the log at `target`.
+    pub fn open(target: &AccessLogTarget) -> io::Result<Self> {
+        match target {
+            AccessLogTarget::Stdout => Ok(Self::new(io::stdout())),
+            AccessLogTarget::File(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(Self::new(file))
+            }
+        }
+    }
+
+    pub fn new<W>(writer: W) -> Self
+    where
+        W: Write + Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel::<Vec<u8>>(Self::CAPACITY);
+
+        let mut writer = LineWriter::new(writer);
+        let writer = std::thread::spawn(move || {
+            for line in rx {
+                if let Err(err) = writer.write_all(&line) {
+                    tracing::warn!("Failed to write access log: {}", err);
+                }
+            }
+        });
+
+        Self {
+            tx: Some(tx),
+            writer: Some(writer),
+        }
+    }
+
+    /// Appends `entry` to the log. Failed writes are logged and the entry is lost.
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let mut line = serde_json::to_vec(entry).unwrap();
+        line.push(b'\n');
+
+        match self.tx.as_ref().unwrap().try_send(line) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("Failed to write access log: too many pending entries");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                tracing::warn!("Failed to write access log: writer stopped");
+            }
+        }
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once all entries are written.
+        drop(self.tx.take());
+
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl std::fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessLog").finish_non_exhaustive()
+    }
+}
+
+/// A closed connection in the [`AccessLog`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AccessLogEntry {
+    pub connection_id: String,
+    /// The hex encoded resource, `None` if the handshake didn't complete.
+    pub resource_id: Option<String>,
+    /// One of `handshake`, `publish` or `request`.
+    pub mode: &'static str,
+    pub peer_addr: SocketAddr,
+    pub duration_ms: u64,
+    /// Payload bytes sent in data packets, including retransmissions.
+    pub bytes_sent: u64,
+    /// Payload bytes received in data packets, including retransmissions.
+    pub bytes_recv: u64,
+    /// Data packets lost in either direction.
+    pub packets_lost: u64,
+    /// The last round-trip time in microseconds.
+    pub rtt_us: u64,
+    /// The reason the connection was closed, e.g. `IdleTimeout`.
+    pub close_reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use super::{AccessLog, AccessLogEntry, AccessLogTarget};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_access_log() {
+        let buf = Buffer::default();
+        let log = AccessLog::new(buf.clone());
+
+        let entry = AccessLogEntry {
+            connection_id: "127.0.0.1:9000:1:2".to_owned(),
+            resource_id: Some("1".to_owned()),
+            mode: "publish",
+            peer_addr: "127.0.0.1:9000".parse().unwrap(),
+            duration_ms: 1500,
+            bytes_sent: 0,
+            bytes_recv: 1316,
+            packets_lost: 1,
+            rtt_us: 100_000,
+            close_reason: "PeerShutdown".to_owned(),
+        };
+        log.write(&entry);
+        log.write(&entry);
+        drop(log);
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["resource_id"], "1");
+        assert_eq!(lines[0]["mode"], "publish");
+        assert_eq!(lines[0]["peer_addr"], "127.0.0.1:9000");
+        assert_eq!(lines[0]["duration_ms"], 1500);
+        assert_eq!(lines[0]["close_reason"], "PeerShutdown");
+    }
+
+    #[test]
+    fn test_target_from_path() {
+        assert_eq!(AccessLogTarget::from_path("-"), AccessLogTarget::Stdout);
+        assert_eq!(
+            AccessLogTarget::from_path("/var/log/access.log"),
+            AccessLogTarget::File("/var/log/access.log".into())
+        );
+    }
+}
@@ -1,23 +1,224 @@
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+use super::access_log::AccessLogTarget;
+use super::loss::LossConfig;
+use super::queue::QueueKind;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     /// The tuple to bind the server to.
     pub bind: SocketAddr,
     /// The number of workers or ``
     pub workers: Option<usize>,
+    /// Whether every connection is handled by a single worker, selected by the hash of the
+    /// peer address. Otherwise a packet is handled by the worker that received it.
+    pub worker_sharding: bool,
 
-    /// The size of `SO_RCVBUF` in bytes.
-    pub rcvbuf: usize,
-    /// The size of `SO_SNDBUF` in bytes.
-    pub sndbuf: usize,
+    /// The size of `SO_RCVBUF` in bytes. `None` keeps the system default.
+    pub recv_buffer: Option<usize>,
+    /// The size of `SO_SNDBUF` in bytes. `None` keeps the system default.
+    pub send_buffer: Option<usize>,
 
     pub mtu: u32,
     pub flow_window: u32,
     pub buffer: u32,
+    /// The initial number of unacknowledged data packets for requesting peers. `0` disables
+    /// slow-start.
+    pub initial_window: u32,
+    /// The fraction of the receive buffer of a publishing connection above which the peer is
+    /// sent a congestion warning, from `0.0` to `1.0`. `None` never sends warnings.
+    pub congestion_threshold: Option<f64>,
 
     /// Latency in millis
     pub latency: u16,
+    /// The maximum latency in millis agreed to if the peer proposes a higher latency. Never
+    /// below [`latency`].
+    ///
+    /// [`latency`]: Self::latency
+    pub max_latency: u16,
+    /// The maximum latency in millis to which the jitter buffer of publishing connections grows
+    /// on a lossy link. The latency agreed on in the handshake is the minimum. `None` keeps the
+    /// agreed latency.
+    pub adaptive_latency: Option<u16>,
+
+    /// The maximum rate in bytes per second at which payload is sent to requesting peers. `None`
+    /// sends as fast as the peer acknowledges.
+    pub max_bandwidth: Option<u64>,
+
+    /// The fraction of the RTT for which a gap in the received sequence numbers must persist
+    /// before the missing packets are reported in a NAK, from `0.0` to `1.0`. This avoids
+    /// requesting retransmissions of packets that only arrive out of order. `0.0` reports gaps
+    /// immediately.
+    pub nak_delay: f64,
+    /// The interval of the periodic ACKs sent to publishing peers. ACKs are sent on the first
+    /// tick of the connection after the interval elapsed.
+    pub ack_interval: Duration,
+
+    /// The duration after which a connection is closed if the peer sent no packets, depending on
+    /// its mode. `None` never closes an idle connection of that mode.
+    ///
+    /// Any packet from the peer resets the timeout, including keepalives. Connections that have
+    /// not completed the handshake always use [`DEFAULT_IDLE_TIMEOUT`].
+    ///
+    /// [`DEFAULT_IDLE_TIMEOUT`]: Self::DEFAULT_IDLE_TIMEOUT
+    pub idle_timeout: ModeDuration,
+    /// The interval in which keepalives are sent to an otherwise idle peer, depending on the mode
+    /// of the connection. `None` never sends keepalives to a peer of that mode.
+    ///
+    /// Keepalives keep the NAT mappings between the server and the peer alive, e.g. while the
+    /// source of a requested stream is paused. Connections that have not completed the handshake
+    /// never send keepalives.
+    pub keepalive: ModeDuration,
+    /// The duration after which a connection that is stuck writing to the socket or the session
+    /// is closed. `None` never closes stuck connections.
+    pub watchdog_timeout: Option<Duration>,
+
+    /// The maximum number of mismatched fields in an INDUCTION handshake for which the peer is
+    /// still considered a real client and receives a rejection. Handshakes with more mismatched
+    /// fields are dropped silently.
+    pub reject_threshold: u32,
+
+    /// The duration after the INDUCTION for which the SYN cookie is accepted in the CONCLUSION,
+    /// at most [`SynCookies::MAX_LIFETIME`]. `None` accepts cookies until their key is rotated.
+    ///
+    /// [`SynCookies::MAX_LIFETIME`]: super::cookie::SynCookies::MAX_LIFETIME
+    pub syn_cookie_lifetime: Option<Duration>,
+
+    /// The data structure used for reordering received segments.
+    pub reorder_queue: QueueKind,
+
+    /// How to handle handshakes whose declared peer IP address doesn't match the source address.
+    pub peer_address_check: PeerAddressCheck,
+
+    /// Whether debugging commands for running connections (e.g. forced retransmissions) are
+    /// exposed over the HTTP API.
+    pub debug: bool,
+
+    /// Where a JSON line is written for every closed connection. `None` disables the access log.
+    pub access_log: Option<AccessLogTarget>,
+
+    /// The passphrase from which the key encrypting the SEKs of publishing peers is derived.
+    /// Only encrypted peers are accepted if set, only unencrypted peers otherwise.
+    #[serde(skip_serializing)]
+    pub passphrase: Option<String>,
+
+    /// Synthetic loss of incoming and outgoing packets. Only applied if [`debug`] is enabled.
+    ///
+    /// [`debug`]: Self::debug
+    pub loss: Option<LossConfig>,
+}
+
+impl Config {
+    /// The default idle timeout of both modes.
+    pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+
+    /// The default keepalive interval of both modes.
+    pub const DEFAULT_KEEPALIVE: Duration = Duration::from_secs(1);
+}
+
+/// The subset of the [`Config`] that can be changed while the server is running, e.g. when the
+/// config file is reloaded. The latency only applies to new connections, the idle timeout,
+/// keepalive interval and ACK interval also to existing connections.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// Latency in millis
+    pub latency: u16,
+    /// The maximum latency in millis agreed to if the peer proposes a higher latency.
+    pub max_latency: u16,
+    pub idle_timeout: ModeDuration,
+    pub keepalive: ModeDuration,
+    /// The interval between periodic ACKs sent to publishing peers.
+    pub ack_interval: Duration,
+}
+
+impl From<&Config> for RuntimeConfig {
+    fn from(src: &Config) -> Self {
+        Self {
+            latency: src.latency,
+            max_latency: src.max_latency,
+            idle_timeout: src.idle_timeout,
+            keepalive: src.keepalive,
+            ack_interval: src.ack_interval,
+        }
+    }
+}
+
+/// A duration depending on the mode of a connection. `None` disables whatever the duration is
+/// used for in that mode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModeDuration {
+    /// The duration for connections publishing a stream.
+    pub publish: Option<Duration>,
+    /// The duration for connections requesting a stream.
+    pub request: Option<Duration>,
+}
+
+impl ModeDuration {
+    /// Creates a new `ModeDuration` with the given durations for both modes.
+    pub const fn new(publish: Option<Duration>, request: Option<Duration>) -> Self {
+        Self { publish, request }
+    }
+}
+
+/// The handling of a mismatch between the peer IP address declared in a handshake and the address
+/// the server is bound to. Clients connecting through a NAT legitimately cause mismatches.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerAddressCheck {
+    /// Don't compare the addresses.
+    #[default]
+    Off,
+    /// Log mismatches, but accept the handshake.
+    Log,
+    /// Reject handshakes with mismatching addresses with `REJ_ROGUE`.
+    Reject,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Duration;
+
+    use super::{Config, ModeDuration, PeerAddressCheck, QueueKind};
+
+    /// Returns the [`Config`] used by the tests of the srt module.
+    pub fn config() -> Config {
+        Config {
+            bind: "127.0.0.1:0".parse().unwrap(),
+            workers: Some(1),
+            worker_sharding: false,
+            recv_buffer: None,
+            send_buffer: None,
+            mtu: 1500,
+            flow_window: 8192,
+            buffer: 8192,
+            initial_window: 0,
+            congestion_threshold: None,
+            latency: 120,
+            max_latency: 1000,
+            adaptive_latency: None,
+            max_bandwidth: None,
+            nak_delay: 0.25,
+            ack_interval: Duration::from_millis(10),
+            access_log: None,
+            idle_timeout: ModeDuration::new(
+                Some(Config::DEFAULT_IDLE_TIMEOUT),
+                Some(Config::DEFAULT_IDLE_TIMEOUT),
+            ),
+            keepalive: ModeDuration::new(
+                Some(Config::DEFAULT_KEEPALIVE),
+                Some(Config::DEFAULT_KEEPALIVE),
+            ),
+            watchdog_timeout: Some(Duration::from_secs(10)),
+            reject_threshold: 1,
+            syn_cookie_lifetime: None,
+            reorder_queue: QueueKind::Sorted,
+            peer_address_check: PeerAddressCheck::Off,
+            debug: false,
+            loss: None,
+            passphrase: None,
+        }
+    }
 }
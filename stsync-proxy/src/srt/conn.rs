@@ -4,33 +4,42 @@ use std::future::Future;
 use std::hash::{Hash, Hasher};
 use std::hint;
 use std::net::IpAddr;
+use std::ops::RangeInclusive;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::task::{ready, Context, Poll};
 use std::time::{Duration, Instant};
 
+use futures::future::BoxFuture;
 use futures::sink::{Close, Feed};
+use futures::task::noop_waker_ref;
 use futures::{pin_mut, FutureExt, SinkExt, StreamExt};
 use ragequit::{ShutdownListener, SHUTDOWN};
 use tokio::sync::mpsc;
-use tokio::time::{Interval, MissedTickBehavior};
-use tracing::{event, span, Level, Span};
+use tokio::time::Sleep;
+use tracing::{event, field, span, Level, Span};
 
-use crate::session::{LiveStream, SessionManager};
+use crate::session::{self, LiveStream, Mode, ResourceId, SessionId, SessionManager};
 use crate::srt::proto::Nak;
-use crate::srt::{EncryptionField, HandshakeType, VERSION};
+use crate::srt::{EncryptionField, HandshakeType, SrtVersion, StreamMode, VERSION};
 use crate::utils::Shared;
 
-use super::metrics::ConnectionMetrics;
-use super::proto::{Ack, AckAck, DropRequest, Handshake, Keepalive, Shutdown, Timestamp};
+use super::access_log::AccessLogEntry;
+use super::config::Config;
+use super::crypto;
+use super::metrics::{ConnectionMetrics, ConnectionModeMetric};
+use super::proto::{
+    Ack, AckAck, CongestionWarning, DropRequest, Handshake, Keepalive, LightAck, PeerError,
+    Shutdown, SmallAck, Timestamp,
+};
 use super::sink::OutputSink;
 use super::socket::SrtSocket;
 use super::state::{ConnectionId, State};
 use super::stream::SrtStream;
-use super::utils::Sequence;
+use super::utils::{MessageNumber, Sequence};
 use super::{
-    ControlPacketType, DataPacket, Error, ExtensionField, ExtensionType, HandshakeExtension,
-    IsPacket, Packet, PacketType,
+    ControlPacketType, DataPacket, Error, ExtensionContent, ExtensionField, ExtensionType,
+    HandshakeExtension, Header, IsPacket, Packet, PacketType,
 };
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -52,13 +61,28 @@ where
     pub metrics: Arc<ConnectionMetrics>,
 
     incoming: mpsc::Receiver<Packet>,
+    control: mpsc::Receiver<Control>,
     socket: Shared<SrtSocket>,
 
     /// Time of the first sent packet.
     start_time: Instant,
+    /// Time the connection was created. Unlike `start_time` this is never reset.
+    created: Instant,
     /// Whether the timestamp is currently in a wrapping period. Once the timestamp wraps around,
     /// it should reset `start_time`.
     timestamp_is_wrapping: bool,
+    /// The last timestamp returned by [`Self::timestamp`].
+    last_timestamp: Timestamp,
+    /// The resource published or requested by the peer, once the handshake completed.
+    resource_id: Option<ResourceId>,
+    /// The host of the resource selected by the peer, if any.
+    host: Option<String>,
+    /// The authorization of the peer while the handshake waits for it.
+    authorization: Option<Authorization>,
+    /// Whether the receive buffer is filled beyond the congestion threshold.
+    congested: bool,
+    /// Why the connection is being closed, once [`Self::close`] was called.
+    close_reason: Option<CloseReason>,
 
     server_sequence_number: Sequence,
     client_sequence_number: Sequence,
@@ -66,19 +90,41 @@ where
     mode: ConnectionMode<S>,
 
     inflight_acks: LossList,
+    /// The time at which the next periodic ACK is due in publish mode. Light ACKs don't move it.
+    next_ack: tokio::time::Instant,
+    /// The number of data packets received since the last ACK of any kind.
+    light_ack_packets: u32,
     loss_list: LossList,
     rtt: Rtt,
+    rate: RateEstimator,
+    /// The maximum number of unacknowledged packets agreed on in the handshake.
+    flow_window: u32,
+    /// The congestion window for data packets in request mode.
+    window: SendWindow,
+    pacing: Pacing,
+    /// The adaptive latency of the receive buffer in publish mode, `None` if disabled.
+    adaptive_latency: Option<AdaptiveLatency>,
+    /// The maximum send rate in request mode, `None` if unlimited.
+    rate_limit: Option<RateLimit>,
+    /// Measures the rate of data sent in request mode.
+    send_rate: RateEstimator,
 
     tick_interval: TickInterval,
 
     /// Timestamp of the last packet received by the peer.
-    last_time: Instant,
+    last_time: tokio::time::Instant,
+    /// Timestamp of the last packet sent to the peer.
+    last_sent: tokio::time::Instant,
 
     /// Self-referential struct.
     poll_state: PollState<S>,
+    /// Closes the connection if it is stuck in a write state, armed while a write is pending.
+    watchdog: Pin<Box<Sleep>>,
+    watchdog_armed: bool,
 
     /// Maximum transmission unit, the maximum size for an Ethernet frame. The default is 1500,
-    /// which is the maximum size for an Ethernet frame.
+    /// which is the maximum size for an Ethernet frame. Lowered to the MTU proposed by the peer
+    /// in the handshake.
     mtu: u16,
 
     queue: TransmissionQueue,
@@ -91,6 +137,15 @@ where
     ///
     /// The value is in milliseconds, not in microseconds.
     latency: Duration,
+    /// Whether the peer agreed to drop packets that arrive too late (TLPKTDROP). If set, data
+    /// packets older than `latency` are not sent anymore and a [`DropRequest`] is sent instead.
+    too_late_drop: bool,
+    /// Whether the peer sends with TSBPD (TSBPDSND). Without TSBPD, received data packets without
+    /// the order flag are delivered immediately instead of in order.
+    tsbpd: bool,
+    /// The id of the socket group of the peer if it sent a GROUP extension. Bonding is not
+    /// implemented, every member connection is handled independently.
+    group_id: Option<u32>,
 
     shutdown: Pin<Box<ShutdownListener>>,
     peer_address: IpAddr,
@@ -100,6 +155,13 @@ impl<S> Connection<S>
 where
     S: SessionManager,
 {
+    /// The maximum duration to transmit the remaining packets when closing the connection.
+    const DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// The number of received data packets after which a Light ACK is sent between the periodic
+    /// ACKs.
+    const LIGHT_ACK_PACKETS: u32 = 64;
+
     /// Creates a new `Connection`.
     ///
     /// # Safety
@@ -116,27 +178,61 @@ where
         peer_address: IpAddr,
     ) -> (Self, ConnectionHandle) {
         let (tx, rx) = mpsc::channel(1024);
+        let (control_tx, control_rx) = mpsc::channel(16);
 
         let metrics = Arc::new(ConnectionMetrics::new());
+        metrics
+            .max_send_rate
+            .set(state.config.max_bandwidth.unwrap_or(0) as usize);
         state.conn_metrics.lock().insert(id, metrics.clone());
         state.metrics.connections_total.inc();
         state.metrics.connections_handshake_current.inc();
 
-        let resource_span = span!(Level::DEBUG, "Connection");
+        // The span is on the highest level so its fields are included at any log level.
+        let resource_span = span!(
+            Level::ERROR,
+            "Connection",
+            connection_id = %id,
+            resource_id = field::Empty,
+        );
 
         let this = Self {
             id,
             incoming: rx,
+            control: control_rx,
             state: state.into(),
             mode: ConnectionMode::Induction { syn_cookie },
             inflight_acks: LossList::new(),
+            next_ack: tokio::time::Instant::now(),
+            light_ack_packets: 0,
             rtt: Rtt::new(),
+            rate: RateEstimator::new(Instant::now()),
+            flow_window: state.config.flow_window,
+            window: SendWindow::new(
+                Sequence::new(seqnum),
+                state.config.initial_window,
+                state.config.flow_window,
+            ),
+            pacing: Pacing::new(),
+            adaptive_latency: None,
+            rate_limit: state.config.max_bandwidth.map(RateLimit::new),
+            send_rate: RateEstimator::new(Instant::now()),
             tick_interval: TickInterval::new(),
             start_time: Instant::now(),
+            created: Instant::now(),
             timestamp_is_wrapping: false,
+            last_timestamp: Timestamp::default(),
+            resource_id: None,
+            host: None,
+            authorization: None,
+            congested: false,
+            close_reason: None,
             socket: socket.into(),
-            last_time: Instant::now(),
+            last_time: tokio::time::Instant::now(),
+            last_sent: tokio::time::Instant::now(),
             poll_state: PollState::default(),
+            watchdog: Box::pin(tokio::time::sleep(Duration::ZERO)),
+            watchdog_armed: false,
             metrics,
             mtu: 1500,
             queue: TransmissionQueue::default(),
@@ -145,11 +241,18 @@ where
             client_sequence_number: Sequence::new(seqnum),
             loss_list: LossList::new(),
             latency: Duration::ZERO,
+            too_late_drop: false,
+            tsbpd: false,
+            group_id: None,
             shutdown: Box::pin(SHUTDOWN.listen()),
             peer_address,
         };
 
-        let handle = ConnectionHandle { id, tx };
+        let handle = ConnectionHandle {
+            id,
+            tx,
+            control: control_tx,
+        };
 
         (this, handle)
     }
@@ -170,13 +273,12 @@ where
         if self.timestamp_is_wrapping && !timestamp.is_wrapping() {
             event!(parent: &self.resource_span, Level::DEBUG, "left TS wrapping period");
 
-            self.start_time = Instant::now();
+            // The timestamp zero of the new wrap cycle.
+            self.start_time += Timestamp::WRAP;
             self.timestamp_is_wrapping = false;
 
-            match &mut self.mode {
-                ConnectionMode::Publish(sink) => sink.update_start(self.start_time),
-                ConnectionMode::Request { stream } => stream.update_start(self.start_time),
-                _ => (),
+            if let ConnectionMode::Request { stream } = &mut self.mode {
+                stream.update_start(self.start_time);
             }
         }
 
@@ -190,6 +292,23 @@ where
             self.timestamp_is_wrapping = true;
         }
 
+        // Outgoing timestamps must never go backwards, even if the start time was moved forward.
+        // The comparison is serial, so the regular wrap around is not a regression.
+        let timestamp = if timestamp < self.last_timestamp {
+            tracing::debug!(
+                "Clamping timestamp {:?} to last timestamp {:?}",
+                timestamp,
+                self.last_timestamp
+            );
+
+            self.last_timestamp
+        } else {
+            timestamp
+        };
+
+        debug_assert!(timestamp >= self.last_timestamp);
+        self.last_timestamp = timestamp;
+
         timestamp
     }
 
@@ -200,57 +319,87 @@ where
         assert!(matches!(self.poll_state, PollState::Read));
 
         // Empty the sending queue before doing anything else.
-        if let Some(packet) = self.queue.pop() {
-            // Update connection stats.
-            match packet.header.packet_type() {
-                PacketType::Data => {
-                    self.metrics.data_bytes_sent.original.add(1);
-                    self.metrics.data_bytes_sent.original.add(packet.size());
-                }
-                PacketType::Control => {
-                    self.metrics.ctrl_packets_sent.add(1);
-                    self.metrics.ctrl_bytes_sent.add(packet.size());
-                }
-            }
-
-            let socket = self.socket;
-            let addr = self.id.addr;
-            let fut = Box::pin(async move {
-                unsafe {
-                    socket.as_ref().send_to(packet, addr).await?;
-                }
-                Ok(())
-            });
-
+        let packets = self.pop_batch();
+        if !packets.is_empty() {
+            let fut = self.write(packets);
             self.poll_state = PollState::Write(fut);
 
             // Immediately move into write state.
             return Poll::Ready(Ok(()));
         }
 
+        if let Some(Authorization::Pending { future, .. }) = &mut self.authorization {
+            if let Poll::Ready(res) = future.poll_unpin(cx) {
+                if let Some(Authorization::Pending { packet, .. }) =
+                    self.authorization.replace(Authorization::Done(res))
+                {
+                    self.handle_handshake(packet)?;
+                }
+
+                return Poll::Ready(Ok(()));
+            }
+        }
+
         match self.incoming.poll_recv(cx) {
             Poll::Ready(Some(packet)) => {
                 self.handle_packet(packet)?;
                 return Poll::Ready(Ok(()));
             }
+            // The server dropped the handle of the connection.
             Poll::Ready(None) => {
-                self.close()?;
+                self.close(CloseReason::ServerShutdown)?;
                 return Poll::Ready(Ok(()));
             }
             Poll::Pending => (),
         }
 
+        if let Poll::Ready(Some(control)) = self.control.poll_recv(cx) {
+            self.handle_control(control)?;
+            return Poll::Ready(Ok(()));
+        }
+
         if self.tick_interval.poll_unpin(cx).is_ready() {
             self.tick()?;
+
+            // Make sure the peer timeout is still enforced on time for idle connections.
+            if let Some(timeout) = self.idle_timeout() {
+                let deadline = self.last_time + timeout;
+                self.tick_interval.limit(deadline);
+            }
+
+            if let Some(interval) = self.keepalive_interval() {
+                let deadline = self.last_sent + interval;
+                self.tick_interval.limit(deadline);
+            }
+
+            // Poll again to register the rescheduled tick.
+            return Poll::Ready(Ok(()));
         }
 
         let this = unsafe { self.get_unchecked_mut() };
 
         if let ConnectionMode::Request { stream } = &mut this.mode {
             let mut count = 0;
-            while let Poll::Ready(res) = stream.poll_next_unpin(cx) {
+            // Once the window is full the connection is woken up by the next ACK.
+            while this.window.can_send(this.server_sequence_number)
+                && this.pacing.poll_ready(cx).is_ready()
+                && this
+                    .rate_limit
+                    .as_mut()
+                    .is_none_or(|limit| limit.poll_ready(cx).is_ready())
+            {
+                let res = match stream.poll_next_unpin(cx) {
+                    Poll::Ready(res) => res,
+                    Poll::Pending => break,
+                };
+
                 match res {
                     Some((buf, ts, msgnum)) => {
+                        if let Some(limit) = &mut this.rate_limit {
+                            limit.consume(buf.len());
+                        }
+                        this.send_rate.record(buf.len());
+
                         let packet = DataPacket::builder()
                             .sequence_number(this.server_sequence_number)
                             .message_number(msgnum)
@@ -265,16 +414,22 @@ where
                         packet.header.destination_socket_id = this.id.client_socket_id.0;
 
                         this.queue.push(packet);
+                        this.pacing.sent();
                         count += 1;
                     }
                     None => {
-                        this.close()?;
+                        this.close(CloseReason::EndOfStream)?;
                         return Poll::Ready(Ok(()));
                     }
                 }
             }
 
+            this.metrics
+                .pacing_interval
+                .set(this.pacing.interval.as_micros() as usize);
+
             if count > 0 {
+                this.tick_interval.activate();
                 return Poll::Ready(Ok(()));
             }
         }
@@ -282,6 +437,44 @@ where
         Poll::Pending
     }
 
+    /// Returns a future that transmits `packets` to the peer.
+    fn write(&self, mut packets: Vec<Packet>) -> Pin<Box<dyn Future<Output = Result<()>>>> {
+        // Update connection stats.
+        for packet in &packets {
+            match packet.header.packet_type() {
+                PacketType::Data => {
+                    self.metrics.data_bytes_sent.original.add(1);
+                    self.metrics.data_bytes_sent.original.add(packet.size());
+                }
+                PacketType::Control => {
+                    self.metrics.ctrl_packets_sent.add(1);
+                    self.metrics.ctrl_bytes_sent.add(packet.size());
+                }
+            }
+        }
+
+        // Dropped packets still count as sent, they are lost on the way to the peer.
+        if let Some(loss) = &self.state().loss {
+            packets.retain(|packet| {
+                let drop = loss.drop_packet(packet);
+                if drop {
+                    tracing::trace!("Injected loss of packet to {}", self.id.addr);
+                }
+
+                !drop
+            });
+        }
+
+        let socket = self.socket;
+        let addr = self.id.addr;
+        Box::pin(async move {
+            unsafe {
+                socket.as_ref().send_batch(&packets, addr).await?;
+            }
+            Ok(())
+        })
+    }
+
     /// Prepares the `Connection` for calls to [`poll_read`]. This method must be called before
     /// calling [`poll_read`] if the state was not already [`Read`].
     ///
@@ -291,6 +484,63 @@ where
         self.poll_state = PollState::Read;
     }
 
+    /// Returns `true` if the published stream deliberately holds back the connection until its
+    /// requesting streams caught up.
+    fn is_backpressured(&self) -> bool {
+        match self.resource_id {
+            Some(resource_id) => self
+                .state()
+                .session_manager
+                .is_backpressured(self.host.as_deref(), resource_id),
+            None => false,
+        }
+    }
+
+    /// Returns the latency of segments received from now on, i.e. the agreed latency raised by
+    /// the adaptive latency.
+    fn effective_latency(&self) -> Duration {
+        self.adaptive_latency
+            .as_ref()
+            .map_or(self.latency, |adaptive| adaptive.current)
+    }
+
+    /// Polls the watchdog while the current write state is pending. The watchdog is armed on the
+    /// first call after the connection made progress. Once it fires the connection is closed
+    /// immediately, dropping the pending write.
+    fn poll_watchdog(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let timeout = match self.state().config.watchdog_timeout {
+            Some(timeout) => timeout,
+            None => return Poll::Pending,
+        };
+
+        // The sink delivers its queued segments for up to twice the latency before closing.
+        let timeout = match self.poll_state {
+            PollState::Close(_) => timeout + self.effective_latency() * 2,
+            _ => timeout,
+        };
+
+        if !self.watchdog_armed {
+            let deadline = tokio::time::Instant::now() + timeout;
+            self.watchdog.as_mut().reset(deadline);
+            self.watchdog_armed = true;
+        }
+
+        if self.watchdog.as_mut().poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+
+        tracing::warn!(
+            "Closing connection {} stuck in {} state for {:?}",
+            self.id,
+            self.poll_state.name(),
+            timeout
+        );
+
+        self.close_reason = Some(CloseReason::Watchdog);
+        self.poll_state = PollState::Closed;
+        Poll::Ready(())
+    }
+
     fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
         event!(parent: &self.resource_span, Level::TRACE, "Connection.poll_write");
 
@@ -321,14 +571,65 @@ where
 
                 match fut.poll(cx) {
                     Poll::Pending => Poll::Pending,
-                    Poll::Ready(_) => {
+                    Poll::Ready(Ok(())) => {
                         self.init_read();
                         Poll::Ready(Ok(()))
                     }
+                    // The stream doesn't accept any more segments, e.g. because the session
+                    // manager closed it.
+                    Poll::Ready(Err(err)) => {
+                        tracing::warn!("Failed to write to stream: {}", err);
+                        self.init_read();
+                        Poll::Ready(self.close(CloseReason::Error))
+                    }
+                }
+            }
+            _ => unsafe { hint::unreachable_unchecked() },
+        }
+    }
+
+    fn poll_drain(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        event!(parent: &self.resource_span, Level::TRACE, "Connection.poll_drain");
+
+        #[cfg(debug_assertions)]
+        assert!(matches!(self.poll_state, PollState::Drain { .. }));
+
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let (deadline, write) = match &mut this.poll_state {
+            PollState::Drain { deadline, write } => (deadline, write),
+            _ => unsafe { hint::unreachable_unchecked() },
+        };
+
+        if deadline.as_mut().poll(cx).is_ready() {
+            tracing::debug!("Failed to drain transmission queue of {} in time", this.id);
+            return Poll::Ready(this.finish_close());
+        }
+
+        if let Some(fut) = write {
+            match fut.as_mut().poll(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Ok(())) => *write = None,
+                // The socket is broken, there is no point in sending the remaining packets.
+                Poll::Ready(Err(err)) => {
+                    tracing::debug!("Failed to drain transmission queue: {}", err);
+                    return Poll::Ready(this.finish_close());
                 }
             }
+        }
+
+        let packets = this.pop_batch();
+        if packets.is_empty() {
+            return Poll::Ready(this.finish_close());
+        }
+
+        let fut = this.write(packets);
+        match &mut this.poll_state {
+            PollState::Drain { write, .. } => *write = Some(fut),
             _ => unsafe { hint::unreachable_unchecked() },
         }
+
+        Poll::Ready(Ok(()))
     }
 
     fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
@@ -337,15 +638,17 @@ where
         #[cfg(debug_assertions)]
         assert!(matches!(self.poll_state, PollState::Close(_)));
 
-        // TODO: Drain the transmission queue, the assume the connection is dead.
-
         match &mut self.poll_state {
             PollState::Close(fut) => {
                 pin_mut!(fut);
 
                 match fut.poll(cx) {
                     Poll::Pending => Poll::Pending,
-                    Poll::Ready(Err(err)) => Poll::Ready(Ok(())),
+                    Poll::Ready(Err(err)) => {
+                        tracing::debug!("Failed to close stream: {}", err);
+                        self.poll_state = PollState::Closed;
+                        Poll::Ready(Ok(()))
+                    }
                     Poll::Ready(Ok(())) => {
                         tracing::debug!("Connection to {} closed", self.id);
                         self.poll_state = PollState::Closed;
@@ -361,7 +664,8 @@ where
         tracing::trace!("Connection.handle_packet");
 
         // Update connection stats.
-        self.last_time = Instant::now();
+        self.last_time = tokio::time::Instant::now();
+        self.tick_interval.activate();
 
         match packet.header.packet_type() {
             PacketType::Data => match packet.downcast() {
@@ -405,10 +709,7 @@ where
                             Ok(())
                         }
                     },
-                    ControlPacketType::CongestionWarning => {
-                        tracing::warn!("Unhandled CongestionWarning");
-                        Ok(())
-                    }
+                    ControlPacketType::CongestionWarning => self.handle_congestion_warning(),
                     ControlPacketType::Shutdown => match packet.downcast() {
                         Ok(packet) => self.handle_shutdown(packet),
                         Err(err) => {
@@ -427,9 +728,13 @@ where
                         tracing::warn!("Unhandled DropReq");
                         Ok(())
                     }
-                    // The peer error packet is only used for file transmission congestion
-                    // control and not used for live streams.
-                    ControlPacketType::PeerError => Ok(()),
+                    ControlPacketType::PeerError => match packet.downcast() {
+                        Ok(packet) => self.handle_peer_error(packet),
+                        Err(err) => {
+                            tracing::debug!("Failed to downcast peer error packet: {}", err);
+                            Ok(())
+                        }
+                    },
                     ControlPacketType::UserDefined => Ok(()),
                 }
             }
@@ -439,68 +744,215 @@ where
     fn tick(&mut self) -> Result<()> {
         // Server initiated shutdown.
         if self.shutdown.is_in_progress() {
-            return self.close();
+            return self.close(CloseReason::ServerShutdown);
+        }
+
+        // Drop the connection if the peer didn't send anything for too long.
+        if let Some(timeout) = self.idle_timeout() {
+            if self.last_time.elapsed() >= timeout {
+                tracing::debug!("Closing connection after {:?} without response", timeout);
+                return self.close(CloseReason::IdleTimeout);
+            }
         }
 
-        // Drop the connection after 15s of no response from the peer.
-        if self.last_time.elapsed() >= Duration::from_secs(15) {
-            return self.close();
+        // Keep idle connections alive. Pending packets are sent anyway.
+        if let Some(interval) = self.keepalive_interval() {
+            if self.queue.is_empty() && self.last_sent.elapsed() >= interval {
+                self.send(Keepalive::builder().build())?;
+            }
+        }
+
+        if self.mode.is_request() {
+            let now = Instant::now();
+            if now.saturating_duration_since(self.send_rate.since) >= Duration::from_secs(1) {
+                let (_, bytes_rate) = self.send_rate.rates(now);
+                self.metrics.send_rate.set(bytes_rate as usize);
+            }
         }
 
         // Send ACKs to the peer in publish mode.
         if self.mode.is_publish() {
+            let timestamp = self.timestamp();
+            self.send_naks(timestamp);
+
             // Purge all lost packets.
             let packets_lost = self.loss_list.clear(self.rtt);
 
-            self.metrics.data_bytes_recv.lost.add(packets_lost);
+            self.metrics.data_packets_recv.lost.add(packets_lost);
             // Estimation for a max-sized packet.
             self.metrics
                 .data_bytes_recv
                 .lost
                 .add(packets_lost * self.mtu as usize);
 
+            if let Some(adaptive) = &mut self.adaptive_latency {
+                if let Some(latency) = adaptive.update(packets_lost, self.rtt, Instant::now()) {
+                    tracing::debug!("Adapting latency to {:?}", latency);
+
+                    if let ConnectionMode::Publish(sink) = &mut self.mode {
+                        sink.set_latency(latency);
+                    }
+                    self.metrics
+                        .effective_latency
+                        .set(latency.as_millis() as usize);
+                }
+            }
+
             let acks_lost = self.inflight_acks.clear(self.rtt);
             self.metrics.ctrl_packets_lost.add(acks_lost);
             self.metrics.ctrl_bytes_lost.add(acks_lost * 44);
 
-            let timespan = self.start_time.elapsed().as_secs() as u32;
-
-            let packets_recv_rate;
-            let bytes_recv_rate;
-            if timespan == 0 {
-                packets_recv_rate = 0;
-                bytes_recv_rate = 0;
-            } else {
-                packets_recv_rate = self.metrics.data_bytes_recv.original.get() as u32 / timespan;
-                bytes_recv_rate = self.metrics.data_bytes_recv.original.get() as u32 / timespan;
-            }
+            let (packets_recv_rate, bytes_recv_rate) = self.rate.rates(Instant::now());
 
             let sink = match &self.mode {
                 ConnectionMode::Publish(sink) => sink,
                 _ => unreachable!(),
             };
 
-            let packet = Ack::builder()
-                .acknowledgement_number(self.server_sequence_number.get())
-                .last_acknowledged_packet_sequence_number(self.client_sequence_number.get())
-                .rtt(self.rtt.rtt)
-                .rtt_variance(self.rtt.rtt_variance)
-                .avaliable_buffer_size(sink.buffer_left() as u32)
-                .packets_receiving_rate(packets_recv_rate)
-                .estimated_link_capacity(packets_recv_rate)
-                .receiving_rate(bytes_recv_rate)
-                .build();
+            let link_capacity = self.rate.link_capacity();
+            let buffer_left = sink.buffer_left() as u32;
+
+            self.metrics.reorder_queue_len.set(sink.queue_len());
+            self.metrics
+                .reorder_oldest_age
+                .set(sink.queue_age(Instant::now()).as_millis() as usize);
 
+            // Periodic ACKs are due every ACK interval, regardless of any Light ACKs sent in
+            // between.
+            let now = tokio::time::Instant::now();
+            if now < self.next_ack {
+                return Ok(());
+            }
+            let ack_interval = self.state().runtime.read().ack_interval;
+            self.next_ack = now + ack_interval;
+            self.light_ack_packets = 0;
+
+            // Both variants are acknowledged with an ACKACK, the first RTT sample comes from a
+            // Small ACK.
             self.inflight_acks.push(self.server_sequence_number);
 
-            self.server_sequence_number += 1;
+            match ack_kind(self.rtt, link_capacity) {
+                AckKind::Small => {
+                    let packet = SmallAck::builder()
+                        .acknowledgement_number(self.server_sequence_number.get())
+                        .last_acknowledged_packet_sequence_number(self.client_sequence_number.get())
+                        .rtt(self.rtt.rtt)
+                        .rtt_variance(self.rtt.rtt_variance)
+                        .avaliable_buffer_size(buffer_left)
+                        .build();
+
+                    self.server_sequence_number += 1;
+                    self.send(packet)?;
+                }
+                AckKind::Full => {
+                    let packet = Ack::builder()
+                        .acknowledgement_number(self.server_sequence_number.get())
+                        .last_acknowledged_packet_sequence_number(self.client_sequence_number.get())
+                        .rtt(self.rtt.rtt)
+                        .rtt_variance(self.rtt.rtt_variance)
+                        .avaliable_buffer_size(buffer_left)
+                        .packets_receiving_rate(packets_recv_rate)
+                        .estimated_link_capacity(link_capacity)
+                        .receiving_rate(bytes_recv_rate)
+                        .build();
 
-            self.send(packet)?;
+                    self.server_sequence_number += 1;
+                    self.send(packet)?;
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Removes and returns up to [`SrtSocket::BATCH_SIZE`] packets from the transmission queue,
+    /// so they can be sent with a single syscall. See [`pop_queue`] for details.
+    ///
+    /// [`pop_queue`]: Self::pop_queue
+    fn pop_batch(&mut self) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        while packets.len() < SrtSocket::BATCH_SIZE {
+            match self.pop_queue() {
+                Some(packet) => packets.push(packet),
+                None => break,
+            }
+        }
+
+        if !packets.is_empty() {
+            self.last_sent = tokio::time::Instant::now();
+        }
+
+        packets
+    }
+
+    /// Removes and returns the next packet from the transmission queue.
+    ///
+    /// If too-late packet drop is enabled, data packets that can no longer arrive in time are
+    /// skipped and a single [`DropRequest`] for the skipped sequence numbers is returned instead.
+    fn pop_queue(&mut self) -> Option<Packet> {
+        if !self.too_late_drop {
+            return self.queue.pop();
+        }
+
+        let now = self.timestamp();
+
+        let mut dropped: Option<RangeInclusive<u32>> = None;
+        let mut message_number = None;
+        while let Some(packet) = self.queue.peek_mut() {
+            if packet.header.packet_type() != PacketType::Data
+                || !is_too_late(now, packet.header.timestamp, self.latency)
+            {
+                break;
+            }
+
+            let seq = packet.header.as_data_unchecked().packet_sequence_number();
+            let start = match &dropped {
+                None => {
+                    message_number = Some(packet.header.as_data_unchecked().message_number());
+                    seq
+                }
+                Some(range) if range.end().wrapping_add(1) == seq => *range.start(),
+                // Not contiguous, the packet is dropped with the next call.
+                Some(_) => break,
+            };
+            dropped = Some(start..=seq);
+
+            let packet = self.queue.pop().unwrap();
+            self.metrics.data_packets_sent.dropped.inc();
+            self.metrics.data_bytes_sent.dropped.add(packet.size());
+        }
+
+        match dropped {
+            Some(range) => {
+                tracing::debug!("Dropping too late packets {:?}", range);
+                Some(self.drop_request(range, message_number, now))
+            }
+            None => self.queue.pop(),
+        }
+    }
+
+    /// Creates a [`DropRequest`] for the packets in `range`. `message_number` is the message of
+    /// the first packet, if known.
+    fn drop_request(
+        &self,
+        range: RangeInclusive<u32>,
+        message_number: Option<MessageNumber>,
+        timestamp: Timestamp,
+    ) -> Packet {
+        let dropreq = DropRequest::builder()
+            // Message number of zero indicates we don't know the actual
+            // message number.
+            .message_number(message_number.map_or(0, MessageNumber::get))
+            .first_packet_sequence_number(*range.start())
+            .last_packet_sequence_number(*range.end())
+            .build();
+
+        let mut packet = dropreq.upcast();
+        packet.header.timestamp = timestamp;
+        packet.header.destination_socket_id = self.id.client_socket_id.0;
+        packet
+    }
+
     /// Sends a packet to the peer.
     fn send<T>(&mut self, packet: T) -> Result<()>
     where
@@ -526,14 +978,99 @@ where
         Ok(())
     }
 
-    /// Closes the connection.
-    fn close(&mut self) -> Result<()> {
-        self.send(Shutdown::builder().build())?;
+    /// Returns the idle timeout for the current mode of the connection.
+    fn idle_timeout(&self) -> Option<Duration> {
+        match self.mode {
+            ConnectionMode::Induction { .. } => Some(Config::DEFAULT_IDLE_TIMEOUT),
+            ConnectionMode::Publish(_) => self.state().runtime.read().idle_timeout.publish,
+            ConnectionMode::Request { .. } => self.state().runtime.read().idle_timeout.request,
+        }
+    }
 
-        if let ConnectionMode::Publish(sink) = &mut self.mode {
-            #[cfg(debug_assertions)]
-            assert!(matches!(self.poll_state, PollState::Read));
+    /// Sends a NAK for every range of packets that is still missing after the NAK delay and was
+    /// not reported yet.
+    fn send_naks(&mut self, timestamp: Timestamp) {
+        let delay = self.rtt.rtt as f64 * self.state().config.nak_delay;
+        let ranges = self.loss_list.take_due(Duration::from_micros(delay as u64));
+
+        // We attempt to recover the lost packets only if we can expect them to arrive before we
+        // would have already consumed them. If we cannot receive the lost packets in time we
+        // ignore them.
+        if !self.rtt.is_reachable(self.effective_latency()) {
+            return;
+        }
+
+        for range in ranges {
+            // We don't actually validate that the NAK reaches its destination. If it gets lost we
+            // simply skip the packets.
+            let mut packet = Nak::builder()
+                .lost_packet_sequence_numbers(range)
+                .build()
+                .upcast();
+            packet.header.timestamp = timestamp;
+            packet.header.destination_socket_id = self.id.client_socket_id.0;
+            self.queue.push_prio(packet);
+        }
+    }
+
+    /// Returns the entry of the connection in the access log after it was closed with `reason`.
+    fn access_log_entry(&self, reason: CloseReason) -> AccessLogEntry {
+        let metrics = self.metrics.snapshot();
+        let (sent, recv) = (&metrics.data_bytes_sent, &metrics.data_bytes_recv);
+
+        AccessLogEntry {
+            connection_id: self.id.to_string(),
+            resource_id: self.resource_id.map(|id| id.to_string()),
+            mode: match self.mode {
+                ConnectionMode::Induction { .. } => "handshake",
+                ConnectionMode::Publish(_) => "publish",
+                ConnectionMode::Request { .. } => "request",
+            },
+            peer_addr: self.id.addr,
+            duration_ms: self.created.elapsed().as_millis() as u64,
+            bytes_sent: sent.original + sent.retransmitted,
+            bytes_recv: recv.original + recv.retransmitted,
+            packets_lost: metrics.data_packets_sent.lost + metrics.data_packets_recv.lost,
+            rtt_us: metrics.rtt as u64,
+            close_reason: format!("{:?}", reason),
+        }
+    }
+
+    /// Returns the keepalive interval for the current mode of the connection.
+    fn keepalive_interval(&self) -> Option<Duration> {
+        match self.mode {
+            ConnectionMode::Induction { .. } => None,
+            ConnectionMode::Publish(_) => self.state().runtime.read().keepalive.publish,
+            ConnectionMode::Request { .. } => self.state().runtime.read().keepalive.request,
+        }
+    }
+
+    /// Closes the connection. All remaining packets in the transmission queue are transmitted
+    /// before the connection is closed.
+    ///
+    /// A [`Shutdown`] is only sent if the handshake completed. Peers in the handshake phase have
+    /// no established connection to shut down.
+    fn close(&mut self, reason: CloseReason) -> Result<()> {
+        self.close_reason = Some(reason);
+
+        if !matches!(self.mode, ConnectionMode::Induction { .. }) {
+            self.send(Shutdown::builder().build())?;
+        }
+
+        #[cfg(debug_assertions)]
+        assert!(matches!(self.poll_state, PollState::Read));
+
+        self.poll_state = PollState::Drain {
+            deadline: Box::pin(tokio::time::sleep(Self::DRAIN_TIMEOUT)),
+            write: None,
+        };
+
+        Ok(())
+    }
 
+    /// Closes the sink after the transmission queue was drained.
+    fn finish_close(&mut self) -> Result<()> {
+        if let ConnectionMode::Publish(sink) = &mut self.mode {
             let fut = sink.close();
             let fut = unsafe { std::mem::transmute(fut) };
 
@@ -545,17 +1082,16 @@ where
         Ok(())
     }
 
-    fn handle_data(&mut self, packet: DataPacket) -> Result<()> {
+    fn handle_data(&mut self, mut packet: DataPacket) -> Result<()> {
         #[cfg(debug_assertions)]
         assert!(matches!(self.poll_state, PollState::Read));
 
         let timestamp = self.timestamp();
 
         // Only handle data packets from peers that are publishing.
-        let tx = match &mut self.mode {
-            ConnectionMode::Publish(tx) => tx,
-            _ => return Ok(()),
-        };
+        if !self.mode.is_publish() {
+            return Ok(());
+        }
 
         let seqnum = Sequence::new(packet.packet_sequence_number());
 
@@ -588,24 +1124,63 @@ where
             self.loss_list
                 .extend(self.client_sequence_number.get()..seqnum.get());
 
-            // We attempt to recover the lost packet only if we can expect it to arrive
-            // before we would have already consumed it. If we cannot receive the lost
-            // packet in time we ignore it.
-            if self.rtt.is_reachable(self.latency) {
-                // We attempt to recover the lost packet by sending NAK right away. We don't
-                // actually validate that it reaches its destination. If it gets lost we simply
-                // skip the packet.
-                let builder = Nak::builder()
-                    .lost_packet_sequence_numbers(self.client_sequence_number.get()..seqnum.get());
+            // The missing packets are usually only reordered. They are reported in `tick` once
+            // they are still missing after the NAK delay.
+            if self.state().config.nak_delay == 0.0 {
+                self.send_naks(timestamp);
+            }
+        }
+
+        // Oversized packets break the MTU assumptions of the requesting peers. The packet is
+        // dropped the same as one that failed to decrypt.
+        let max_payload_size = self.max_payload_size();
+        if packet.data.len() > max_payload_size {
+            tracing::debug!(
+                "Dropping packet {} with payload of {} bytes, exceeding the maximum of {}",
+                seqnum,
+                packet.data.len(),
+                max_payload_size
+            );
+
+            self.metrics.oversized_packets.inc();
+            self.metrics.data_packets_recv.dropped.inc();
+            self.metrics.data_bytes_recv.dropped.add(packet.data.len());
+
+            if !is_retransmitted {
+                self.client_sequence_number = seqnum + 1;
+            }
+
+            return Ok(());
+        }
+
+        let tx = match &mut self.mode {
+            ConnectionMode::Publish(tx) => tx,
+            _ => unreachable!(),
+        };
+
+        if let Err(err) = tx.decrypt(&mut packet) {
+            tracing::debug!("Failed to decrypt packet {}: {}", seqnum, err);
+
+            self.metrics.decrypt_failures.inc();
+            self.metrics.data_packets_recv.dropped.inc();
+            self.metrics.data_bytes_recv.dropped.add(packet.data.len());
 
-                let mut packet = builder.build().upcast();
-                packet.header.timestamp = timestamp;
-                packet.header.destination_socket_id = self.id.client_socket_id.0;
-                self.queue.push_prio(packet);
+            // The packet was received, retransmitting it would not help.
+            if !is_retransmitted {
+                self.client_sequence_number = seqnum + 1;
             }
+
+            return Ok(());
         }
 
         // Track the accepted packet.
+        self.rate.push(
+            seqnum.get(),
+            packet.data.len(),
+            is_retransmitted,
+            Instant::now(),
+        );
+
         if is_retransmitted {
             self.metrics.data_packets_recv.retransmitted.inc();
             self.metrics
@@ -617,10 +1192,14 @@ where
             self.metrics.data_bytes_recv.original.add(packet.data.len());
         }
 
+        let buffer_usage = tx.buffer_usage();
+
         let fut = tx.feed(packet);
         let fut = unsafe { std::mem::transmute(fut) };
         self.poll_state = PollState::WriteSink(fut);
 
+        self.check_congestion(buffer_usage)?;
+
         // Only move the sequence forward if the packet was an original (not retransmitted).
         if !is_retransmitted {
             // The next expected sequence is now seqnum + 1, i.e. the sequence
@@ -628,13 +1207,48 @@ where
             self.client_sequence_number = seqnum + 1;
         }
 
+        // Acknowledge fast senders between the periodic ACKs. Light ACKs are not acknowledged
+        // with an ACKACK.
+        self.light_ack_packets += 1;
+        if self.light_ack_packets >= Self::LIGHT_ACK_PACKETS {
+            self.light_ack_packets = 0;
+            self.send(
+                LightAck::builder()
+                    .last_acknowledged_packet_sequence_number(self.client_sequence_number.get())
+                    .build(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a congestion warning to the publishing peer once the usage of the receive buffer
+    /// crosses the configured threshold. No further warnings are sent until the usage dropped
+    /// below the threshold again.
+    fn check_congestion(&mut self, buffer_usage: f64) -> Result<()> {
+        let threshold = match self.state().config.congestion_threshold {
+            Some(threshold) => threshold,
+            None => return Ok(()),
+        };
+
+        let congested = buffer_usage >= threshold;
+        if congested && !self.congested {
+            tracing::debug!(
+                "Receive buffer is {:.0}% full, sending congestion warning",
+                buffer_usage * 100.0
+            );
+
+            self.send_prio(CongestionWarning::builder().build())?;
+        }
+
+        self.congested = congested;
         Ok(())
     }
 
     fn handle_shutdown(&mut self, _packet: Shutdown) -> Result<()> {
         event!(parent: &self.resource_span, Level::DEBUG, "peer is closing");
 
-        self.close()
+        self.close(CloseReason::PeerShutdown)
     }
 
     fn handle_ackack(&mut self, packet: AckAck) -> Result<()> {
@@ -656,15 +1270,66 @@ where
         Ok(())
     }
 
+    fn handle_congestion_warning(&mut self) -> Result<()> {
+        // Only data packets sent by us are paced.
+        if let ConnectionMode::Request { .. } = self.mode {
+            self.pacing.congestion();
+
+            tracing::debug!(
+                "Received congestion warning, pacing interval is now {:?}",
+                self.pacing.interval
+            );
+
+            self.metrics
+                .pacing_interval
+                .set(self.pacing.interval.as_micros() as usize);
+        }
+
+        Ok(())
+    }
+
     fn handle_keepalive(&mut self, _packet: Keepalive) -> Result<()> {
         event!(parent: &self.resource_span, Level::DEBUG, "keepalive");
 
         self.send(Keepalive::builder().build())
     }
 
-    fn handle_ack(&mut self, packet: Ack) -> Result<()> {
-        // We only accpet ACK packets when the peer requests a stream.
+    /// Handles a PEERERROR packet. Some implementations use it to signal fatal application
+    /// errors, so it is reported to the [`SessionManager`]. The connection stays open until the
+    /// peer shuts it down.
+    fn handle_peer_error(&mut self, packet: PeerError) -> Result<()> {
+        let code = packet.error_code();
+        tracing::warn!("Peer {} reported error {}", self.id, code);
+
+        self.metrics.peer_errors.inc();
+
+        if let Some(resource_id) = self.resource_id {
+            self.state()
+                .session_manager
+                .on_peer_error(resource_id, code);
+        }
+
+        Ok(())
+    }
+
+    fn handle_ack(&mut self, packet: Ack) -> Result<()> {
+        // We only accpet ACK packets when the peer requests a stream.
         if let ConnectionMode::Request { .. } = self.mode {
+            // The sequence number only has 31 bits, the top bit is never set by a valid peer.
+            let seq = packet.last_acknowledged_packet_sequence_number;
+            if seq & (1 << 31) != 0 {
+                tracing::debug!("Invalid ACK sequence number {:#x}, dropping", seq);
+                return Ok(());
+            }
+
+            // The peer cannot acknowledge packets that were never sent.
+            let mut seq = Sequence::new(seq);
+            if seq > self.server_sequence_number {
+                seq = self.server_sequence_number;
+            }
+
+            self.window.ack(seq);
+
             self.rtt.rtt = packet.rtt;
             self.rtt.rtt_variance = packet.rtt_variance;
 
@@ -704,16 +1369,25 @@ where
                 "Missmatched version {} in HS (expected 5), rejecting",
                 packet.version
             );
-            return Ok(());
+            return self.reject(HandshakeType::REJ_VERSION);
         }
 
-        if packet.encryption_field != EncryptionField::NONE {
+        // Without a passphrase encrypted peers would send payloads that cannot be decrypted.
+        // With a passphrase only encrypted peers are accepted.
+        let passphrase = self.state().config.passphrase.clone();
+        let encrypted =
+            packet.encryption_field != EncryptionField::NONE || packet.extension_field.kmreg();
+        if encrypted != passphrase.is_some() {
             tracing::debug!(
-                "Missmatched encryption_field {:?} in HS (expected {:?}), rejecting",
+                "Missmatched encryption_field {:?} in HS (encryption {}), rejecting",
                 packet.encryption_field,
-                EncryptionField::NONE,
+                if passphrase.is_some() {
+                    "required"
+                } else {
+                    "disabled"
+                },
             );
-            return Ok(());
+            return self.reject(HandshakeType::REJ_INSECURE);
         }
 
         if packet.syn_cookie != syn_cookie {
@@ -726,10 +1400,42 @@ where
             return Ok(());
         }
 
+        // The cookie was issued to the peer, but the peer took too long to complete the
+        // handshake or replays an old CONCLUSION.
+        if self.state().syn_cookies.is_expired(packet.syn_cookie) {
+            tracing::debug!("Expired syn_cookie {} in HS, rejecting", packet.syn_cookie);
+
+            return self.reject(HandshakeType::REJ_RDVCOOKIE);
+        }
+
+        // Handled again once a pending authorization completed.
+        let conclusion = packet.clone();
+
         packet.syn_cookie = 0;
         packet.srt_socket_id = self.id.server_socket_id.0;
         packet.initial_packet_sequence_number = self.server_sequence_number.get();
 
+        // The flow window is the smaller of both proposals. Peers proposing no window accept
+        // ours.
+        let flow_window = self.state().config.flow_window;
+        self.flow_window = match packet.maximum_flow_window_size {
+            0 => flow_window,
+            n => n.min(flow_window),
+        };
+        packet.maximum_flow_window_size = self.flow_window;
+
+        // The same applies to the MTU.
+        let mtu = self.state().config.mtu.min(u16::MAX.into());
+        self.mtu = match packet.maximum_transmission_unit_size {
+            0 => mtu,
+            n => n.min(mtu),
+        } as u16;
+        packet.maximum_transmission_unit_size = self.mtu.into();
+
+        if self.max_payload_size() == 0 {
+            return self.reject(HandshakeType::REJ_ROGUE);
+        }
+
         // The HSREQ extension is required for TSBD.
         // The CONFIG extension is required for stream authentication.
         if !packet.extension_field.hsreq() || !packet.extension_field.config() {
@@ -737,7 +1443,7 @@ where
         }
 
         // Handle handshake extensions.
-        if let Some(mut ext) = packet.extensions.remove_hsreq() {
+        let send_latency = if let Some(mut ext) = packet.extensions.remove_hsreq() {
             // The CRYPT and REXMITFLG flags must always be set.
             if !ext.srt_flags.has_crypt() || !ext.srt_flags.has_rexmitflg() {
                 return self.reject(HandshakeType::REJ_ROGUE);
@@ -749,15 +1455,34 @@ where
                 return self.reject(HandshakeType::REJ_ROGUE);
             }
 
-            // The recommended delay is 4*RTT, making this a suitable value for
-            // networks with up to 250ms delay.
+            // The agreed latency of each direction is the larger of both proposals, limited to
+            // `max_latency`. The sender delay of the peer is our receiver delay and vice versa.
             // Also see https://github.com/Haivision/srt/issues/1630#issuecomment-719384626
-            ext.sender_tsbpd_delay = self.state().config.latency;
-            ext.receiver_tsbpd_delay = self.state().config.latency;
+            let runtime = *self.state().runtime.read();
+            let latency = runtime.latency;
+            let max_latency = runtime.max_latency.max(latency);
+            let negotiate = |proposed: u16| proposed.max(latency).min(max_latency);
+
+            let recv_latency = negotiate(ext.sender_tsbpd_delay);
+            let send_latency = negotiate(ext.receiver_tsbpd_delay);
+            ext.receiver_tsbpd_delay = recv_latency;
+            ext.sender_tsbpd_delay = send_latency;
+
+            // Publishing peers only send data to us. Requesting peers switch to the sending
+            // latency once the mode is known.
+            self.latency = Duration::from_millis(recv_latency as u64);
+
+            tracing::debug!(
+                "Agreed on stream latency of {}ms (recv) and {}ms (send)",
+                recv_latency,
+                send_latency
+            );
 
-            self.latency = Duration::from_millis(ext.sender_tsbpd_delay as u64);
+            self.too_late_drop = ext.srt_flags.has_tlpktdrop();
+            self.tsbpd = ext.srt_flags.has_tsbpdsnd();
 
-            tracing::debug!("Agreed on stream latency of {:?}", self.latency);
+            self.metrics.peer_version.set(ext.srt_version as usize);
+            tracing::debug!("Peer uses SRT version {}", SrtVersion(ext.srt_version));
 
             packet.extensions.0.push(HandshakeExtension {
                 extension_type: ExtensionType::HSRSP,
@@ -766,9 +1491,51 @@ where
             });
 
             packet.extension_field = ExtensionField::HSREQ;
+            send_latency
         } else {
             tracing::debug!("rejecting due to missing HSREQ extension");
             return self.reject(HandshakeType::REJ_ROGUE);
+        };
+
+        // The peer sends its SEKs wrapped with the passphrase in the KMREQ extension. The KMRSP
+        // echoes the key material to confirm it.
+        let keys = match &passphrase {
+            Some(passphrase) => {
+                let Some(km) = packet.extensions.remove_key_material() else {
+                    tracing::debug!("rejecting due to missing KMREQ extension");
+                    return self.reject(HandshakeType::REJ_INSECURE);
+                };
+
+                let keys = match crypto::unwrap_keys(&km, passphrase) {
+                    Ok(keys) => keys,
+                    Err(err) => {
+                        tracing::debug!("rejecting due to invalid key material: {}", err);
+                        return self.reject(HandshakeType::REJ_BADSECRET);
+                    }
+                };
+
+                let ext = ExtensionContent::KeyMaterial(km);
+                packet.extensions.0.push(HandshakeExtension {
+                    extension_type: ExtensionType::KMRSP,
+                    extension_length: ext.len() as u16,
+                    extension_content: ext,
+                });
+                packet.extension_field =
+                    ExtensionField(packet.extension_field.0 | ExtensionField::KMREQ.0);
+
+                keys
+            }
+            None => Vec::new(),
+        };
+
+        // The response doesn't include a GROUP extension, the connection is not bonded.
+        if let Some(ext) = packet.extensions.remove_group() {
+            tracing::debug!(
+                "Peer is a member of {} group {}",
+                ext.group_type,
+                ext.group_id
+            );
+            self.group_id = Some(ext.group_id);
         }
 
         // StreamId extension
@@ -783,86 +1550,132 @@ where
                 }
             };
 
+            let host = sid.host();
+            if host.is_some_and(|host| !is_valid_host(host)) {
+                tracing::debug!("Rejecting invalid host {:?}", host);
+                return self.reject(HandshakeType::REJ_ROGUE);
+            }
+
             let resource_id = sid.resource().map(|id| id.parse().ok()).flatten();
             let session_id = sid.session().map(|id| id.parse().ok()).flatten();
 
-            match sid.mode() {
-                Some("request") => {
+            if let Some(id) = resource_id {
+                self.resource_span
+                    .record("resource_id", field::display::<ResourceId>(id));
+            }
+
+            match sid.stream_mode() {
+                Ok(StreamMode::Request) => {
+                    // Sending encrypted payloads is not supported.
+                    if !keys.is_empty() {
+                        tracing::debug!("rejecting encrypted request");
+                        return self.reject(HandshakeType::REJ_INSECURE);
+                    }
+
                     tracing::info!(
-                        "Peer {} wants to request resource {:?} with key {:?}",
+                        "Peer {} wants to request resource {:?} on host {:?} with key {:?}",
                         self.id,
                         resource_id,
+                        host,
                         session_id
                     );
 
-                    let stream = match self
-                        .state()
-                        .session_manager
-                        .request(resource_id, session_id)
-                    {
+                    match self.authorize(conclusion, host, resource_id, session_id, Mode::Request) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(err)) => return self.reject(rejection(err)),
+                        Poll::Pending => return Ok(()),
+                    }
+
+                    let stream = match self.state().session_manager.request(host, resource_id) {
                         Ok(stream) => stream,
-                        Err(err) => {
-                            let code =
-                                if err.is_invalid_resource_id() || err.is_invalid_credentials() {
-                                    HandshakeType::REJ_BADSECRET
-                                } else {
-                                    HandshakeType::REJ_UNKNOWN
-                                };
-
-                            return self.reject(code);
-                        }
+                        Err(err) => return self.reject(rejection(err)),
                     };
 
+                    let resource_id = stream.resource_id();
                     let stream = SrtStream::new(
                         stream,
                         self.state().config.buffer as usize,
                         self.client_sequence_number,
                         self.start_time,
+                        self.max_payload_size(),
                     );
 
                     self.state().metrics.connections_handshake_current.dec();
                     self.state().metrics.connections_request_current.inc();
 
+                    self.window = SendWindow::new(
+                        self.server_sequence_number,
+                        self.state().config.initial_window,
+                        self.flow_window,
+                    );
+                    self.latency = Duration::from_millis(send_latency as u64);
+                    self.resource_id = Some(resource_id);
+                    *self.metrics.resource_id.lock() = Some(resource_id);
                     self.mode = ConnectionMode::Request { stream };
+                    self.metrics
+                        .mode
+                        .set(ConnectionModeMetric::Request as usize);
                 }
-                Some("publish") => {
+                Ok(StreamMode::Publish) => {
                     tracing::info!(
-                        "Peer {} wants to publish to resource {:?} with key {:?}",
+                        "Peer {} wants to publish to resource {:?} on host {:?} with key {:?}",
                         self.id,
                         resource_id,
+                        host,
                         session_id
                     );
 
-                    let sink = match self
-                        .state()
-                        .session_manager
-                        .publish(resource_id, session_id)
-                    {
+                    match self.authorize(conclusion, host, resource_id, session_id, Mode::Publish) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(err)) => return self.reject(rejection(err)),
+                        Poll::Pending => return Ok(()),
+                    }
+
+                    let sink = match self.state().session_manager.publish(host, resource_id) {
                         Ok(sink) => sink,
-                        Err(err) => {
-                            let code =
-                                if err.is_invalid_resource_id() || err.is_invalid_credentials() {
-                                    HandshakeType::REJ_BADSECRET
-                                } else {
-                                    HandshakeType::REJ_UNKNOWN
-                                };
-
-                            return self.reject(code);
-                        }
+                        Err(err) => return self.reject(rejection(err)),
                     };
 
                     self.state().metrics.connections_handshake_current.dec();
                     self.state().metrics.connections_publish_current.inc();
 
-                    self.mode = ConnectionMode::Publish(OutputSink::new(
+                    self.resource_id = Some(sink.resource_id());
+                    self.host = host.map(str::to_owned);
+                    *self.metrics.resource_id.lock() = self.resource_id;
+                    self.metrics
+                        .mode
+                        .set(ConnectionModeMetric::Publish as usize);
+                    let mut sink = OutputSink::new(
                         sink,
                         self.start_time,
                         self.latency,
                         self.state().config.buffer as usize,
-                    ));
+                        self.state().config.reorder_queue,
+                        self.tsbpd,
+                    );
+                    for (flag, sek) in keys {
+                        if let Err(err) = sink.set_key(flag, Some(sek)) {
+                            tracing::debug!("rejecting due to invalid key: {}", err);
+                            return self.reject(HandshakeType::REJ_BADSECRET);
+                        }
+                    }
+                    self.mode = ConnectionMode::Publish(sink);
+
+                    if let Some(ceiling) = self.state().config.adaptive_latency {
+                        self.adaptive_latency = Some(AdaptiveLatency::new(
+                            self.latency,
+                            Duration::from_millis(ceiling.into()),
+                            Instant::now(),
+                        ));
+                    }
+                }
+                Ok(mode @ StreamMode::Bidirectional) => {
+                    tracing::debug!("rejecting due to unsupported STREAMID::mode {}", mode);
+                    return self.reject(HandshakeType::REJ_ROGUE);
                 }
-                _ => {
-                    tracing::debug!("rejecting due to invalid STREAMID::mode");
+                Err(err) => {
+                    tracing::debug!("rejecting due to invalid STREAMID::mode: {}", err);
+                    self.state().metrics.handshake_invalid_mode.inc();
                     return self.reject(HandshakeType::REJ_ROGUE);
                 }
             }
@@ -871,13 +1684,76 @@ where
             return self.reject(HandshakeType::REJ_ROGUE);
         }
 
+        self.metrics.latency.set(self.latency.as_millis() as usize);
+        self.metrics
+            .effective_latency
+            .set(self.latency.as_millis() as usize);
+
         self.send(packet)
     }
 
+    /// Authorizes the peer with the [`SessionManager`]. Returns [`Poll::Pending`] while the
+    /// authorization is in progress. The CONCLUSION `packet` is handled again once it completed.
+    fn authorize(
+        &mut self,
+        packet: Handshake,
+        host: Option<&str>,
+        resource_id: Option<ResourceId>,
+        session_id: Option<SessionId>,
+        mode: Mode,
+    ) -> Poll<std::result::Result<(), session::Error>> {
+        match self.authorization.take() {
+            Some(Authorization::Done(res)) => return Poll::Ready(res),
+            // The peer retransmits the CONCLUSION until it receives a response.
+            Some(pending @ Authorization::Pending { .. }) => {
+                self.authorization = Some(pending);
+                return Poll::Pending;
+            }
+            None => (),
+        }
+
+        let mut future =
+            self.state()
+                .session_manager
+                .authorize(host, resource_id, session_id, mode);
+
+        // Most authorizations complete immediately. Otherwise the future is polled in
+        // `poll_read`, which registers the waker.
+        match future.poll_unpin(&mut Context::from_waker(noop_waker_ref())) {
+            Poll::Ready(res) => Poll::Ready(res),
+            Poll::Pending => {
+                tracing::debug!("Waiting for authorization of peer {}", self.id);
+                self.authorization = Some(Authorization::Pending { future, packet });
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Returns the maximum payload of a data packet that fits into the negotiated MTU.
+    fn max_payload_size(&self) -> usize {
+        (self.mtu as usize).saturating_sub(self.packet_overhead())
+    }
+
+    /// Returns the size of the IP, UDP and SRT headers, which are included in the MTU.
+    fn packet_overhead(&self) -> usize {
+        let ip = match self.id.addr.ip() {
+            IpAddr::V4(_) => 20,
+            // IPv4-mapped addresses of a dual-stack socket are sent with an IPv4 header.
+            IpAddr::V6(addr) if addr.to_ipv4_mapped().is_some() => 20,
+            IpAddr::V6(_) => 40,
+        };
+
+        ip + 8 + Header::SIZE
+    }
+
     /// Rejects the remote connection using the given `reason`.
     fn reject(&mut self, reason: HandshakeType) -> Result<()> {
         event!(parent: &self.resource_span, Level::DEBUG, "Rejecting client {} with reason {:?}", self.id, reason);
 
+        if let Some(counter) = self.state().metrics.handshake_rejections.get(reason) {
+            counter.inc();
+        }
+
         let mut packet = Handshake::default();
         packet.header.set_packet_type(PacketType::Control);
         packet.version = VERSION;
@@ -885,7 +1761,7 @@ where
         packet.extension_field = ExtensionField::NONE;
         packet.initial_packet_sequence_number = self.server_sequence_number.get();
         packet.maximum_transmission_unit_size = self.mtu as u32;
-        packet.maximum_flow_window_size = 8192;
+        packet.maximum_flow_window_size = self.flow_window;
         packet.handshake_type = reason;
         packet.srt_socket_id = self.id.server_socket_id.0;
         packet.syn_cookie = 0;
@@ -906,8 +1782,16 @@ where
             _ => return Ok(()),
         };
 
+        // Contiguous ranges of packets that cannot be retransmitted, with the message number of
+        // the first packet.
+        let mut dropped: Vec<(RangeInclusive<u32>, Option<MessageNumber>)> = Vec::new();
+
         for seq in packet.lost_packet_sequence_numbers.iter() {
-            match stream.get(seq.into()) {
+            let segment = stream.get(seq.into()).filter(|(_, ts, _)| {
+                !self.too_late_drop || !is_too_late(timestamp, *ts, self.latency)
+            });
+
+            match segment {
                 Some((buf, ts, msgnum)) => {
                     let packet = DataPacket::builder()
                         .sequence_number(seq)
@@ -923,30 +1807,61 @@ where
 
                     self.queue.push_prio(packet);
                 }
-                None => {
-                    let dropreq = DropRequest::builder()
-                        // Message number of zero indicates we don't know the actual
-                        // message number anymore.
-                        .message_number(0)
-                        .first_packet_sequence_number(packet.lost_packet_sequence_numbers.first())
-                        .last_packet_sequence_number(packet.lost_packet_sequence_numbers.last())
-                        .build();
-
-                    let mut packet = dropreq.upcast();
-                    packet.header.timestamp = timestamp;
-                    packet.header.destination_socket_id = self.id.client_socket_id.0;
-
-                    self.queue.push(packet);
-                }
+                None => match dropped.last_mut() {
+                    Some((range, _)) if range.end().wrapping_add(1) == seq => {
+                        *range = *range.start()..=seq;
+                    }
+                    _ => dropped.push((seq..=seq, stream.message_number(seq.into()))),
+                },
             }
         }
 
+        for (range, message_number) in dropped {
+            let packet = self.drop_request(range, message_number, timestamp);
+            self.queue.push(packet);
+        }
+
         Ok(())
     }
 
     fn handle_dropreq(&mut self, _packet: DropRequest) -> Result<()> {
         Ok(())
     }
+
+    /// Handle a [`Control`] command from the operator.
+    fn handle_control(&mut self, control: Control) -> Result<()> {
+        match control {
+            Control::Retransmit(range) => {
+                // The range is checked by the sender already, but never trust the channel to
+                // do the bounding for us.
+                if Control::retransmit_len(&range) > Control::MAX_RETRANSMIT {
+                    tracing::debug!("Ignoring oversized retransmit range {:?}", range);
+                    return Ok(());
+                }
+
+                event!(
+                    parent: &self.resource_span,
+                    Level::INFO,
+                    "Forcing retransmission of {:?}",
+                    range
+                );
+
+                // Inject a synthetic NAK as if the peer reported the range as lost.
+                let nak = Nak::builder().lost_packet_sequence_numbers(range).build();
+                self.handle_nak(nak)
+            }
+            Control::Close => {
+                event!(
+                    parent: &self.resource_span,
+                    Level::INFO,
+                    "Closing connection {} on request of the operator",
+                    self.id
+                );
+
+                self.close(CloseReason::Operator)
+            }
+        }
+    }
 }
 
 impl<S> Future for Connection<S>
@@ -956,6 +1871,14 @@ where
     type Output = Result<()>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Attach the connection fields to all events emitted while polling.
+        let span = self.resource_span.clone();
+        let _enter = span.enter();
+
+        // Snapshots see all metric updates of this poll at once.
+        let metrics = self.metrics.clone();
+        let _guard = metrics.write();
+
         loop {
             match &self.poll_state {
                 PollState::Read => match self.as_mut().poll_read(cx) {
@@ -964,22 +1887,37 @@ where
                     _ => (),
                 },
                 PollState::Write(_) => match self.as_mut().poll_write(cx) {
-                    Poll::Pending => return Poll::Pending,
+                    Poll::Pending => ready!(self.poll_watchdog(cx)),
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                     _ => (),
                 },
+                // Waiting for the stream because of backpressure from the requesting streams is
+                // not a stuck connection. Stalled requesting streams are closed by their own
+                // watchdog.
                 PollState::WriteSink(_) => match self.as_mut().poll_write_sink(cx) {
+                    Poll::Pending if self.is_backpressured() => {
+                        self.watchdog_armed = false;
+                        return Poll::Pending;
+                    }
+                    Poll::Pending => ready!(self.poll_watchdog(cx)),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    _ => (),
+                },
+                PollState::Drain { .. } => match self.as_mut().poll_drain(cx) {
                     Poll::Pending => return Poll::Pending,
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                     _ => (),
                 },
                 PollState::Close(_) => match self.as_mut().poll_close(cx) {
-                    Poll::Pending => return Poll::Pending,
+                    Poll::Pending => ready!(self.poll_watchdog(cx)),
                     Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
                     _ => (),
                 },
                 PollState::Closed => return Poll::Ready(Ok(())),
             }
+
+            // The connection made progress, the watchdog only fires for a single stuck state.
+            self.watchdog_armed = false;
         }
     }
 }
@@ -1046,19 +1984,88 @@ where
             ConnectionMode::Publish(_) => state.metrics.connections_publish_current.dec(),
             ConnectionMode::Request { .. } => state.metrics.connections_request_current.dec(),
         }
+
+        // Connections that are dropped without being closed failed with an error.
+        let reason = self.close_reason.unwrap_or(CloseReason::Error);
+        state.metrics.connections_closed.get(reason).inc();
+
+        if let Some(access_log) = &state.access_log {
+            access_log.write(&self.access_log_entry(reason));
+        }
     }
 }
 
+/// The reason a [`Connection`] was closed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The peer didn't send any packets within the idle timeout.
+    IdleTimeout,
+    /// The peer sent a [`Shutdown`].
+    PeerShutdown,
+    /// The requested stream ended.
+    EndOfStream,
+    /// The server is shutting down.
+    ServerShutdown,
+    /// The connection was stuck sending for longer than the watchdog timeout.
+    Watchdog,
+    /// The connection was closed by the operator.
+    Operator,
+    /// The connection failed with an error.
+    Error,
+}
+
 #[derive(Clone, Debug)]
 pub struct ConnectionHandle {
     pub id: ConnectionId,
     tx: mpsc::Sender<Packet>,
+    control: mpsc::Sender<Control>,
 }
 
 impl ConnectionHandle {
     pub async fn send(&self, packet: Packet) {
         let _ = self.tx.send(packet).await;
     }
+
+    /// Sends a [`Control`] command to the connection. Returns `false` if the connection is
+    /// closed or has too many pending commands.
+    pub fn control(&self, control: Control) -> bool {
+        self.control.try_send(control).is_ok()
+    }
+}
+
+/// A command from the operator to a running [`Connection`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Control {
+    /// Retransmit the packets with the sequence numbers in the range, as if the peer had sent a
+    /// NAK for them. Only has an effect on connections in request mode.
+    Retransmit(RangeInclusive<u32>),
+    /// Close the connection, sending a [`Shutdown`] to the peer.
+    Close,
+}
+
+impl Control {
+    /// The maximum number of packets in a single [`Retransmit`] command.
+    ///
+    /// [`Retransmit`]: Self::Retransmit
+    pub const MAX_RETRANSMIT: u32 = 1024;
+
+    /// Creates a new [`Retransmit`] command for the packets `first..=last`. Returns `None` if
+    /// the range is empty or longer than [`MAX_RETRANSMIT`].
+    ///
+    /// [`Retransmit`]: Self::Retransmit
+    /// [`MAX_RETRANSMIT`]: Self::MAX_RETRANSMIT
+    pub fn retransmit(first: u32, last: u32) -> Option<Self> {
+        let range = first..=last;
+        if range.is_empty() || Self::retransmit_len(&range) > Self::MAX_RETRANSMIT {
+            return None;
+        }
+
+        Some(Self::Retransmit(range))
+    }
+
+    fn retransmit_len(range: &RangeInclusive<u32>) -> u32 {
+        range.end().saturating_sub(*range.start()).saturating_add(1)
+    }
 }
 
 impl Hash for ConnectionHandle {
@@ -1091,17 +2098,52 @@ impl PartialEq for ConnectionHandle {
 
 impl Eq for ConnectionHandle {}
 
+/// Returns `true` if a packet with the timestamp `ts` can no longer be delivered within
+/// `latency` at `now`. A zero `latency` never drops packets.
+fn is_too_late(now: Timestamp, ts: Timestamp, latency: Duration) -> bool {
+    !latency.is_zero() && now.to_duration_since(ts).is_some_and(|age| age > latency)
+}
+
+/// The variant of the periodic ACK sent to a publishing peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AckKind {
+    /// A Small ACK without the receiving rate and link capacity fields.
+    Small,
+    /// A full ACK.
+    Full,
+}
+
+/// Selects the ACK variant for the current estimates.
+///
+/// Small ACKs are sent until the RTT was measured from at least one ACKACK and the link capacity
+/// was estimated from at least one packet pair. Until then the rate fields would only report the
+/// initial values, which the peer uses to configure its sending rate.
+fn ack_kind(rtt: Rtt, link_capacity: u32) -> AckKind {
+    if rtt.samples == 0 || link_capacity == 0 {
+        AckKind::Small
+    } else {
+        AckKind::Full
+    }
+}
+
 /// A list to keep track of lost packets. Internally a `LossList` is a stack with all sequence
 /// numbers sorted in ascending order. This sorting is not done automatically, it is only possible
 /// to push new sequence numbers that are greater than the last one.
 #[derive(Clone, Debug, Default)]
 pub struct LossList {
-    inner: Vec<(Sequence, Instant)>,
+    /// The lost sequence numbers with the time they were detected, or the time they were
+    /// reported once [`Self::take_due`] returned them.
+    inner: Vec<(Sequence, tokio::time::Instant)>,
+    /// The last sequence number returned by [`Self::take_due`].
+    reported: Option<Sequence>,
 }
 
 impl LossList {
     pub const fn new() -> Self {
-        Self { inner: Vec::new() }
+        Self {
+            inner: Vec::new(),
+            reported: None,
+        }
     }
 
     /// Returns the number of sequence numbers in the `LossList`.
@@ -1124,19 +2166,30 @@ impl LossList {
     /// Panics when `debug_assertions` is enabled and the pushed sequence number `seq` is smaller
     /// than the last pushed sequence number on the stack.
     pub fn push(&mut self, seq: Sequence) {
-        self.push_in(seq, Instant::now())
+        self.push_in(seq, tokio::time::Instant::now())
     }
 
     /// Clears all sequence numbers that should be considered lost from the stack. Returns the
-    /// number of removed sequence numbers. [`Rtt`] is used to determine whether a sequence number
-    /// is likely still inflight, or should be considered lost.
+    /// number of removed sequence numbers. [`Rtt`] is used to determine whether the
+    /// retransmission of a sequence number is likely still inflight, or should be considered
+    /// lost: a sequence number is lost once it was pushed, or reported if it was returned by
+    /// [`Self::take_due`], more than `RTT + 4 * RTTVar` ago.
     pub fn clear(&mut self, rtt: Rtt) -> usize {
-        self.clear_in(rtt, Instant::now())
+        self.clear_in(rtt, tokio::time::Instant::now())
+    }
+
+    /// Returns the ranges of sequence numbers that were pushed at least `delay` ago and were not
+    /// returned before, i.e. the packets that are still missing after they could have been
+    /// reordered. The returned sequence numbers are considered reported from now on.
+    pub fn take_due(&mut self, delay: Duration) -> Vec<RangeInclusive<u32>> {
+        self.take_due_in(delay, tokio::time::Instant::now())
     }
 
     /// Removes a sequence number from the `LossList`. Returns the [`Instant`] at which the
-    /// sequence number was inserted.
-    pub fn remove(&mut self, seq: Sequence) -> Option<Instant> {
+    /// sequence number was inserted, or reported if it was reported already.
+    ///
+    /// [`Instant`]: tokio::time::Instant
+    pub fn remove(&mut self, seq: Sequence) -> Option<tokio::time::Instant> {
         if let Ok(index) = self.inner.binary_search_by(|(n, _)| n.cmp(&seq)) {
             let (_, ts) = self.inner.remove(index);
             return Some(ts);
@@ -1171,7 +2224,7 @@ impl LossList {
         *seq
     }
 
-    fn push_in(&mut self, seq: Sequence, now: Instant) {
+    fn push_in(&mut self, seq: Sequence, now: tokio::time::Instant) {
         #[cfg(debug_assertions)]
         if let Some((n, _)) = self.inner.last() {
             if seq <= *n {
@@ -1182,22 +2235,55 @@ impl LossList {
         self.inner.push((seq, now));
     }
 
-    fn clear_in(&mut self, rtt: Rtt, now: Instant) -> usize {
-        // TODO: A binary search could also be benefitial here.
-        let mut num_removed = 0;
-        while !self.is_empty() {
-            let (_, ts) = unsafe { self.inner.get_unchecked(0) };
+    fn take_due_in(
+        &mut self,
+        delay: Duration,
+        now: tokio::time::Instant,
+    ) -> Vec<RangeInclusive<u32>> {
+        let start = match self.reported {
+            Some(reported) => self.inner.partition_point(|(seq, _)| *seq <= reported),
+            None => 0,
+        };
+
+        let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+        for (seq, ts) in &mut self.inner[start..] {
+            // Entries are pushed in order, all following entries are newer.
+            if now.saturating_duration_since(*ts) < delay {
+                break;
+            }
+
+            self.reported = Some(*seq);
+            // The retransmission is only expected after the report.
+            *ts = now;
 
-            let diff = (now - *ts).as_micros() as u32;
-            if diff < rtt.rtt * 2 {
-                return num_removed;
+            let seq = seq.get();
+            match ranges.last_mut() {
+                // Ranges never wrap around.
+                Some(range) if range.end().checked_add(1) == Some(seq) => {
+                    *range = *range.start()..=seq;
+                }
+                _ => ranges.push(seq..=seq),
             }
+        }
+
+        ranges
+    }
 
-            self.inner.remove(0);
-            num_removed += 1;
+    fn clear_in(&mut self, rtt: Rtt, now: tokio::time::Instant) -> usize {
+        if self.is_empty() {
+            return 0;
         }
 
-        num_removed
+        // Compare the `Duration`s directly, entries may be older than `u32::MAX` micros.
+        let timeout = Duration::from_micros(rtt.rtt.into())
+            + Duration::from_micros(rtt.rtt_variance.into()) * 4;
+
+        // Reporting resets the timestamp of an entry, so the timestamps are not ordered.
+        let len = self.len();
+        self.inner
+            .retain(|(_, ts)| now.saturating_duration_since(*ts) < timeout);
+
+        len - self.len()
     }
 }
 
@@ -1215,13 +2301,48 @@ where
             self.inner.reserve(len);
         }
 
-        let now = Instant::now();
+        let now = tokio::time::Instant::now();
         for seq in iter {
             self.push_in(seq.into(), now);
         }
     }
 }
 
+/// The authorization of the peer by the [`SessionManager`] during the handshake.
+enum Authorization {
+    /// The authorization is in progress. `packet` is the CONCLUSION that started it.
+    Pending {
+        future: BoxFuture<'static, std::result::Result<(), session::Error>>,
+        packet: Handshake,
+    },
+    /// The authorization completed, but the handshake did not continue yet.
+    Done(std::result::Result<(), session::Error>),
+}
+
+/// Returns `true` if `host` is a host name or address, optionally with a port. The host
+/// namespaces the streams of the [`SessionManager`], so arbitrary values are not accepted.
+fn is_valid_host(host: &str) -> bool {
+    const MAX_LEN: usize = 255;
+
+    !host.is_empty()
+        && host.len() <= MAX_LEN
+        && host
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-.:[]".contains(&b))
+}
+
+/// Returns the reason to reject the handshake of a peer that failed to publish or request a
+/// stream with `err`.
+fn rejection(err: session::Error) -> HandshakeType {
+    if err.is_invalid_resource_id() || err.is_invalid_credentials() {
+        HandshakeType::REJ_BADSECRET
+    } else if err.is_resource_busy() {
+        HandshakeType::REJ_RESOURCE
+    } else {
+        HandshakeType::REJ_UNKNOWN
+    }
+}
+
 enum ConnectionMode<S>
 where
     S: SessionManager,
@@ -1252,6 +2373,8 @@ where
 pub struct Rtt {
     pub rtt: u32,
     pub rtt_variance: u32,
+    /// The number of measurements, `0` while the initial estimate is used.
+    pub samples: u32,
 }
 
 impl Rtt {
@@ -1260,10 +2383,13 @@ impl Rtt {
         Self {
             rtt: 100_000,
             rtt_variance: 50_000,
+            samples: 0,
         }
     }
 
     pub fn update(&mut self, new: u32) {
+        self.samples = self.samples.saturating_add(1);
+
         self.rtt_variance = ((3.0 / 4.0) * self.rtt_variance as f32
             + (1.0 / 4.0) * self.rtt.abs_diff(new) as f32) as u32;
 
@@ -1278,130 +2404,2908 @@ impl Rtt {
     }
 }
 
-impl Default for Rtt {
-    #[inline]
-    fn default() -> Self {
-        Self::new()
-    }
+/// The congestion window for sending data packets in request mode.
+///
+/// The window starts at the configured initial window, so a new connection doesn't overflow the
+/// initial buffer of the peer. Like TCP slow-start, every acknowledged packet increases the window
+/// by one packet, doubling it every RTT, until it reaches the flow window.
+#[derive(Copy, Clone, Debug)]
+struct SendWindow {
+    /// The next sequence number expected by the peer.
+    acked: Sequence,
+    /// The maximum number of unacknowledged packets.
+    size: u32,
+    max: u32,
 }
 
-#[derive(Debug)]
-#[repr(transparent)]
-pub struct TickInterval(Interval);
-
-impl TickInterval {
-    pub fn new() -> Self {
-        let mut interval = tokio::time::interval(Duration::from_millis(10));
-        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+impl SendWindow {
+    /// Creates a new `SendWindow` for packets starting at `start`. An `initial` window of `0`
+    /// disables slow-start.
+    fn new(start: Sequence, initial: u32, max: u32) -> Self {
+        let size = if initial == 0 { max } else { initial.min(max) };
 
-        Self(interval)
+        Self {
+            acked: start,
+            size,
+            max,
+        }
     }
-}
 
-impl Default for TickInterval {
-    #[inline]
-    fn default() -> Self {
-        Self::new()
+    /// Returns `true` if the packet with the sequence number `next` can be sent.
+    fn can_send(&self, next: Sequence) -> bool {
+        (next - self.acked).get() < self.size
     }
-}
 
-impl Future for TickInterval {
-    type Output = ();
+    /// Acknowledges all packets before `seq`.
+    fn ack(&mut self, seq: Sequence) {
+        if seq <= self.acked {
+            return;
+        }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.0.poll_tick(cx).map(|_| ())
+        let num = (seq - self.acked).get();
+        self.acked = seq;
+        self.size = self.size.saturating_add(num).min(self.max);
     }
 }
 
-enum PollState<S>
-where
-    S: SessionManager,
-{
-    Read,
-    Write(Pin<Box<dyn Future<Output = Result<()>>>>),
-    WriteSink(Feed<'static, OutputSink<S>, DataPacket>),
-    Close(Close<'static, OutputSink<S>, DataPacket>),
-    Closed,
+/// Paces data packets in request mode after the peer reported congestion.
+///
+/// Every congestion warning doubles the minimum interval between two data packets, starting at
+/// [`MIN`] up to [`MAX`]. Every [`BACKOFF`] without a new warning the interval is halved again,
+/// until pacing is disabled once it drops below [`MIN`].
+///
+/// [`MIN`]: Self::MIN
+/// [`MAX`]: Self::MAX
+/// [`BACKOFF`]: Self::BACKOFF
+#[derive(Debug)]
+struct Pacing {
+    /// The minimum time between two data packets. Zero if pacing is disabled.
+    interval: Duration,
+    /// The time at which the interval is reduced next.
+    recover: tokio::time::Instant,
+    /// Timer until the next data packet can be sent.
+    sleep: Pin<Box<Sleep>>,
 }
 
-impl<S> Default for PollState<S>
-where
-    S: SessionManager,
-{
-    #[inline]
-    fn default() -> Self {
-        Self::Read
-    }
-}
+impl Pacing {
+    const MIN: Duration = Duration::from_micros(100);
+    const MAX: Duration = Duration::from_millis(10);
+    const BACKOFF: Duration = Duration::from_secs(1);
 
-/// A packet transmission queue.
-#[derive(Clone, Debug, Default)]
-pub struct TransmissionQueue {
-    queue: VecDeque<Packet>,
-    prio: VecDeque<Packet>,
-}
+    fn new() -> Self {
+        let now = tokio::time::Instant::now();
 
-impl TransmissionQueue {
-    /// Pushes a new [`Packet`] onto the default queue.
-    pub fn push(&mut self, packet: Packet) {
-        self.queue.push_back(packet);
+        Self {
+            interval: Duration::ZERO,
+            recover: now,
+            sleep: Box::pin(tokio::time::sleep_until(now)),
+        }
     }
 
-    /// Pushes a new [`Packet`] onto the priority queue.
-    pub fn push_prio(&mut self, packet: Packet) {
-        self.prio.push_back(packet);
+    /// Slows down sending after a congestion warning from the peer.
+    fn congestion(&mut self) {
+        self.interval = (self.interval * 2).clamp(Self::MIN, Self::MAX);
+        self.recover = tokio::time::Instant::now() + Self::BACKOFF;
     }
 
-    pub fn pop(&mut self) -> Option<Packet> {
-        match self.prio.pop_front() {
-            Some(packet) => Some(packet),
-            None => self.queue.pop_front(),
+    /// Polls until the next data packet can be sent.
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let now = tokio::time::Instant::now();
+        while !self.interval.is_zero() && now >= self.recover {
+            self.interval /= 2;
+            if self.interval < Self::MIN {
+                self.interval = Duration::ZERO;
+            }
+
+            self.recover += Self::BACKOFF;
         }
+
+        if self.interval.is_zero() {
+            return Poll::Ready(());
+        }
+
+        self.sleep.as_mut().poll(cx)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.queue.is_empty() && self.prio.is_empty()
+    /// Delays the next data packet by the current interval.
+    fn sent(&mut self) {
+        if !self.interval.is_zero() {
+            let deadline = tokio::time::Instant::now() + self.interval;
+            self.sleep.as_mut().reset(deadline);
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::time::{Duration, Instant};
-
-    use super::{LossList, Rtt};
-
-    #[test]
-    fn test_rtt() {
-        let mut rtt = Rtt::new();
-        assert_eq!(rtt.rtt, 100_000);
-        assert_eq!(rtt.rtt_variance, 50_000);
+/// Adapts the latency of the receive buffer in publish mode to the link.
+///
+/// Unrecovered loss grows the latency by [`STEP`], an RTT above a quarter of the latency raises it
+/// to four times the RTT. Every [`RECOVER`] without loss the latency shrinks by [`STEP`] again.
+/// The latency always stays between the latency agreed on in the handshake and the configured
+/// ceiling.
+///
+/// [`STEP`]: Self::STEP
+/// [`RECOVER`]: Self::RECOVER
+#[derive(Copy, Clone, Debug)]
+struct AdaptiveLatency {
+    floor: Duration,
+    ceiling: Duration,
+    current: Duration,
+    /// The time of the last loss or change of the latency.
+    last: Instant,
+}
 
-        rtt.update(100_000);
-        assert_eq!(rtt.rtt, 100_000);
-        assert_eq!(rtt.rtt_variance, 37_500);
+impl AdaptiveLatency {
+    const STEP: Duration = Duration::from_millis(20);
+    const RECOVER: Duration = Duration::from_secs(10);
 
-        let mut rtt = Rtt::new();
-        rtt.update(0);
-        assert_eq!(rtt.rtt, 87_500);
-        assert_eq!(rtt.rtt_variance, 62_500);
+    fn new(floor: Duration, ceiling: Duration, now: Instant) -> Self {
+        Self {
+            floor,
+            ceiling: ceiling.max(floor),
+            current: floor,
+            last: now,
+        }
+    }
+
+    /// Updates the latency with the number of packets `lost` since the last update and the
+    /// current `rtt`. Returns the new latency if it changed.
+    fn update(&mut self, lost: usize, rtt: Rtt, now: Instant) -> Option<Duration> {
+        // Retransmissions need about 4 RTTs to arrive within the latency. The initial estimate
+        // is not used, it is not based on the link.
+        let min = if rtt.samples == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_micros(u64::from(rtt.rtt) * 4)
+        };
+
+        let latency = if lost > 0 {
+            self.last = now;
+            (self.current + Self::STEP).max(min)
+        } else if min > self.current {
+            min
+        } else if now.saturating_duration_since(self.last) >= Self::RECOVER {
+            self.last = now;
+            self.current.saturating_sub(Self::STEP).max(min)
+        } else {
+            return None;
+        };
+
+        let latency = latency.clamp(self.floor, self.ceiling);
+        if latency == self.current {
+            return None;
+        }
+
+        self.current = latency;
+        self.last = now;
+        Some(latency)
+    }
+}
+
+/// Limits the rate of data sent in request mode using a token bucket.
+///
+/// The bucket fills with `rate` bytes per second and holds at most [`BURST`] worth of bytes.
+/// A data packet can be sent while the bucket is not empty. Sending takes the size of the packet
+/// from the bucket, which may leave it in debt until it refills.
+///
+/// [`BURST`]: Self::BURST
+#[derive(Debug)]
+struct RateLimit {
+    /// The rate in bytes per second.
+    rate: u64,
+    /// The available bytes, negative while in debt.
+    tokens: i64,
+    /// The time at which the bucket was last refilled.
+    last: tokio::time::Instant,
+    /// Timer until the bucket is no longer in debt.
+    sleep: Pin<Box<Sleep>>,
+}
+
+impl RateLimit {
+    /// The duration of sending at the full rate that the bucket can hold.
+    const BURST: Duration = Duration::from_millis(10);
+
+    fn new(rate: u64) -> Self {
+        let now = tokio::time::Instant::now();
+
+        Self {
+            rate,
+            tokens: 0,
+            last: now,
+            sleep: Box::pin(tokio::time::sleep_until(now)),
+        }
+    }
+
+    /// Polls until the next data packet can be sent.
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let now = tokio::time::Instant::now();
+
+        let bytes =
+            |duration: Duration| (self.rate as u128 * duration.as_nanos() / 1_000_000_000) as i64;
+
+        let burst = bytes(Self::BURST);
+        let refill = bytes(now - self.last);
+        if refill > 0 {
+            self.tokens = self.tokens.saturating_add(refill).min(burst);
+            self.last = now;
+        }
+
+        if self.tokens >= 0 {
+            return Poll::Ready(());
+        }
+
+        let wait = Duration::from_secs_f64(-self.tokens as f64 / self.rate as f64);
+        self.sleep.as_mut().reset(now + wait);
+        self.sleep.as_mut().poll(cx)
+    }
+
+    /// Takes a data packet with `size` bytes of payload from the bucket.
+    fn consume(&mut self, size: usize) {
+        self.tokens = self.tokens.saturating_sub(size as i64);
+    }
+}
+
+/// Estimates the receiving rate and link capacity reported in ACKs.
+///
+/// The receiving rate is measured over the interval since the last ACK. The link capacity is
+/// estimated from packet pairs: the sender transmits every packet with a sequence number that is
+/// a multiple of 16 immediately followed by the next packet. The arrival interval of such a pair
+/// is the time the link needs to carry one packet.
+#[derive(Clone, Debug)]
+struct RateEstimator {
+    /// Start of the current measurement interval.
+    since: Instant,
+    packets: u64,
+    bytes: u64,
+    /// Arrival time of the first packet of the current packet pair.
+    probe: Option<(u32, Instant)>,
+    /// The most recent packet pair intervals.
+    intervals: VecDeque<Duration>,
+}
+
+impl RateEstimator {
+    /// The number of packet pair intervals used for the link capacity estimate.
+    const PROBE_WINDOW: usize = 16;
+
+    fn new(now: Instant) -> Self {
+        Self {
+            since: now,
+            packets: 0,
+            bytes: 0,
+            probe: None,
+            intervals: VecDeque::with_capacity(Self::PROBE_WINDOW),
+        }
+    }
+
+    /// Records the arrival of a data packet with `size` bytes of payload.
+    fn push(&mut self, seq: u32, size: usize, is_retransmitted: bool, now: Instant) {
+        self.record(size);
+
+        // Retransmissions are not sent as pairs.
+        if is_retransmitted {
+            return;
+        }
+
+        match self.probe.take() {
+            Some((first, ts)) if seq == first.wrapping_add(1) => {
+                if self.intervals.len() == Self::PROBE_WINDOW {
+                    self.intervals.pop_front();
+                }
+
+                self.intervals.push_back(now - ts);
+            }
+            _ => (),
+        }
+
+        if seq.is_multiple_of(16) {
+            self.probe = Some((seq, now));
+        }
+    }
+
+    /// Records a data packet with `size` bytes of payload for the rates only.
+    fn record(&mut self, size: usize) {
+        self.packets += 1;
+        self.bytes += size as u64;
+    }
+
+    /// Returns the receiving rate in packets/s and bytes/s since the last call and starts a new
+    /// measurement interval.
+    fn rates(&mut self, now: Instant) -> (u32, u32) {
+        let elapsed = now.saturating_duration_since(self.since).as_secs_f64();
+
+        let rates = if elapsed > 0.0 {
+            (
+                (self.packets as f64 / elapsed) as u32,
+                (self.bytes as f64 / elapsed) as u32,
+            )
+        } else {
+            (0, 0)
+        };
+
+        self.since = now;
+        self.packets = 0;
+        self.bytes = 0;
+
+        rates
+    }
+
+    /// Returns the estimated link capacity in packets/s, or 0 if it is unknown.
+    fn link_capacity(&self) -> u32 {
+        if self.intervals.is_empty() {
+            return 0;
+        }
+
+        // The median filters out pairs that were delayed by cross traffic.
+        let mut intervals: Vec<_> = self.intervals.iter().copied().collect();
+        intervals.sort_unstable();
+        let median = intervals[intervals.len() / 2];
+
+        if median.is_zero() {
+            return 0;
+        }
+
+        (1.0 / median.as_secs_f64()) as u32
+    }
+}
+
+impl Default for Rtt {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An adaptive interval driving [`Connection::tick`].
+///
+/// The interval starts at [`MIN`] and doubles for every tick without activity on the connection,
+/// up to [`MAX`]. Any activity resets the interval back to [`MIN`]. This avoids waking up idle
+/// connections 100 times a second.
+///
+/// [`MIN`]: Self::MIN
+/// [`MAX`]: Self::MAX
+#[derive(Debug)]
+pub struct TickInterval {
+    sleep: Pin<Box<Sleep>>,
+    period: Duration,
+    /// Whether there was any activity since the last tick.
+    active: bool,
+}
+
+impl TickInterval {
+    /// The interval of an active connection.
+    pub const MIN: Duration = Duration::from_millis(10);
+    /// The maximum interval of an idle connection.
+    pub const MAX: Duration = Duration::from_millis(500);
+
+    pub fn new() -> Self {
+        Self {
+            sleep: Box::pin(tokio::time::sleep(Self::MIN)),
+            period: Self::MIN,
+            active: false,
+        }
+    }
+
+    /// Marks the connection as active, resetting the interval to [`MIN`].
+    ///
+    /// [`MIN`]: Self::MIN
+    pub fn activate(&mut self) {
+        self.active = true;
+
+        if self.period != Self::MIN {
+            self.period = Self::MIN;
+
+            let deadline = tokio::time::Instant::now() + Self::MIN;
+            if deadline < self.sleep.deadline() {
+                self.sleep.as_mut().reset(deadline);
+            }
+        }
+    }
+
+    /// Ensures that the next tick happens no later than `deadline`.
+    pub fn limit(&mut self, deadline: tokio::time::Instant) {
+        if deadline < self.sleep.deadline() {
+            self.sleep.as_mut().reset(deadline);
+        }
+    }
+}
+
+impl Default for TickInterval {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Future for TickInterval {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        match this.sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                if this.active {
+                    this.period = Self::MIN;
+                } else {
+                    this.period = (this.period * 2).min(Self::MAX);
+                }
+
+                this.active = false;
+
+                let deadline = tokio::time::Instant::now() + this.period;
+                this.sleep.as_mut().reset(deadline);
+
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+enum PollState<S>
+where
+    S: SessionManager,
+{
+    Read,
+    Write(Pin<Box<dyn Future<Output = Result<()>>>>),
+    WriteSink(Feed<'static, OutputSink<S>, DataPacket>),
+    /// Transmitting the remaining packets before closing.
+    Drain {
+        deadline: Pin<Box<Sleep>>,
+        write: Option<Pin<Box<dyn Future<Output = Result<()>>>>>,
+    },
+    Close(Close<'static, OutputSink<S>, DataPacket>),
+    Closed,
+}
+
+impl<S> PollState<S>
+where
+    S: SessionManager,
+{
+    /// Returns the name of the state for logging.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Read => "Read",
+            Self::Write(_) => "Write",
+            Self::WriteSink(_) => "WriteSink",
+            Self::Drain { .. } => "Drain",
+            Self::Close(_) => "Close",
+            Self::Closed => "Closed",
+        }
+    }
+}
+
+impl<S> Default for PollState<S>
+where
+    S: SessionManager,
+{
+    #[inline]
+    fn default() -> Self {
+        Self::Read
+    }
+}
+
+/// A packet transmission queue.
+#[derive(Clone, Debug, Default)]
+pub struct TransmissionQueue {
+    queue: VecDeque<Packet>,
+    prio: VecDeque<Packet>,
+}
+
+impl TransmissionQueue {
+    /// Pushes a new [`Packet`] onto the default queue.
+    pub fn push(&mut self, packet: Packet) {
+        self.queue.push_back(packet);
+    }
+
+    /// Pushes a new [`Packet`] onto the priority queue.
+    pub fn push_prio(&mut self, packet: Packet) {
+        self.prio.push_back(packet);
+    }
+
+    /// Returns a mutable reference to the next [`Packet`] returned by [`pop`].
+    ///
+    /// [`pop`]: Self::pop
+    pub fn peek_mut(&mut self) -> Option<&mut Packet> {
+        match self.prio.front_mut() {
+            Some(packet) => Some(packet),
+            None => self.queue.front_mut(),
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Packet> {
+        match self.prio.pop_front() {
+            Some(packet) => Some(packet),
+            None => self.queue.pop_front(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.prio.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{poll_fn, Future};
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use futures::future::BoxFuture;
+    use futures::{Sink, SinkExt};
+
+    use crate::session::buffer::{BufferSessionManager, SessionKey};
+    use crate::session::{
+        self, LiveSink, LiveStream, Mode, ResourceId, SessionError, SessionId, SessionManager,
+    };
+    use crate::srt::access_log::{AccessLog, AccessLogTarget};
+    use crate::srt::config::tests::config;
+    use crate::srt::config::{Config, ModeDuration};
+    use crate::srt::crypto::tests::key_material;
+    use crate::srt::crypto::Sek;
+    use crate::srt::loss::LossConfig;
+    use tokio::net::UdpSocket;
+    use tokio::sync::oneshot;
+
+    use crate::proto::{Bits, Decode, U32};
+    use crate::srt::proto::{
+        Ack, DropRequest, Handshake, Keepalive, Nak, PeerError, Shutdown, Timestamp,
+    };
+    use crate::srt::socket::SrtSocket;
+    use crate::srt::state::{ConnectionId, State};
+    use crate::srt::{
+        ControlPacketType, DataPacket, EncryptionField, EncryptionFlag, ExtensionContent,
+        ExtensionField, ExtensionType, GroupExtension, GroupType, HandshakeExtension,
+        HandshakeExtensionFlags, HandshakeExtensionMessage, HandshakeType, IsPacket, Packet,
+        PacketType, StreamIdExtension,
+    };
+
+    use super::{
+        ack_kind, AckKind, AdaptiveLatency, CloseReason, Connection, ConnectionHandle, Control,
+        LossList, Pacing, PollState, RateEstimator, RateLimit, Rtt, Sequence, TickInterval,
+    };
+
+    const SYN_COOKIE: u32 = 0x1234;
+
+    /// Creates a new [`Connection`] in the INDUCTION phase.
+    fn connection(
+        state: &State<BufferSessionManager>,
+        socket: &SrtSocket,
+    ) -> (Connection<BufferSessionManager>, ConnectionHandle) {
+        connection_to(state, socket, "127.0.0.1:9000".parse().unwrap())
+    }
+
+    /// Creates a new [`Connection`] in the INDUCTION phase to the peer at `addr`.
+    fn connection_to(
+        state: &State<BufferSessionManager>,
+        socket: &SrtSocket,
+        addr: SocketAddr,
+    ) -> (Connection<BufferSessionManager>, ConnectionHandle) {
+        let id = ConnectionId {
+            addr,
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+
+        unsafe { Connection::new(id, state, socket, 0, SYN_COOKIE, addr.ip()) }
+    }
+
+    /// Registers a session for `resource` on `state`.
+    fn session(state: &State<BufferSessionManager>, resource: u64, session: u64) {
+        state.session_manager.registry.insert(SessionKey {
+            resource_id: ResourceId(resource),
+            session_id: SessionId(session),
+            expires: Instant::now() + Duration::from_secs(60),
+        });
+    }
+
+    /// Creates a CONCLUSION [`Handshake`] with the HSREQ and SID extensions.
+    fn conclusion(stream_id: &str, srt_version: u32) -> Handshake {
+        let mut packet = Handshake {
+            version: 5,
+            handshake_type: HandshakeType::CONCLUSION,
+            syn_cookie: SYN_COOKIE,
+            extension_field: ExtensionField(ExtensionField::HSREQ.0 | ExtensionField::CONFIG.0),
+            ..Default::default()
+        };
+
+        packet.extensions.0.push(HandshakeExtension {
+            extension_type: ExtensionType::HSREQ,
+            extension_length: 3,
+            extension_content: HandshakeExtensionMessage {
+                srt_version,
+                srt_flags: HandshakeExtensionFlags::TSBPDSND
+                    | HandshakeExtensionFlags::TSBPDRCV
+                    | HandshakeExtensionFlags::CRYPT
+                    | HandshakeExtensionFlags::REXMITFLG
+                    | HandshakeExtensionFlags::TLPKTDROP,
+                receiver_tsbpd_delay: 120,
+                sender_tsbpd_delay: 120,
+            }
+            .into(),
+        });
+
+        let ext = ExtensionContent::StreamId(StreamIdExtension {
+            content: stream_id.to_owned(),
+        });
+        packet.extensions.0.push(HandshakeExtension {
+            extension_type: ExtensionType::SID,
+            extension_length: ext.len() as u16,
+            extension_content: ext,
+        });
+
+        packet
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tick_interval() {
+        let mut interval = TickInterval::new();
+        assert_eq!(interval.period, TickInterval::MIN);
+
+        // Idle ticks back off up to `MAX`.
+        let mut period = TickInterval::MIN;
+        while period < TickInterval::MAX {
+            (&mut interval).await;
+            period = (period * 2).min(TickInterval::MAX);
+            assert_eq!(interval.period, period);
+        }
+
+        (&mut interval).await;
+        assert_eq!(interval.period, TickInterval::MAX);
+
+        // Activity immediately resets the interval.
+        let now = tokio::time::Instant::now();
+        interval.activate();
+        (&mut interval).await;
+        assert_eq!(now.elapsed(), TickInterval::MIN);
+        assert_eq!(interval.period, TickInterval::MIN);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tick_interval_limit() {
+        let mut interval = TickInterval::new();
+        for _ in 0..8 {
+            (&mut interval).await;
+        }
+        assert_eq!(interval.period, TickInterval::MAX);
+
+        let now = tokio::time::Instant::now();
+        interval.limit(now + Duration::from_millis(30));
+        (&mut interval).await;
+        assert_eq!(now.elapsed(), Duration::from_millis(30));
+    }
+
+    /// Compares the CPU time of 5000 idle requesting connections with the adaptive tick and with
+    /// a tick fixed at [`TickInterval::MIN`], as before the tick adapted to idle connections.
+    ///
+    /// Run with `cargo test --release bench_idle_connections -- --ignored --nocapture`.
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore]
+    async fn bench_idle_connections() {
+        use futures::stream::FuturesUnordered;
+        use futures::StreamExt;
+
+        use crate::srt::utils::cpu_time;
+
+        const COUNT: u16 = 5000;
+        const DURATION: Duration = Duration::from_secs(10);
+
+        for adaptive in [false, true] {
+            let state = State::new(BufferSessionManager::new(Default::default()), config());
+            let socket = SrtSocket::new(state.config.bind).unwrap();
+            session(&state, 1, 1);
+
+            let mut handles = Vec::new();
+            let conns = FuturesUnordered::new();
+            for port in 0..COUNT {
+                let addr = SocketAddr::from(([127, 0, 0, 1], 10_000 + port));
+                let (mut conn, handle) = connection_to(&state, &socket, addr);
+                conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+                    .unwrap();
+                while conn.queue.pop().is_some() {}
+                handles.push(handle);
+
+                let mut conn = Box::pin(conn);
+                conns.push(poll_fn(move |cx| {
+                    if !adaptive {
+                        unsafe { conn.as_mut().get_unchecked_mut() }
+                            .tick_interval
+                            .activate();
+                    }
+
+                    conn.as_mut().poll(cx)
+                }));
+            }
+
+            let start = cpu_time();
+            let res = tokio::time::timeout(DURATION, conns.collect::<Vec<_>>()).await;
+            assert!(res.is_err());
+
+            println!(
+                "adaptive={} connections={}: {:?} CPU in {:?}",
+                adaptive,
+                COUNT,
+                cpu_time() - start,
+                DURATION
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_drains_queue() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection_to(&state, &socket, peer.local_addr().unwrap());
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        for _ in 0..3 {
+            conn.send(Keepalive::builder().build()).unwrap();
+        }
+
+        conn.close(CloseReason::ServerShutdown).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), conn)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut types = Vec::new();
+        let mut buf = [0; 1500];
+        while let Ok(res) =
+            tokio::time::timeout(Duration::from_millis(50), peer.recv(&mut buf)).await
+        {
+            let len = res.unwrap();
+            let mut packet = Packet::decode(&mut &buf[..len]).unwrap();
+            types.push(packet.header.as_control().unwrap().control_type());
+        }
+
+        assert_eq!(
+            types,
+            [
+                ControlPacketType::Handshake,
+                ControlPacketType::Keepalive,
+                ControlPacketType::Keepalive,
+                ControlPacketType::Keepalive,
+                ControlPacketType::Shutdown,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_loss() {
+        let config = Config {
+            debug: true,
+            loss: Some(LossConfig {
+                sequence_numbers: vec![1],
+                ..Default::default()
+            }),
+            ..config()
+        };
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+        let (conn, _handle) = connection_to(&state, &socket, peer.local_addr().unwrap());
+        let packet = |seq: u32| DataPacket::builder().sequence_number(seq).build().upcast();
+        conn.write(vec![
+            packet(0),
+            packet(1),
+            packet(2),
+            Keepalive::builder().build().upcast(),
+        ])
+        .await
+        .unwrap();
+        // The retransmission is let through.
+        conn.write(vec![packet(1)]).await.unwrap();
+
+        let mut received = Vec::new();
+        let mut buf = [0; 1500];
+        while let Ok(res) =
+            tokio::time::timeout(Duration::from_millis(50), peer.recv(&mut buf)).await
+        {
+            let len = res.unwrap();
+            let mut packet = Packet::decode(&mut &buf[..len]).unwrap();
+            received.push(match packet.header.packet_type() {
+                PacketType::Data => {
+                    Some(packet.header.as_data_unchecked().packet_sequence_number())
+                }
+                PacketType::Control => None,
+            });
+        }
+        assert_eq!(received, [Some(0), Some(2), None, Some(1)]);
+
+        // Dropped packets are still counted as sent.
+        assert_eq!(conn.metrics.ctrl_packets_sent.get(), 1);
+    }
+
+    /// Closes the connection in the mode set up by `handshake`, returns the control packets
+    /// received by the peer and checks that all metrics are released.
+    async fn close_from<F>(handshake: F) -> Vec<ControlPacketType>
+    where
+        F: FnOnce(&mut Connection<BufferSessionManager>),
+    {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, handle) = connection_to(&state, &socket, peer.local_addr().unwrap());
+        state.pool.insert(handle);
+        handshake(&mut conn);
+
+        conn.close(CloseReason::ServerShutdown).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        drop(conn);
+
+        assert_eq!(state.metrics.connections_handshake_current.get(), 0);
+        assert_eq!(state.metrics.connections_publish_current.get(), 0);
+        assert_eq!(state.metrics.connections_request_current.get(), 0);
+        assert!(state.conn_metrics.lock().is_empty());
+        assert_eq!(state.pool.len(), 0);
+
+        let mut types = Vec::new();
+        let mut buf = [0; 1500];
+        while let Ok(res) =
+            tokio::time::timeout(Duration::from_millis(50), peer.recv(&mut buf)).await
+        {
+            let len = res.unwrap();
+            let mut packet = Packet::decode(&mut &buf[..len]).unwrap();
+            types.push(packet.header.as_control().unwrap().control_type());
+        }
+
+        types
+    }
+
+    #[tokio::test]
+    async fn test_close_induction() {
+        let types = close_from(|_| ()).await;
+        assert!(types.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_close_publish() {
+        let types = close_from(|conn| {
+            conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+                .unwrap();
+            assert!(conn.mode.is_publish());
+        })
+        .await;
+
+        assert_eq!(
+            types,
+            [ControlPacketType::Handshake, ControlPacketType::Shutdown]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_request() {
+        let types = close_from(|conn| {
+            conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+                .unwrap();
+            assert!(conn.mode.is_request());
+        })
+        .await;
+
+        assert_eq!(
+            types,
+            [ControlPacketType::Handshake, ControlPacketType::Shutdown]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handshake_peer_version() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        assert!(conn.mode.is_publish());
+        assert_eq!(conn.metrics.peer_version.get(), 0x00010403);
+    }
+
+    #[tokio::test]
+    async fn test_mtu_negotiation() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        // The peer proposes a smaller MTU.
+        let (mut conn, _handle) = connection(&state, &socket);
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+        packet.maximum_transmission_unit_size = 1400;
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.maximum_transmission_unit_size, 1400);
+        assert_eq!(conn.max_payload_size(), 1356);
+
+        // The IPv6 header is larger than the IPv4 header.
+        for (addr, size) in [("[::1]:9000", 1336), ("[::ffff:127.0.0.1]:9000", 1356)] {
+            let (mut conn, _handle) = connection_to(&state, &socket, addr.parse().unwrap());
+            let mut packet = conclusion("#!::m=request,r=1,s=1", 0x00010403);
+            packet.maximum_transmission_unit_size = 1400;
+            conn.handle_handshake(packet).unwrap();
+            assert_eq!(conn.max_payload_size(), size);
+        }
+
+        // The smallest valid MTU fits one byte of payload for IPv6 peers.
+        let min_mtu = crate::config::Srt::MIN_MTU;
+        for (mtu, size) in [(min_mtu, 1), (min_mtu - 1, 0)] {
+            let addr = "[::1]:9000".parse().unwrap();
+            let (mut conn, _handle) = connection_to(&state, &socket, addr);
+            let mut packet = conclusion("#!::m=request,r=1,s=1", 0x00010403);
+            packet.maximum_transmission_unit_size = mtu;
+            conn.handle_handshake(packet).unwrap();
+            assert_eq!(conn.max_payload_size(), size);
+        }
+
+        // No payload fits into the MTU.
+        let (mut conn, _handle) = connection(&state, &socket);
+        let mut packet = conclusion("#!::m=request,r=1,s=1", 0x00010403);
+        packet.maximum_transmission_unit_size = 44;
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_ROGUE);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_invalid_host() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let long = "a".repeat(256);
+        for host in ["", "exa mple.com", "example.com\"", long.as_str()] {
+            let (mut conn, _handle) = connection(&state, &socket);
+            let sid = format!("#!::h={},m=publish,r=1,s=1", host);
+            conn.handle_handshake(conclusion(&sid, 0x00010403)).unwrap();
+            let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+            assert_eq!(resp.handshake_type, HandshakeType::REJ_ROGUE, "{:?}", host);
+        }
+
+        // Sessions are used up by a successful handshake.
+        for (session_id, host) in [(2, "example.com"), (3, "127.0.0.1:9000"), (4, "[::1]:9000")] {
+            session(&state, 1, session_id);
+
+            let (mut conn, _handle) = connection(&state, &socket);
+            let sid = format!("#!::h={},m=publish,r=1,s={}", host, session_id);
+            conn.handle_handshake(conclusion(&sid, 0x00010403)).unwrap();
+            assert!(conn.mode.is_publish(), "{:?}", host);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_encryption() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+        packet.encryption_field = EncryptionField::AES128;
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_INSECURE);
+
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+        packet.extension_field = ExtensionField(packet.extension_field.0 | ExtensionField::KMREQ.0);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_INSECURE);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_key_material() {
+        let mut config = config();
+        config.passphrase = Some("passphrase".to_owned());
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let key_material = |passphrase| {
+            let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+            packet.extension_field =
+                ExtensionField(packet.extension_field.0 | ExtensionField::KMREQ.0);
+
+            let ext = ExtensionContent::KeyMaterial(key_material(
+                passphrase,
+                EncryptionFlag::Even,
+                &[1; 16],
+            ));
+            packet.extensions.0.push(HandshakeExtension {
+                extension_type: ExtensionType::KMREQ,
+                extension_length: ext.len() as u16,
+                extension_content: ext,
+            });
+            packet
+        };
+
+        // Unencrypted peers are rejected.
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_INSECURE);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(key_material("wrong passphrase"))
+            .unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_BADSECRET);
+
+        // The key material is confirmed in the KMRSP.
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(key_material("passphrase")).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::CONCLUSION);
+        assert!(resp.extension_field.kmreg());
+        assert!(resp
+            .extensions
+            .0
+            .iter()
+            .any(|ext| ext.extension_type == ExtensionType::KMRSP));
+        assert!(conn.mode.is_publish());
+
+        // Payloads encrypted with the even key are decrypted.
+        let mut body = *b"hello";
+        Sek::new(&[1; 16], [7; 16]).unwrap().apply(0, &mut body);
+        let packet = DataPacket::builder()
+            .sequence_number(0u32)
+            .encryption(EncryptionFlag::Even)
+            .body(Bytes::copy_from_slice(&body))
+            .build();
+        conn.handle_data(packet).unwrap();
+        assert_eq!(conn.metrics.decrypt_failures.get(), 0);
+
+        // Encrypted requests are not supported.
+        let mut packet = key_material("passphrase");
+        packet.extensions.0[1] = conclusion("#!::m=request,r=1,s=2", 0x00010403)
+            .extensions
+            .0
+            .remove(1);
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_INSECURE);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_group() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010501);
+        packet.extensions.0.push(HandshakeExtension {
+            extension_type: ExtensionType::GROUP,
+            extension_length: 2,
+            extension_content: ExtensionContent::Group(GroupExtension {
+                group_id: 0x4000_0001,
+                group_type: GroupType::BACKUP,
+                flags: 0,
+                weight: 10,
+            }),
+        });
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(packet).unwrap();
+        assert!(conn.mode.is_publish());
+        assert_eq!(conn.group_id, Some(0x4000_0001));
+
+        // The connection is not bonded.
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::CONCLUSION);
+        assert!(resp
+            .extensions
+            .0
+            .iter()
+            .all(|ext| ext.extension_type != ExtensionType::GROUP));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_handshake_cookie_expired() {
+        let config = Config {
+            syn_cookie_lifetime: Some(Duration::from_secs(60)),
+            ..config()
+        };
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let id = ConnectionId {
+            addr,
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+
+        for accepted in [true, false] {
+            let cookie = state.syn_cookies.generate(addr);
+            if !accepted {
+                tokio::time::advance(Duration::from_secs(120)).await;
+            }
+
+            let (mut conn, _handle) =
+                unsafe { Connection::new(id, &state, &socket, 0, cookie, addr.ip()) };
+
+            let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+            packet.syn_cookie = cookie;
+            conn.handle_handshake(packet).unwrap();
+            assert_eq!(conn.mode.is_publish(), accepted);
+
+            let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+            if accepted {
+                assert_eq!(resp.handshake_type, HandshakeType::CONCLUSION);
+                session(&state, 1, 1);
+            } else {
+                assert_eq!(resp.handshake_type, HandshakeType::REJ_RDVCOOKIE);
+            }
+        }
+    }
+
+    #[test]
+    fn test_control_retransmit() {
+        assert_eq!(Control::retransmit(5, 7), Some(Control::Retransmit(5..=7)));
+        assert_eq!(Control::retransmit(5, 5), Some(Control::Retransmit(5..=5)));
+        assert_eq!(Control::retransmit(7, 5), None);
+        assert!(Control::retransmit(0, Control::MAX_RETRANSMIT - 1).is_some());
+        assert_eq!(Control::retransmit(0, Control::MAX_RETRANSMIT), None);
+        assert_eq!(Control::retransmit(0, u32::MAX), None);
+    }
+
+    #[tokio::test]
+    async fn test_control_retransmit_request() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        assert!(conn.mode.is_request());
+        while conn.queue.pop().is_some() {}
+
+        // Nothing was sent yet, the peer is told to drop the packets instead.
+        assert!(handle.control(Control::retransmit(5, 7).unwrap()));
+        let control = conn.control.recv().await.unwrap();
+        conn.handle_control(control).unwrap();
+
+        let mut packet = conn.queue.pop().unwrap();
+        assert_eq!(
+            packet.header.as_control().unwrap().control_type(),
+            ControlPacketType::DropReq
+        );
+        let dropreq: DropRequest = packet.downcast().unwrap();
+        assert_eq!(dropreq.first_packet_sequence_number, 5);
+        assert_eq!(dropreq.last_packet_sequence_number, 7);
+
+        // Oversized ranges are ignored.
+        while conn.queue.pop().is_some() {}
+        conn.handle_control(Control::Retransmit(0..=Control::MAX_RETRANSMIT))
+            .unwrap();
+        assert!(conn.queue.pop().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_control_close() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        assert_eq!(*conn.metrics.resource_id.lock(), Some(ResourceId(1)));
+        while conn.queue.pop().is_some() {}
+
+        assert!(handle.control(Control::Close));
+        let control = conn.control.recv().await.unwrap();
+        conn.handle_control(control).unwrap();
+        assert_eq!(conn.close_reason, Some(CloseReason::Operator));
+        assert!(matches!(conn.poll_state, PollState::Drain { .. }));
+
+        let mut packet = conn.queue.pop().unwrap();
+        assert_eq!(
+            packet.header.as_control().unwrap().control_type(),
+            ControlPacketType::Shutdown
+        );
+
+        drop(conn);
+        assert_eq!(state.metrics.connections_closed.operator.get(), 1);
+    }
+
+    /// Returns the [`DropRequest`] range of `packet`.
+    fn dropreq_range(mut packet: Packet) -> (u32, u32) {
+        assert_eq!(
+            packet.header.as_control().unwrap().control_type(),
+            ControlPacketType::DropReq
+        );
+        let dropreq: DropRequest = packet.downcast().unwrap();
+        (
+            dropreq.first_packet_sequence_number,
+            dropreq.last_packet_sequence_number,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_too_late_drop_nak() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let mut sink = state
+            .session_manager
+            .publish(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        assert!(conn.too_late_drop);
+        while conn.queue.pop().is_some() {}
+
+        // Transmit a single segment.
+        sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+        poll_fn(|cx| pinned.as_mut().poll_read(cx)).await.unwrap();
+        let seq = conn
+            .queue
+            .pop()
+            .unwrap()
+            .header
+            .as_data_unchecked()
+            .packet_sequence_number();
+
+        let nak = || Nak::builder().lost_packet_sequence_numbers(seq).build();
+
+        // Still in time.
+        conn.handle_nak(nak()).unwrap();
+        let packet = conn.queue.pop().unwrap();
+        assert_eq!(packet.header.packet_type(), PacketType::Data);
+
+        // The segment is older than the latency.
+        conn.start_time -= Duration::from_secs(1);
+        conn.handle_nak(nak()).unwrap();
+        assert_eq!(dropreq_range(conn.queue.pop().unwrap()), (seq, seq));
+        assert!(conn.queue.pop().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dropreq_message_number() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let mut sink = state
+            .session_manager
+            .publish(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        for _ in 0..3 {
+            sink.send(Bytes::from_static(b"hello")).await.unwrap();
+        }
+        let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+        poll_fn(|cx| pinned.as_mut().poll_read(cx)).await.unwrap();
+
+        let mut seqs = Vec::new();
+        while let Some(mut packet) = conn.queue.pop() {
+            seqs.push(packet.header.as_data_unchecked().packet_sequence_number());
+        }
+        assert_eq!(seqs.len(), 3);
+
+        // All segments are too late, the peer is told to drop them.
+        conn.start_time -= Duration::from_secs(1);
+        let nak = Nak::builder()
+            .lost_packet_sequence_numbers(seqs[1]..=seqs[2])
+            .build();
+        conn.handle_nak(nak).unwrap();
+
+        let packet = conn.queue.pop().unwrap();
+        let dropreq: DropRequest = packet.downcast().unwrap();
+        assert_eq!(dropreq.first_packet_sequence_number, seqs[1]);
+        assert_eq!(dropreq.last_packet_sequence_number, seqs[2]);
+        assert_eq!(dropreq.message_number(), 2);
+
+        // The segment was never sent.
+        let nak = Nak::builder()
+            .lost_packet_sequence_numbers(seqs[2] + 1)
+            .build();
+        conn.handle_nak(nak).unwrap();
+
+        let packet = conn.queue.pop().unwrap();
+        let dropreq: DropRequest = packet.downcast().unwrap();
+        assert_eq!(dropreq.first_packet_sequence_number, seqs[2] + 1);
+        assert_eq!(dropreq.message_number(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_too_late_drop_queue() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.latency = Duration::from_millis(120);
+        conn.too_late_drop = true;
+        conn.start_time -= Duration::from_secs(1);
+
+        for seq in [1u32, 2, 3, 5] {
+            let packet = DataPacket::builder()
+                .sequence_number(seq)
+                .message_number(seq + 10)
+                .build();
+            conn.queue.push(packet.upcast());
+        }
+        let mut packet = DataPacket::builder().sequence_number(6u32).build().upcast();
+        packet.header.timestamp = conn.timestamp();
+        conn.queue.push(packet);
+
+        let packet = conn.pop_queue().unwrap();
+        let dropreq: DropRequest = packet.clone().downcast().unwrap();
+        assert_eq!(dropreq.message_number(), 11);
+        assert_eq!(dropreq_range(packet), (1, 3));
+        assert_eq!(dropreq_range(conn.pop_queue().unwrap()), (5, 5));
+        let mut packet = conn.pop_queue().unwrap();
+        assert_eq!(
+            packet.header.as_data_unchecked().packet_sequence_number(),
+            6
+        );
+        assert!(conn.pop_queue().is_none());
+        assert_eq!(conn.metrics.data_packets_sent.dropped.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_slow_start() {
+        let mut config = config();
+        config.initial_window = 4;
+        config.flow_window = 12;
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let mut sink = state
+            .session_manager
+            .publish(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        for _ in 0..16 {
+            sink.feed(Bytes::from_static(b"hello")).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        // Only the initial window is sent before the first ACK.
+        let mut sent = poll_data(&mut conn).await;
+        assert_eq!(sent.len(), 4);
+        assert!(poll_data(&mut conn).await.is_empty());
+
+        // Every acknowledged packet grows the window by one.
+        let ack = Ack::builder()
+            .last_acknowledged_packet_sequence_number(sent[2] + 1)
+            .build();
+        conn.handle_ack(ack).unwrap();
+        while conn.queue.pop().is_some() {}
+        sent.extend(poll_data(&mut conn).await);
+        assert_eq!(sent.len(), 3 + 7);
+
+        // The window never exceeds the flow window.
+        conn.window.ack(Sequence::new(sent[9] + 1));
+        sent.extend(poll_data(&mut conn).await);
+        assert_eq!(sent.len(), 16);
+        assert_eq!(conn.window.size, 12);
+    }
+
+    #[tokio::test]
+    async fn test_ack_invalid_sequence() {
+        let mut config = config();
+        config.initial_window = 4;
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+        let acked = conn.window.acked;
+
+        // The top bit is never set in a sequence number.
+        let ack = Ack::builder()
+            .last_acknowledged_packet_sequence_number(0x8000_0000)
+            .build();
+        conn.handle_ack(ack).unwrap();
+        assert_eq!(conn.window.acked, acked);
+        assert!(conn.queue.pop().is_none());
+
+        // Packets that were never sent cannot be acknowledged.
+        let ack = Ack::builder()
+            .last_acknowledged_packet_sequence_number((conn.server_sequence_number + 100).get())
+            .build();
+        conn.handle_ack(ack).unwrap();
+        assert_eq!(conn.window.acked, conn.server_sequence_number);
+        assert_eq!(conn.window.size, 4);
+    }
+
+    #[test]
+    fn test_adaptive_latency() {
+        let now = Instant::now();
+        let step = AdaptiveLatency::STEP;
+        let floor = Duration::from_millis(120);
+
+        let mut latency = AdaptiveLatency::new(floor, floor + step * 2, now);
+        let mut rtt = Rtt::new();
+        assert_eq!(latency.update(0, rtt, now), None);
+
+        // Loss grows the latency up to the ceiling.
+        assert_eq!(latency.update(3, rtt, now), Some(floor + step));
+        assert_eq!(latency.update(1, rtt, now), Some(floor + step * 2));
+        assert_eq!(latency.update(1, rtt, now), None);
+
+        // The latency shrinks back to the floor once the link recovered.
+        let now = now + AdaptiveLatency::RECOVER;
+        assert_eq!(latency.update(0, rtt, now - step), None);
+        assert_eq!(latency.update(0, rtt, now), Some(floor + step));
+        assert_eq!(latency.update(0, rtt, now), None);
+        let now = now + AdaptiveLatency::RECOVER;
+        assert_eq!(latency.update(0, rtt, now), Some(floor));
+        assert_eq!(latency.update(0, rtt, now + AdaptiveLatency::RECOVER), None);
+
+        // A measured RTT raises the latency to four times the RTT.
+        rtt.update(35_000);
+        let mut latency = AdaptiveLatency::new(floor, Duration::from_secs(1), now);
+        let expected = Duration::from_micros(u64::from(rtt.rtt) * 4);
+        assert!(expected > floor);
+        assert_eq!(latency.update(0, rtt, now), Some(expected));
+
+        // It doesn't shrink below the RTT.
+        let now = now + AdaptiveLatency::RECOVER;
+        assert_eq!(latency.update(0, rtt, now), None);
+    }
+
+    #[tokio::test]
+    async fn test_flow_window() {
+        let mut config = config();
+        config.flow_window = 12;
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let mut sink = state
+            .session_manager
+            .publish(None, Some(ResourceId(1)))
+            .unwrap();
+
+        // The peer proposes a smaller flow window.
+        let (mut conn, _handle) = connection(&state, &socket);
+        let mut packet = conclusion("#!::m=request,r=1,s=1", 0x00010403);
+        packet.maximum_flow_window_size = 6;
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.maximum_flow_window_size, 6);
+        while conn.queue.pop().is_some() {}
+
+        for _ in 0..10 {
+            sink.feed(Bytes::from_static(b"hello")).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        // Sending pauses once the window is full.
+        let mut sent = poll_data(&mut conn).await;
+        assert_eq!(sent.len(), 6);
+        assert!(poll_data(&mut conn).await.is_empty());
+
+        // And resumes on the next ACK.
+        let ack = Ack::builder()
+            .last_acknowledged_packet_sequence_number(sent[1] + 1)
+            .build();
+        conn.handle_ack(ack).unwrap();
+        while conn.queue.pop().is_some() {}
+        sent.extend(poll_data(&mut conn).await);
+        assert_eq!(sent.len(), 8);
+        assert!(poll_data(&mut conn).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_monotonic() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.start_time -= Duration::from_secs(10);
+        let first = conn.timestamp();
+
+        // The clock goes backwards.
+        conn.start_time += Duration::from_secs(5);
+        assert_eq!(conn.timestamp(), first);
+
+        conn.start_time -= Duration::from_secs(10);
+        assert!(conn.timestamp() > first);
+
+        // Wrapping around is not a regression.
+        conn.last_timestamp = Timestamp::from_micros(u32::MAX - 1000);
+        conn.start_time = Instant::now();
+        assert!(conn.timestamp().as_micros() < 1_000_000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_pacing() {
+        let mut pacing = Pacing::new();
+        assert!(ready(&mut pacing));
+
+        pacing.congestion();
+        assert_eq!(pacing.interval, Pacing::MIN);
+        pacing.congestion();
+        assert_eq!(pacing.interval, Pacing::MIN * 2);
+        for _ in 0..16 {
+            pacing.congestion();
+        }
+        assert_eq!(pacing.interval, Pacing::MAX);
+
+        assert!(ready(&mut pacing));
+        pacing.sent();
+        assert!(!ready(&mut pacing));
+        tokio::time::advance(Pacing::MAX).await;
+        assert!(ready(&mut pacing));
+
+        // The interval recovers once no warning was received for `BACKOFF`.
+        tokio::time::advance(Pacing::BACKOFF).await;
+        assert!(ready(&mut pacing));
+        assert_eq!(pacing.interval, Pacing::MAX / 2);
+
+        tokio::time::advance(Pacing::BACKOFF * 8).await;
+        assert!(ready(&mut pacing));
+        assert_eq!(pacing.interval, Duration::ZERO);
+        pacing.sent();
+        assert!(ready(&mut pacing));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limit() {
+        // 100 bytes per millisecond.
+        let mut limit = RateLimit::new(100_000);
+        assert!(limit_ready(&mut limit));
+
+        limit.consume(1000);
+        assert!(!limit_ready(&mut limit));
+        tokio::time::advance(Duration::from_millis(9)).await;
+        assert!(!limit_ready(&mut limit));
+        tokio::time::advance(Duration::from_millis(1)).await;
+        assert!(limit_ready(&mut limit));
+
+        // The bucket holds at most `BURST` worth of bytes.
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(limit_ready(&mut limit));
+        assert_eq!(limit.tokens, 1000);
+
+        limit.consume(1000);
+        assert!(limit_ready(&mut limit));
+        limit.consume(1);
+        assert!(!limit_ready(&mut limit));
+    }
+
+    fn limit_ready(limit: &mut RateLimit) -> bool {
+        let waker = futures::task::noop_waker();
+        limit
+            .poll_ready(&mut std::task::Context::from_waker(&waker))
+            .is_ready()
+    }
+
+    /// Returns `true` if `pacing` allows sending a packet.
+    fn ready(pacing: &mut Pacing) -> bool {
+        let waker = futures::task::noop_waker();
+        pacing
+            .poll_ready(&mut std::task::Context::from_waker(&waker))
+            .is_ready()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_congestion_warning() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let mut sink = state
+            .session_manager
+            .publish(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        for _ in 0..4 {
+            sink.feed(Bytes::from_static(b"hello")).await.unwrap();
+        }
+        sink.flush().await.unwrap();
+
+        conn.handle_congestion_warning().unwrap();
+        assert_eq!(
+            conn.metrics.pacing_interval.get(),
+            Pacing::MIN.as_micros() as usize
+        );
+
+        // Only a single packet is sent per interval.
+        assert_eq!(poll_data(&mut conn).await.len(), 1);
+        assert!(poll_data(&mut conn).await.is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        let mut config = config();
+        config.idle_timeout = ModeDuration::new(None, Some(Duration::from_secs(30)));
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let (mut publish, _handle) =
+            connection_to(&state, &socket, "127.0.0.1:9001".parse().unwrap());
+        publish
+            .handle_handshake(conclusion("#!::m=publish,r=1,s=2", 0x00010403))
+            .unwrap();
+
+        let (mut request, _handle) = connection(&state, &socket);
+        request
+            .handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        request.tick().unwrap();
+        assert!(matches!(request.poll_state, PollState::Read));
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        request.tick().unwrap();
+        assert!(matches!(request.poll_state, PollState::Drain { .. }));
+
+        // The timeout of publishing connections is disabled.
+        tokio::time::advance(Duration::from_secs(3600)).await;
+        publish.tick().unwrap();
+        assert!(matches!(publish.poll_state, PollState::Read));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_idle_timeout() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        // The reloaded timeout applies to the existing connection.
+        let mut config = config();
+        config.idle_timeout.request = Some(Duration::from_secs(30));
+        state.reload(&config);
+
+        tokio::time::advance(Config::DEFAULT_IDLE_TIMEOUT).await;
+        conn.tick().unwrap();
+        assert!(matches!(conn.poll_state, PollState::Read));
+
+        tokio::time::advance(Duration::from_secs(30) - Config::DEFAULT_IDLE_TIMEOUT).await;
+        conn.tick().unwrap();
+        assert!(matches!(conn.poll_state, PollState::Drain { .. }));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reload_ack_interval() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        conn.tick().unwrap();
+        assert_eq!(conn.inflight_acks.len(), 1);
+
+        // The reloaded interval applies from the next periodic ACK of the existing connection.
+        let mut config = config();
+        config.ack_interval = Duration::from_millis(100);
+        state.reload(&config);
+
+        tokio::time::advance(Duration::from_millis(10)).await;
+        conn.tick().unwrap();
+        assert_eq!(conn.inflight_acks.len(), 2);
+
+        tokio::time::advance(Duration::from_millis(50)).await;
+        conn.tick().unwrap();
+        assert_eq!(conn.inflight_acks.len(), 2);
+
+        tokio::time::advance(Duration::from_millis(50)).await;
+        conn.tick().unwrap();
+        assert_eq!(conn.inflight_acks.len(), 3);
+    }
+
+    /// Completes a handshake in which the peer proposes the given receiver and sender latency.
+    /// Returns the agreed latency of the connection and the (receiver, sender) latency of the
+    /// HSRSP.
+    fn negotiate_latency(mode: &str, peer_recv: u16, peer_send: u16) -> (Duration, (u16, u16)) {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let mut packet = conclusion(&format!("#!::m={},r=1,s=1", mode), 0x00010403);
+        for ext in &mut packet.extensions.0 {
+            if let ExtensionContent::Handshake(ext) = &mut ext.extension_content {
+                ext.receiver_tsbpd_delay = peer_recv;
+                ext.sender_tsbpd_delay = peer_send;
+            }
+        }
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(packet).unwrap();
+        assert_eq!(conn.metrics.latency.get() as u128, conn.latency.as_millis());
+
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        let hsrsp = resp
+            .extensions
+            .0
+            .iter()
+            .find_map(|ext| match ext.extension_content {
+                ExtensionContent::Handshake(msg) if ext.extension_type == ExtensionType::HSRSP => {
+                    Some(msg)
+                }
+                _ => None,
+            })
+            .unwrap();
+
+        (
+            conn.latency,
+            (hsrsp.receiver_tsbpd_delay, hsrsp.sender_tsbpd_delay),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_latency_negotiation() {
+        // The configured latency is 120ms with a maximum of 1000ms.
+        let ms = Duration::from_millis;
+
+        // The peer proposes a higher latency.
+        assert_eq!(negotiate_latency("publish", 50, 500), (ms(500), (500, 120)));
+        assert_eq!(negotiate_latency("request", 500, 50), (ms(500), (120, 500)));
+
+        // The peer proposes a lower latency.
+        assert_eq!(negotiate_latency("publish", 50, 50), (ms(120), (120, 120)));
+        assert_eq!(negotiate_latency("request", 50, 50), (ms(120), (120, 120)));
+
+        // Proposals above the maximum are clamped.
+        assert_eq!(
+            negotiate_latency("publish", 5000, 5000),
+            (ms(1000), (1000, 1000))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejection_metrics() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        // The session was never created.
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        let rejections = &state.metrics.handshake_rejections;
+        let counter = rejections.get(HandshakeType::REJ_BADSECRET).unwrap();
+        assert_eq!(counter.get(), 1);
+        assert_eq!(rejections.iter().map(|(_, ctr)| ctr.get()).sum::<u64>(), 1);
+        assert!(rejections.get(HandshakeType::CONCLUSION).is_none());
+
+        for (code, _) in rejections.iter() {
+            assert!(code.rejection_reason().is_some());
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_keepalive() {
+        let mut config = config();
+        config.keepalive = ModeDuration::new(None, Some(Duration::from_secs(1)));
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+        conn.pop_batch();
+
+        // The requested stream is idle.
+        tokio::time::advance(Duration::from_millis(999)).await;
+        conn.tick().unwrap();
+        assert!(conn.queue.is_empty());
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        conn.tick().unwrap();
+        let mut packets = conn.pop_batch();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(
+            packets[0].header.as_control().unwrap().control_type(),
+            ControlPacketType::Keepalive
+        );
+
+        // Sending any packet resets the interval.
+        tokio::time::advance(Duration::from_millis(500)).await;
+        conn.tick().unwrap();
+        assert!(conn.queue.is_empty());
+    }
+
+    /// Feeds a data packet with the sequence number `seq` into the publishing `conn`. Returns
+    /// the sequence numbers reported in NAKs.
+    async fn receive(conn: &mut Connection<BufferSessionManager>, seq: u32) -> Vec<u32> {
+        let packet = DataPacket::builder().sequence_number(seq).build();
+        conn.handle_data(packet).unwrap();
+
+        let mut pinned = unsafe { Pin::new_unchecked(&mut *conn) };
+        let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_write_sink(cx)));
+
+        naks(conn)
+    }
+
+    /// Pops all queued packets of `conn`, returning the sequence numbers reported in NAKs.
+    fn naks(conn: &mut Connection<BufferSessionManager>) -> Vec<u32> {
+        let mut lost = Vec::new();
+        while let Some(packet) = conn.queue.pop() {
+            if let Ok(nak) = packet.downcast::<Nak>() {
+                lost.extend(nak.lost_packet_sequence_numbers.iter());
+            }
+        }
+
+        lost
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_nak_delay() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+        conn.rtt.rtt = 40_000;
+
+        // The reordered packet arrives before the NAK delay.
+        assert!(receive(&mut conn, 0).await.is_empty());
+        assert!(receive(&mut conn, 2).await.is_empty());
+        assert!(receive(&mut conn, 1).await.is_empty());
+        conn.tick().unwrap();
+        assert!(naks(&mut conn).is_empty());
+        tokio::time::advance(Duration::from_millis(9)).await;
+        conn.tick().unwrap();
+        assert!(naks(&mut conn).is_empty());
+
+        // The packet is still missing after the NAK delay of a quarter RTT.
+        assert!(receive(&mut conn, 4).await.is_empty());
+        conn.tick().unwrap();
+        assert!(naks(&mut conn).is_empty());
+        tokio::time::advance(Duration::from_millis(9)).await;
+        conn.tick().unwrap();
+        assert!(naks(&mut conn).is_empty());
+        tokio::time::advance(Duration::from_millis(1)).await;
+        conn.tick().unwrap();
+        assert_eq!(naks(&mut conn), [3]);
+
+        // The loss is only reported once.
+        conn.tick().unwrap();
+        assert!(naks(&mut conn).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_nak_adaptive_latency() {
+        let mut config = config();
+        config.nak_delay = 0.0;
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        // The RTT exceeds the agreed latency, but not the adapted latency.
+        conn.rtt.rtt = 200_000;
+        let mut adaptive =
+            AdaptiveLatency::new(conn.latency, Duration::from_secs(1), Instant::now());
+        adaptive.current = Duration::from_millis(800);
+        conn.adaptive_latency = Some(adaptive);
+
+        assert!(receive(&mut conn, 0).await.is_empty());
+        assert_eq!(receive(&mut conn, 2).await, [1]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_payload() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        // The peer proposes a smaller MTU.
+        let (mut conn, _handle) = connection(&state, &socket);
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+        packet.maximum_transmission_unit_size = 1400;
+        conn.handle_handshake(packet).unwrap();
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.maximum_transmission_unit_size, 1400);
+        while conn.queue.pop().is_some() {}
+
+        let packet = DataPacket::builder()
+            .sequence_number(0u32)
+            .body(vec![0; 1357])
+            .build();
+        conn.handle_data(packet).unwrap();
+        assert_eq!(conn.metrics.data_packets_recv.original.get(), 0);
+        assert_eq!(conn.metrics.data_packets_recv.dropped.get(), 1);
+        assert_eq!(conn.metrics.oversized_packets.get(), 1);
+
+        let packet = DataPacket::builder()
+            .sequence_number(1u32)
+            .body(vec![0; 1356])
+            .build();
+        conn.handle_data(packet).unwrap();
+        assert_eq!(conn.metrics.data_packets_recv.original.get(), 1);
+        assert_eq!(conn.metrics.oversized_packets.get(), 1);
+
+        // The dropped packet is not reported as lost.
+        let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+        let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_write_sink(cx)));
+        assert!(naks(&mut conn).is_empty());
+        assert!(conn.loss_list.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_congestion_threshold() {
+        let mut config = config();
+        config.buffer = 8;
+        config.congestion_threshold = Some(0.5);
+        let state = State::new(BufferSessionManager::new(Default::default()), config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        // The segments are held back for the latency, filling up the buffer.
+        let mut warnings = Vec::new();
+        for seq in 0..8u32 {
+            let packet = DataPacket::builder().sequence_number(seq).build();
+            conn.handle_data(packet).unwrap();
+
+            // Finish feeding the segment without transmitting the queued packets.
+            let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+            let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_write_sink(cx)));
+            assert!(matches!(conn.poll_state, PollState::Read));
+
+            while let Some(mut packet) = conn.queue.pop() {
+                if packet.header.packet_type() == PacketType::Control
+                    && packet.header.as_control_unchecked().control_type()
+                        == ControlPacketType::CongestionWarning
+                {
+                    warnings.push(seq);
+                }
+            }
+        }
+
+        // Only a single warning is sent once 4 of 8 segments are buffered.
+        assert_eq!(warnings, [4]);
+    }
+
+    /// What the sinks and streams of a [`TestManager`] do.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+    enum Behavior {
+        /// Segments pass through the wrapped [`BufferSessionManager`].
+        Buffer,
+        /// Requested streams end immediately.
+        EmptyStreams,
+        /// Published sinks are already closed.
+        ClosedSinks,
+        /// Published sinks never accept a segment.
+        WedgedSinks,
+    }
+
+    /// A [`SessionManager`] wrapping a [`BufferSessionManager`] that records the reported peer
+    /// errors and can defer authorizations or replace the sinks and streams.
+    #[derive(Debug)]
+    struct TestManager {
+        manager: BufferSessionManager,
+        behavior: Behavior,
+        /// Whether authorizations wait for the test to complete them through `pending`.
+        deferred_auth: bool,
+        pending: std::sync::Mutex<Vec<oneshot::Sender<Result<(), session::Error>>>>,
+        errors: std::sync::Mutex<Vec<(ResourceId, u32)>>,
+    }
+
+    impl TestManager {
+        fn new(behavior: Behavior) -> Self {
+            Self {
+                manager: BufferSessionManager::new(Default::default()),
+                behavior,
+                deferred_auth: false,
+                pending: Default::default(),
+                errors: Default::default(),
+            }
+        }
+
+        /// Registers a session for `resource`.
+        fn session(&self, resource: u64, session: u64) {
+            self.manager.registry.insert(SessionKey {
+                resource_id: ResourceId(resource),
+                session_id: SessionId(session),
+                expires: Instant::now() + Duration::from_secs(60),
+            });
+        }
+    }
+
+    impl SessionManager for TestManager {
+        type Sink = TestSink;
+        type Stream = TestStream;
+
+        fn authorize(
+            &self,
+            host: Option<&str>,
+            resource_id: Option<ResourceId>,
+            session_id: Option<SessionId>,
+            mode: Mode,
+        ) -> BoxFuture<'static, Result<(), session::Error>> {
+            if !self.deferred_auth {
+                return self.manager.authorize(host, resource_id, session_id, mode);
+            }
+
+            let (tx, rx) = oneshot::channel();
+            self.pending.lock().unwrap().push(tx);
+            Box::pin(async move { rx.await.unwrap() })
+        }
+
+        fn publish(
+            &self,
+            host: Option<&str>,
+            resource_id: Option<ResourceId>,
+        ) -> Result<LiveSink<Self::Sink>, session::Error> {
+            let sink = self.manager.publish(host, resource_id)?;
+            let resource_id = sink.resource_id();
+
+            let sink = match self.behavior {
+                Behavior::ClosedSinks => TestSink::Closed,
+                Behavior::WedgedSinks => TestSink::Wedged,
+                _ => TestSink::Buffer(sink),
+            };
+            Ok(LiveSink::new(resource_id, sink))
+        }
+
+        fn request(
+            &self,
+            host: Option<&str>,
+            resource_id: Option<ResourceId>,
+        ) -> Result<LiveStream<Self::Stream>, session::Error> {
+            let stream = self.manager.request(host, resource_id)?;
+            let resource_id = stream.resource_id();
+
+            let stream = match self.behavior {
+                Behavior::EmptyStreams => TestStream::Empty,
+                _ => TestStream::Buffer(stream),
+            };
+            Ok(LiveStream::new(resource_id, stream))
+        }
+
+        fn on_peer_error(&self, resource_id: ResourceId, code: u32) {
+            self.errors.lock().unwrap().push((resource_id, code));
+        }
+    }
+
+    #[derive(Debug)]
+    enum TestSink {
+        Buffer(LiveSink<<BufferSessionManager as SessionManager>::Sink>),
+        Closed,
+        Wedged,
+    }
+
+    impl Sink<Bytes> for TestSink {
+        type Error = SessionError;
+
+        fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            match self.get_mut() {
+                Self::Buffer(sink) => Pin::new(sink).poll_ready(cx),
+                Self::Closed => Poll::Ready(Err(SessionError::Closed)),
+                Self::Wedged => Poll::Pending,
+            }
+        }
+
+        fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+            match self.get_mut() {
+                Self::Buffer(sink) => Pin::new(sink).start_send(item),
+                Self::Closed => Err(SessionError::Closed),
+                Self::Wedged => unreachable!(),
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            match self.get_mut() {
+                Self::Buffer(sink) => Pin::new(sink).poll_flush(cx),
+                Self::Closed => Poll::Ready(Err(SessionError::Closed)),
+                Self::Wedged => Poll::Pending,
+            }
+        }
+
+        fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            match self.get_mut() {
+                Self::Buffer(sink) => Pin::new(sink).poll_close(cx),
+                Self::Closed => Poll::Ready(Err(SessionError::Closed)),
+                Self::Wedged => Poll::Pending,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    enum TestStream {
+        Buffer(LiveStream<<BufferSessionManager as SessionManager>::Stream>),
+        Empty,
+    }
+
+    impl futures::Stream for TestStream {
+        type Item = Bytes;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            match self.get_mut() {
+                Self::Buffer(stream) => Pin::new(stream).poll_next(cx),
+                Self::Empty => Poll::Ready(None),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_error() {
+        let manager = TestManager::new(Behavior::Buffer);
+        manager.session(1, 1);
+        let state = State::new(manager, config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let id = ConnectionId {
+            addr,
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+        let (mut conn, _handle) =
+            unsafe { Connection::new(id, &state, &socket, 0, SYN_COOKIE, addr.ip()) };
+
+        // Errors before the handshake completed are not associated with a resource.
+        conn.handle_packet(peer_error(1)).unwrap();
+        assert!(state.session_manager.errors.lock().unwrap().is_empty());
+
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        conn.handle_packet(peer_error(4000)).unwrap();
+
+        assert_eq!(conn.metrics.peer_errors.get(), 2);
+        assert_eq!(
+            *state.session_manager.errors.lock().unwrap(),
+            [(ResourceId(1), 4000)]
+        );
+        assert!(matches!(conn.poll_state, PollState::Read));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_deferred_authorization() {
+        let manager = TestManager {
+            deferred_auth: true,
+            ..TestManager::new(Behavior::Buffer)
+        };
+        let state = State::new(manager, config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let id = ConnectionId {
+            addr,
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+
+        for (res, expected) in [
+            (Ok(()), HandshakeType::CONCLUSION),
+            (
+                Err(session::Error::InvalidCredentials),
+                HandshakeType::REJ_BADSECRET,
+            ),
+            (
+                Err(session::Error::ResourceBusy),
+                HandshakeType::REJ_RESOURCE,
+            ),
+        ] {
+            let (mut conn, _handle) =
+                unsafe { Connection::new(id, &state, &socket, 0, SYN_COOKIE, addr.ip()) };
+
+            // The handshake is only answered once the authorization completed, also for
+            // retransmissions of the CONCLUSION.
+            for _ in 0..2 {
+                conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+                    .unwrap();
+                assert!(conn.queue.pop().is_none());
+            }
+            assert_eq!(state.session_manager.pending.lock().unwrap().len(), 1);
+
+            let tx = state.session_manager.pending.lock().unwrap().pop().unwrap();
+            tx.send(res).unwrap();
+
+            let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+            let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_read(cx)));
+
+            let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+            assert_eq!(resp.handshake_type, expected);
+            assert_eq!(
+                conn.mode.is_publish(),
+                expected == HandshakeType::CONCLUSION
+            );
+        }
+    }
+
+    /// Creates a PEERERROR packet with the error `code`.
+    fn peer_error(code: u32) -> Packet {
+        let mut packet = PeerError::default();
+        packet.header.seg1 = Bits(U32(code));
+        packet.upcast()
+    }
+
+    /// Polls `conn` once and returns the sequence numbers of all data packets it queued.
+    async fn poll_data(conn: &mut Connection<BufferSessionManager>) -> Vec<u32> {
+        let mut pinned = unsafe { Pin::new_unchecked(&mut *conn) };
+        let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_read(cx)));
+
+        let mut sent = Vec::new();
+        while let Some(mut packet) = conn.queue.pop() {
+            if packet.header.packet_type() == PacketType::Data {
+                sent.push(packet.header.as_data_unchecked().packet_sequence_number());
+            }
+        }
+        sent
     }
 
     #[test]
-    fn test_loss_list() {
+    fn test_rate_estimator_rates() {
+        let now = Instant::now();
+        let mut rate = RateEstimator::new(now);
+
+        for seq in 0..100 {
+            rate.push(
+                seq,
+                1000,
+                false,
+                now + Duration::from_millis(seq as u64 * 5),
+            );
+        }
+
+        // 100 packets in 500ms.
+        assert_eq!(rate.rates(now + Duration::from_millis(500)), (200, 200_000));
+
+        // The next interval starts empty.
+        assert_eq!(rate.rates(now + Duration::from_millis(1000)), (0, 0));
+        assert_eq!(rate.rates(now + Duration::from_millis(1000)), (0, 0));
+    }
+
+    #[test]
+    fn test_rate_estimator_link_capacity() {
         let now = Instant::now();
+        let mut rate = RateEstimator::new(now);
+        assert_eq!(rate.link_capacity(), 0);
+
+        // Packet pairs arriving 100us apart, sent once per 10ms.
+        let mut ts = now;
+        for seq in 0..160 {
+            if seq % 16 == 1 {
+                ts += Duration::from_micros(100);
+            } else {
+                ts += Duration::from_millis(10);
+            }
+
+            rate.push(seq, 1000, false, ts);
+        }
+        assert_eq!(rate.link_capacity(), 10_000);
+
+        // A single delayed pair does not affect the median.
+        rate.push(160, 1000, false, ts);
+        rate.push(161, 1000, false, ts + Duration::from_millis(50));
+        assert_eq!(rate.link_capacity(), 10_000);
+
+        // Retransmissions are not pairs.
+        let mut rate = RateEstimator::new(now);
+        rate.push(0, 1000, false, now);
+        rate.push(1, 1000, true, now + Duration::from_micros(100));
+        assert_eq!(rate.link_capacity(), 0);
+    }
+
+    #[test]
+    fn test_rtt() {
+        let mut rtt = Rtt::new();
+        assert_eq!(rtt.rtt, 100_000);
+        assert_eq!(rtt.rtt_variance, 50_000);
+
+        rtt.update(100_000);
+        assert_eq!(rtt.rtt, 100_000);
+        assert_eq!(rtt.rtt_variance, 37_500);
+
+        let mut rtt = Rtt::new();
+        rtt.update(0);
+        assert_eq!(rtt.rtt, 87_500);
+        assert_eq!(rtt.rtt_variance, 62_500);
+        assert_eq!(rtt.samples, 1);
+    }
+
+    #[test]
+    fn test_ack_kind() {
+        let mut rtt = Rtt::new();
+        assert_eq!(ack_kind(rtt, 0), AckKind::Small);
+        assert_eq!(ack_kind(rtt, 1000), AckKind::Small);
+
+        rtt.update(20_000);
+        assert_eq!(ack_kind(rtt, 0), AckKind::Small);
+        assert_eq!(ack_kind(rtt, 1000), AckKind::Full);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ack_interval() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        while conn.queue.pop().is_some() {}
+
+        // Pops all queued ACKs, returning `true` for Light ACKs.
+        let acks = |conn: &mut Connection<BufferSessionManager>| {
+            let mut acks = Vec::new();
+            while let Some(mut packet) = conn.queue.pop() {
+                if packet.header.packet_type() == PacketType::Control
+                    && matches!(
+                        packet.header.as_control_unchecked().control_type(),
+                        ControlPacketType::Ack
+                    )
+                {
+                    acks.push(packet.body.len() == 4);
+                }
+            }
+            acks
+        };
+
+        // The first periodic ACK is due immediately, the next one after the interval.
+        conn.tick().unwrap();
+        assert_eq!(acks(&mut conn), [false]);
+        tokio::time::advance(Duration::from_millis(5)).await;
+        conn.tick().unwrap();
+        assert!(acks(&mut conn).is_empty());
+
+        // A Light ACK is sent after 64 packets.
+        for seq in 0..63 {
+            receive(&mut conn, seq).await;
+        }
+        let packet = DataPacket::builder().sequence_number(63u32).build();
+        conn.handle_data(packet).unwrap();
+        assert_eq!(acks(&mut conn), [true]);
+        let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+        let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_write_sink(cx)));
+
+        // The Light ACK doesn't delay the periodic ACK.
+        tokio::time::advance(Duration::from_millis(5)).await;
+        conn.tick().unwrap();
+        assert_eq!(acks(&mut conn), [false]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ack_lost() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        // The first periodic ACK is never acknowledged by an ACKACK.
+        conn.tick().unwrap();
+        assert_eq!(conn.inflight_acks.len(), 1);
+
+        // RTT + 4 * RTTVar = 300ms
+        tokio::time::advance(Duration::from_millis(299)).await;
+        conn.tick().unwrap();
+        assert_eq!(conn.metrics.ctrl_packets_lost.get(), 0);
+
+        tokio::time::advance(Duration::from_millis(1)).await;
+        conn.tick().unwrap();
+        assert_eq!(conn.metrics.ctrl_packets_lost.get(), 1);
+        assert_eq!(conn.metrics.ctrl_bytes_lost.get(), 44);
+
+        // Only the ACK sent after 299ms is still inflight.
+        assert_eq!(conn.inflight_acks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_stream_mode() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        for (stream_id, invalid) in [
+            ("#!::m=bidirectional,r=1,s=1", 0),
+            ("#!::m=Publish,r=1,s=1", 1),
+            ("#!::r=1,s=1", 2),
+        ] {
+            let (mut conn, _handle) = connection(&state, &socket);
+            conn.handle_handshake(conclusion(stream_id, 0x00010403))
+                .unwrap();
+            assert!(!conn.mode.is_publish());
+
+            let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+            assert_eq!(resp.handshake_type, HandshakeType::REJ_ROGUE);
+            assert_eq!(state.metrics.handshake_invalid_mode.get(), invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_version() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        let mut packet = conclusion("#!::m=publish,r=1,s=1", 0x00010403);
+        packet.version = 4;
+        conn.handle_handshake(packet).unwrap();
+        assert!(!conn.mode.is_publish());
+
+        let resp: Handshake = conn.queue.pop().unwrap().downcast().unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_VERSION);
+    }
+
+    #[test]
+    fn test_loss_list() {
+        let now = tokio::time::Instant::now();
 
         let mut list = LossList::new();
-        list.push_in(1.into(), now);
-        list.push_in(2.into(), now + Duration::new(1, 0));
-        list.push_in(3.into(), now + Duration::new(2, 0));
+        for (seq, secs) in [(1, 0), (2, 1), (3, 2)] {
+            let ts = now + Duration::new(secs, 0);
+            list.push_in(seq.into(), ts);
+            list.take_due_in(Duration::ZERO, ts);
+        }
         assert_eq!(list.len(), 3);
 
+        // RTT + 4 * RTTVar = 300ms
         let rtt = Rtt::new();
         assert_eq!(list.clear_in(rtt, now), 0);
         assert_eq!(list.len(), 3);
-        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(500)), 1);
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(299)), 0);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(300)), 1);
         assert_eq!(list.len(), 2);
         assert_eq!(list.clear_in(rtt, now + Duration::from_secs(5)), 2);
         assert_eq!(list.len(), 0);
     }
+
+    #[test]
+    fn test_loss_list_reported() {
+        let now = tokio::time::Instant::now();
+        let delay = Duration::from_millis(100);
+        let rtt = Rtt::new();
+
+        let mut list = LossList::new();
+        list.push_in(1.into(), now);
+        list.push_in(2.into(), now + delay);
+
+        // The timeout of a reported entry starts with the report, not when the loss was
+        // detected.
+        let reported = now + delay;
+        assert_eq!(list.take_due_in(delay, reported), [1..=1]);
+        assert_eq!(list.clear_in(rtt, reported + Duration::from_millis(299)), 0);
+        assert_eq!(list.len(), 2);
+
+        // Entries that were never reported expire as well.
+        assert_eq!(list.clear_in(rtt, reported + Duration::from_millis(300)), 2);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn test_loss_list_rtt_variance() {
+        let now = tokio::time::Instant::now();
+
+        let mut list = LossList::new();
+        list.push_in(1.into(), now);
+        list.take_due_in(Duration::ZERO, now);
+
+        // Late by more than 2 * RTT on a jittery link, but still within RTT + 4 * RTTVar.
+        let rtt = Rtt {
+            rtt: 20_000,
+            rtt_variance: 15_000,
+            samples: 1,
+        };
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(50)), 0);
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(80)), 1);
+
+        // Without variance the timeout is the RTT.
+        list.push_in(2.into(), now);
+        list.take_due_in(Duration::ZERO, now);
+        let rtt = Rtt {
+            rtt: 20_000,
+            rtt_variance: 0,
+            samples: 1,
+        };
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(19)), 0);
+        assert_eq!(list.clear_in(rtt, now + Duration::from_millis(20)), 1);
+    }
+
+    #[test]
+    fn test_loss_list_take_due() {
+        let now = tokio::time::Instant::now();
+        let delay = Duration::from_millis(10);
+
+        let mut list = LossList::new();
+        for seq in [1, 2, 3, 5] {
+            list.push_in(seq.into(), now);
+        }
+        list.push_in(6.into(), now + delay);
+
+        assert!(list.take_due_in(delay, now).is_empty());
+        assert_eq!(list.take_due_in(delay, now + delay), [1..=3, 5..=5]);
+
+        // Already reported sequence numbers are not returned again.
+        list.remove(2.into());
+        assert!(list.take_due_in(delay, now + delay).is_empty());
+        assert_eq!(list.take_due_in(delay, now + delay * 2), [6..=6]);
+    }
+
+    #[test]
+    fn test_loss_list_old_entry() {
+        let now = tokio::time::Instant::now();
+
+        let mut list = LossList::new();
+        list.push_in(1.into(), now);
+        list.take_due_in(Duration::ZERO, now);
+
+        // More than `u32::MAX` micros (~71 minutes) later. The truncated elapsed time would
+        // be below the RTT.
+        let later = now + Duration::from_micros(u64::from(u32::MAX) + 1);
+        assert_eq!(list.clear_in(Rtt::new(), later), 1);
+        assert!(list.is_empty());
+    }
+
+    /// Returns the number of closed connections for each [`CloseReason`].
+    fn closed<S>(state: &State<S>) -> [u64; 5]
+    where
+        S: SessionManager,
+    {
+        let closed = &state.metrics.connections_closed;
+        [
+            CloseReason::IdleTimeout,
+            CloseReason::PeerShutdown,
+            CloseReason::EndOfStream,
+            CloseReason::ServerShutdown,
+            CloseReason::Error,
+        ]
+        .map(|reason| closed.get(reason).get())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_close_reason() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+        session(&state, 1, 2);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        tokio::time::advance(Config::DEFAULT_IDLE_TIMEOUT).await;
+        conn.tick().unwrap();
+        drop(conn);
+        assert_eq!(closed(&state), [1, 0, 0, 0, 0]);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        conn.handle_shutdown(Shutdown::builder().build()).unwrap();
+        drop(conn);
+        assert_eq!(closed(&state), [1, 1, 0, 0, 0]);
+
+        // The server drops the handle of the connection.
+        let (mut conn, handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=2", 0x00010403))
+            .unwrap();
+        drop(handle);
+        tokio::time::timeout(Duration::from_secs(1), &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        drop(conn);
+        assert_eq!(closed(&state), [1, 1, 0, 1, 0]);
+
+        // A connection that is dropped without being closed.
+        let (conn, _handle) = connection(&state, &socket);
+        drop(conn);
+        assert_eq!(closed(&state), [1, 1, 0, 1, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_access_log() {
+        let path = std::env::temp_dir().join(format!("stsync-access-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let access_log = AccessLog::open(&AccessLogTarget::File(path.clone())).unwrap();
+        let state = State::with_access_log(
+            BufferSessionManager::new(Default::default()),
+            config(),
+            Some(access_log),
+        );
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+        conn.metrics.data_bytes_recv.original.add(1316);
+
+        // A packet that was reported long enough ago is lost on the next tick.
+        let reported = tokio::time::Instant::now() - Duration::from_secs(1);
+        conn.loss_list.push_in(1.into(), reported);
+        conn.loss_list.take_due_in(Duration::ZERO, reported);
+        conn.tick().unwrap();
+        assert!(conn.loss_list.is_empty());
+
+        conn.handle_shutdown(Shutdown::builder().build()).unwrap();
+        let id = conn.id;
+        drop(conn);
+        // Wait for the writer thread to finish.
+        drop(state);
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+
+        let line = &lines[0];
+        assert_eq!(line["connection_id"], id.to_string());
+        assert_eq!(line["resource_id"], "1");
+        assert_eq!(line["mode"], "publish");
+        assert_eq!(line["peer_addr"], id.addr.to_string());
+        assert_eq!(line["bytes_recv"], 1316);
+        assert_eq!(line["packets_lost"], 1);
+        assert_eq!(line["close_reason"], "PeerShutdown");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        // A write that never completes.
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.poll_state = PollState::Write(Box::pin(futures::future::pending()));
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            Pin::new(&mut conn).poll(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(conn.close_reason, Some(CloseReason::Watchdog));
+
+        drop(conn);
+        assert_eq!(state.metrics.connections_closed.watchdog.get(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog_rearm() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.poll_state = PollState::Write(Box::pin(async {
+            tokio::time::sleep(Duration::from_secs(9)).await;
+            Ok(())
+        }));
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        assert!(matches!(conn.poll_state, PollState::Read));
+
+        // The next stuck state gets the full timeout.
+        conn.poll_state = PollState::Write(Box::pin(futures::future::pending()));
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        assert_eq!(conn.close_reason, None);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_ready());
+        assert_eq!(conn.close_reason, Some(CloseReason::Watchdog));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog_backpressure() {
+        let manager = BufferSessionManager::new(session::buffer::Config {
+            channel_capacity: 1,
+            backpressure: true,
+            ..Default::default()
+        });
+        let mut config = config();
+        config.buffer = 1;
+        let state = State::new(manager, config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        session(&state, 1, 1);
+
+        let (mut conn, _handle) = connection(&state, &socket);
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        // A requesting stream that never reads.
+        let _stream = state
+            .session_manager
+            .request(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for seq in 0..3u32 {
+            conn.handle_data(DataPacket::builder().sequence_number(seq).build())
+                .unwrap();
+            tokio::time::advance(Duration::from_secs(1)).await;
+            let _ = Pin::new(&mut conn).poll_write_sink(&mut cx);
+        }
+        assert!(matches!(conn.poll_state, PollState::WriteSink(_)));
+
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        assert_eq!(conn.close_reason, None);
+    }
+
+    #[tokio::test]
+    async fn test_close_end_of_stream() {
+        let manager = TestManager::new(Behavior::EmptyStreams);
+        manager.session(1, 1);
+        let state = State::new(manager, config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let id = ConnectionId {
+            addr: "127.0.0.1:9000".parse().unwrap(),
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+        let (mut conn, _handle) =
+            unsafe { Connection::new(id, &state, &socket, 0, SYN_COOKIE, id.addr.ip()) };
+        conn.handle_handshake(conclusion("#!::m=request,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        tokio::time::timeout(Duration::from_secs(1), &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        drop(conn);
+        assert_eq!(closed(&state), [0, 0, 1, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_close_sink_error() {
+        let manager = TestManager::new(Behavior::ClosedSinks);
+        manager.session(1, 1);
+        let state = State::new(manager, config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let id = ConnectionId {
+            addr: "127.0.0.1:9000".parse().unwrap(),
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+        let (mut conn, _handle) =
+            unsafe { Connection::new(id, &state, &socket, 0, SYN_COOKIE, id.addr.ip()) };
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        // The first segment is only queued, the second fails to write the first to the stream.
+        for seq in 0..2u32 {
+            let packet = DataPacket::builder().sequence_number(seq).build();
+            conn.handle_data(packet).unwrap();
+
+            let mut pinned = unsafe { Pin::new_unchecked(&mut conn) };
+            let _ = futures::poll!(poll_fn(|cx| pinned.as_mut().poll_write_sink(cx)));
+        }
+        assert_eq!(conn.close_reason, Some(CloseReason::Error));
+
+        tokio::time::timeout(Duration::from_secs(1), &mut conn)
+            .await
+            .unwrap()
+            .unwrap();
+        drop(conn);
+        assert_eq!(closed(&state), [0, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog_wedged_sink() {
+        let mut config = config();
+        config.buffer = 1;
+        let manager = TestManager::new(Behavior::WedgedSinks);
+        manager.session(1, 1);
+        let state = State::new(manager, config);
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+
+        let id = ConnectionId {
+            addr: "127.0.0.1:9000".parse().unwrap(),
+            server_socket_id: 1.into(),
+            client_socket_id: 1.into(),
+        };
+        let (mut conn, _handle) =
+            unsafe { Connection::new(id, &state, &socket, 0, SYN_COOKIE, id.addr.ip()) };
+        conn.handle_handshake(conclusion("#!::m=publish,r=1,s=1", 0x00010403))
+            .unwrap();
+
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        for seq in 0..3u32 {
+            if !matches!(conn.poll_state, PollState::Read) {
+                break;
+            }
+
+            conn.handle_data(DataPacket::builder().sequence_number(seq).build())
+                .unwrap();
+            tokio::time::advance(Duration::from_secs(1)).await;
+            let _ = Pin::new(&mut conn).poll_write_sink(&mut cx);
+        }
+        assert!(matches!(conn.poll_state, PollState::WriteSink(_)));
+
+        // Without backpressure a sink that never accepts the segment is stuck.
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        tokio::time::advance(Duration::from_secs(9)).await;
+        assert!(Pin::new(&mut conn).poll(&mut cx).is_pending());
+        assert_eq!(conn.close_reason, None);
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(matches!(
+            Pin::new(&mut conn).poll(&mut cx),
+            Poll::Ready(Ok(()))
+        ));
+        assert_eq!(conn.close_reason, Some(CloseReason::Watchdog));
+    }
 }
@@ -0,0 +1,247 @@
+//! Timestamp-bound SYN cookies
+//!
+//! The SYN cookie in the INDUCTION response binds the CONCLUSION to the address of the peer and
+//! the time of the INDUCTION. Like TCP SYN cookies, the upper 8 bits of a cookie contain the time
+//! of issue in seconds (modulo 256) and the lower 24 bits a truncated HMAC-SHA256 over the peer
+//! address and the full time of issue.
+//!
+//! The HMAC key is rotated every [`SynCookies::ROTATION`] seconds, cookies issued with the
+//! previous key stay valid until the next rotation.
+//!
+//! The time is measured with a monotonic clock from the creation of the [`SynCookies`], so
+//! adjusting the system clock neither expires cookies early nor extends their lifetime.
+use std::fmt::{self, Debug, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use parking_lot::{Mutex, MutexGuard};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use tokio::time::Instant;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Issues and validates the SYN cookies of handshakes.
+pub struct SynCookies {
+    lifetime: Option<Duration>,
+    keys: Mutex<Keys>,
+    /// The start of the time of issue of all cookies.
+    epoch: Instant,
+}
+
+impl SynCookies {
+    /// The maximum lifetime of a cookie. The time of issue in the cookie wraps after that.
+    pub const MAX_LIFETIME: Duration = Duration::from_secs(255);
+
+    /// The interval in seconds in which the HMAC key is rotated.
+    pub const ROTATION: u64 = 256;
+
+    /// Creates a new `SynCookies` with random keys. Cookies older than `lifetime` are expired,
+    /// `None` only expires cookies once their key was rotated out.
+    pub fn new(lifetime: Option<Duration>) -> Self {
+        Self {
+            lifetime,
+            keys: Mutex::new(Keys {
+                current: Keys::random(),
+                previous: Keys::random(),
+                rotated: 0,
+            }),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Returns a new cookie for the peer at `addr`.
+    #[inline]
+    pub fn generate(&self, addr: SocketAddr) -> u32 {
+        self.generate_at(addr, self.now())
+    }
+
+    /// Returns a new cookie for the peer at `addr` issued at `now` seconds since the epoch.
+    pub fn generate_at(&self, addr: SocketAddr, now: u64) -> u32 {
+        let keys = self.keys(now);
+        let mac = hmac(&keys.current, addr, now).finalize().into_bytes();
+
+        u32::from_be_bytes([now as u8, mac[0], mac[1], mac[2]])
+    }
+
+    /// Returns `true` if `cookie` was issued to the peer at `addr` within the lifetime of the
+    /// current or previous key. This doesn't check whether the cookie is expired.
+    #[inline]
+    pub fn verify(&self, cookie: u32, addr: SocketAddr) -> bool {
+        self.verify_at(cookie, addr, self.now())
+    }
+
+    fn verify_at(&self, cookie: u32, addr: SocketAddr, now: u64) -> bool {
+        let time = now.wrapping_sub(u64::from(age(cookie, now)));
+        let keys = {
+            let keys = self.keys(now);
+            [keys.current, keys.previous]
+        };
+
+        let mac = &cookie.to_be_bytes()[1..];
+        keys.iter()
+            .any(|key| hmac(key, addr, time).verify_truncated_left(mac).is_ok())
+    }
+
+    /// Returns `true` if `cookie` was issued more than the lifetime ago.
+    #[inline]
+    pub fn is_expired(&self, cookie: u32) -> bool {
+        self.is_expired_at(cookie, self.now())
+    }
+
+    fn is_expired_at(&self, cookie: u32, now: u64) -> bool {
+        self.lifetime
+            .is_some_and(|lifetime| u64::from(age(cookie, now)) > lifetime.as_secs())
+    }
+
+    /// Returns the current time in seconds since the epoch.
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_secs()
+    }
+
+    /// Returns the keys at `now`, rotating them if necessary.
+    fn keys(&self, now: u64) -> MutexGuard<'_, Keys> {
+        let mut keys = self.keys.lock();
+
+        let elapsed = now.saturating_sub(keys.rotated);
+        if elapsed >= Self::ROTATION {
+            // Cookies of the current key are at most `ROTATION` seconds old if it is rotated
+            // again within the next interval, otherwise they expired already.
+            keys.previous = if elapsed < Self::ROTATION * 2 {
+                keys.current
+            } else {
+                Keys::random()
+            };
+            keys.current = Keys::random();
+            keys.rotated = now;
+        }
+
+        keys
+    }
+}
+
+impl Debug for SynCookies {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SynCookies")
+            .field("lifetime", &self.lifetime)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Keys {
+    current: [u8; 32],
+    previous: [u8; 32],
+    /// The time in seconds since the epoch at which `current` was created.
+    rotated: u64,
+}
+
+impl Keys {
+    fn random() -> [u8; 32] {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+        key
+    }
+}
+
+/// Returns the HMAC over `addr` and `time` using `key`.
+fn hmac(key: &[u8; 32], addr: SocketAddr, time: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).unwrap();
+
+    match addr.ip() {
+        IpAddr::V4(ip) => mac.update(&ip.octets()),
+        IpAddr::V6(ip) => mac.update(&ip.octets()),
+    }
+    mac.update(&addr.port().to_be_bytes());
+    mac.update(&time.to_be_bytes());
+
+    mac
+}
+
+/// Returns the age of `cookie` in seconds at `now`.
+fn age(cookie: u32, now: u64) -> u8 {
+    (now as u8).wrapping_sub((cookie >> 24) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use super::SynCookies;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:9000".parse().unwrap()
+    }
+
+    fn cookies() -> SynCookies {
+        let cookies = SynCookies::new(Some(Duration::from_secs(60)));
+        cookies.keys.lock().rotated = NOW;
+        cookies
+    }
+
+    #[test]
+    fn test_cookie_valid() {
+        let cookies = cookies();
+        let cookie = cookies.generate_at(addr(), NOW);
+
+        assert_eq!(u64::from(cookie >> 24), NOW & 0xFF);
+        for now in [NOW, NOW + 1, NOW + 60] {
+            assert!(cookies.verify_at(cookie, addr(), now));
+            assert!(!cookies.is_expired_at(cookie, now));
+        }
+    }
+
+    #[test]
+    fn test_cookie_expired() {
+        let cookies = cookies();
+        let cookie = cookies.generate_at(addr(), NOW);
+
+        for now in [NOW + 61, NOW + 255] {
+            assert!(cookies.verify_at(cookie, addr(), now));
+            assert!(cookies.is_expired_at(cookie, now));
+        }
+
+        // Without a lifetime cookies only expire with their key.
+        let cookies = SynCookies::new(None);
+        let cookie = cookies.generate_at(addr(), NOW);
+        assert!(!cookies.is_expired_at(cookie, NOW + 255));
+    }
+
+    #[test]
+    fn test_cookie_forged() {
+        let cookies = cookies();
+        let cookie = cookies.generate_at(addr(), NOW);
+
+        // Cookies are bound to the peer address.
+        assert!(!cookies.verify_at(cookie, "127.0.0.1:9001".parse().unwrap(), NOW));
+        assert!(!cookies.verify_at(cookie, "127.0.0.2:9000".parse().unwrap(), NOW));
+
+        // Changing the time of issue invalidates the MAC.
+        assert!(!cookies.verify_at(cookie.wrapping_sub(1 << 24), addr(), NOW));
+
+        for bit in 0..24 {
+            assert!(!cookies.verify_at(cookie ^ (1 << bit), addr(), NOW));
+        }
+
+        // Cookies of a different key are rejected.
+        let other = SynCookies::new(None);
+        assert!(!cookies.verify_at(other.generate_at(addr(), NOW), addr(), NOW));
+    }
+
+    #[test]
+    fn test_cookie_rotation() {
+        let cookies = cookies();
+        let cookie = cookies.generate_at(addr(), NOW + 100);
+
+        // The key is rotated, but cookies of the previous key are still accepted.
+        let now = NOW + 300;
+        let new = cookies.generate_at(addr(), now);
+        assert_eq!(cookies.keys.lock().rotated, now);
+        assert!(cookies.verify_at(cookie, addr(), now));
+        assert!(cookies.verify_at(new, addr(), now));
+    }
+}
@@ -0,0 +1,352 @@
+//! SRT payload encryption
+//!
+//! The payload of data packets is encrypted using AES-CTR with the Stream Encrypting Key (SEK).
+//! Two SEKs (even and odd) may be active at the same time to allow key refreshes. The key used
+//! for a packet is indicated by the KK field of the data packet header.
+//!
+//! The SEKs are exchanged in the KMREQ handshake extension, wrapped with the Key Encrypting Key
+//! (KEK) derived from a passphrase shared by both peers.
+//!
+//! See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#section-6
+use aes::cipher::{KeyIvInit, StreamCipher};
+use aes::{Aes128, Aes192, Aes256};
+use aes_kw::{KekAes128, KekAes192, KekAes256};
+use bytes::{Bytes, BytesMut};
+use sha1::Sha1;
+use thiserror::Error;
+
+use super::{EncryptionFlag, KeyMaterialExtension};
+
+type Aes128Ctr = ctr::Ctr128BE<Aes128>;
+type Aes192Ctr = ctr::Ctr128BE<Aes192>;
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+#[derive(Copy, Clone, Debug, Error)]
+pub enum DecryptError {
+    #[error("no key for {0:?}")]
+    NoKey(EncryptionFlag),
+}
+
+/// An error installing the SEKs of a connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Error)]
+pub enum KeyError {
+    #[error("invalid key slot {0:?}")]
+    InvalidSlot(EncryptionFlag),
+    #[error("unsupported key material")]
+    Unsupported,
+    /// The keys could not be unwrapped, the peer uses a different passphrase.
+    #[error("bad secret")]
+    BadSecret,
+}
+
+/// The number of PBKDF2 iterations used to derive the KEK.
+const KEK_ITERATIONS: u32 = 2048;
+
+/// A Stream Encrypting Key.
+#[derive(Clone)]
+pub struct Sek {
+    key: Key,
+    salt: [u8; 16],
+}
+
+#[derive(Clone)]
+enum Key {
+    Aes128([u8; 16]),
+    Aes192([u8; 24]),
+    Aes256([u8; 32]),
+}
+
+impl Sek {
+    /// Creates a new `Sek` from the raw `key` and `salt` as transmitted in the key material.
+    /// Returns `None` if `key` is not a valid AES key length.
+    pub fn new(key: &[u8], salt: [u8; 16]) -> Option<Self> {
+        let key = match key.len() {
+            16 => Key::Aes128(key.try_into().unwrap()),
+            24 => Key::Aes192(key.try_into().unwrap()),
+            32 => Key::Aes256(key.try_into().unwrap()),
+            _ => return None,
+        };
+
+        Some(Self { key, salt })
+    }
+
+    /// Applies the keystream for the packet with the sequence number `seq` to `buf`. Since AES-CTR
+    /// is symmetric this both encrypts and decrypts.
+    pub fn apply(&self, seq: u32, buf: &mut [u8]) {
+        // The IV is the 112 most significant bits of the salt XORed with the packet index
+        // shifted left by 16 bits. The remaining 16 bits are the block counter.
+        let mut iv = [0; 16];
+        iv[..14].copy_from_slice(&self.salt[..14]);
+        for (b, s) in iv[10..14].iter_mut().zip(seq.to_be_bytes()) {
+            *b ^= s;
+        }
+
+        match &self.key {
+            Key::Aes128(key) => Aes128Ctr::new(key.into(), &iv.into()).apply_keystream(buf),
+            Key::Aes192(key) => Aes192Ctr::new(key.into(), &iv.into()).apply_keystream(buf),
+            Key::Aes256(key) => Aes256Ctr::new(key.into(), &iv.into()).apply_keystream(buf),
+        }
+    }
+}
+
+impl std::fmt::Debug for Sek {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the key.
+        f.debug_struct("Sek").finish_non_exhaustive()
+    }
+}
+
+/// The even and odd [`Sek`]s of a connection.
+#[derive(Clone, Default)]
+pub struct KeySlots {
+    pub even: Option<Sek>,
+    pub odd: Option<Sek>,
+}
+
+impl KeySlots {
+    /// Sets the [`Sek`] for the given `flag`. Passing `None` removes the key. Returns an error if
+    /// `flag` is not [`Even`] or [`Odd`].
+    ///
+    /// [`Even`]: EncryptionFlag::Even
+    /// [`Odd`]: EncryptionFlag::Odd
+    pub fn set(&mut self, flag: EncryptionFlag, sek: Option<Sek>) -> Result<(), KeyError> {
+        match flag {
+            EncryptionFlag::Even => self.even = sek,
+            EncryptionFlag::Odd => self.odd = sek,
+            _ => return Err(KeyError::InvalidSlot(flag)),
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts the `payload` of the packet with the sequence number `seq` using the key
+    /// indicated by `flag`. Unencrypted payloads are returned unchanged.
+    pub fn decrypt(
+        &self,
+        flag: EncryptionFlag,
+        seq: u32,
+        payload: Bytes,
+    ) -> Result<Bytes, DecryptError> {
+        let sek = match flag {
+            EncryptionFlag::None => return Ok(payload),
+            EncryptionFlag::Even => self.even.as_ref(),
+            EncryptionFlag::Odd => self.odd.as_ref(),
+            EncryptionFlag::Both => None,
+        };
+
+        let sek = sek.ok_or(DecryptError::NoKey(flag))?;
+
+        let mut buf = BytesMut::from(&payload[..]);
+        sek.apply(seq, &mut buf);
+        Ok(buf.freeze())
+    }
+}
+
+/// Unwraps the SEKs of the key material `km` with the KEK derived from `passphrase`. Returns the
+/// keys with the slot they belong to.
+pub fn unwrap_keys(
+    km: &KeyMaterialExtension,
+    passphrase: &str,
+) -> Result<Vec<(EncryptionFlag, Sek)>, KeyError> {
+    let key_length = usize::from(km.key_length);
+    if km.cipher != KeyMaterialExtension::CIPHER_CTR
+        || km.keki != 0
+        || !matches!(key_length, 16 | 24 | 32)
+    {
+        return Err(KeyError::Unsupported);
+    }
+
+    let salt: [u8; 16] = km
+        .salt
+        .as_slice()
+        .try_into()
+        .map_err(|_| KeyError::Unsupported)?;
+
+    // The KEK is derived from the passphrase and the last 64 bits of the salt.
+    let mut kek = [0; 32];
+    let kek = &mut kek[..key_length];
+    pbkdf2::pbkdf2_hmac::<Sha1>(passphrase.as_bytes(), &salt[8..], KEK_ITERATIONS, kek);
+
+    let mut keys = [0; 64];
+    let keys = &mut keys[..km.wrapped_keys.len().saturating_sub(8)];
+    let res = match key_length {
+        16 => KekAes128::new(kek.as_ref().into()).unwrap(&km.wrapped_keys, keys),
+        24 => KekAes192::new(kek.as_ref().into()).unwrap(&km.wrapped_keys, keys),
+        _ => KekAes256::new(kek.as_ref().into()).unwrap(&km.wrapped_keys, keys),
+    };
+    res.map_err(|_| KeyError::BadSecret)?;
+
+    let flags: &[EncryptionFlag] = match km.key_flags {
+        EncryptionFlag::Even => &[EncryptionFlag::Even],
+        EncryptionFlag::Odd => &[EncryptionFlag::Odd],
+        EncryptionFlag::Both => &[EncryptionFlag::Even, EncryptionFlag::Odd],
+        EncryptionFlag::None => return Err(KeyError::Unsupported),
+    };
+
+    if keys.len() != flags.len() * key_length {
+        return Err(KeyError::Unsupported);
+    }
+
+    Ok(flags
+        .iter()
+        .zip(keys.chunks_exact(key_length))
+        .map(|(flag, key)| (*flag, Sek::new(key, salt).unwrap()))
+        .collect())
+}
+
+impl std::fmt::Debug for KeySlots {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the keys.
+        f.debug_struct("KeySlots")
+            .field("even", &self.even.is_some())
+            .field("odd", &self.odd.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use aes::cipher::{BlockEncrypt, KeyInit};
+    use aes::Aes128;
+    use aes_kw::KekAes128;
+    use bytes::Bytes;
+    use sha1::Sha1;
+
+    use crate::srt::{EncryptionFlag, KeyMaterialExtension};
+
+    use super::{unwrap_keys, KeyError, KeySlots, Sek, KEK_ITERATIONS};
+
+    /// Creates the key material of a peer wrapping the 16 byte `keys` with `passphrase`.
+    pub(crate) fn key_material(
+        passphrase: &str,
+        key_flags: EncryptionFlag,
+        keys: &[u8],
+    ) -> KeyMaterialExtension {
+        let salt = [7; 16];
+
+        let mut kek = [0; 16];
+        pbkdf2::pbkdf2_hmac::<Sha1>(passphrase.as_bytes(), &salt[8..], KEK_ITERATIONS, &mut kek);
+
+        let mut wrapped_keys = vec![0; keys.len() + 8];
+        KekAes128::new(&kek.into())
+            .wrap(keys, &mut wrapped_keys)
+            .unwrap();
+
+        KeyMaterialExtension {
+            key_flags,
+            keki: 0,
+            cipher: KeyMaterialExtension::CIPHER_CTR,
+            auth: 0,
+            stream_encapsulation: KeyMaterialExtension::SE_SRT,
+            salt: salt.to_vec(),
+            key_length: 16,
+            wrapped_keys,
+        }
+    }
+
+    #[test]
+    fn test_unwrap_keys() {
+        let km = key_material(
+            "passphrase",
+            EncryptionFlag::Both,
+            &[[1; 16], [2; 16]].concat(),
+        );
+
+        let keys = unwrap_keys(&km, "passphrase").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].0, EncryptionFlag::Even);
+        assert_eq!(keys[1].0, EncryptionFlag::Odd);
+
+        // The SEKs are the unwrapped keys with the salt of the key material.
+        let mut buf = *b"hello world";
+        Sek::new(&[2; 16], [7; 16]).unwrap().apply(3, &mut buf);
+        keys[1].1.apply(3, &mut buf);
+        assert_eq!(&buf, b"hello world");
+
+        assert_eq!(
+            unwrap_keys(&km, "wrong passphrase").unwrap_err(),
+            KeyError::BadSecret
+        );
+
+        let mut km = key_material("passphrase", EncryptionFlag::Even, &[1; 16]);
+        km.cipher = 1;
+        assert_eq!(
+            unwrap_keys(&km, "passphrase").unwrap_err(),
+            KeyError::Unsupported
+        );
+    }
+
+    #[test]
+    fn test_key_slots_set() {
+        let sek = Sek::new(&[1; 16], [2; 16]).unwrap();
+        let mut keys = KeySlots::default();
+
+        keys.set(EncryptionFlag::Odd, Some(sek.clone())).unwrap();
+        assert!(keys.odd.is_some());
+        keys.set(EncryptionFlag::Odd, None).unwrap();
+        assert!(keys.odd.is_none());
+
+        for flag in [EncryptionFlag::None, EncryptionFlag::Both] {
+            assert_eq!(
+                keys.set(flag, Some(sek.clone())).unwrap_err(),
+                KeyError::InvalidSlot(flag)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sek_iv() {
+        let key = [
+            0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf,
+            0x4f, 0x3c,
+        ];
+        let sek = Sek::new(&key, [0xaa; 16]).unwrap();
+
+        // The keystream of the first block is the encrypted IV.
+        let mut iv = [0xaa; 16];
+        iv[10..14].copy_from_slice(&[0xab, 0xa8, 0xa9, 0xae]);
+        iv[14..].copy_from_slice(&[0x00, 0x00]);
+        let mut block = iv.into();
+        Aes128::new(&key.into()).encrypt_block(&mut block);
+
+        let mut keystream = [0; 16];
+        sek.apply(0x01020304, &mut keystream);
+        assert_eq!(keystream, block.as_slice());
+
+        // Round trip.
+        let mut buf = *b"hello world";
+        sek.apply(42, &mut buf);
+        assert_ne!(&buf, b"hello world");
+        sek.apply(42, &mut buf);
+        assert_eq!(&buf, b"hello world");
+    }
+
+    #[test]
+    fn test_key_slots_decrypt() {
+        let sek = Sek::new(&[1; 16], [2; 16]).unwrap();
+        let keys = KeySlots {
+            even: Some(sek.clone()),
+            odd: None,
+        };
+
+        let mut buf = *b"hello world";
+        sek.apply(7, &mut buf);
+        let payload = Bytes::copy_from_slice(&buf);
+
+        assert_eq!(
+            keys.decrypt(EncryptionFlag::Even, 7, payload.clone())
+                .unwrap(),
+            "hello world"
+        );
+        assert!(keys
+            .decrypt(EncryptionFlag::Odd, 7, payload.clone())
+            .is_err());
+        assert!(keys.decrypt(EncryptionFlag::Both, 7, payload).is_err());
+
+        let payload = Bytes::from_static(b"plain");
+        assert_eq!(
+            keys.decrypt(EncryptionFlag::None, 7, payload).unwrap(),
+            "plain"
+        );
+    }
+}
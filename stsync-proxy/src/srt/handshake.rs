@@ -4,6 +4,9 @@
 //!
 //! See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#section-4.3
 
+use std::net::IpAddr;
+
+use super::config::PeerAddressCheck;
 use super::conn::Connection;
 use super::proto::Handshake;
 use super::server::SrtStream;
@@ -54,10 +57,51 @@ where
     tracing::trace!("INDUCTION");
     debug_assert!(packet.handshake_type.is_induction());
 
-    srt_assert!(packet.version, 4);
-    srt_assert!(packet.encryption_field, EncryptionField::NONE);
-    srt_assert!(packet.extension_field, ExtensionField::INDUCTION);
-    srt_assert!(packet.syn_cookie, 0);
+    let (mismatches, reason) = induction_mismatches(&packet);
+    if mismatches != 0 {
+        // A handshake with only few mismatched fields is most likely a real client with wrong
+        // parameters. Everything else is probably not a SRT client at all.
+        if mismatches <= state.config.reject_threshold {
+            tracing::info!(
+                "Rejecting INDUCTION from {} with {} mismatched fields: {:?}",
+                stream.addr,
+                mismatches,
+                reason
+            );
+
+            return reject(&packet, reason, stream, state).await;
+        }
+
+        tracing::trace!(
+            "Dropping INDUCTION from {} with {} mismatched fields",
+            stream.addr,
+            mismatches
+        );
+
+        return Ok(());
+    }
+
+    if !peer_address_matches(&packet, state.config.bind.ip()) {
+        match state.config.peer_address_check {
+            PeerAddressCheck::Off => (),
+            PeerAddressCheck::Log => {
+                tracing::info!(
+                    "INDUCTION from {} declares server address {}",
+                    stream.addr,
+                    IpAddr::from(packet.peer_ip_address)
+                );
+            }
+            PeerAddressCheck::Reject => {
+                tracing::info!(
+                    "Rejecting INDUCTION from {} declaring server address {}",
+                    stream.addr,
+                    IpAddr::from(packet.peer_ip_address)
+                );
+
+                return reject(&packet, HandshakeType::REJ_ROGUE, stream, state).await;
+            }
+        }
+    }
 
     let client_socket_id = packet.srt_socket_id;
     let server_socket_id = packet.srt_socket_id;
@@ -65,7 +109,7 @@ where
     let client_seqnum = packet.initial_packet_sequence_number;
     let server_seqnum = client_seqnum;
 
-    let syn_cookie = state.random();
+    let syn_cookie = state.syn_cookies.generate(stream.addr);
 
     let mut resp = Handshake::default();
     resp.header.timestamp = Timestamp::default();
@@ -77,7 +121,10 @@ where
     resp.syn_cookie = syn_cookie;
     resp.srt_socket_id = server_socket_id;
     resp.initial_packet_sequence_number = server_seqnum;
-    resp.peer_ip_address = stream.addr.ip().into();
+    // IPv4 peers on a dual-stack socket appear as IPv4-mapped IPv6 addresses, but expect their
+    // own IPv4 address to be echoed back.
+    let peer_address = stream.addr.ip().to_canonical();
+    resp.peer_ip_address = peer_address.into();
 
     resp.maximum_transmission_unit_size = state.config.mtu;
     resp.maximum_flow_window_size = state.config.flow_window;
@@ -98,7 +145,7 @@ where
             stream.socket,
             client_seqnum,
             syn_cookie,
-            stream.addr.ip(),
+            peer_address,
         )
     };
 
@@ -116,6 +163,70 @@ where
     Ok(())
 }
 
+/// Returns the number of fields in the INDUCTION `packet` that don't match the expected values
+/// and the rejection reason to report to the peer.
+fn induction_mismatches(packet: &Handshake) -> (u32, HandshakeType) {
+    let mut mismatches = 0;
+    let mut reason = HandshakeType::REJ_ROGUE;
+
+    if packet.version != 4 {
+        mismatches += 1;
+        reason = HandshakeType::REJ_VERSION;
+    }
+
+    if packet.encryption_field != EncryptionField::NONE {
+        mismatches += 1;
+    }
+
+    if packet.extension_field != ExtensionField::INDUCTION {
+        mismatches += 1;
+    }
+
+    if packet.syn_cookie != 0 {
+        mismatches += 1;
+    }
+
+    (mismatches, reason)
+}
+
+/// Returns `true` if the peer IP address declared in `packet` matches the address `local` the
+/// server is bound to. Like the responses of the server, callers declare the address of the
+/// remote side, i.e. the server. Peers that don't declare an address, and any address if the
+/// server is bound to the unspecified address, always match.
+fn peer_address_matches(packet: &Handshake, local: IpAddr) -> bool {
+    let declared = IpAddr::from(packet.peer_ip_address).to_canonical();
+    let local = local.to_canonical();
+    declared.is_unspecified() || local.is_unspecified() || declared == local
+}
+
+/// Rejects the handshake `packet` using the given `reason`.
+async fn reject<S>(
+    packet: &Handshake,
+    reason: HandshakeType,
+    stream: SrtStream<'_>,
+    state: &State<S>,
+) -> Result<(), Error>
+where
+    S: SessionManager,
+{
+    if let Some(counter) = state.metrics.handshake_rejections.get(reason) {
+        counter.inc();
+    }
+
+    let mut resp = Handshake::default();
+    resp.header.timestamp = Timestamp::default();
+    resp.header.destination_socket_id = packet.srt_socket_id;
+
+    resp.handshake_type = reason;
+    resp.version = 5;
+    resp.extension_field = ExtensionField::NONE;
+    resp.srt_socket_id = packet.srt_socket_id;
+    resp.initial_packet_sequence_number = packet.initial_packet_sequence_number;
+    resp.peer_ip_address = stream.addr.ip().to_canonical().into();
+
+    stream.send(resp).await
+}
+
 async fn handshake_conclusion<S>(
     packet: Handshake,
     stream: SrtStream<'_>,
@@ -127,9 +238,32 @@ where
     tracing::trace!("CONCLUSION");
     debug_assert!(packet.handshake_type.is_conclusion());
 
-    srt_assert!(packet.version, 5);
     srt_assert!(packet.encryption_field, EncryptionField::NONE);
 
+    // A cookie that wasn't issued to the peer is most likely spoofed. It is dropped without a
+    // response since rejecting it would also abort the handshake of the real peer.
+    if !state.syn_cookies.verify(packet.syn_cookie, stream.addr) {
+        tracing::debug!(
+            "Invalid syn_cookie {} from peer {}",
+            packet.syn_cookie,
+            stream.addr
+        );
+
+        return Ok(());
+    }
+
+    // The legacy HSv4 handshake is not supported. The peer is rejected explicitly so it
+    // doesn't wait until the connection times out.
+    if packet.version != 5 {
+        tracing::debug!(
+            "Unsupported handshake version {} from peer {}",
+            packet.version,
+            stream.addr
+        );
+
+        return reject(&packet, HandshakeType::REJ_VERSION, stream, state).await;
+    }
+
     let conn = match state.pool.find_client_id(stream.addr, packet.srt_socket_id) {
         Some(conn) => conn,
         None => {
@@ -147,3 +281,198 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use crate::proto::Decode;
+    use crate::session::buffer::BufferSessionManager;
+    use crate::srt::config::tests::config;
+    use crate::srt::config::{Config, PeerAddressCheck};
+    use crate::srt::proto::Handshake;
+    use crate::srt::server::SrtStream;
+    use crate::srt::socket::SrtSocket;
+    use crate::srt::state::State;
+    use crate::srt::{EncryptionField, ExtensionField, HandshakeType, Packet};
+
+    use super::{handshake, induction_mismatches};
+
+    fn induction() -> Handshake {
+        Handshake {
+            version: 4,
+            handshake_type: HandshakeType::INDUCTION,
+            encryption_field: EncryptionField::NONE,
+            extension_field: ExtensionField::INDUCTION,
+            srt_socket_id: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Sends `packet` to the handshake handler and returns the response, if any.
+    async fn respond(packet: Handshake) -> Option<Handshake> {
+        respond_with(config(), packet).await
+    }
+
+    /// Sends `packet` to the handshake handler using `config` and returns the response, if any.
+    /// The peer is bound to `127.0.0.1`.
+    async fn respond_with(config: Config, packet: Handshake) -> Option<Handshake> {
+        respond_map(config, packet, |_, _, packet| packet).await
+    }
+
+    /// Like [`respond_with`], but `f` maps the packet before it is sent, e.g. to add a SYN
+    /// cookie for the address of the peer.
+    async fn respond_map<F>(config: Config, packet: Handshake, f: F) -> Option<Handshake>
+    where
+        F: FnOnce(&State<BufferSessionManager>, SocketAddr, Handshake) -> Handshake,
+    {
+        // Accepted handshakes spawn a connection that must not outlive the state and socket.
+        let state: &_ = Box::leak(Box::new(State::new(
+            BufferSessionManager::new(Default::default()),
+            config,
+        )));
+        let socket: &_ = Box::leak(Box::new(SrtSocket::new(state.config.bind).unwrap()));
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let packet = f(state, peer.local_addr().unwrap(), packet);
+        let stream = SrtStream {
+            socket,
+            addr: peer.local_addr().unwrap(),
+        };
+
+        handshake(packet, stream, state).await.unwrap();
+
+        let mut buf = [0; 1500];
+        let len = tokio::time::timeout(Duration::from_millis(100), peer.recv(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+
+        let packet = Packet::decode(&mut &buf[..len]).unwrap();
+        Some(packet.downcast().unwrap())
+    }
+
+    #[test]
+    fn test_induction_mismatches() {
+        assert_eq!(induction_mismatches(&induction()).0, 0);
+
+        let mut packet = induction();
+        packet.extension_field = ExtensionField::NONE;
+        assert_eq!(induction_mismatches(&packet), (1, HandshakeType::REJ_ROGUE));
+
+        let mut packet = induction();
+        packet.version = 5;
+        assert_eq!(
+            induction_mismatches(&packet),
+            (1, HandshakeType::REJ_VERSION)
+        );
+
+        let mut packet = induction();
+        packet.version = 0;
+        packet.extension_field = ExtensionField(0xFFFF);
+        packet.syn_cookie = 0xDEADBEEF;
+        assert_eq!(induction_mismatches(&packet).0, 3);
+    }
+
+    #[tokio::test]
+    async fn test_induction_reject() {
+        let mut packet = induction();
+        packet.extension_field = ExtensionField::NONE;
+
+        let resp = respond(packet).await.unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_ROGUE);
+        assert_eq!(resp.header.destination_socket_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_induction_drop() {
+        let mut packet = induction();
+        packet.version = 0;
+        packet.extension_field = ExtensionField::NONE;
+        packet.syn_cookie = 0xDEADBEEF;
+
+        assert!(respond(packet).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conclusion_version() {
+        let conclusion = |version| Handshake {
+            version,
+            handshake_type: HandshakeType::CONCLUSION,
+            extension_field: ExtensionField::HSREQ,
+            ..induction()
+        };
+        let with_cookie = |state: &State<_>, addr, mut packet: Handshake| {
+            packet.syn_cookie = state.syn_cookies.generate(addr);
+            packet
+        };
+
+        // Legacy HSv4 peers are rejected explicitly.
+        let resp = respond_map(config(), conclusion(4), with_cookie)
+            .await
+            .unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::REJ_VERSION);
+        assert_eq!(resp.header.destination_socket_id, 1);
+
+        // Unless the cookie is invalid.
+        assert!(respond(conclusion(4)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_peer_address_check() {
+        // The server is bound to a different address than the peer at `127.0.0.1`.
+        let server = Ipv4Addr::new(127, 0, 0, 2);
+        let config = |check| Config {
+            bind: SocketAddr::new(server.into(), 0),
+            peer_address_check: check,
+            ..config()
+        };
+
+        // An INDUCTION as sent by a libsrt caller, declaring the address it connects to.
+        let libsrt = |addr: Ipv4Addr| Handshake {
+            initial_packet_sequence_number: 0x1234_5678,
+            maximum_transmission_unit_size: 1500,
+            maximum_flow_window_size: 8192,
+            peer_ip_address: IpAddr::V4(addr).into(),
+            ..induction()
+        };
+
+        // The server address and undeclared addresses are accepted.
+        for addr in [server, Ipv4Addr::UNSPECIFIED] {
+            let resp = respond_with(config(PeerAddressCheck::Reject), libsrt(addr))
+                .await
+                .unwrap();
+            assert_eq!(resp.handshake_type, HandshakeType::INDUCTION);
+            assert_eq!(
+                IpAddr::from(resp.peer_ip_address),
+                IpAddr::V4(Ipv4Addr::LOCALHOST)
+            );
+        }
+
+        // The address of the peer itself is not the server address.
+        for addr in [Ipv4Addr::LOCALHOST, Ipv4Addr::new(192, 0, 2, 1)] {
+            let resp = respond_with(config(PeerAddressCheck::Reject), libsrt(addr))
+                .await
+                .unwrap();
+            assert_eq!(resp.handshake_type, HandshakeType::REJ_ROGUE);
+        }
+
+        // Mismatches are only logged if not rejecting.
+        let packet = libsrt(Ipv4Addr::new(192, 0, 2, 1));
+        let resp = respond_with(config(PeerAddressCheck::Log), packet.clone())
+            .await
+            .unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::INDUCTION);
+
+        // A server bound to the unspecified address can't tell which address was used.
+        let config = Config {
+            bind: "0.0.0.0:0".parse().unwrap(),
+            ..config(PeerAddressCheck::Reject)
+        };
+        let resp = respond_with(config, packet).await.unwrap();
+        assert_eq!(resp.handshake_type, HandshakeType::INDUCTION);
+    }
+}
@@ -0,0 +1,107 @@
+//! Synthetic packet loss
+//!
+//! **This is only intended for testing the loss recovery (NAK and retransmission) of the
+//! server. It must not be enabled in production.**
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use ahash::AHashSet;
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{Packet, PacketType};
+
+/// The configuration of a [`LossInjector`]. A packet is dropped if any of the conditions match.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct LossConfig {
+    /// The fraction of data packets to drop randomly, from `0.0` to `1.0`.
+    pub rate: f64,
+    /// Drop every n-th data packet.
+    pub every: Option<u64>,
+    /// The sequence numbers of data packets to drop. Every sequence number is only dropped
+    /// once, retransmissions are let through.
+    pub sequence_numbers: Vec<u32>,
+}
+
+/// Drops incoming and outgoing data packets according to a [`LossConfig`]. Both directions share
+/// the same conditions, e.g. `every` counts the data packets of both directions.
+#[derive(Debug)]
+pub struct LossInjector {
+    rate: f64,
+    every: Option<u64>,
+    count: AtomicU64,
+    /// Sequence numbers that still need to be dropped.
+    sequence_numbers: Mutex<AHashSet<u32>>,
+}
+
+impl LossInjector {
+    pub fn new(config: LossConfig) -> Self {
+        Self {
+            rate: config.rate,
+            every: config.every.filter(|n| *n != 0),
+            count: AtomicU64::new(0),
+            sequence_numbers: Mutex::new(config.sequence_numbers.into_iter().collect()),
+        }
+    }
+
+    /// Returns `true` if `packet` should be dropped. Control packets are never dropped.
+    pub fn drop_packet(&self, packet: &Packet) -> bool {
+        if packet.header.packet_type() != PacketType::Data {
+            return false;
+        }
+
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(every) = self.every {
+            if count.is_multiple_of(every) {
+                return true;
+            }
+        }
+
+        let mut header = packet.header;
+        let seq = header.as_data_unchecked().packet_sequence_number();
+        if self.sequence_numbers.lock().remove(&seq) {
+            return true;
+        }
+
+        self.rate > 0.0 && rand::thread_rng().gen_bool(self.rate.min(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::srt::proto::Keepalive;
+    use crate::srt::{DataPacket, IsPacket, Packet};
+
+    use super::{LossConfig, LossInjector};
+
+    fn packet(seq: u32) -> Packet {
+        DataPacket::builder().sequence_number(seq).build().upcast()
+    }
+
+    #[test]
+    fn test_loss_injector() {
+        let loss = LossInjector::new(LossConfig {
+            every: Some(3),
+            sequence_numbers: vec![1],
+            ..Default::default()
+        });
+
+        let dropped: Vec<bool> = [0, 1, 2, 3, 4, 5, 1]
+            .into_iter()
+            .map(|seq| loss.drop_packet(&packet(seq)))
+            .collect();
+        assert_eq!(dropped, [false, true, true, false, false, true, false]);
+
+        assert!(!loss.drop_packet(&Keepalive::builder().build().upcast()));
+
+        let loss = LossInjector::new(LossConfig {
+            rate: 1.0,
+            ..Default::default()
+        });
+        assert!(loss.drop_packet(&packet(0)));
+
+        let loss = LossInjector::new(LossConfig::default());
+        assert!((0..100).all(|seq| !loss.drop_packet(&packet(seq))));
+    }
+}
@@ -1,4 +1,14 @@
+use std::hint;
+use std::sync::atomic::{self, AtomicU64, Ordering};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
 use crate::metrics::{Counter, Gauge};
+use crate::session::ResourceId;
+
+use super::conn::CloseReason;
+use super::HandshakeType;
 
 #[derive(Debug, Default)]
 pub struct ServerMetrics {
@@ -6,6 +16,14 @@ pub struct ServerMetrics {
     pub connections_publish_current: Gauge,
     pub connections_request_current: Gauge,
     pub connections_handshake_current: Gauge,
+    /// Datagrams dropped because they exceeded the receive buffer and were possibly truncated.
+    pub datagrams_truncated: Counter,
+    /// Handshakes rejected because the stream id had a missing or unknown mode.
+    pub handshake_invalid_mode: Counter,
+    /// Sessions not created over the HTTP API because their resource reached the session limit.
+    pub sessions_rejected: Counter,
+    pub connections_closed: ClosedMetrics,
+    pub handshake_rejections: RejectionMetrics,
 }
 
 impl ServerMetrics {
@@ -15,10 +33,81 @@ impl ServerMetrics {
             connections_publish_current: Gauge::new(),
             connections_request_current: Gauge::new(),
             connections_handshake_current: Gauge::new(),
+            datagrams_truncated: Counter::new(),
+            handshake_invalid_mode: Counter::new(),
+            sessions_rejected: Counter::new(),
+            connections_closed: ClosedMetrics::new(),
+            handshake_rejections: RejectionMetrics::new(),
         }
     }
 }
 
+/// The number of closed connections for each [`CloseReason`].
+#[derive(Debug, Default)]
+pub struct ClosedMetrics {
+    pub idle_timeout: Counter,
+    pub peer_shutdown: Counter,
+    pub end_of_stream: Counter,
+    pub server_shutdown: Counter,
+    pub watchdog: Counter,
+    pub operator: Counter,
+    pub error: Counter,
+}
+
+impl ClosedMetrics {
+    pub const fn new() -> Self {
+        Self {
+            idle_timeout: Counter::new(),
+            peer_shutdown: Counter::new(),
+            end_of_stream: Counter::new(),
+            server_shutdown: Counter::new(),
+            watchdog: Counter::new(),
+            operator: Counter::new(),
+            error: Counter::new(),
+        }
+    }
+
+    /// Returns the [`Counter`] for connections closed because of `reason`.
+    pub fn get(&self, reason: CloseReason) -> &Counter {
+        match reason {
+            CloseReason::IdleTimeout => &self.idle_timeout,
+            CloseReason::PeerShutdown => &self.peer_shutdown,
+            CloseReason::EndOfStream => &self.end_of_stream,
+            CloseReason::ServerShutdown => &self.server_shutdown,
+            CloseReason::Watchdog => &self.watchdog,
+            CloseReason::Operator => &self.operator,
+            CloseReason::Error => &self.error,
+        }
+    }
+}
+
+/// The number of rejected handshakes for each rejection code.
+#[derive(Debug, Default)]
+pub struct RejectionMetrics([Counter; 16]);
+
+impl RejectionMetrics {
+    pub const fn new() -> Self {
+        Self([const { Counter::new() }; 16])
+    }
+
+    /// Returns the [`Counter`] for handshakes rejected with `reason`. Returns `None` if `reason`
+    /// is not a rejection code.
+    pub fn get(&self, reason: HandshakeType) -> Option<&Counter> {
+        let index = reason
+            .to_u32()
+            .checked_sub(HandshakeType::REJ_UNKNOWN.to_u32())?;
+        self.0.get(index as usize)
+    }
+
+    /// Returns an iterator over all rejection codes and their [`Counter`]s.
+    pub fn iter(&self) -> impl Iterator<Item = (HandshakeType, &Counter)> {
+        self.0.iter().enumerate().map(|(index, counter)| {
+            let code = HandshakeType::REJ_UNKNOWN.to_u32() + index as u32;
+            (HandshakeType::from_u32(code).unwrap(), counter)
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct ConnectionMetrics {
     pub ctrl_packets_sent: Counter,
@@ -33,6 +122,42 @@ pub struct ConnectionMetrics {
     pub data_bytes_recv: StreamMetrics,
     pub rtt: Gauge,
     pub rtt_variance: Gauge,
+    /// The SRT version of the peer as advertised in the HSREQ extension.
+    pub peer_version: Gauge,
+    /// Data packets dropped because they could not be decrypted.
+    pub decrypt_failures: Counter,
+    /// Data packets dropped because their payload exceeded the negotiated MTU.
+    pub oversized_packets: Counter,
+    /// The minimum interval between two data packets in microseconds, 0 if not paced.
+    pub pacing_interval: Gauge,
+    /// PEERERROR packets received from the peer.
+    pub peer_errors: Counter,
+    /// The configured maximum send rate in bytes per second, 0 if unlimited.
+    pub max_send_rate: Gauge,
+    /// The rate of payload sent in request mode in bytes per second, measured over about a
+    /// second.
+    pub send_rate: Gauge,
+    /// The TSBPD delay agreed on in the handshake in milliseconds, 0 before the handshake
+    /// completed.
+    pub latency: Gauge,
+    /// The current latency of the receive buffer in milliseconds. Differs from `latency` only if
+    /// adaptive latency is enabled.
+    pub effective_latency: Gauge,
+    /// The [`ConnectionModeMetric`] of the connection.
+    pub mode: Gauge,
+    /// The number of received segments waiting for their delivery time in publish mode.
+    pub reorder_queue_len: Gauge,
+    /// The time since the timestamp of the oldest segment waiting for its delivery time in
+    /// milliseconds.
+    pub reorder_oldest_age: Gauge,
+    /// The resource the connection publishes or requests, `None` before the handshake
+    /// completed.
+    pub resource_id: Mutex<Option<ResourceId>>,
+    /// The sequence of the seqlock making [`snapshot`] consistent. Odd while a [`WriteGuard`] is
+    /// alive.
+    ///
+    /// [`snapshot`]: Self::snapshot
+    seq: AtomicU64,
 }
 
 impl ConnectionMetrics {
@@ -50,6 +175,150 @@ impl ConnectionMetrics {
             data_bytes_recv: StreamMetrics::new(),
             rtt: Gauge::new(),
             rtt_variance: Gauge::new(),
+            peer_version: Gauge::new(),
+            decrypt_failures: Counter::new(),
+            oversized_packets: Counter::new(),
+            pacing_interval: Gauge::new(),
+            peer_errors: Counter::new(),
+            max_send_rate: Gauge::new(),
+            send_rate: Gauge::new(),
+            latency: Gauge::new(),
+            effective_latency: Gauge::new(),
+            mode: Gauge::new(),
+            reorder_queue_len: Gauge::new(),
+            reorder_oldest_age: Gauge::new(),
+            resource_id: Mutex::new(None),
+            seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts updating the metrics. All updates until the returned [`WriteGuard`] is dropped are
+    /// observed by [`snapshot`] either completely or not at all.
+    ///
+    /// There must only be a single writer at a time, i.e. the connection owning the metrics.
+    ///
+    /// [`snapshot`]: Self::snapshot
+    pub fn write(&self) -> WriteGuard<'_> {
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        debug_assert!(
+            seq.is_multiple_of(2),
+            "concurrent writers of ConnectionMetrics"
+        );
+        atomic::fence(Ordering::Release);
+
+        WriteGuard { metrics: self }
+    }
+
+    /// Returns a copy of the current values of all metrics.
+    ///
+    /// The snapshot never contains only part of the updates made under a single [`WriteGuard`].
+    /// If a writer is active the snapshot waits for it to finish.
+    pub fn snapshot(&self) -> ConnectionMetricsSnapshot {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if !seq.is_multiple_of(2) {
+                hint::spin_loop();
+                continue;
+            }
+
+            let snapshot = self.read();
+
+            atomic::fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return snapshot;
+            }
+        }
+    }
+
+    /// Reads every metric exactly once.
+    fn read(&self) -> ConnectionMetricsSnapshot {
+        ConnectionMetricsSnapshot {
+            ctrl_packets_sent: self.ctrl_packets_sent.get(),
+            ctrl_packets_recv: self.ctrl_packets_recv.get(),
+            ctrl_packets_lost: self.ctrl_packets_lost.get(),
+            ctrl_bytes_sent: self.ctrl_bytes_sent.get(),
+            ctrl_bytes_recv: self.ctrl_bytes_recv.get(),
+            ctrl_bytes_lost: self.ctrl_bytes_lost.get(),
+            data_packets_sent: self.data_packets_sent.snapshot(),
+            data_packets_recv: self.data_packets_recv.snapshot(),
+            data_bytes_sent: self.data_bytes_sent.snapshot(),
+            data_bytes_recv: self.data_bytes_recv.snapshot(),
+            rtt: self.rtt.get(),
+            rtt_variance: self.rtt_variance.get(),
+            peer_version: self.peer_version.get(),
+            decrypt_failures: self.decrypt_failures.get(),
+            oversized_packets: self.oversized_packets.get(),
+            pacing_interval: self.pacing_interval.get(),
+            peer_errors: self.peer_errors.get(),
+            max_send_rate: self.max_send_rate.get(),
+            send_rate: self.send_rate.get(),
+            latency: self.latency.get(),
+            effective_latency: self.effective_latency.get(),
+            mode: ConnectionModeMetric::from_usize(self.mode.get()),
+            reorder_queue_len: self.reorder_queue_len.get(),
+            reorder_oldest_age: self.reorder_oldest_age.get(),
+        }
+    }
+}
+
+/// Ends a batch of updates started by [`ConnectionMetrics::write`] when dropped.
+#[derive(Debug)]
+pub struct WriteGuard<'a> {
+    metrics: &'a ConnectionMetrics,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.metrics.seq.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// A copy of the values of [`ConnectionMetrics`] at one point in time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionMetricsSnapshot {
+    pub ctrl_packets_sent: u64,
+    pub ctrl_packets_recv: u64,
+    pub ctrl_packets_lost: u64,
+    pub ctrl_bytes_sent: u64,
+    pub ctrl_bytes_recv: u64,
+    pub ctrl_bytes_lost: u64,
+    pub data_packets_sent: StreamMetricsSnapshot,
+    pub data_packets_recv: StreamMetricsSnapshot,
+    pub data_bytes_sent: StreamMetricsSnapshot,
+    pub data_bytes_recv: StreamMetricsSnapshot,
+    pub rtt: usize,
+    pub rtt_variance: usize,
+    pub peer_version: usize,
+    pub decrypt_failures: u64,
+    pub oversized_packets: u64,
+    pub pacing_interval: usize,
+    pub peer_errors: u64,
+    pub max_send_rate: usize,
+    pub send_rate: usize,
+    pub latency: usize,
+    pub effective_latency: usize,
+    pub mode: ConnectionModeMetric,
+    pub reorder_queue_len: usize,
+    pub reorder_oldest_age: usize,
+}
+
+/// The mode of a connection, stored in [`ConnectionMetrics::mode`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionModeMetric {
+    /// The handshake didn't complete yet.
+    #[default]
+    Handshake = 0,
+    Publish = 1,
+    Request = 2,
+}
+
+impl ConnectionModeMetric {
+    fn from_usize(n: usize) -> Self {
+        match n {
+            1 => Self::Publish,
+            2 => Self::Request,
+            _ => Self::Handshake,
         }
     }
 }
@@ -76,4 +345,92 @@ impl StreamMetrics {
             lost: Counter::new(),
         }
     }
+
+    /// Returns a copy of the current values of all counters.
+    pub fn snapshot(&self) -> StreamMetricsSnapshot {
+        StreamMetricsSnapshot {
+            original: self.original.get(),
+            retransmitted: self.retransmitted.get(),
+            dropped: self.dropped.get(),
+            lost: self.lost.get(),
+        }
+    }
+}
+
+/// A copy of the values of [`StreamMetrics`] at one point in time.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StreamMetricsSnapshot {
+    pub original: u64,
+    pub retransmitted: u64,
+    pub dropped: u64,
+    pub lost: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::{ConnectionMetrics, ConnectionModeMetric};
+
+    #[test]
+    fn test_snapshot_concurrent() {
+        let metrics = Arc::new(ConnectionMetrics::new());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let metrics = metrics.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut count = 0;
+                while !done.load(Ordering::Relaxed) {
+                    let _guard = metrics.write();
+                    metrics.data_packets_recv.original.inc();
+                    metrics.data_bytes_recv.original.add(1316);
+                    metrics.ctrl_packets_sent.inc();
+                    count += 1;
+                }
+                count
+            })
+        };
+
+        // Counters never go backwards between snapshots, and updates made together are never
+        // torn apart.
+        let mut last = metrics.snapshot();
+        for _ in 0..100_000 {
+            let snapshot = metrics.snapshot();
+            assert_eq!(
+                snapshot.data_bytes_recv.original,
+                snapshot.data_packets_recv.original * 1316
+            );
+            assert_eq!(
+                snapshot.ctrl_packets_sent,
+                snapshot.data_packets_recv.original
+            );
+            assert!(snapshot.data_packets_recv.original >= last.data_packets_recv.original);
+            last = snapshot;
+        }
+        done.store(true, Ordering::Relaxed);
+        let count = writer.join().unwrap();
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.data_packets_recv.original, count);
+        assert_eq!(snapshot.data_bytes_recv.original, count * 1316);
+        assert_eq!(snapshot.ctrl_packets_sent, count);
+        assert_eq!(snapshot.data_packets_recv.lost, 0);
+    }
+
+    #[test]
+    fn test_snapshot_json() {
+        let metrics = ConnectionMetrics::new();
+        metrics.mode.set(ConnectionModeMetric::Publish as usize);
+        metrics.latency.set(120);
+        metrics.data_packets_recv.lost.inc();
+
+        let json = serde_json::to_value(metrics.snapshot()).unwrap();
+        assert_eq!(json["mode"], "publish");
+        assert_eq!(json["latency"], 120);
+        assert_eq!(json["data_packets_recv"]["lost"], 1);
+    }
 }
@@ -0,0 +1,121 @@
+//! Batched datagram I/O using `recvmmsg(2)` and `sendmmsg(2)`.
+//!
+//! Both functions are non-blocking and only perform a single syscall. They are expected to be
+//! driven by the readiness of the socket.
+use std::io::{Error, IoSlice, Result};
+use std::mem::{self, MaybeUninit};
+use std::net::SocketAddr;
+use std::os::unix::io::RawFd;
+use std::ptr;
+
+use bytes::BytesMut;
+use socket2::SockAddr;
+
+/// The maximum number of datagrams transferred in a single syscall.
+pub const BATCH_SIZE: usize = 32;
+
+/// Receives up to [`BATCH_SIZE`] datagrams into the spare capacity of `bufs`, one datagram per
+/// buffer. The length of each buffer is extended by the received datagram. The buffer index,
+/// length and sender of every received datagram are appended to `received`. Datagrams without an
+/// IP sender are skipped and leave their buffer unchanged.
+///
+/// Returns the number of received datagrams, including skipped ones.
+pub fn recvmmsg(
+    fd: RawFd,
+    bufs: &mut [BytesMut],
+    received: &mut Vec<(usize, usize, SocketAddr)>,
+) -> Result<usize> {
+    let len = bufs.len().min(BATCH_SIZE);
+
+    // SAFETY: All of these are plain C structs for which zero is a valid value.
+    let mut addrs: [libc::sockaddr_storage; BATCH_SIZE] = unsafe { mem::zeroed() };
+    let mut iovecs: [libc::iovec; BATCH_SIZE] = unsafe { mem::zeroed() };
+    let mut msgs: [libc::mmsghdr; BATCH_SIZE] = unsafe { mem::zeroed() };
+
+    for i in 0..len {
+        let spare = bufs[i].spare_capacity_mut();
+        iovecs[i] = libc::iovec {
+            iov_base: spare.as_mut_ptr().cast(),
+            iov_len: spare.len(),
+        };
+
+        msgs[i].msg_hdr.msg_name = ptr::addr_of_mut!(addrs[i]).cast();
+        msgs[i].msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msgs[i].msg_hdr.msg_iov = ptr::addr_of_mut!(iovecs[i]);
+        msgs[i].msg_hdr.msg_iovlen = 1;
+    }
+
+    // SAFETY: The first `len` headers point to valid buffers and address storage that outlive
+    // the call.
+    let res = unsafe {
+        libc::recvmmsg(
+            fd,
+            msgs.as_mut_ptr(),
+            len as libc::c_uint,
+            libc::MSG_DONTWAIT,
+            ptr::null_mut(),
+        )
+    };
+    if res < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let count = res as usize;
+    for (index, ((msg, storage), buf)) in msgs.iter().zip(addrs).zip(bufs).take(count).enumerate() {
+        // SAFETY: The kernel initialized `msg_namelen` bytes of the address storage.
+        let addr = unsafe { SockAddr::new(storage, msg.msg_hdr.msg_namelen) };
+
+        // UDP datagrams always have an IPv4 or IPv6 sender.
+        if let Some(addr) = addr.as_socket() {
+            // SAFETY: The kernel initialized `msg_len` bytes of the spare capacity.
+            unsafe {
+                buf.set_len(buf.len() + msg.msg_len as usize);
+            }
+
+            received.push((index, msg.msg_len as usize, addr));
+        }
+    }
+
+    Ok(count)
+}
+
+/// Sends up to [`BATCH_SIZE`] datagrams from `datagrams` to `addr`. Every datagram is sent as the
+/// concatenation of its slices.
+///
+/// Returns the number of sent datagrams, which may be less than `datagrams.len()`.
+pub fn sendmmsg(fd: RawFd, datagrams: &[[IoSlice<'_>; 2]], addr: SocketAddr) -> Result<usize> {
+    let len = datagrams.len().min(BATCH_SIZE);
+    let addr = SockAddr::from(addr);
+
+    let mut msgs: [MaybeUninit<libc::mmsghdr>; BATCH_SIZE] =
+        unsafe { MaybeUninit::uninit().assume_init() };
+
+    for (msg, slices) in msgs.iter_mut().zip(datagrams).take(len) {
+        // SAFETY: `mmsghdr` is a plain C struct for which zero is a valid value.
+        let mut hdr: libc::mmsghdr = unsafe { mem::zeroed() };
+        hdr.msg_hdr.msg_name = addr.as_ptr() as *mut libc::c_void;
+        hdr.msg_hdr.msg_namelen = addr.len();
+        // `IoSlice` is guaranteed to be ABI compatible with `iovec`. The kernel does not write
+        // to the buffers of outgoing messages.
+        hdr.msg_hdr.msg_iov = slices.as_ptr() as *mut libc::iovec;
+        hdr.msg_hdr.msg_iovlen = slices.len() as _;
+
+        msg.write(hdr);
+    }
+
+    // SAFETY: The first `len` headers are initialized and point to buffers and an address that
+    // outlive the call.
+    let res = unsafe {
+        libc::sendmmsg(
+            fd,
+            msgs.as_mut_ptr().cast(),
+            len as libc::c_uint,
+            libc::MSG_DONTWAIT,
+        )
+    };
+    if res < 0 {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(res as usize)
+}
@@ -1,13 +1,19 @@
 //! Secure Reliable Transport (SRT) implementation.
 //!
 //! https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01
+pub mod access_log;
 mod buffer;
 pub mod config;
 mod conn;
+pub mod cookie;
+mod crypto;
 mod handshake;
+pub mod loss;
 mod metrics;
+#[cfg(target_os = "linux")]
+mod mmsg;
 pub mod proto;
-mod queue;
+pub mod queue;
 pub mod server;
 mod sink;
 mod socket;
@@ -16,11 +22,12 @@ mod stream;
 mod utils;
 
 pub use config::Config;
+pub use conn::Control;
 
 use std::{
     collections::HashMap,
     convert::Infallible,
-    fmt::Debug,
+    fmt::{Debug, Display},
     io::{self, ErrorKind, Write},
     ops::{BitAnd, BitOr},
     str::FromStr,
@@ -38,6 +45,23 @@ use self::{
 /// The SRT version supported by this library.
 pub const VERSION: u32 = 0x00010501;
 
+/// A SRT version as used in the HSREQ extension, displayed as `major.minor.patch`.
+///
+/// The version is encoded as `0x00XXYYZZ` where `XX` is the major, `YY` the minor and `ZZ` the
+/// patch version.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SrtVersion(pub u32);
+
+impl Display for SrtVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let major = (self.0 >> 16) & 0xFF;
+        let minor = (self.0 >> 8) & 0xFF;
+        let patch = self.0 & 0xFF;
+
+        write!(f, "{}.{}.{}", major, minor, patch)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("io: {0}")]
@@ -56,6 +80,16 @@ pub enum Error {
     FromUtf8Error(std::str::Utf8Error),
     #[error("unsupported extension {0:?}")]
     UnsupportedExtension(ExtensionType),
+    #[error("stream id too long: {0} bytes")]
+    StreamIdTooLong(usize),
+    #[error("extension length {length} does not match content length {content}")]
+    ExtensionLengthMismatch { length: u16, content: u32 },
+    #[error("sequence number {0} exceeds 31 bits")]
+    SequenceNumberOverflow(u32),
+    #[error("packet of {len} bytes exceeds the maximum of {max} bytes")]
+    PacketTooLarge { len: usize, max: usize },
+    #[error("invalid key material")]
+    InvalidKeyMaterial,
 }
 
 impl From<Infallible> for Error {
@@ -69,7 +103,7 @@ impl From<Infallible> for Error {
 pub struct InvalidControlType(u16);
 
 /// SRT header followed directly by UDP header.
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct Header {
     /// First bit indicates packet type: 0 = Data, 1 = Control.
     /// The rest is packet type dependent.
@@ -81,7 +115,8 @@ pub struct Header {
 }
 
 impl Header {
-    pub const SIZE: usize = 128;
+    /// The size of the encoded header in bytes.
+    pub const SIZE: usize = 16;
 
     pub fn packet_type(&self) -> PacketType {
         // First BE bit.
@@ -110,6 +145,27 @@ impl Header {
     pub fn as_data_unchecked(&mut self) -> DataHeader<'_> {
         DataHeader { header: self }
     }
+
+    /// Returns the flags of a data packet header.
+    fn data_flags(&self) -> DataFlags {
+        let packet_position = PacketPosition::from_bits(self.seg1.bits(0..2).0);
+
+        let encryption = match self.seg1.bits(3..5).0 {
+            0b00 => EncryptionFlag::None,
+            0b01 => EncryptionFlag::Even,
+            0b10 => EncryptionFlag::Odd,
+            0b11 => EncryptionFlag::Both,
+            _ => unreachable!(),
+        };
+
+        DataFlags {
+            packet_position,
+            ordered: self.seg1.bits(2) != 0,
+            encryption,
+            retransmitted: self.seg1.bits(5) != 0,
+            message_number: unsafe { MessageNumber::new_unchecked(self.seg1.bits(6..32).0) },
+        }
+    }
 }
 
 impl Encode for Header {
@@ -191,42 +247,49 @@ impl DataPacket {
         self.header.seg0.bits(1..32).0
     }
 
+    /// Returns all flags of the packet header.
+    #[inline]
+    pub fn flags(&self) -> DataFlags {
+        self.header.data_flags()
+    }
+
     pub fn packet_position_flag(&self) -> PacketPosition {
-        match self.header.seg1.bits(0..2).0 {
-            0b10 => PacketPosition::First,
-            0b00 => PacketPosition::Middle,
-            0b11 => PacketPosition::Solo,
-            _ => unreachable!(),
-        }
+        self.flags().packet_position
     }
 
     pub fn order_flag(&self) -> OrderFlag {
-        match self.header.seg1.bits(2).0 {
-            1 => OrderFlag::InOrder,
-            0 => OrderFlag::NotInOrder,
-            _ => unreachable!(),
+        if self.flags().ordered {
+            OrderFlag::InOrder
+        } else {
+            OrderFlag::NotInOrder
         }
     }
 
     pub fn encryption_flag(&self) -> EncryptionFlag {
-        match self.header.seg1.bits(3..5).0 {
-            0b00 => EncryptionFlag::None,
-            0b01 => EncryptionFlag::Even,
-            0b11 => EncryptionFlag::Odd,
-            _ => unreachable!(),
-        }
+        self.flags().encryption
     }
 
     /// 1 if packet was retransmitted.
     pub fn retransmission_flag(&self) -> u8 {
-        self.header.seg1.bits(5).0 as u8
+        self.flags().retransmitted as u8
     }
 
-    pub fn message_number(&mut self) -> MessageNumber {
-        self.header().message_number()
+    pub fn message_number(&self) -> MessageNumber {
+        self.flags().message_number
     }
 }
 
+/// The flags of a data packet header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DataFlags {
+    pub packet_position: PacketPosition,
+    pub ordered: bool,
+    /// The key used to encrypt the payload.
+    pub encryption: EncryptionFlag,
+    pub retransmitted: bool,
+    pub message_number: MessageNumber,
+}
+
 impl IsPacket for DataPacket {
     type Error = Error;
 
@@ -243,21 +306,12 @@ impl IsPacket for DataPacket {
             data: packet.body,
         })
     }
-}
-
-#[derive(Clone, Debug)]
-pub struct ShutdownPacket {
-    header: Header,
-}
 
-impl Encode for ShutdownPacket {
-    type Error = Error;
-
-    fn encode<W>(&self, writer: W) -> Result<(), Self::Error>
-    where
-        W: Write,
-    {
-        self.header.encode(writer)
+    fn peek(packet: &Packet) -> Result<Self, Self::Error> {
+        Ok(Self {
+            header: packet.header,
+            data: packet.body.clone(),
+        })
     }
 }
 
@@ -281,10 +335,13 @@ pub enum OrderFlag {
     NotInOrder,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EncryptionFlag {
     None,
     Even,
     Odd,
+    /// Both keys. Only valid in key material control packets.
+    Both,
 }
 
 pub struct ControlPacket {
@@ -426,6 +483,10 @@ impl Encode for EncryptionField {
         self.to_u16().encode(writer)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.0)
+    }
 }
 
 impl Decode for EncryptionField {
@@ -571,6 +632,34 @@ impl HandshakeType {
     pub fn is_rejection(self) -> bool {
         self.0 >= Self::REJ_UNKNOWN.0 && self.0 <= Self::REJ_GROUP.0
     }
+
+    /// Returns a stable name of the rejection code, e.g. `"badsecret"` for [`REJ_BADSECRET`].
+    /// Returns `None` if `self` is not a rejection code.
+    ///
+    /// [`REJ_BADSECRET`]: Self::REJ_BADSECRET
+    pub fn rejection_reason(self) -> Option<&'static str> {
+        let reason = match self {
+            Self::REJ_UNKNOWN => "unknown",
+            Self::REJ_SYSTEM => "system",
+            Self::REJ_PEER => "peer",
+            Self::REJ_RESOURCE => "resource",
+            Self::REJ_ROGUE => "rogue",
+            Self::REJ_BACKLOG => "backlog",
+            Self::REJ_IPE => "ipe",
+            Self::REJ_CLOSE => "close",
+            Self::REJ_VERSION => "version",
+            Self::REJ_RDVCOOKIE => "rdvcookie",
+            Self::REJ_BADSECRET => "badsecret",
+            Self::REJ_INSECURE => "insecure",
+            Self::REJ_MESSAGEAPI => "messageapi",
+            Self::REJ_CONGESTION => "congestion",
+            Self::REJ_FILTER => "filter",
+            Self::REJ_GROUP => "group",
+            _ => return None,
+        };
+
+        Some(reason)
+    }
 }
 
 impl Encode for HandshakeType {
@@ -583,6 +672,10 @@ impl Encode for HandshakeType {
         self.0.encode(writer)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.0)
+    }
 }
 
 impl Decode for HandshakeType {
@@ -614,6 +707,11 @@ impl IsPacket for Packet {
         Ok(packet)
     }
 
+    #[inline]
+    fn peek(packet: &Packet) -> Result<Self, Self::Error> {
+        Ok(packet.clone())
+    }
+
     #[inline]
     fn upcast(self) -> Packet {
         self
@@ -632,6 +730,30 @@ impl Packet {
     {
         T::downcast(self)
     }
+
+    /// Decodes a `Packet` like [`Decode::decode`], but fails with [`Error::PacketTooLarge`] if
+    /// `bytes` contains more than `max_len` bytes, including the header. Nothing is consumed in
+    /// that case.
+    pub fn decode_limited<B>(bytes: &mut B, max_len: usize) -> Result<Self, Error>
+    where
+        B: Buf,
+    {
+        let len = bytes.remaining();
+        if len > max_len {
+            return Err(Error::PacketTooLarge { len, max: max_len });
+        }
+
+        Self::decode(bytes)
+    }
+
+    /// Decodes the packet as `T` without consuming it. This is useful to inspect the fields of a
+    /// packet, e.g. for logging, before it is passed on.
+    pub fn peek<T>(&self) -> Result<T, Error>
+    where
+        T: IsPacket<Error = Error>,
+    {
+        T::peek(self)
+    }
 }
 
 impl Encode for Packet {
@@ -697,45 +819,55 @@ impl<'a> DataHeader<'a> {
         self.header.seg0.set_bits(1..32, n);
     }
 
+    /// Returns all flags of the header.
+    #[inline]
+    pub fn flags(&self) -> DataFlags {
+        self.header.data_flags()
+    }
+
     pub fn packet_position(&self) -> PacketPosition {
-        match self.header.seg1.bits(0..2).0 {
-            0b10 => PacketPosition::First,
-            0b00 => PacketPosition::Middle,
-            0b01 => PacketPosition::Last,
-            0b11 => PacketPosition::Solo,
-            _ => unreachable!(),
-        }
+        self.flags().packet_position
     }
 
     pub fn set_packet_position(&mut self, pos: PacketPosition) {
-        let val = match pos {
-            PacketPosition::First => 0b10,
-            PacketPosition::Middle => 0b00,
-            PacketPosition::Last => 0b01,
-            PacketPosition::Solo => 0b11,
-        };
-
-        self.header.seg1.set_bits(0..2, val);
+        self.header.seg1.set_bits(0..2, pos.to_bits());
     }
 
     pub fn is_ordered(&self) -> bool {
-        self.header.seg1.bits(2) != 0
+        self.flags().ordered
     }
 
     pub fn set_ordered(&mut self, n: bool) {
         self.header.seg1.set_bits(2, n as u32)
     }
 
-    pub fn kk(&self) -> ! {
-        unimplemented!()
+    pub fn encryption_flag(&self) -> EncryptionFlag {
+        self.flags().encryption
     }
 
-    pub fn set_kk(&mut self) -> ! {
-        unimplemented!()
+    pub fn set_encryption_flag(&mut self, flag: EncryptionFlag) {
+        let val = match flag {
+            EncryptionFlag::None => 0b00,
+            EncryptionFlag::Even => 0b01,
+            EncryptionFlag::Odd => 0b10,
+            EncryptionFlag::Both => 0b11,
+        };
+
+        self.header.seg1.set_bits(3..5, val);
+    }
+
+    #[deprecated(note = "use `encryption_flag` instead")]
+    pub fn kk(&self) -> EncryptionFlag {
+        self.encryption_flag()
+    }
+
+    #[deprecated(note = "use `set_encryption_flag` instead")]
+    pub fn set_kk(&mut self, flag: EncryptionFlag) {
+        self.set_encryption_flag(flag);
     }
 
     pub fn is_retransmitted(&self) -> bool {
-        self.header.seg1.bits(5) != 0
+        self.flags().retransmitted
     }
 
     pub fn set_retransmitted(&mut self, n: bool) {
@@ -743,8 +875,7 @@ impl<'a> DataHeader<'a> {
     }
 
     pub fn message_number(&self) -> MessageNumber {
-        let bits = self.header.seg1.bits(6..32).0;
-        unsafe { MessageNumber::new_unchecked(bits) }
+        self.flags().message_number
     }
 
     pub fn set_message_number(&mut self, n: u32) {
@@ -765,6 +896,33 @@ pub enum PacketPosition {
     Solo,
 }
 
+impl PacketPosition {
+    /// Decodes the position from the two PP bits of a data packet header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` has more than the lowest two bits set.
+    fn from_bits(bits: u32) -> Self {
+        match bits {
+            0b10 => Self::First,
+            0b00 => Self::Middle,
+            0b01 => Self::Last,
+            0b11 => Self::Solo,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Encodes the position into the two PP bits of a data packet header.
+    fn to_bits(self) -> u32 {
+        match self {
+            Self::First => 0b10,
+            Self::Middle => 0b00,
+            Self::Last => 0b01,
+            Self::Solo => 0b11,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct AckPacket {
     header: Header,
@@ -822,7 +980,7 @@ impl Encode for AckPacket {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[repr(transparent)]
 pub struct Extensions(pub Vec<HandshakeExtension>);
 
@@ -856,6 +1014,30 @@ impl Extensions {
         None
     }
 
+    pub fn remove_group(&mut self) -> Option<GroupExtension> {
+        let index = self
+            .0
+            .iter()
+            .position(|ext| ext.extension_type == ExtensionType::GROUP)?;
+
+        match self.0.remove(index).extension_content {
+            ExtensionContent::Group(ext) => Some(ext),
+            _ => None,
+        }
+    }
+
+    pub fn remove_key_material(&mut self) -> Option<KeyMaterialExtension> {
+        let index = self
+            .0
+            .iter()
+            .position(|ext| ext.extension_type == ExtensionType::KMREQ)?;
+
+        match self.0.remove(index).extension_content {
+            ExtensionContent::KeyMaterial(ext) => Some(ext),
+            _ => None,
+        }
+    }
+
     pub fn remove_stream_id(&mut self) -> Option<StreamIdExtension> {
         let mut index: usize = 0;
 
@@ -889,6 +1071,10 @@ impl Encode for Extensions {
 
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        self.0.iter().map(Encode::size_hint).sum()
+    }
 }
 
 impl Decode for Extensions {
@@ -900,8 +1086,14 @@ impl Decode for Extensions {
     {
         // TODO: Preallocate
         let mut extensions = Vec::new();
-        loop {
-            match HandshakeExtension::decode(bytes) {
+        while bytes.remaining() >= 4 {
+            // Decode every extension only from its own content, so that extensions extending to
+            // the end of the buffer (e.g. SID) can be followed by other extensions.
+            let header = bytes.copy_to_bytes(4);
+            let length = usize::from(u16::from_be_bytes([header[2], header[3]])) * 4;
+            let content = bytes.copy_to_bytes(length.min(bytes.remaining()));
+
+            match HandshakeExtension::decode(&mut header.chain(content)) {
                 Ok(ext) => {
                     extensions.push(ext);
                 }
@@ -915,6 +1107,8 @@ impl Decode for Extensions {
                 Err(err) => return Err(err),
             }
         }
+
+        Ok(Self(extensions))
     }
 }
 
@@ -936,6 +1130,12 @@ pub trait IsPacket: Sized {
     /// Note that `downcast` only reads from the front. Any bytes unnecessary for the downcasting
     /// process will be dropped.
     fn downcast(packet: Packet) -> Result<Self, Self::Error>;
+
+    /// Decodes this packet type from a borrowed [`Packet`], leaving the `packet` untouched.
+    /// Returns the same errors as [`downcast`].
+    ///
+    /// [`downcast`]: Self::downcast
+    fn peek(packet: &Packet) -> Result<Self, Self::Error>;
 }
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
@@ -980,6 +1180,10 @@ impl Encode for ExtensionType {
         self.to_u16().encode(writer)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.0)
+    }
 }
 
 impl Decode for ExtensionType {
@@ -997,11 +1201,11 @@ impl Decode for ExtensionType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct HandshakeExtension {
     pub extension_type: ExtensionType,
-    /// Length of the content **IN FOUR-BYTE GROUPS**. In order word to get the length of 3
-    /// multiply by 3: `let bytes = extension_length * 3;`.
+    /// Length of the content **IN FOUR-BYTE GROUPS**. In order word to get the length in bytes
+    /// multiply by 4: `let bytes = extension_length * 4;`.
     pub extension_length: u16,
     pub extension_content: ExtensionContent,
 }
@@ -1015,6 +1219,14 @@ impl Encode for HandshakeExtension {
     where
         W: Write,
     {
+        let content = self.extension_content.len();
+        if u32::from(self.extension_length) != content {
+            return Err(Error::ExtensionLengthMismatch {
+                length: self.extension_length,
+                content,
+            });
+        }
+
         self.extension_type.encode(&mut writer)?;
         self.extension_length.encode(&mut writer)?;
 
@@ -1022,9 +1234,15 @@ impl Encode for HandshakeExtension {
             ExtensionContent::Handshake(ext) => ext.encode(writer),
             ExtensionContent::KeyMaterial(ext) => ext.encode(writer),
             ExtensionContent::StreamId(ext) => ext.encode(writer),
-            ExtensionContent::Group(_) => Ok(()),
+            ExtensionContent::Group(ext) => ext.encode(writer),
         }
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.extension_type)
+            + Encode::size_hint(&self.extension_length)
+            + self.extension_content.len() as usize * 4
+    }
 }
 
 impl Decode for HandshakeExtension {
@@ -1041,7 +1259,23 @@ impl Decode for HandshakeExtension {
             ExtensionType::HSREQ | ExtensionType::HSRSP => {
                 ExtensionContent::Handshake(HandshakeExtensionMessage::decode(bytes)?)
             }
-            ExtensionType::SID => ExtensionContent::StreamId(StreamIdExtension::decode(bytes)?),
+            ExtensionType::SID => {
+                // The stream id extends to the end of the packet and must fill exactly
+                // `extension_length` four-byte groups.
+                let remaining = bytes.remaining();
+                if remaining != usize::from(extension_length) * 4 {
+                    return Err(Error::ExtensionLengthMismatch {
+                        length: extension_length,
+                        content: u32::try_from(remaining).unwrap_or(u32::MAX).div_ceil(4),
+                    });
+                }
+
+                ExtensionContent::StreamId(StreamIdExtension::decode(bytes)?)
+            }
+            ExtensionType::KMREQ | ExtensionType::KMRSP => {
+                ExtensionContent::KeyMaterial(KeyMaterialExtension::decode(bytes)?)
+            }
+            ExtensionType::GROUP => ExtensionContent::Group(GroupExtension::decode(bytes)?),
             _ => return Err(Error::UnsupportedExtension(extension_type)),
         };
 
@@ -1053,7 +1287,7 @@ impl Decode for HandshakeExtension {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
 pub struct HandshakeExtensionMessage {
     pub srt_version: u32,
     pub srt_flags: HandshakeExtensionFlags,
@@ -1075,6 +1309,13 @@ impl Encode for HandshakeExtensionMessage {
 
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.srt_version)
+            + Encode::size_hint(&self.srt_flags)
+            + Encode::size_hint(&self.receiver_tsbpd_delay)
+            + Encode::size_hint(&self.sender_tsbpd_delay)
+    }
 }
 
 impl Decode for HandshakeExtensionMessage {
@@ -1146,6 +1387,14 @@ impl HandshakeExtensionFlags {
         Some(Self(n))
     }
 
+    /// Returns `true` if the [`TSBPDSND`] flag is set.
+    ///
+    /// [`TSBPDSND`]: Self::TSBPDSND
+    #[inline]
+    pub const fn has_tsbpdsnd(self) -> bool {
+        self.0 & Self::TSBPDSND.0 != 0
+    }
+
     /// Returns `true` if the `CRYPT` flag is set.
     #[inline]
     pub const fn has_crypt(self) -> bool {
@@ -1158,6 +1407,14 @@ impl HandshakeExtensionFlags {
         self.0 & Self::REXMITFLG.0 != 0
     }
 
+    /// Returns `true` if the [`TLPKTDROP`] flag is set.
+    ///
+    /// [`TLPKTDROP`]: Self::TLPKTDROP
+    #[inline]
+    pub const fn has_tlpktdrop(self) -> bool {
+        self.0 & Self::TLPKTDROP.0 != 0
+    }
+
     /// Returns `true` if the [`STREAM`] flag is set.
     ///
     /// `STREAM`: Self::STREAM
@@ -1193,6 +1450,10 @@ impl Encode for HandshakeExtensionFlags {
         self.0.encode(writer)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.0)
+    }
 }
 
 impl Decode for HandshakeExtensionFlags {
@@ -1206,20 +1467,75 @@ impl Decode for HandshakeExtensionFlags {
     }
 }
 
-#[derive(Clone, Debug)]
+/// The key material message of a KMREQ or KMRSP extension. It carries the SEKs wrapped with the
+/// KEK derived from the passphrase.
+///
+/// See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#section-3.2.2
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct KeyMaterialExtension {
-    // TOOO: impl
+    /// The keys contained in `wrapped_keys`, [`Even`], [`Odd`] or [`Both`].
+    ///
+    /// [`Even`]: EncryptionFlag::Even
+    /// [`Odd`]: EncryptionFlag::Odd
+    /// [`Both`]: EncryptionFlag::Both
+    pub key_flags: EncryptionFlag,
+    /// The index of the KEK, always `0` for the default KEK.
+    pub keki: u32,
+    pub cipher: u8,
+    pub auth: u8,
+    pub stream_encapsulation: u8,
+    pub salt: Vec<u8>,
+    /// The length of a single SEK in bytes.
+    pub key_length: u8,
+    /// The SEKs, even before odd, wrapped with the KEK using RFC 3394.
+    pub wrapped_keys: Vec<u8>,
+}
+
+impl KeyMaterialExtension {
+    /// The first byte, the version `1` and packet type `2` (KMmsg).
+    const VERSION: u8 = 0x12;
+    const SIGN: u16 = 0x2029;
+
+    /// AES-CTR
+    pub const CIPHER_CTR: u8 = 2;
+    /// MPEG-TS/SRT
+    pub const SE_SRT: u8 = 2;
 }
 
 impl Encode for KeyMaterialExtension {
     type Error = Error;
 
-    fn encode<W>(&self, writer: W) -> Result<(), Self::Error>
+    fn encode<W>(&self, mut writer: W) -> Result<(), Self::Error>
     where
         W: Write,
     {
+        let kk: u8 = match self.key_flags {
+            EncryptionFlag::None => 0b00,
+            EncryptionFlag::Even => 0b01,
+            EncryptionFlag::Odd => 0b10,
+            EncryptionFlag::Both => 0b11,
+        };
+
+        Self::VERSION.encode(&mut writer)?;
+        Self::SIGN.encode(&mut writer)?;
+        kk.encode(&mut writer)?;
+        self.keki.encode(&mut writer)?;
+        self.cipher.encode(&mut writer)?;
+        self.auth.encode(&mut writer)?;
+        self.stream_encapsulation.encode(&mut writer)?;
+        // Resv2 and Resv3
+        0u8.encode(&mut writer)?;
+        0u16.encode(&mut writer)?;
+        ((self.salt.len() / 4) as u8).encode(&mut writer)?;
+        (self.key_length / 4).encode(&mut writer)?;
+        writer.write_all(&self.salt)?;
+        writer.write_all(&self.wrapped_keys)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        16 + self.salt.len() + self.wrapped_keys.len()
+    }
 }
 
 impl Decode for KeyMaterialExtension {
@@ -1229,19 +1545,70 @@ impl Decode for KeyMaterialExtension {
     where
         B: Buf,
     {
-        Ok(Self {})
+        let version = u8::decode(bytes)?;
+        let sign = u16::decode(bytes)?;
+        if version != Self::VERSION || sign != Self::SIGN {
+            return Err(Error::InvalidKeyMaterial);
+        }
+
+        let key_flags = match u8::decode(bytes)? & 0b11 {
+            0b01 => EncryptionFlag::Even,
+            0b10 => EncryptionFlag::Odd,
+            0b11 => EncryptionFlag::Both,
+            _ => return Err(Error::InvalidKeyMaterial),
+        };
+
+        let keki = u32::decode(bytes)?;
+        let cipher = u8::decode(bytes)?;
+        let auth = u8::decode(bytes)?;
+        let stream_encapsulation = u8::decode(bytes)?;
+        let _resv2 = u8::decode(bytes)?;
+        let _resv3 = u16::decode(bytes)?;
+        let salt_length = usize::from(u8::decode(bytes)?) * 4;
+        let key_length = u8::decode(bytes)?.checked_mul(4).unwrap_or(0);
+
+        if bytes.remaining() < salt_length {
+            return Err(io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        let salt = bytes.copy_to_bytes(salt_length).to_vec();
+
+        // The wrapped keys extend to the end of the extension and contain one or two keys
+        // following a 64-bit integrity check value.
+        let keys = if key_flags == EncryptionFlag::Both {
+            2
+        } else {
+            1
+        };
+        if key_length == 0 || bytes.remaining() != 8 + keys * usize::from(key_length) {
+            return Err(Error::InvalidKeyMaterial);
+        }
+        let wrapped_keys = bytes.copy_to_bytes(bytes.remaining()).to_vec();
+
+        Ok(Self {
+            key_flags,
+            keki,
+            cipher,
+            auth,
+            stream_encapsulation,
+            salt,
+            key_length,
+            wrapped_keys,
+        })
     }
 }
 
 /// A UTF-8 string with up to 512 bytes.
 ///
 /// The string is internally encoded little-endian words (u32).
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct StreamIdExtension {
     pub content: String,
 }
 
 impl StreamIdExtension {
+    /// The maximum length of a `StreamIdExtension` in bytes.
+    pub const MAX_LEN: usize = 512;
+
     pub fn parse(&self) -> Result<StandardStreamId, StandardStreamIdError> {
         self.content.parse()
     }
@@ -1290,6 +1657,11 @@ impl Encode for StreamIdExtension {
 
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        // Padded with zeros to a multiple of four bytes.
+        self.content.len().div_ceil(4) * 4
+    }
 }
 
 impl Decode for StreamIdExtension {
@@ -1300,27 +1672,98 @@ impl Decode for StreamIdExtension {
         B: Buf,
     {
         let mut vec = Vec::decode(bytes)?;
-        let mut buf = &mut *vec;
-
-        let mut string = String::with_capacity(buf.len());
+        if vec.len() > Self::MAX_LEN {
+            return Err(Error::StreamIdTooLong(vec.len()));
+        }
 
-        while let Some(mut bytes) = buf.get_mut(0..4) {
-            // Remove any trailing filler.
-            while bytes.starts_with(&[0]) {
-                bytes = &mut bytes[1..];
-            }
+        vec.truncate(vec.len() / 4 * 4);
+        for chunk in vec.chunks_exact_mut(4) {
+            chunk.reverse();
+        }
 
-            bytes.reverse();
-            let chunk = match std::str::from_utf8(bytes) {
-                Ok(c) => c,
-                Err(err) => return Err(Error::FromUtf8Error(err)),
-            };
+        // Remove the trailing filler.
+        while vec.last() == Some(&0) {
+            vec.pop();
+        }
 
-            string.push_str(chunk);
-            buf = &mut buf[4..];
+        // Characters may span multiple words, so the string can only be validated as a whole.
+        match String::from_utf8(vec) {
+            Ok(content) => Ok(Self { content }),
+            Err(err) => Err(Error::FromUtf8Error(err.utf8_error())),
         }
+    }
+}
+
+/// The GROUP extension sent by a peer that is a member of a socket group, i.e. that bonds
+/// multiple connections for link redundancy.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct GroupExtension {
+    /// The id of the group on the peer.
+    pub group_id: u32,
+    pub group_type: GroupType,
+    pub flags: u8,
+    /// The weight of the connection within the group, used to prioritize backup links.
+    pub weight: u16,
+}
+
+impl Encode for GroupExtension {
+    type Error = Error;
+
+    fn encode<W>(&self, mut writer: W) -> Result<(), Self::Error>
+    where
+        W: Write,
+    {
+        let data = u32::from(self.group_type.0) << 24
+            | u32::from(self.flags) << 16
+            | u32::from(self.weight);
+
+        self.group_id.encode(&mut writer)?;
+        data.encode(&mut writer)?;
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        8
+    }
+}
+
+impl Decode for GroupExtension {
+    type Error = Error;
 
-        Ok(Self { content: string })
+    fn decode<B>(bytes: &mut B) -> Result<Self, Self::Error>
+    where
+        B: Buf,
+    {
+        let group_id = u32::decode(bytes)?;
+        let data = u32::decode(bytes)?;
+
+        Ok(Self {
+            group_id,
+            group_type: GroupType((data >> 24) as u8),
+            flags: (data >> 16) as u8,
+            weight: data as u16,
+        })
+    }
+}
+
+/// The bonding mode of a socket group.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GroupType(pub u8);
+
+impl GroupType {
+    /// All data is sent over all member connections.
+    pub const BROADCAST: Self = Self(1);
+    /// Data is sent over a single member connection, the others take over if it fails.
+    pub const BACKUP: Self = Self(2);
+}
+
+impl Display for GroupType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::BROADCAST => f.write_str("broadcast"),
+            Self::BACKUP => f.write_str("backup"),
+            Self(n) => write!(f, "{}", n),
+        }
     }
 }
 
@@ -1364,6 +1807,11 @@ impl StandardStreamId {
     pub fn mode(&self) -> Option<&str> {
         self.map.get("m").map(|s| s.as_str())
     }
+
+    /// Returns the parsed [`StreamMode`].
+    pub fn stream_mode(&self) -> Result<StreamMode, StreamModeError> {
+        self.mode().ok_or(StreamModeError::Missing)?.parse()
+    }
 }
 
 impl FromStr for StandardStreamId {
@@ -1391,6 +1839,55 @@ impl FromStr for StandardStreamId {
     }
 }
 
+/// The mode of a [`StandardStreamId`], i.e. the direction of the stream from the view of the
+/// peer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StreamMode {
+    /// The peer receives the stream.
+    Request,
+    /// The peer sends the stream.
+    Publish,
+    /// The peer sends and receives.
+    Bidirectional,
+}
+
+impl StreamMode {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Request => "request",
+            Self::Publish => "publish",
+            Self::Bidirectional => "bidirectional",
+        }
+    }
+}
+
+impl Display for StreamMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StreamMode {
+    type Err = StreamModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "request" => Ok(Self::Request),
+            "publish" => Ok(Self::Publish),
+            "bidirectional" => Ok(Self::Bidirectional),
+            _ => Err(StreamModeError::Unknown(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum StreamModeError {
+    #[error("missing mode")]
+    Missing,
+    #[error("unknown mode {0:?}")]
+    Unknown(String),
+}
+
 #[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
 pub struct ExtensionField(u16);
 
@@ -1432,6 +1929,10 @@ impl Encode for ExtensionField {
     {
         self.0.encode(writer)
     }
+
+    fn size_hint(&self) -> usize {
+        Encode::size_hint(&self.0)
+    }
 }
 
 impl Decode for ExtensionField {
@@ -1456,32 +1957,23 @@ impl Debug for ExtensionField {
 }
 
 /// See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#section-3.2.1
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ExtensionContent {
     Handshake(HandshakeExtensionMessage),
     KeyMaterial(KeyMaterialExtension),
     StreamId(StreamIdExtension),
-    /// Unimplemented in current standart.
-    Group(()),
+    Group(GroupExtension),
 }
 
 impl ExtensionContent {
+    /// Returns the length of the encoded content in four-byte groups.
     pub fn len(&self) -> u32 {
         match self {
             Self::Handshake(_) => 3,
-            // Unimplemented
-            Self::KeyMaterial(_) => 0,
-            Self::StreamId(ext) => {
-                let len = ext.content.len() as u32;
-                match len % 3 {
-                    0 => len,
-                    1 => len + 2,
-                    2 => len + 1,
-                    _ => unreachable!(),
-                }
-            }
-            // unimplemented
-            Self::Group(_) => 0,
+            Self::KeyMaterial(ext) => (ext.size_hint() as u32).div_ceil(4),
+            // Padded with zeros to a multiple of four bytes.
+            Self::StreamId(ext) => (ext.content.len() as u32).div_ceil(4),
+            Self::Group(_) => 2,
         }
     }
 }
@@ -1494,9 +1986,17 @@ impl From<HandshakeExtensionMessage> for ExtensionContent {
 
 #[cfg(test)]
 mod tests {
+    use streamsync_api::srt::{StreamIdBuilder, StreamIdError};
+
     use crate::proto::{Decode, Encode};
 
-    use super::StreamIdExtension;
+    use super::utils::MessageNumber;
+    use super::{
+        DataFlags, DataPacket, EncryptionFlag, Error, ExtensionContent, ExtensionType, Extensions,
+        GroupExtension, GroupType, HandshakeExtension, HandshakeExtensionMessage, Header, IsPacket,
+        KeyMaterialExtension, Packet, PacketPosition, SrtVersion, StandardStreamId,
+        StreamIdExtension, StreamMode, StreamModeError,
+    };
 
     #[test]
     fn test_streamid_extension() {
@@ -1512,4 +2012,369 @@ mod tests {
 
         assert_eq!(ext.encode_to_vec().unwrap(), buf);
     }
+
+    #[test]
+    fn test_data_flags() {
+        let mut packet = DataPacket::default();
+
+        let mut header = packet.header();
+        header.set_packet_position(PacketPosition::Last);
+        header.set_ordered(true);
+        header.set_retransmitted(true);
+        header.set_encryption_flag(EncryptionFlag::Odd);
+        header.set_message_number(42);
+
+        let flags = DataFlags {
+            packet_position: PacketPosition::Last,
+            ordered: true,
+            encryption: EncryptionFlag::Odd,
+            retransmitted: true,
+            message_number: MessageNumber::new(42),
+        };
+        assert_eq!(packet.header().flags(), flags);
+        assert_eq!(packet.flags(), flags);
+
+        assert_eq!(packet.packet_position_flag(), PacketPosition::Last);
+        assert_eq!(packet.encryption_flag(), EncryptionFlag::Odd);
+        assert_eq!(packet.retransmission_flag(), 1);
+        assert_eq!(packet.message_number(), MessageNumber::new(42));
+
+        #[allow(deprecated)]
+        {
+            packet.header().set_kk(EncryptionFlag::Even);
+            assert_eq!(packet.header().kk(), EncryptionFlag::Even);
+        }
+    }
+
+    #[test]
+    fn test_encryption_flag() {
+        for (bits, flag) in [
+            (0b00, EncryptionFlag::None),
+            (0b01, EncryptionFlag::Even),
+            (0b10, EncryptionFlag::Odd),
+            (0b11, EncryptionFlag::Both),
+        ] {
+            let mut packet = DataPacket::builder().encryption(flag).build();
+            assert_eq!(packet.header.seg1.bits(3..5).0, bits);
+
+            let buf = packet.header.encode_to_vec().unwrap();
+            assert_eq!((buf[4] >> 3) & 0b11, bits as u8);
+
+            packet.header = Header::decode(&mut buf.as_slice()).unwrap();
+            assert_eq!(packet.encryption_flag(), flag);
+            assert_eq!(packet.header().encryption_flag(), flag);
+        }
+    }
+
+    #[test]
+    fn test_packet_position() {
+        for (bits, position) in [
+            (0b10, PacketPosition::First),
+            (0b00, PacketPosition::Middle),
+            (0b01, PacketPosition::Last),
+            (0b11, PacketPosition::Solo),
+        ] {
+            let mut packet = DataPacket::default();
+            packet.header.seg1.set_bits(0..2, bits);
+
+            assert_eq!(packet.packet_position_flag(), position);
+            assert_eq!(packet.header().packet_position(), position);
+
+            let mut packet = DataPacket::default();
+            packet.header().set_packet_position(position);
+            assert_eq!(packet.header.seg1.bits(0..2).0, bits);
+        }
+    }
+
+    #[test]
+    fn test_srt_version() {
+        assert_eq!(SrtVersion(0x00010501).to_string(), "1.5.1");
+        assert_eq!(SrtVersion(0x00010403).to_string(), "1.4.3");
+    }
+
+    #[test]
+    fn test_streamid_extension_max_len() {
+        let buf = [0x61; StreamIdExtension::MAX_LEN];
+        let ext = StreamIdExtension::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(ext.content.len(), StreamIdExtension::MAX_LEN);
+
+        let buf = [0x61; StreamIdExtension::MAX_LEN + 1];
+        let res = StreamIdExtension::decode(&mut buf.as_slice());
+        assert!(matches!(res, Err(Error::StreamIdTooLong(513))));
+    }
+
+    #[test]
+    fn test_extension_length() {
+        let content = ExtensionContent::StreamId(StreamIdExtension {
+            content: "#!::m=request,r=1235".to_owned(),
+        });
+        assert_eq!(content.len(), 5);
+
+        let mut ext = HandshakeExtension {
+            extension_type: ExtensionType::SID,
+            extension_length: 5,
+            extension_content: content,
+        };
+        // Type and length followed by the content.
+        assert_eq!(ext.encode_to_vec().unwrap().len(), 4 + 20);
+
+        ext.extension_length = 4;
+        assert!(matches!(
+            ext.encode_to_vec(),
+            Err(Error::ExtensionLengthMismatch {
+                length: 4,
+                content: 5
+            })
+        ));
+
+        // Padded to a multiple of four bytes.
+        let content = ExtensionContent::StreamId(StreamIdExtension {
+            content: "a".repeat(StreamIdExtension::MAX_LEN - 1),
+        });
+        assert_eq!(content.len(), 128);
+
+        for len in (1..=9).chain([StreamIdExtension::MAX_LEN]) {
+            let content = ExtensionContent::StreamId(StreamIdExtension {
+                content: "a".repeat(len),
+            });
+            let ext = HandshakeExtension {
+                extension_type: ExtensionType::SID,
+                extension_length: content.len() as u16,
+                extension_content: content,
+            };
+
+            let buf = ext.encode_to_vec().unwrap();
+            assert_eq!(usize::from(ext.extension_length) * 4, buf.len() - 4);
+        }
+    }
+
+    #[test]
+    fn test_extension_length_decode() {
+        let ext = HandshakeExtension {
+            extension_type: ExtensionType::SID,
+            extension_length: 2,
+            extension_content: ExtensionContent::StreamId(StreamIdExtension {
+                content: "#!::r=1".to_owned(),
+            }),
+        };
+        let buf = ext.encode_to_vec().unwrap();
+
+        let decoded = HandshakeExtension::decode(&mut &buf[..]).unwrap();
+        assert_eq!(decoded.extension_length, 2);
+
+        // Length too short for the content.
+        let mut short = buf.clone();
+        short[3] = 1;
+        assert!(matches!(
+            HandshakeExtension::decode(&mut &short[..]),
+            Err(Error::ExtensionLengthMismatch {
+                length: 1,
+                content: 2
+            })
+        ));
+
+        // Length too long for the content.
+        let mut long = buf.clone();
+        long[3] = 64;
+        assert!(matches!(
+            HandshakeExtension::decode(&mut &long[..]),
+            Err(Error::ExtensionLengthMismatch {
+                length: 64,
+                content: 2
+            })
+        ));
+
+        // Content not padded to four bytes.
+        let mut unaligned = buf;
+        unaligned.push(0);
+        assert!(matches!(
+            HandshakeExtension::decode(&mut &unaligned[..]),
+            Err(Error::ExtensionLengthMismatch {
+                length: 2,
+                content: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_packet_decode_limited() {
+        let buf = DataPacket::builder()
+            .body(vec![0; 100])
+            .build()
+            .upcast()
+            .encode_to_vec()
+            .unwrap();
+        // 16 bytes header.
+        assert_eq!(buf.len(), 16 + 100);
+
+        let packet = Packet::decode_limited(&mut buf.as_slice(), buf.len()).unwrap();
+        assert_eq!(packet.body.len(), 100);
+
+        let mut slice = buf.as_slice();
+        assert!(matches!(
+            Packet::decode_limited(&mut slice, buf.len() - 1),
+            Err(Error::PacketTooLarge { len: 116, max: 115 })
+        ));
+        assert_eq!(slice.len(), buf.len());
+    }
+
+    #[test]
+    fn test_group_extension() {
+        let buf = [0x00, 0x00, 0x30, 0x39, 0x02, 0x00, 0x00, 0x0A];
+        let ext = GroupExtension::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(
+            ext,
+            GroupExtension {
+                group_id: 12345,
+                group_type: GroupType::BACKUP,
+                flags: 0,
+                weight: 10,
+            }
+        );
+        assert_eq!(ext.encode_to_vec().unwrap(), buf);
+    }
+
+    #[test]
+    fn test_key_material_extension() {
+        let mut buf = vec![
+            0x12, 0x20, 0x29, 0x01, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00,
+            0x04, 0x04,
+        ];
+        buf.extend([0xaa; 16]);
+        buf.extend([0xbb; 24]);
+
+        let ext = KeyMaterialExtension::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(ext.key_flags, EncryptionFlag::Even);
+        assert_eq!(ext.cipher, KeyMaterialExtension::CIPHER_CTR);
+        assert_eq!(ext.salt, [0xaa; 16]);
+        assert_eq!(ext.key_length, 16);
+        assert_eq!(ext.wrapped_keys, [0xbb; 24]);
+        assert_eq!(ext.encode_to_vec().unwrap(), buf);
+        assert_eq!(ExtensionContent::KeyMaterial(ext).len(), 14);
+
+        // The wrapped keys must match the key length.
+        assert!(KeyMaterialExtension::decode(&mut &buf[..buf.len() - 4]).is_err());
+
+        // Unknown versions are rejected.
+        buf[0] = 0x13;
+        assert!(KeyMaterialExtension::decode(&mut buf.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_extensions_decode_group() {
+        let sid = ExtensionContent::StreamId(StreamIdExtension {
+            content: "#!::r=1".to_owned(),
+        });
+        let group = GroupExtension {
+            group_id: 1,
+            group_type: GroupType::BROADCAST,
+            flags: 0,
+            weight: 0,
+        };
+
+        // The GROUP extension follows the SID extension.
+        let extensions = Extensions(vec![
+            HandshakeExtension {
+                extension_type: ExtensionType::HSREQ,
+                extension_length: 3,
+                extension_content: HandshakeExtensionMessage::default().into(),
+            },
+            HandshakeExtension {
+                extension_type: ExtensionType::SID,
+                extension_length: sid.len() as u16,
+                extension_content: sid,
+            },
+            HandshakeExtension {
+                extension_type: ExtensionType::GROUP,
+                extension_length: 2,
+                extension_content: ExtensionContent::Group(group),
+            },
+        ]);
+        let buf = extensions.encode_to_vec().unwrap();
+
+        let mut extensions = Extensions::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(extensions.0.len(), 3);
+        assert_eq!(extensions.remove_group(), Some(group));
+        assert_eq!(extensions.remove_stream_id().unwrap().content, "#!::r=1");
+        assert!(extensions.remove_hsreq().is_some());
+    }
+
+    #[test]
+    fn test_stream_id_builder() {
+        let sid = StreamIdBuilder::new()
+            .mode("publish")
+            .resource("1a")
+            .session("2b")
+            .user("admin")
+            .build()
+            .unwrap();
+
+        // 31 bytes padded to 8 words.
+        let buf = StreamIdExtension {
+            content: sid.clone(),
+        }
+        .encode_to_vec()
+        .unwrap();
+        assert_eq!(buf.len(), 32);
+
+        let ext = StreamIdExtension::decode(&mut buf.as_slice()).unwrap();
+        assert_eq!(ext.content, sid);
+
+        let parsed = ext.parse().unwrap();
+        assert_eq!(parsed.user(), Some("admin"));
+        assert_eq!(parsed.resource(), Some("1a"));
+        assert_eq!(parsed.session(), Some("2b"));
+        assert_eq!(parsed.mode(), Some("publish"));
+        assert_eq!(parsed.type_(), None);
+        assert_eq!(parsed.host(), None);
+    }
+
+    #[test]
+    fn test_stream_id_builder_invalid() {
+        assert_eq!(StreamIdBuilder::new().build(), Err(StreamIdError::Empty));
+
+        // Separators in values would inject other keys.
+        for val in ["1,m=request", "1=2", ","] {
+            assert_eq!(
+                StreamIdBuilder::new().resource(val).build(),
+                Err(StreamIdError::InvalidValue("r"))
+            );
+        }
+
+        // Everything the builder accepts parses back to the same keys.
+        let sid = StreamIdBuilder::new()
+            .resource("")
+            .mode("request")
+            .build()
+            .unwrap();
+        let parsed: StandardStreamId = sid.parse().unwrap();
+        assert_eq!(parsed.resource(), Some(""));
+        assert_eq!(parsed.mode(), Some("request"));
+        assert_eq!(parsed.session(), None);
+    }
+
+    #[test]
+    fn test_stream_mode() {
+        for mode in [
+            StreamMode::Request,
+            StreamMode::Publish,
+            StreamMode::Bidirectional,
+        ] {
+            let sid: StandardStreamId = format!("#!::m={}", mode).parse().unwrap();
+            assert_eq!(sid.stream_mode(), Ok(mode));
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+
+        let sid: StandardStreamId = "#!::r=1".parse().unwrap();
+        assert_eq!(sid.stream_mode(), Err(StreamModeError::Missing));
+
+        // Modes are case sensitive.
+        for mode in ["Publish", "pub", ""] {
+            let sid: StandardStreamId = format!("#!::m={}", mode).parse().unwrap();
+            assert_eq!(
+                sid.stream_mode(),
+                Err(StreamModeError::Unknown(mode.to_owned()))
+            );
+        }
+    }
 }
@@ -3,7 +3,7 @@ pub mod header;
 
 mod timestamp;
 
-pub use timestamp::Timestamp;
+pub use timestamp::{Timestamp, TimestampUnwrapper};
 
 use std::{
     io::Write,
@@ -18,18 +18,18 @@ use crate::proto::{Bits, Decode, Encode, Zeroable, U32};
 
 use self::{
     builder::{
-        AckAckBuilder, AckBuilder, DropRequestBuilder, KeepaliveBuilder, LightAckBuilder,
-        NakBuilder, ShutdownBuilder,
+        AckAckBuilder, AckBuilder, CongestionWarningBuilder, DropRequestBuilder, KeepaliveBuilder,
+        LightAckBuilder, NakBuilder, ShutdownBuilder, SmallAckBuilder,
     },
     header::{
-        AckAckHeader, AckHeader, DropRequestHeader, HandshakeHeader, KeepaliveHeader, NakHeader,
-        ShutdownHeader,
+        AckAckHeader, AckHeader, CongestionWarningHeader, DropRequestHeader, HandshakeHeader,
+        KeepaliveHeader, NakHeader, PeerErrorHeader, ShutdownHeader,
     },
 };
 
 use super::{EncryptionField, Error, ExtensionField, Extensions, HandshakeType, Header};
 
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct Handshake {
     pub header: HandshakeHeader,
     /// A base protocol version number.  Currently used
@@ -83,7 +83,8 @@ pub struct Handshake {
 /// [`Ipv4Addr`] or [`Ipv6Addr`].
 ///
 /// The value internally consists of four `u32` fields. In the case of an [`Ipv4Addr`] only the
-/// first field is occupied.
+/// first field is occupied. Every field holds four octets of the address in little-endian order,
+/// matching the layout used by the reference implementation.
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct PeerIpAddress(u128);
 
@@ -126,7 +127,7 @@ impl From<IpAddr> for PeerIpAddress {
 impl From<Ipv4Addr> for PeerIpAddress {
     #[inline]
     fn from(value: Ipv4Addr) -> Self {
-        let bits = u32::from(value).to_be();
+        let bits = u32::from_le_bytes(value.octets());
         Self((bits as u128) << 96)
     }
 }
@@ -134,7 +135,12 @@ impl From<Ipv4Addr> for PeerIpAddress {
 impl From<Ipv6Addr> for PeerIpAddress {
     #[inline]
     fn from(value: Ipv6Addr) -> Self {
-        let bits = u128::from(value).to_be();
+        let mut bits = 0;
+        for chunk in value.octets().chunks_exact(4) {
+            let word = u32::from_le_bytes(chunk.try_into().unwrap());
+            bits = (bits << 32) | word as u128;
+        }
+
         Self(bits)
     }
 }
@@ -143,21 +149,25 @@ impl From<PeerIpAddress> for IpAddr {
     #[inline]
     fn from(value: PeerIpAddress) -> Self {
         // Ipv6
-        if value.0 & (1 << 96) - 1 != 0 {
-            let bits = value.0;
+        if value.0 & ((1 << 96) - 1) != 0 {
+            let mut octets = [0; 16];
+            for (index, chunk) in octets.chunks_exact_mut(4).enumerate() {
+                let word = (value.0 >> (96 - index * 32)) as u32;
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
 
-            IpAddr::V6(Ipv6Addr::from(bits.to_be()))
+            IpAddr::V6(Ipv6Addr::from(octets))
             // Ipv4
         } else {
             let bits = (value.0 >> 96) as u32;
 
-            IpAddr::V4(Ipv4Addr::from(bits.to_be()))
+            IpAddr::V4(Ipv4Addr::from(bits.to_le_bytes()))
         }
     }
 }
 
 /// The `Keep-Alive` packet.
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct Keepalive {
     pub header: KeepaliveHeader,
     _unused: u32,
@@ -169,7 +179,7 @@ impl Keepalive {
     }
 }
 
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct Ack {
     pub header: AckHeader,
     pub last_acknowledged_packet_sequence_number: u32,
@@ -198,7 +208,7 @@ impl Ack {
 /// A Light ACK control packet includes only the Last Acknowledged
 /// Packet Sequence Number field.  The Type-specific Information field
 /// should be set to 0.
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct LightAck {
     pub header: AckHeader,
     pub last_acknowledged_packet_sequence_number: u32,
@@ -210,7 +220,9 @@ impl LightAck {
     }
 }
 
-#[derive(Clone, Debug, Default, Packet)]
+/// A Small ACK control packet includes the fields up to and including the Available Buffer
+/// Size field.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct SmallAck {
     pub header: AckHeader,
     pub last_acknowledged_packet_sequence_number: u32,
@@ -219,6 +231,16 @@ pub struct SmallAck {
     pub avaliable_buffer_size: u32,
 }
 
+impl SmallAck {
+    pub fn builder() -> SmallAckBuilder {
+        SmallAckBuilder::new()
+    }
+
+    pub fn set_acknowledgement_number(&mut self, n: u32) {
+        self.header.seg1 = Bits(U32(n));
+    }
+}
+
 ///     0                   1                   2                   3
 /// 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
 /// +-+-+-+-+-+-+-+-+-+-+-+-+- SRT Header +-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -230,7 +252,7 @@ pub struct SmallAck {
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// |                     Destination Socket ID                     |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct AckAck {
     pub header: AckAckHeader,
     _unused: u32,
@@ -259,7 +281,7 @@ impl AckAck {
 
 unsafe impl Zeroable for AckAck {}
 
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct Nak {
     pub header: NakHeader,
     /// A single or a list of lost sequence numbers.
@@ -287,7 +309,23 @@ impl Nak {
     }
 }
 
-#[derive(Clone, Debug, Default, Packet)]
+/// The `Congestion Warning` packet. It is sent by a receiver to signal that it cannot keep up
+/// with the sender, which should then slow down.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
+pub struct CongestionWarning {
+    pub header: CongestionWarningHeader,
+    _unused: u32,
+}
+
+impl CongestionWarning {
+    /// Creates a new [`CongestionWarningBuilder`].
+    #[inline]
+    pub fn builder() -> CongestionWarningBuilder {
+        CongestionWarningBuilder::new()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct Shutdown {
     pub header: ShutdownHeader,
     _unused: u32,
@@ -301,7 +339,7 @@ impl Shutdown {
     }
 }
 
-#[derive(Clone, Debug, Default, Packet)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
 pub struct DropRequest {
     pub header: DropRequestHeader,
     pub first_packet_sequence_number: u32,
@@ -322,6 +360,21 @@ impl DropRequest {
     }
 }
 
+/// The `Peer Error` packet. It is sent by the peer when an error occurred on its side, e.g. a
+/// failed write in file mode.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Packet)]
+pub struct PeerError {
+    pub header: PeerErrorHeader,
+    _unused: u32,
+}
+
+impl PeerError {
+    /// The application defined error code, stored in the Type-specific Information field.
+    pub fn error_code(&self) -> u32 {
+        self.header.seg1.0 .0
+    }
+}
+
 /// A list of a single sequence number, or a range of sequence numbers.
 ///
 /// See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#appendix-A
@@ -382,6 +435,13 @@ impl Encode for SequenceNumbers {
     where
         W: Write,
     {
+        // The first bit is the range flag.
+        for num in [self.first(), self.last()] {
+            if num >> 31 != 0 {
+                return Err(Error::SequenceNumberOverflow(num));
+            }
+        }
+
         match self {
             Self::Single(num) => {
                 let mut bits = Bits(U32(*num));
@@ -391,7 +451,7 @@ impl Encode for SequenceNumbers {
             }
             Self::Range(range) => {
                 let mut start = Bits(U32(*range.start()));
-                start.set_bits(1, 0);
+                start.set_bits(0, 1);
 
                 let mut end = Bits(U32(*range.end()));
                 end.set_bits(0, 0);
@@ -524,17 +584,30 @@ impl Iterator for SequenceNumbersIter {
 
 impl ExactSizeIterator for SequenceNumbersIter {
     fn len(&self) -> usize {
-        self.end - self.start + 1
+        // `start` is past `end` once the iterator is exhausted.
+        (self.end + 1).saturating_sub(self.start)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::ops::DerefMut;
+
+    use proptest::prelude::*;
+
+    use crate::proto::{Bits, Decode, Encode, U32};
 
-    use crate::proto::Decode;
+    use crate::srt::{
+        EncryptionField, Error, ExtensionContent, ExtensionField, ExtensionType, Extensions,
+        GroupExtension, GroupType, HandshakeExtension, HandshakeExtensionFlags,
+        HandshakeExtensionMessage, HandshakeType, Header, IsPacket, Packet, StreamIdExtension,
+    };
 
-    use super::{PeerIpAddress, SequenceNumbers};
+    use super::{
+        Ack, AckAck, CongestionWarning, DropRequest, Handshake, Keepalive, LightAck, Nak,
+        PeerError, PeerIpAddress, SequenceNumbers, Shutdown, ShutdownHeader, SmallAck, Timestamp,
+    };
 
     #[test]
     fn test_sequence_numbers() {
@@ -561,6 +634,21 @@ mod tests {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn test_sequence_numbers_overflow() {
+        assert!(SequenceNumbers::Single((1 << 31) - 1)
+            .encode_to_vec()
+            .is_ok());
+        assert!(matches!(
+            SequenceNumbers::Single(1 << 31).encode_to_vec(),
+            Err(Error::SequenceNumberOverflow(0x8000_0000))
+        ));
+        assert!(matches!(
+            SequenceNumbers::Range(1..=u32::MAX).encode_to_vec(),
+            Err(Error::SequenceNumberOverflow(u32::MAX))
+        ));
+    }
+
     #[test]
     fn test_peer_ip_address() {
         let addr = PeerIpAddress::from(Ipv4Addr::new(192, 168, 178, 68));
@@ -569,5 +657,379 @@ mod tests {
             IpAddr::from(addr),
             IpAddr::V4(Ipv4Addr::new(192, 168, 178, 68))
         );
+
+        let buf = addr.encode_to_vec().unwrap();
+        assert_eq!(
+            buf,
+            [0x44, 0xb2, 0xa8, 0xc0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(PeerIpAddress::decode(&mut buf.as_slice()).unwrap(), addr);
+    }
+
+    #[test]
+    fn test_peer_ip_address_v6() {
+        let ip = Ipv6Addr::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1);
+        let addr = PeerIpAddress::from(ip);
+        assert_eq!(IpAddr::from(addr), IpAddr::V6(ip));
+
+        let buf = addr.encode_to_vec().unwrap();
+        assert_eq!(
+            buf,
+            [0xb8, 0x0d, 0x01, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0x01, 0, 0, 0]
+        );
+        assert_eq!(PeerIpAddress::decode(&mut buf.as_slice()).unwrap(), addr);
+    }
+
+    /// Asserts that the reserved fields of the encoded control `packet` are zero.
+    fn assert_reserved_zero(packet: Packet) {
+        let buf = packet.encode_to_vec().unwrap();
+        assert_eq!(buf.len(), 20);
+
+        // Reserved type-specific bits of the header.
+        assert_eq!(&buf[2..4], [0, 0]);
+        // Reserved control information field.
+        assert_eq!(&buf[16..20], [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_reserved_fields_zero() {
+        assert_reserved_zero(Keepalive::builder().build().upcast());
+        assert_reserved_zero(AckAck::builder().acknowledgement_number(3).build().upcast());
+        assert_reserved_zero(Shutdown::builder().build().upcast());
+
+        // Reserved fields received from a peer are not echoed back.
+        let mut keepalive = Keepalive::builder().build();
+        keepalive._unused = 0xFFFF_FFFF;
+        assert_reserved_zero(keepalive.upcast());
+    }
+
+    #[test]
+    fn test_shutdown_decode() {
+        let mut buf: &[u8] = &[
+            0x80, 0x05, 0x00, 0x00, // Control type
+            0x00, 0x00, 0x00, 0x00, // Type-specific information
+            0x00, 0x00, 0x03, 0xE8, // Timestamp
+            0x00, 0x00, 0x00, 0x2A, // Destination socket id
+            0x00, 0x00, 0x00, 0x00, // Control information field
+        ];
+
+        let packet = Packet::decode(&mut buf).unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(packet.body.len(), 4);
+
+        let shutdown: Shutdown = packet.clone().downcast().unwrap();
+        assert_eq!(shutdown.upcast().body, packet.body);
+
+        // The control information field consumed from a pipelined buffer.
+        let mut body: &[u8] = &[0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF];
+        Shutdown::decode_body(&mut body, ShutdownHeader::default()).unwrap();
+        assert_eq!(body, [0xFF, 0xFF]);
+
+        // The control information field is mandatory.
+        let mut packet = packet;
+        packet.body.truncate(0);
+        assert!(packet.downcast::<Shutdown>().is_err());
+    }
+
+    #[test]
+    fn test_peek() {
+        let packet = Ack::builder()
+            .acknowledgement_number(7)
+            .last_acknowledged_packet_sequence_number(42)
+            .rtt(1000)
+            .build()
+            .upcast();
+
+        let ack: Ack = packet.peek().unwrap();
+        assert_eq!(ack.acknowledgement_number(), 7);
+        assert_eq!(ack.last_acknowledged_packet_sequence_number, 42);
+        assert_eq!(ack.rtt, 1000);
+
+        // The packet is untouched and can still be peeked or downcast.
+        assert!(matches!(
+            packet.peek::<AckAck>(),
+            Err(Error::InvalidControlType(_))
+        ));
+
+        let ack: Ack = packet.downcast().unwrap();
+        assert_eq!(ack.last_acknowledged_packet_sequence_number, 42);
+        assert_eq!(ack.rtt, 1000);
+    }
+
+    #[test]
+    fn test_handshake_size_hint() {
+        let mut packet = Handshake {
+            version: 5,
+            handshake_type: HandshakeType::CONCLUSION,
+            extension_field: ExtensionField(ExtensionField::HSREQ.0 | ExtensionField::CONFIG.0),
+            ..Default::default()
+        };
+        // The fixed fields of the handshake.
+        assert_eq!(packet.body_size_hint(), 48);
+        assert_eq!(packet.clone().upcast().body.len(), 48);
+
+        packet.extensions.0.push(HandshakeExtension {
+            extension_type: ExtensionType::HSREQ,
+            extension_length: 3,
+            extension_content: HandshakeExtensionMessage::default().into(),
+        });
+
+        // Padded to a multiple of four bytes.
+        let ext = ExtensionContent::StreamId(StreamIdExtension {
+            content: "#!::m=publish,r=1,s=1".to_owned(),
+        });
+        packet.extensions.0.push(HandshakeExtension {
+            extension_type: ExtensionType::SID,
+            extension_length: ext.len() as u16,
+            extension_content: ext,
+        });
+
+        let extensions = packet.extensions.encode_to_vec().unwrap();
+        assert_eq!(packet.extensions.size_hint(), extensions.len());
+        assert_eq!(extensions.len(), 4 + 12 + 4 + 24);
+
+        let hint = packet.body_size_hint();
+        assert_eq!(hint, 48 + extensions.len());
+        assert_eq!(packet.upcast().body.len(), hint);
+    }
+
+    /// Encodes `packet` and decodes it again.
+    fn round_trip<T>(packet: T) -> T
+    where
+        T: IsPacket<Error = Error>,
+    {
+        let buf = packet.upcast().encode_to_vec().unwrap();
+        let mut bytes = buf.as_slice();
+        let packet = Packet::decode(&mut bytes).unwrap();
+        assert!(bytes.is_empty());
+
+        packet.downcast().unwrap()
+    }
+
+    /// A control packet header `H` with random type-specific information, timestamp and
+    /// destination socket id.
+    fn header<H>() -> impl Strategy<Value = H>
+    where
+        H: Default + DerefMut<Target = Header> + std::fmt::Debug,
+    {
+        any::<(u32, u32, u32)>().prop_map(|(seg1, timestamp, destination_socket_id)| {
+            let mut header = H::default();
+            header.seg1 = Bits(U32(seg1));
+            header.timestamp = Timestamp::from_micros(timestamp);
+            header.destination_socket_id = destination_socket_id;
+            header
+        })
+    }
+
+    /// A sequence number, which uses only 31 bits.
+    fn sequence_number() -> impl Strategy<Value = u32> {
+        0..1u32 << 31
+    }
+
+    fn sequence_numbers() -> impl Strategy<Value = SequenceNumbers> {
+        prop_oneof![
+            sequence_number().prop_map(SequenceNumbers::Single),
+            (sequence_number(), sequence_number())
+                .prop_map(|(a, b)| { SequenceNumbers::Range(a.min(b)..=a.max(b)) }),
+        ]
+    }
+
+    fn extension() -> impl Strategy<Value = HandshakeExtension> {
+        let hsreq = (
+            prop_oneof![Just(ExtensionType::HSREQ), Just(ExtensionType::HSRSP)],
+            any::<(u32, u32, u16, u16)>(),
+        )
+            .prop_map(|(extension_type, (version, flags, recv, send))| {
+                (
+                    extension_type,
+                    ExtensionContent::Handshake(HandshakeExtensionMessage {
+                        srt_version: version,
+                        srt_flags: HandshakeExtensionFlags(flags),
+                        receiver_tsbpd_delay: recv,
+                        sender_tsbpd_delay: send,
+                    }),
+                )
+            });
+
+        // Trailing NULs are indistinguishable from the padding.
+        let sid = "[^\\x00]{0,128}".prop_map(|content| {
+            (
+                ExtensionType::SID,
+                ExtensionContent::StreamId(StreamIdExtension { content }),
+            )
+        });
+
+        let group =
+            any::<(u32, u8, u8, u16)>().prop_map(|(group_id, group_type, flags, weight)| {
+                (
+                    ExtensionType::GROUP,
+                    ExtensionContent::Group(GroupExtension {
+                        group_id,
+                        group_type: GroupType(group_type),
+                        flags,
+                        weight,
+                    }),
+                )
+            });
+
+        prop_oneof![hsreq, sid, group].prop_map(|(extension_type, extension_content)| {
+            HandshakeExtension {
+                extension_type,
+                extension_length: extension_content.len() as u16,
+                extension_content,
+            }
+        })
+    }
+
+    prop_compose! {
+        fn handshake()(
+            header in header(),
+            version in any::<u32>(),
+            encryption_field in prop::sample::select(vec![
+                EncryptionField::NONE,
+                EncryptionField::AES128,
+                EncryptionField::AES192,
+                EncryptionField::AES256,
+            ]),
+            extension_field in any::<u16>(),
+            (initial_packet_sequence_number, maximum_transmission_unit_size, maximum_flow_window_size)
+                in any::<(u32, u32, u32)>(),
+            handshake_type in prop::sample::select(
+                [0xFFFF_FFFD, 0xFFFF_FFFE, 0xFFFF_FFFF, 0, 1]
+                    .into_iter()
+                    .chain(1000..=1015)
+                    .collect::<Vec<u32>>(),
+            ),
+            (srt_socket_id, syn_cookie) in any::<(u32, u32)>(),
+            peer_ip_address in any::<u128>(),
+            extensions in prop::collection::vec(extension(), 0..4),
+        ) -> Handshake {
+            Handshake {
+                header,
+                version,
+                encryption_field,
+                extension_field: ExtensionField(extension_field),
+                initial_packet_sequence_number,
+                maximum_transmission_unit_size,
+                maximum_flow_window_size,
+                handshake_type: HandshakeType::from_u32(handshake_type).unwrap(),
+                srt_socket_id,
+                syn_cookie,
+                peer_ip_address: PeerIpAddress(peer_ip_address),
+                extensions: Extensions(extensions),
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_handshake_round_trip(packet in handshake()) {
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_keepalive_round_trip(header in header()) {
+            let packet = Keepalive { header, _unused: 0 };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_ack_round_trip(
+            header in header(),
+            last_acknowledged_packet_sequence_number in sequence_number(),
+            (rtt, rtt_variance, avaliable_buffer_size) in any::<(u32, u32, u32)>(),
+            (packets_receiving_rate, estimated_link_capacity, receiving_rate)
+                in any::<(u32, u32, u32)>(),
+        ) {
+            let packet = Ack {
+                header,
+                last_acknowledged_packet_sequence_number,
+                rtt,
+                rtt_variance,
+                avaliable_buffer_size,
+                packets_receiving_rate,
+                estimated_link_capacity,
+                receiving_rate,
+            };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_light_ack_round_trip(
+            header in header(),
+            last_acknowledged_packet_sequence_number in sequence_number(),
+        ) {
+            let packet = LightAck {
+                header,
+                last_acknowledged_packet_sequence_number,
+            };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_small_ack_round_trip(
+            header in header(),
+            last_acknowledged_packet_sequence_number in sequence_number(),
+            (rtt, rtt_variance, avaliable_buffer_size) in any::<(u32, u32, u32)>(),
+        ) {
+            let packet = SmallAck {
+                header,
+                last_acknowledged_packet_sequence_number,
+                rtt,
+                rtt_variance,
+                avaliable_buffer_size,
+            };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_ackack_round_trip(header in header()) {
+            let packet = AckAck { header, _unused: 0 };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_nak_round_trip(
+            header in header(),
+            lost_packet_sequence_numbers in sequence_numbers(),
+        ) {
+            let packet = Nak {
+                header,
+                lost_packet_sequence_numbers,
+            };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_congestion_warning_round_trip(header in header()) {
+            let packet = CongestionWarning { header, _unused: 0 };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_shutdown_round_trip(header in header()) {
+            let packet = Shutdown { header, _unused: 0 };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_drop_request_round_trip(
+            header in header(),
+            first_packet_sequence_number in sequence_number(),
+            last_packet_sequence_number in sequence_number(),
+        ) {
+            let packet = DropRequest {
+                header,
+                first_packet_sequence_number,
+                last_packet_sequence_number,
+            };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
+
+        #[test]
+        fn test_peer_error_round_trip(header in header()) {
+            let packet = PeerError { header, _unused: 0 };
+            prop_assert_eq!(round_trip(packet.clone()), packet);
+        }
     }
 }
@@ -1,9 +1,10 @@
 use bytes::Bytes;
 
-use crate::srt::{DataPacket, PacketPosition};
+use crate::srt::{DataPacket, EncryptionFlag, PacketPosition};
 
 use super::{
-    Ack, AckAck, DropRequest, Keepalive, LightAck, Nak, SequenceNumbers, Shutdown, SmallAck,
+    Ack, AckAck, CongestionWarning, DropRequest, Keepalive, LightAck, Nak, SequenceNumbers,
+    Shutdown, SmallAck,
 };
 
 /// A builder for a [`Keepalive`] packet.
@@ -115,6 +116,11 @@ impl SmallAckBuilder {
         Self(SmallAck::default())
     }
 
+    pub fn acknowledgement_number(mut self, n: u32) -> Self {
+        self.0.set_acknowledgement_number(n);
+        self
+    }
+
     pub const fn last_acknowledged_packet_sequence_number(mut self, n: u32) -> Self {
         self.0.last_acknowledged_packet_sequence_number = n;
         self
@@ -130,6 +136,11 @@ impl SmallAckBuilder {
         self
     }
 
+    pub const fn avaliable_buffer_size(mut self, n: u32) -> Self {
+        self.0.avaliable_buffer_size = n;
+        self
+    }
+
     /// Consumes this `SmallAckBuilder`, returning the constructed [`SmallAck`] packet.
     #[inline]
     pub const fn build(self) -> SmallAck {
@@ -210,6 +221,25 @@ impl ShutdownBuilder {
     }
 }
 
+/// A builder for a [`CongestionWarning`] packet.
+#[derive(Clone, Debug, Default)]
+pub struct CongestionWarningBuilder(CongestionWarning);
+
+impl CongestionWarningBuilder {
+    /// Creates a new `CongestionWarningBuilder`.
+    #[inline]
+    pub fn new() -> Self {
+        Self(CongestionWarning::default())
+    }
+
+    /// Consumes this `CongestionWarningBuilder`, returning the constructed [`CongestionWarning`]
+    /// packet.
+    #[inline]
+    pub const fn build(self) -> CongestionWarning {
+        self.0
+    }
+}
+
 /// A builder for a [`DropRequest`] packet.
 #[derive(Clone, Debug, Default)]
 pub struct DropRequestBuilder(DropRequest);
@@ -303,6 +333,16 @@ impl DataPacketBuilder {
         self
     }
 
+    /// Sets the key encryption flag of the [`DataPacket`]. This only sets the flag, the body is
+    /// not encrypted.
+    ///
+    /// **The default value is [`EncryptionFlag::None`].**
+    #[inline]
+    pub fn encryption(mut self, val: EncryptionFlag) -> Self {
+        self.0.header().set_encryption_flag(val);
+        self
+    }
+
     #[inline]
     pub fn body<T>(mut self, val: T) -> Self
     where
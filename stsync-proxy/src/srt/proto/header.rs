@@ -4,7 +4,10 @@ use crate::srt::{ControlPacketType, Error, PacketType};
 
 // Unused imports are used for autogenerated documentation.
 #[allow(unused_imports)]
-use super::{Ack, AckAck, DropRequest, Handshake, Header, Keepalive, Nak, Shutdown};
+use super::{
+    Ack, AckAck, CongestionWarning, DropRequest, Handshake, Header, Keepalive, Nak, PeerError,
+    Shutdown,
+};
 
 macro_rules! header_impl {
     ($id:ident, $typ:expr $(, $doc:ty)?) => {
@@ -13,7 +16,7 @@ macro_rules! header_impl {
             #[doc = ""]
             #[doc = concat!("`", stringify!($doc), "` is a wrapper around [`Header`].")]
         )?
-        #[derive(Copy, Clone, Debug)]
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
         pub struct $id(Header);
 
         impl TryFrom<Header> for $id {
@@ -67,6 +70,12 @@ header_impl!(HandshakeHeader, ControlPacketType::Handshake, Handshake);
 header_impl!(KeepaliveHeader, ControlPacketType::Keepalive, Keepalive);
 header_impl!(AckHeader, ControlPacketType::Ack, Ack);
 header_impl!(NakHeader, ControlPacketType::Nak, Nak);
+header_impl!(
+    CongestionWarningHeader,
+    ControlPacketType::CongestionWarning,
+    CongestionWarning
+);
 header_impl!(ShutdownHeader, ControlPacketType::Shutdown, Shutdown);
 header_impl!(AckAckHeader, ControlPacketType::AckAck, AckAck);
 header_impl!(DropRequestHeader, ControlPacketType::DropReq, DropRequest);
+header_impl!(PeerErrorHeader, ControlPacketType::PeerError, PeerError);
@@ -11,10 +11,13 @@ const WRAP_PERIOD: u32 = u32::MAX - Duration::from_secs(30).as_micros() as u32;
 
 /// A SRT timestamp.
 ///
-/// The timestamp wraps around every 01:11:35 hours (`0xFFFF_FFFF`). The wrapping period starts
+/// A `Timestamp` is a wrapping 32-bit count of microseconds since the start of the connection.
+/// It wraps around every 01:11:35 hours (`0xFFFF_FFFF`, see [`WRAP`]). The wrapping period starts
 /// 30 seconds before that point (`0xFE36_3c7F`). The [`PartialEq`] and [`PartialOrd`]
 /// implementations mimic this behaivoir.
 ///
+/// [`WRAP`]: Self::WRAP
+///
 /// See https://datatracker.ietf.org/doc/html/draft-sharabayko-srt-01#section-4.5.1.1 for more
 /// details.
 #[derive(Copy, Clone, Debug, Default, Hash)]
@@ -25,6 +28,9 @@ pub struct Timestamp {
 }
 
 impl Timestamp {
+    /// The time after which a `Timestamp` wraps around to zero.
+    pub const WRAP: Duration = Duration::from_micros(1 << 32);
+
     pub fn from_start(start: Instant) -> Self {
         Self::from_micros(start.elapsed().as_micros() as u32)
     }
@@ -47,6 +53,22 @@ impl Timestamp {
         Duration::from_micros(self.timestamp as u64)
     }
 
+    /// Returns the time elapsed from `epoch` to `self`, accounting for wrap around.
+    ///
+    /// The result is always less than [`WRAP`]. Returns `None` if `self` comes before `epoch`,
+    /// i.e. it is more than half a wrap (about 35 minutes) after `epoch`.
+    ///
+    /// [`WRAP`]: Self::WRAP
+    #[inline]
+    pub fn to_duration_since(self, epoch: Self) -> Option<Duration> {
+        if self < epoch {
+            None
+        } else {
+            let micros = self.timestamp.wrapping_sub(epoch.timestamp);
+            Some(Duration::from_micros(micros as u64))
+        }
+    }
+
     /// Returns `true` if the delivered timestamp is within the wrapping period.
     #[inline]
     pub fn is_wrapping(self) -> bool {
@@ -54,6 +76,54 @@ impl Timestamp {
     }
 }
 
+/// Maps [`Timestamp`]s to [`Instant`]s across wrap arounds.
+///
+/// Every `Timestamp` is unwrapped relative to the latest `Timestamp` seen so far, so consecutive
+/// timestamps must be less than half a wrap (about 35 minutes) apart.
+#[derive(Clone, Debug)]
+pub struct TimestampUnwrapper {
+    /// The [`Instant`] of the timestamp zero in the current wrap cycle.
+    start: Instant,
+    /// The latest `Timestamp` seen.
+    last: Timestamp,
+}
+
+impl TimestampUnwrapper {
+    /// Creates a new `TimestampUnwrapper` where the timestamp zero of the first wrap cycle is at
+    /// `start`.
+    #[inline]
+    pub const fn new(start: Instant) -> Self {
+        Self {
+            start,
+            last: Timestamp::from_micros(0),
+        }
+    }
+
+    /// Returns the [`Instant`] of `timestamp`.
+    ///
+    /// A `timestamp` after the latest one that wrapped around starts a new wrap cycle. Late
+    /// timestamps from the end of the previous wrap cycle map to an [`Instant`] before the start
+    /// of the current cycle.
+    pub fn instant(&mut self, timestamp: Timestamp) -> Instant {
+        let instant = self.start + timestamp.to_duration();
+
+        if timestamp >= self.last {
+            if timestamp.timestamp < self.last.timestamp {
+                self.start += Timestamp::WRAP;
+                self.last = timestamp;
+                return instant + Timestamp::WRAP;
+            }
+
+            self.last = timestamp;
+            instant
+        } else if timestamp.timestamp > self.last.timestamp {
+            instant.checked_sub(Timestamp::WRAP).unwrap_or(self.start)
+        } else {
+            instant
+        }
+    }
+}
+
 impl From<Timestamp> for Duration {
     #[inline]
     fn from(value: Timestamp) -> Self {
@@ -109,3 +179,96 @@ impl Ord for Timestamp {
         serial::cmp::<32>(self.timestamp, other.timestamp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Timestamp, TimestampUnwrapper};
+
+    #[test]
+    fn test_to_duration_since() {
+        let epoch = Timestamp::from_micros(1000);
+        assert_eq!(
+            Timestamp::from_micros(3000).to_duration_since(epoch),
+            Some(Duration::from_micros(2000))
+        );
+        assert_eq!(epoch.to_duration_since(epoch), Some(Duration::ZERO));
+        assert_eq!(Timestamp::from_micros(500).to_duration_since(epoch), None);
+
+        // Across the wrap boundary.
+        let epoch = Timestamp::from_micros(u32::MAX - 999);
+        assert_eq!(
+            Timestamp::from_micros(u32::MAX).to_duration_since(epoch),
+            Some(Duration::from_micros(999))
+        );
+        assert_eq!(
+            Timestamp::from_micros(0).to_duration_since(epoch),
+            Some(Duration::from_micros(1000))
+        );
+        assert_eq!(
+            Timestamp::from_micros(1000).to_duration_since(epoch),
+            Some(Duration::from_micros(2000))
+        );
+        assert_eq!(
+            Timestamp::from_micros(0).to_duration_since(Timestamp::from_micros(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unwrapper() {
+        let start = Instant::now() + Duration::from_secs(60);
+        let mut unwrapper = TimestampUnwrapper::new(start);
+        let micros = |n: u64| Duration::from_micros(n);
+
+        assert_eq!(unwrapper.instant(Timestamp::from_micros(0)), start);
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(1000)),
+            start + micros(1000)
+        );
+
+        // Crossing 2^31 keeps going forward.
+        for n in [(1 << 31) - 1, 1 << 31, (1 << 31) + 1, 3 << 30] {
+            assert_eq!(
+                unwrapper.instant(Timestamp::from_micros(n as u32)),
+                start + micros(n)
+            );
+        }
+
+        // A late timestamp before the latest one.
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(1 << 31)),
+            start + micros(1 << 31)
+        );
+
+        // Crossing 2^32 starts a new wrap cycle.
+        let n = u32::MAX as u64;
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(u32::MAX)),
+            start + micros(n)
+        );
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(0)),
+            start + micros(1 << 32)
+        );
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(1000)),
+            start + micros((1 << 32) + 1000)
+        );
+
+        // Late timestamps from the end of the previous wrap cycle.
+        assert_eq!(
+            unwrapper.instant(Timestamp::from_micros(u32::MAX - 999)),
+            start + micros(n - 999)
+        );
+
+        // The second wrap cycle crosses 2^31 as well.
+        for n in [1 << 30, 1 << 31, 3 << 30] {
+            assert_eq!(
+                unwrapper.instant(Timestamp::from_micros(n as u32)),
+                start + micros((1 << 32) + n)
+            );
+        }
+    }
+}
@@ -1,24 +1,65 @@
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+/// The data structure backing a [`SegmentQueue`].
+///
+/// Which one is faster depends on the reorder depth, not the window size (see `bench_queue`).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueKind {
+    /// A binary heap. Pushing and popping is `O(log n)` in the window size. This is faster than
+    /// [`Sorted`] if most segments arrive deeply out of order.
+    ///
+    /// [`Sorted`]: Self::Sorted
+    Heap,
+    /// A sorted ring buffer. Pushing is `O(d)` in the reorder depth, popping is `O(1)`. This is
+    /// faster than [`Heap`] if most segments arrive in order, as in a live stream.
+    ///
+    /// [`Heap`]: Self::Heap
+    // "auto" selected between both kinds in earlier versions.
+    #[default]
+    #[serde(alias = "auto")]
+    Sorted,
+}
 
 /// A fixed size ordered queue.
 #[derive(Clone, Debug)]
 pub struct SegmentQueue<S>
+where
+    S: Ord,
+{
+    queue: Queue<S>,
+    capacity: usize,
+}
+
+#[derive(Clone, Debug)]
+enum Queue<S>
 where
     S: Ord,
 {
     // We want a min-heap.
-    queue: BinaryHeap<Reverse<S>>,
+    Heap(BinaryHeap<Reverse<S>>),
+    /// Sorted in ascending order.
+    Sorted(VecDeque<S>),
 }
 
 impl<S> SegmentQueue<S>
 where
     S: Ord,
 {
-    #[inline]
-    pub fn new(size: usize) -> Self {
+    /// Creates a new `SegmentQueue` holding up to `size` segments, using the data structure of
+    /// the given `kind`.
+    pub fn new(size: usize, kind: QueueKind) -> Self {
+        let queue = match kind {
+            QueueKind::Heap => Queue::Heap(BinaryHeap::with_capacity(size)),
+            QueueKind::Sorted => Queue::Sorted(VecDeque::with_capacity(size)),
+        };
+
         Self {
-            queue: BinaryHeap::with_capacity(size),
+            queue,
+            capacity: size,
         }
     }
 
@@ -28,27 +69,47 @@ where
             return;
         }
 
-        self.queue.push(Reverse(segment));
+        match &mut self.queue {
+            Queue::Heap(queue) => queue.push(Reverse(segment)),
+            Queue::Sorted(queue) => {
+                // Segments mostly arrive in order, so search from the back.
+                let mut index = queue.len();
+                while index > 0 && queue[index - 1] > segment {
+                    index -= 1;
+                }
+
+                queue.insert(index, segment);
+            }
+        }
     }
 
     #[inline]
-    pub fn peek(&mut self) -> Option<&'_ S> {
-        self.queue.peek().map(|s| &s.0)
+    pub fn peek(&self) -> Option<&'_ S> {
+        match &self.queue {
+            Queue::Heap(queue) => queue.peek().map(|s| &s.0),
+            Queue::Sorted(queue) => queue.front(),
+        }
     }
 
     #[inline]
     pub fn pop(&mut self) -> Option<S> {
-        self.queue.pop().map(|s| s.0)
+        match &mut self.queue {
+            Queue::Heap(queue) => queue.pop().map(|s| s.0),
+            Queue::Sorted(queue) => queue.pop_front(),
+        }
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.queue.len()
+        match &self.queue {
+            Queue::Heap(queue) => queue.len(),
+            Queue::Sorted(queue) => queue.len(),
+        }
     }
 
     #[inline]
     pub fn capacity(&self) -> usize {
-        self.queue.capacity()
+        self.capacity
     }
 
     #[inline]
@@ -58,17 +119,28 @@ where
 
     #[inline]
     pub fn clear(&mut self) {
-        self.queue.clear();
+        match &mut self.queue {
+            Queue::Heap(queue) => queue.clear(),
+            Queue::Sorted(queue) => queue.clear(),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SegmentQueue;
+    use std::time::Instant;
+
+    use super::{QueueKind, SegmentQueue};
 
     #[test]
     fn test_queue() {
-        let mut queue = SegmentQueue::new(8192);
+        for kind in [QueueKind::Heap, QueueKind::Sorted] {
+            test_queue_kind(kind);
+        }
+    }
+
+    fn test_queue_kind(kind: QueueKind) {
+        let mut queue = SegmentQueue::new(8192, kind);
         queue.push(5);
 
         assert_eq!(*queue.peek().unwrap(), 5);
@@ -94,4 +166,63 @@ mod tests {
         assert_eq!(queue.len(), 0);
         assert!(queue.is_empty());
     }
+
+    #[test]
+    fn test_queue_kind_deserialize() {
+        #[derive(serde::Deserialize)]
+        struct Config {
+            kind: QueueKind,
+        }
+
+        for (s, kind) in [
+            ("heap", QueueKind::Heap),
+            ("sorted", QueueKind::Sorted),
+            ("auto", QueueKind::Sorted),
+        ] {
+            let config: Config = toml::from_str(&format!("kind = \"{}\"", s)).unwrap();
+            assert_eq!(config.kind, kind);
+        }
+    }
+
+    /// Pushes `count` segments through a queue holding `window` segments, where every segment
+    /// arrives up to `depth` positions late.
+    fn run(kind: QueueKind, window: usize, depth: usize, count: usize) {
+        let mut queue = SegmentQueue::new(window, kind);
+
+        for block in (0..count).step_by(depth) {
+            // Reverse every block to get a reorder depth of `depth`.
+            for seq in (block..block + depth).rev() {
+                if queue.len() == window {
+                    queue.pop();
+                }
+
+                queue.push(seq);
+            }
+        }
+    }
+
+    /// Compares the queue kinds under small and large reorder depths.
+    ///
+    /// Run with `cargo test --release bench_queue -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_queue() {
+        const COUNT: usize = 1 << 20;
+
+        for window in [128, 8192] {
+            for depth in [1, 4, 64] {
+                for kind in [QueueKind::Heap, QueueKind::Sorted] {
+                    let now = Instant::now();
+                    run(kind, window, depth, COUNT);
+                    println!(
+                        "{:?} window={} depth={}: {:?}",
+                        kind,
+                        window,
+                        depth,
+                        now.elapsed()
+                    );
+                }
+            }
+        }
+    }
 }
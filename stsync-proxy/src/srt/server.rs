@@ -1,3 +1,4 @@
+use ahash::RandomState;
 use bytes::BytesMut;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
@@ -7,14 +8,16 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tracing::{event, span, Level};
 
+use super::access_log::AccessLog;
 use super::config::Config;
 use super::state::State;
-use crate::proto::Decode;
 use crate::session::SessionManager;
-use crate::srt::socket::SrtSocket;
+use crate::srt::proto::Handshake;
+use crate::srt::socket::{RecvBatch, SrtSocket};
 use crate::srt::state::ConnectionId;
 
 use super::{Error, IsPacket, Packet};
@@ -39,6 +42,14 @@ where
 
         let socket = SrtSocket::new(config.bind)?;
 
+        if let Some(size) = config.recv_buffer {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        if let Some(size) = config.send_buffer {
+            socket.set_send_buffer_size(size)?;
+        }
+
         let rx = socket.recv_buffer_size()?;
         let tx = socket.send_buffer_size()?;
 
@@ -51,15 +62,33 @@ where
                 .unwrap_or(1)
         });
 
+        let access_log = match &config.access_log {
+            Some(target) => Some(AccessLog::open(target)?),
+            None => None,
+        };
+
         let socket = Arc::new(socket);
-        let state = State::new(session_manager, config);
+        let state = State::with_access_log(session_manager, config, access_log);
+
+        let shards: Vec<Option<Shard>> = if state.config.worker_sharding {
+            let (shards, rx) = Shards::new(num_workers);
+            rx.into_iter()
+                .map(|rx| Some((shards.clone(), rx)))
+                .collect()
+        } else {
+            (0..num_workers).map(|_| None).collect()
+        };
 
         let workers = FuturesUnordered::new();
-        for i in 0..num_workers {
-            workers.push(Worker::new(i, socket.clone(), state.clone()));
+        for (i, shard) in shards.into_iter().enumerate() {
+            workers.push(Worker::new(i, socket.clone(), state.clone(), shard));
         }
 
-        tracing::info!("Spawned {} worker threads", num_workers);
+        tracing::info!(
+            "Spawned {} worker threads (sharding: {})",
+            num_workers,
+            state.config.worker_sharding
+        );
 
         Ok(Self { state, workers })
     }
@@ -72,6 +101,9 @@ where
     type Output = Result<(), Error>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The socket is bound in `new`, the workers run once the server is polled.
+        self.state.set_ready(true);
+
         loop {
             match self.workers.poll_next_unpin(cx) {
                 Poll::Pending => return Poll::Pending,
@@ -83,6 +115,15 @@ where
     }
 }
 
+impl<S> Drop for Server<S>
+where
+    S: SessionManager,
+{
+    fn drop(&mut self) {
+        self.state.set_ready(false);
+    }
+}
+
 async fn handle_message<S>(
     packet: Packet,
     addr: SocketAddr,
@@ -96,6 +137,17 @@ where
 
     // A destination socket id of 0 indicates a handshake request.
     if packet.header.destination_socket_id == 0 {
+        if tracing::enabled!(Level::TRACE) {
+            if let Ok(hs) = packet.peek::<Handshake>() {
+                tracing::trace!(
+                    "Received handshake {:?} (version {}) from {}",
+                    hs.handshake_type,
+                    hs.version,
+                    stream.addr
+                );
+            }
+        }
+
         match packet.downcast() {
             Ok(packet) => {
                 super::handshake::handshake(packet, stream, state).await?;
@@ -126,6 +178,74 @@ where
     Ok(())
 }
 
+/// Returns `true` if `buf` filled the whole receive buffer of `size` bytes and may have been
+/// truncated. Such datagrams are dropped and counted in the metrics.
+fn is_truncated<S>(buf: &BytesMut, addr: SocketAddr, size: usize, state: &State<S>) -> bool
+where
+    S: SessionManager,
+{
+    if buf.len() != size {
+        return false;
+    }
+
+    tracing::warn!(
+        "Dropping possibly truncated datagram of {} bytes from {}",
+        buf.len(),
+        addr
+    );
+
+    state.metrics.datagrams_truncated.inc();
+    true
+}
+
+/// Decodes and handles a single datagram received into a buffer of `size` bytes.
+async fn handle_datagram<S>(
+    ident: usize,
+    mut buf: BytesMut,
+    addr: SocketAddr,
+    size: usize,
+    socket: &SrtSocket,
+    state: &State<S>,
+    shards: Option<&Shards>,
+) -> Result<(), Error>
+where
+    S: SessionManager,
+{
+    if is_truncated(&buf, addr, size, state) {
+        return Ok(());
+    }
+
+    tracing::trace!("[{}] Got {} bytes from {}", ident, buf.len(), addr);
+
+    let packet = match Packet::decode_limited(&mut buf, state.config.mtu as usize) {
+        Ok(packet) => packet,
+        Err(err) => {
+            tracing::debug!("[{}] Failed to decode packet: {}", ident, err);
+            return Ok(());
+        }
+    };
+
+    if let Some(loss) = &state.loss {
+        if loss.drop_packet(&packet) {
+            tracing::trace!("[{}] Injected loss of packet from {}", ident, addr);
+            return Ok(());
+        }
+    }
+
+    if let Some(shards) = shards {
+        let index = shards.index(addr);
+        if index != ident {
+            if !shards.send(index, packet, addr) {
+                tracing::debug!("[{}] Queue of worker {} is full", ident, index);
+            }
+
+            return Ok(());
+        }
+    }
+
+    handle_message(packet, addr, socket, state).await
+}
+
 #[derive(Clone, Debug)]
 pub struct SrtStream<'a> {
     pub socket: &'a SrtSocket,
@@ -141,13 +261,62 @@ impl<'a> SrtStream<'a> {
     }
 }
 
+/// Distributes packets between workers by the hash of the peer address.
+#[derive(Clone, Debug)]
+struct Shards {
+    workers: Arc<[mpsc::Sender<(Packet, SocketAddr)>]>,
+    hasher: RandomState,
+}
+
+impl Shards {
+    /// The number of packets queued for a worker before packets are dropped.
+    const QUEUE_SIZE: usize = 1024;
+
+    /// Creates new `Shards` for `num_workers` workers, returning the receiving end for every
+    /// worker.
+    fn new(num_workers: usize) -> (Self, Vec<mpsc::Receiver<(Packet, SocketAddr)>>) {
+        let (workers, rx) = (0..num_workers)
+            .map(|_| mpsc::channel(Self::QUEUE_SIZE))
+            .unzip::<_, _, Vec<_>, Vec<_>>();
+
+        let this = Self {
+            workers: workers.into(),
+            hasher: RandomState::new(),
+        };
+
+        (this, rx)
+    }
+
+    /// Returns the index of the worker responsible for packets received from `addr`.
+    ///
+    /// Handshakes are addressed to the socket id 0 and only later packets to the socket id of
+    /// the connection, so only the address routes all packets of a connection to one worker.
+    fn index(&self, addr: SocketAddr) -> usize {
+        self.hasher.hash_one(addr) as usize % self.workers.len()
+    }
+
+    /// Hands `packet` to the worker with the given `index`. Returns `false` if the queue of the
+    /// worker is full and the packet was dropped.
+    fn send(&self, index: usize, packet: Packet, addr: SocketAddr) -> bool {
+        // Never wait for the other worker, it may be waiting for this one.
+        self.workers[index].try_send((packet, addr)).is_ok()
+    }
+}
+
+type Shard = (Shards, mpsc::Receiver<(Packet, SocketAddr)>);
+
 #[derive(Debug)]
 struct Worker {
     handle: JoinHandle<Result<(), Error>>,
 }
 
 impl Worker {
-    pub fn new<S>(ident: usize, socket: Arc<SrtSocket>, state: State<S>) -> Self
+    pub fn new<S>(
+        ident: usize,
+        socket: Arc<SrtSocket>,
+        state: State<S>,
+        shard: Option<Shard>,
+    ) -> Self
     where
         S: SessionManager,
     {
@@ -161,21 +330,37 @@ impl Worker {
                 ident
             );
 
+            // The MTU includes the IP and UDP headers, so no valid datagram can fill the
+            // buffer.
+            let size = state.config.mtu as usize;
+
+            let (shards, mut queue) = match shard {
+                Some((shards, queue)) => (Some(shards), Some(queue)),
+                None => (None, None),
+            };
+
+            let mut batch = RecvBatch::new(size);
+
             loop {
-                let mut buf = BytesMut::zeroed(1500);
-                let (len, addr) = socket.recv_from(&mut buf).await?;
-                tracing::trace!("[{}] Got {} bytes from {}", ident, len, addr);
-                buf.truncate(len);
-
-                let packet = match Packet::decode(&mut buf) {
-                    Ok(packet) => packet,
-                    Err(err) => {
-                        tracing::debug!("[{}] Failed to decode packet: {}", ident, err);
-                        continue;
+                match &mut queue {
+                    Some(queue) => tokio::select! {
+                        res = socket.recv_batch(&mut batch) => {
+                            res?;
+                        }
+                        Some((packet, addr)) = queue.recv() => {
+                            handle_message(packet, addr, &socket, &state).await?;
+                            continue;
+                        }
+                    },
+                    None => {
+                        socket.recv_batch(&mut batch).await?;
                     }
-                };
+                }
 
-                handle_message(packet, addr, &socket, &state).await?;
+                for (buf, addr) in batch.drain() {
+                    handle_datagram(ident, buf, addr, size, &socket, &state, shards.as_ref())
+                        .await?;
+                }
             }
         });
 
@@ -190,3 +375,519 @@ impl Future for Worker {
         self.handle.poll_unpin(cx).map(|res| res.unwrap())
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use bytes::Bytes;
+    use futures::StreamExt;
+    use tokio::net::UdpSocket;
+
+    use crate::proto::{Decode, Encode};
+    use crate::session::buffer::{BufferSessionManager, SessionKey};
+    use crate::session::{ResourceId, SessionId, SessionManager};
+    use crate::srt::config::tests::config;
+    use crate::srt::config::Config;
+    use crate::srt::loss::LossConfig;
+    use crate::srt::socket::{RecvBatch, SrtSocket};
+    use crate::srt::state::State;
+    use crate::srt::{
+        DataPacket, EncryptionField, ExtensionContent, ExtensionField, ExtensionType,
+        HandshakeExtension, HandshakeExtensionFlags, HandshakeExtensionMessage, HandshakeType,
+        Packet, PacketType, StreamIdExtension,
+    };
+
+    use crate::srt::proto::{Handshake, Keepalive, Nak, Shutdown, Timestamp};
+    use crate::srt::IsPacket;
+
+    use super::{is_truncated, Server, Shards};
+
+    #[tokio::test]
+    async fn test_ready() {
+        let server = Server::new(BufferSessionManager::new(Default::default()), config()).unwrap();
+        let state = server.state.clone();
+        assert!(!state.is_ready());
+
+        let handle = tokio::task::spawn(server);
+        tokio::task::yield_now().await;
+        assert!(state.is_ready());
+
+        handle.abort();
+        let _ = handle.await;
+        assert!(!state.is_ready());
+    }
+
+    #[tokio::test]
+    async fn test_recv_datagram_oversized() {
+        let state = State::new(BufferSessionManager::new(Default::default()), config());
+        let socket = SrtSocket::new(state.config.bind).unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        peer.send_to(&[0; 2000], addr).await.unwrap();
+        peer.send_to(&[0; 1000], addr).await.unwrap();
+
+        let size = state.config.mtu as usize;
+        let mut batch = RecvBatch::new(size);
+        let mut datagrams = Vec::new();
+        while datagrams.len() < 2 {
+            socket.recv_batch(&mut batch).await.unwrap();
+            datagrams.extend(batch.drain());
+        }
+
+        let (buf, from) = &datagrams[0];
+        assert!(is_truncated(buf, *from, size, &state));
+        assert_eq!(state.metrics.datagrams_truncated.get(), 1);
+
+        let (buf, from) = &datagrams[1];
+        assert!(!is_truncated(buf, *from, size, &state));
+        assert_eq!(*from, peer.local_addr().unwrap());
+        assert_eq!(buf.len(), 1000);
+        assert_eq!(state.metrics.datagrams_truncated.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_shards() {
+        let (shards, mut rx) = Shards::new(4);
+        let addr = "127.0.0.1:9000".parse().unwrap();
+
+        let packet = |id| {
+            let mut packet = Keepalive::builder().build().upcast();
+            packet.header.destination_socket_id = id;
+            packet
+        };
+
+        // A peer always maps to the same worker.
+        let index = shards.index(addr);
+        for _ in 0..10 {
+            assert_eq!(shards.index(addr), index);
+        }
+
+        // Peers are distributed over all workers.
+        let mut used = [false; 4];
+        for port in 9000..9064 {
+            used[shards.index(SocketAddr::from(([127, 0, 0, 1], port)))] = true;
+        }
+        assert_eq!(used, [true; 4]);
+
+        assert!(shards.send(index, packet(1), addr));
+        let (packet, from) = rx[index].recv().await.unwrap();
+        assert_eq!(packet.header.destination_socket_id, 1);
+        assert_eq!(from, addr);
+
+        // Full queues drop packets instead of waiting.
+        for _ in 0..Shards::QUEUE_SIZE {
+            assert!(shards.send(index, packet.clone(), addr));
+        }
+        assert!(!shards.send(index, packet, addr));
+    }
+
+    /// A peer connecting to a [`Server`] over a real UDP socket.
+    pub struct Peer {
+        socket: UdpSocket,
+        server: SocketAddr,
+        pub socket_id: u32,
+    }
+
+    impl Peer {
+        pub async fn new(server: SocketAddr, socket_id: u32) -> Self {
+            Self {
+                socket: UdpSocket::bind("127.0.0.1:0").await.unwrap(),
+                server,
+                socket_id,
+            }
+        }
+
+        async fn send<T>(&self, packet: T)
+        where
+            T: IsPacket,
+        {
+            let mut buf = Vec::new();
+            packet.upcast().encode(&mut buf).unwrap();
+            self.socket.send_to(&buf, self.server).await.unwrap();
+        }
+
+        async fn recv(&self) -> Packet {
+            let mut buf = [0; 1500];
+            let len = tokio::time::timeout(Duration::from_secs(2), self.socket.recv(&mut buf))
+                .await
+                .expect("timed out waiting for packet")
+                .unwrap();
+
+            Packet::decode(&mut &buf[..len]).unwrap()
+        }
+
+        /// Receives packets until a [`Handshake`] arrives, skipping all other packets.
+        async fn recv_handshake(&self) -> Handshake {
+            loop {
+                let packet = self.recv().await;
+                if let Ok(packet) = packet.downcast::<Handshake>() {
+                    return packet;
+                }
+            }
+        }
+
+        /// Performs the INDUCTION/CONCLUSION handshake using `stream_id`.
+        pub async fn connect(&self, stream_id: &str) {
+            self.send(Handshake {
+                version: 4,
+                handshake_type: HandshakeType::INDUCTION,
+                encryption_field: EncryptionField::NONE,
+                extension_field: ExtensionField::INDUCTION,
+                srt_socket_id: self.socket_id,
+                ..Default::default()
+            })
+            .await;
+
+            let resp = self.recv_handshake().await;
+            assert_eq!(resp.handshake_type, HandshakeType::INDUCTION);
+            assert_eq!(resp.header.destination_socket_id, self.socket_id);
+
+            let mut packet = Handshake {
+                version: 5,
+                handshake_type: HandshakeType::CONCLUSION,
+                syn_cookie: resp.syn_cookie,
+                srt_socket_id: self.socket_id,
+                extension_field: ExtensionField(ExtensionField::HSREQ.0 | ExtensionField::CONFIG.0),
+                ..Default::default()
+            };
+
+            packet.extensions.0.push(HandshakeExtension {
+                extension_type: ExtensionType::HSREQ,
+                extension_length: 3,
+                extension_content: HandshakeExtensionMessage {
+                    srt_version: 0x00010403,
+                    srt_flags: HandshakeExtensionFlags::TSBPDSND
+                        | HandshakeExtensionFlags::TSBPDRCV
+                        | HandshakeExtensionFlags::CRYPT
+                        | HandshakeExtensionFlags::REXMITFLG
+                        | HandshakeExtensionFlags::TLPKTDROP,
+                    receiver_tsbpd_delay: 120,
+                    sender_tsbpd_delay: 120,
+                }
+                .into(),
+            });
+
+            let ext = ExtensionContent::StreamId(StreamIdExtension {
+                content: stream_id.to_owned(),
+            });
+            packet.extensions.0.push(HandshakeExtension {
+                extension_type: ExtensionType::SID,
+                extension_length: ext.len() as u16,
+                extension_content: ext,
+            });
+
+            self.send(packet).await;
+
+            let resp = self.recv_handshake().await;
+            assert_eq!(resp.handshake_type, HandshakeType::CONCLUSION);
+            assert_eq!(resp.srt_socket_id, self.socket_id);
+        }
+
+        pub async fn shutdown(&self) {
+            let mut packet = Shutdown::builder().build().upcast();
+            packet.header.destination_socket_id = self.socket_id;
+            self.send(packet).await;
+        }
+    }
+
+    /// Returns a local address with a port that is currently unused.
+    pub fn unused_addr() -> SocketAddr {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.local_addr().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_publish_request() {
+        let config = Config {
+            bind: unused_addr(),
+            ..config()
+        };
+        let addr = config.bind;
+
+        let server = Server::new(BufferSessionManager::new(Default::default()), config).unwrap();
+        for session in [1, 2] {
+            server.state.session_manager.registry.insert(SessionKey {
+                resource_id: ResourceId(1),
+                session_id: SessionId(session),
+                expires: Instant::now() + Duration::from_secs(60),
+            });
+        }
+        let state = server.state.clone();
+        let handle = tokio::task::spawn(server);
+
+        let requester = Peer::new(addr, 2).await;
+        requester.connect("#!::m=request,r=1,s=2").await;
+
+        let publisher = Arc::new(Peer::new(addr, 1).await);
+        publisher.connect("#!::m=publish,r=1,s=1").await;
+
+        // Segments are only passed on to the session while packets keep arriving, like in a live
+        // stream.
+        let segment = |seq: u32| Bytes::from(vec![seq as u8; 188 * 7]);
+        let publish = tokio::task::spawn({
+            let publisher = publisher.clone();
+            async move {
+                let start = Instant::now();
+                for seq in 0.. {
+                    let mut packet = DataPacket::builder()
+                        .sequence_number(seq)
+                        .message_number(seq + 1)
+                        .body(segment(seq))
+                        .build()
+                        .upcast();
+                    packet.header.timestamp = Timestamp::from_start(start);
+                    packet.header.destination_socket_id = publisher.socket_id;
+                    publisher.send(packet).await;
+
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        });
+
+        // The requester receives the published segments in order.
+        let mut received = Vec::new();
+        while received.len() < 8 {
+            let packet = requester.recv().await;
+            if packet.header.packet_type() != PacketType::Data {
+                continue;
+            }
+
+            assert_eq!(packet.header.destination_socket_id, requester.socket_id);
+            received.push(packet.body);
+        }
+        assert_eq!(received, (0..8).map(segment).collect::<Vec<_>>());
+
+        publish.abort();
+        let _ = publish.await;
+
+        // Connections borrow from the workers and must be closed before the server is dropped.
+        publisher.shutdown().await;
+        requester.shutdown().await;
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while state.pool.len() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("connections were not closed");
+
+        handle.abort();
+        let _ = handle.await;
+    }
+
+    #[tokio::test]
+    async fn test_loss_recovery() {
+        let config = Config {
+            bind: unused_addr(),
+            debug: true,
+            loss: Some(LossConfig {
+                sequence_numbers: vec![2, 3, 4],
+                ..Default::default()
+            }),
+            ..config()
+        };
+        let addr = config.bind;
+
+        let server = Server::new(BufferSessionManager::new(Default::default()), config).unwrap();
+        server.state.session_manager.registry.insert(SessionKey {
+            resource_id: ResourceId(1),
+            session_id: SessionId(1),
+            expires: Instant::now() + Duration::from_secs(60),
+        });
+        let state = server.state.clone();
+        let handle = tokio::task::spawn(server);
+
+        let mut stream = state
+            .session_manager
+            .request(None, Some(ResourceId(1)))
+            .unwrap();
+
+        let publisher = Arc::new(Peer::new(addr, 1).await);
+        publisher.connect("#!::m=publish,r=1,s=1").await;
+
+        // Every packet carries the time it is first sent at, so that retransmissions keep their
+        // place in the stream.
+        let segment = |seq: u32| Bytes::from(vec![seq as u8; 188 * 7]);
+        let packet = move |seq: u32, retransmitted: bool| {
+            let mut packet = DataPacket::builder()
+                .sequence_number(seq)
+                .message_number(seq + 1)
+                .retransmitted(retransmitted)
+                .body(segment(seq))
+                .build()
+                .upcast();
+            packet.header.timestamp = Timestamp::from_micros(seq * 10_000);
+            packet.header.destination_socket_id = 1;
+            packet
+        };
+
+        let publish = tokio::task::spawn({
+            let publisher = publisher.clone();
+            async move {
+                for seq in 0.. {
+                    publisher.send(packet(seq, false)).await;
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        });
+
+        // The consecutive losses are reported in a single NAK.
+        let nak = loop {
+            if let Ok(nak) = publisher.recv().await.downcast::<Nak>() {
+                break nak;
+            }
+        };
+        let lost: Vec<u32> = nak.lost_packet_sequence_numbers.iter().collect();
+        assert_eq!(lost, [2, 3, 4]);
+
+        for seq in lost {
+            publisher.send(packet(seq, true)).await;
+        }
+
+        // The retransmissions fill the gap before the segments are passed on to the session.
+        let mut received = Vec::new();
+        while received.len() < 8 {
+            let buf = tokio::time::timeout(Duration::from_secs(2), stream.next())
+                .await
+                .expect("timed out waiting for segment")
+                .unwrap();
+            received.push(buf);
+        }
+        assert_eq!(received, (0..8).map(segment).collect::<Vec<_>>());
+
+        let metrics = state.conn_metrics.lock().values().next().unwrap().clone();
+        assert_eq!(metrics.data_packets_recv.retransmitted.get(), 3);
+
+        publish.abort();
+        let _ = publish.await;
+
+        publisher.shutdown().await;
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while state.pool.len() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("connections were not closed");
+
+        handle.abort();
+        let _ = handle.await;
+    }
+
+    /// Compares the CPU time of handling the packets of publishing connections with and without
+    /// worker sharding.
+    ///
+    /// Run with `cargo test --release bench_worker_sharding -- --ignored --nocapture`.
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn bench_worker_sharding() {
+        use crate::srt::utils::cpu_time;
+
+        const PEERS: u32 = 8;
+        const PACKETS: u32 = 20_000;
+
+        for worker_sharding in [false, true] {
+            let config = Config {
+                bind: unused_addr(),
+                workers: Some(4),
+                worker_sharding,
+                recv_buffer: Some(1 << 24),
+                ..config()
+            };
+            let addr = config.bind;
+
+            let server =
+                Server::new(BufferSessionManager::new(Default::default()), config).unwrap();
+            for resource in 1..=PEERS {
+                server.state.session_manager.registry.insert(SessionKey {
+                    resource_id: ResourceId(resource.into()),
+                    session_id: SessionId(1),
+                    expires: Instant::now() + Duration::from_secs(60),
+                });
+            }
+            let state = server.state.clone();
+            let handle = tokio::task::spawn(server);
+
+            let mut peers = Vec::new();
+            for resource in 1..=PEERS {
+                let peer = Arc::new(Peer::new(addr, resource).await);
+                peer.connect(&format!("#!::m=publish,r={},s=1", resource))
+                    .await;
+                peers.push(peer);
+            }
+
+            let start = Instant::now();
+            let cpu_start = cpu_time();
+            let tasks: Vec<_> = peers
+                .iter()
+                .cloned()
+                .map(|peer| {
+                    tokio::task::spawn(async move {
+                        for seq in 0..PACKETS {
+                            let mut packet = DataPacket::builder()
+                                .sequence_number(seq)
+                                .message_number(seq + 1)
+                                .body(vec![0; 188 * 7])
+                                .build()
+                                .upcast();
+                            packet.header.timestamp = Timestamp::from_start(start);
+                            packet.header.destination_socket_id = peer.socket_id;
+                            peer.send(packet).await;
+
+                            // Pace the peers, so the socket buffers don't overflow.
+                            if seq % 16 == 15 {
+                                tokio::time::sleep(Duration::from_millis(1)).await;
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for task in tasks {
+                task.await.unwrap();
+            }
+
+            // Wait until the server handled all packets it received.
+            let received = || -> u64 {
+                state
+                    .conn_metrics
+                    .lock()
+                    .values()
+                    .map(|metrics| metrics.data_packets_recv.original.get())
+                    .sum()
+            };
+            let mut last = received();
+            loop {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let count = received();
+                if count == last {
+                    break;
+                }
+                last = count;
+            }
+
+            println!(
+                "worker_sharding={} peers={}: {}/{} packets, {:?} CPU in {:?}",
+                worker_sharding,
+                PEERS,
+                last,
+                PEERS * PACKETS,
+                cpu_time() - cpu_start,
+                start.elapsed()
+            );
+
+            for peer in &peers {
+                peer.shutdown().await;
+            }
+            while state.pool.len() != 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            handle.abort();
+            let _ = handle.await;
+        }
+    }
+}
@@ -1,27 +1,33 @@
 //! SRT Output sink
 use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::future::Future;
-use std::marker::PhantomPinned;
 use std::num::Wrapping;
 use std::pin::Pin;
 use std::task::{ready, Context, Poll};
 use std::time::{Duration, Instant};
 
 use bytes::Bytes;
-use futures::{pin_mut, Sink};
+use futures::Sink;
 use pin_project::pin_project;
 use tokio::time::{sleep_until, Sleep};
 
-use crate::session::{LiveSink, SessionManager};
+use crate::session::{LiveSink, SessionError, SessionManager};
 
+use super::crypto::{DecryptError, KeyError, KeySlots, Sek};
+use super::proto::TimestampUnwrapper;
+use super::queue::QueueKind;
 use super::utils::MessageNumber;
-use super::DataPacket;
+use super::{DataPacket, EncryptionFlag, OrderFlag};
 
 /// A [`Sink`] that receives [`DataPacket`]s and converts them back into data.
 ///
 /// `OutputSink` wraps around a regular sink `S` accepting the data of the underlying network
 /// stream. An `OutputSink` is responsible for reassembling the stream of the sender by reordering
 /// out-of-order segments, dropping duplicate and too-late packets.
+///
+/// If the sender doesn't use TSBPD, segments without the order flag are not reordered and
+/// delivered immediately.
 #[derive(Debug)]
 #[pin_project]
 pub struct OutputSink<S>
@@ -31,55 +37,122 @@ where
     /// Sequence number of the next expected segment.
     next_msgnum: Wrapping<u32>,
     queue: SegmentQueue,
+    /// Segments without the order flag, delivered before any segments in `queue`.
+    unordered: VecDeque<Bytes>,
+    /// Whether the sender uses TSBPD. The order flag is ignored if set.
+    tsbpd: bool,
+    /// The SEKs used to decrypt received segments.
+    keys: KeySlots,
     #[pin]
     sink: LiveSink<S::Sink>,
+    /// The deadline for delivering the queued segments once the sink is closed.
+    close_deadline: Option<Pin<Box<Sleep>>>,
 }
 
 impl<S> OutputSink<S>
 where
     S: SessionManager,
 {
-    /// Creates a new `OutputSink` using `sink` as the underlying [`Sink`].
+    /// Creates a new `OutputSink` using `sink` as the underlying [`Sink`]. `tsbpd` indicates
+    /// whether the sender uses TSBPD.
     pub fn new(
         sink: LiveSink<S::Sink>,
         start: Instant,
         latency: Duration,
         buffer_size: usize,
+        queue_kind: QueueKind,
+        tsbpd: bool,
     ) -> Self {
         Self {
             next_msgnum: Wrapping(1),
+            keys: KeySlots::default(),
             sink,
-            queue: SegmentQueue::new(start, latency, buffer_size),
+            queue: SegmentQueue::new(start, latency, buffer_size, queue_kind),
+            unordered: VecDeque::new(),
+            tsbpd,
+            close_deadline: None,
+        }
+    }
+
+    /// Sets the [`Sek`] for the given `flag`. Passing `None` removes the key. Returns an error if
+    /// `flag` is not [`Even`] or [`Odd`].
+    ///
+    /// [`Even`]: EncryptionFlag::Even
+    /// [`Odd`]: EncryptionFlag::Odd
+    pub fn set_key(&mut self, flag: EncryptionFlag, sek: Option<Sek>) -> Result<(), KeyError> {
+        self.keys.set(flag, sek)
+    }
+
+    /// Decrypts the payload of `packet` in place using the key indicated by its encryption flag.
+    /// This must be done before the packet is fed into the sink. The payload is left unchanged
+    /// if the packet cannot be decrypted.
+    pub fn decrypt(&self, packet: &mut DataPacket) -> Result<(), DecryptError> {
+        let flag = packet.encryption_flag();
+        if flag == EncryptionFlag::None {
+            return Ok(());
         }
+
+        let seq = packet.packet_sequence_number();
+        packet.data = self.keys.decrypt(flag, seq, packet.data.clone())?;
+        Ok(())
     }
 
-    /// Update the starting [`Instant`] of the sink.
+    /// Sets the latency of segments received from now on. Queued segments keep their delivery
+    /// time.
     #[inline]
-    pub fn update_start(&mut self, instant: Instant) {
-        self.queue.start = instant;
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.queue.latency = latency;
     }
 
     /// Returns the remaining capacity in the output buffer.
     #[inline]
     pub fn buffer_left(&self) -> usize {
-        8192 - self.queue.len()
+        self.queue.capacity().saturating_sub(self.buffered())
+    }
+
+    /// Returns the fraction of the output buffer in use, from `0.0` to `1.0`.
+    pub fn buffer_usage(&self) -> f64 {
+        self.buffered() as f64 / self.queue.capacity() as f64
+    }
+
+    /// Returns the number of segments not yet written to the underlying sink.
+    #[inline]
+    fn buffered(&self) -> usize {
+        self.queue.len() + self.unordered.len()
+    }
+
+    /// Returns `true` if all segments were written to the underlying sink.
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.unordered.is_empty()
+    }
+
+    /// Returns the number of segments waiting for their delivery time.
+    #[inline]
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the time since the timestamp of the oldest segment waiting for its delivery time
+    /// at `now`. Returns zero if no segments are waiting.
+    #[inline]
+    pub fn queue_age(&self, now: Instant) -> Duration {
+        self.queue.age(now)
     }
 
     /// Write to output sink with latency.
-    fn poll_write(
-        self: Pin<&mut Self>,
-        cx: &mut Context<'_>,
-    ) -> Poll<Result<(), <S::Sink as Sink<Bytes>>::Error>> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SessionError>> {
         let mut this = self.project();
 
-        ready!(this.sink.as_mut().poll_ready(cx))?;
+        ready!(this.sink.as_mut().poll_ready(cx)).map_err(Into::into)?;
 
-        let fut = this.queue.take();
-        pin_mut!(fut);
-        let segment = ready!(fut.poll(cx));
+        if let Some(payload) = this.unordered.pop_front() {
+            this.sink.start_send(payload).map_err(Into::into)?;
+            return Poll::Ready(Ok(()));
+        }
 
-        if let Some(segment) = segment {
-            this.sink.start_send(segment.payload)?;
+        if let Some(segment) = ready!(this.queue.poll_take(cx)) {
+            this.sink.start_send(segment.payload).map_err(Into::into)?;
         }
 
         Poll::Ready(Ok(()))
@@ -90,44 +163,78 @@ impl<S> Sink<DataPacket> for OutputSink<S>
 where
     S: SessionManager,
 {
-    type Error = <LiveSink<S::Sink> as Sink<Bytes>>::Error;
+    type Error = SessionError;
 
     fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        loop {
-            if self.queue.is_empty() || self.as_mut().poll_write(cx).is_pending() {
+        while !self.is_empty() {
+            if self.as_mut().poll_write(cx)?.is_pending() {
                 break;
             }
         }
-        // self.poll_write(cx);
 
-        // TODO: Implement a fixed buffer size.
+        // The queue is full and nothing could be written. `poll_write` already registered the
+        // waker for when the next segment can be written.
+        if self.buffer_left() == 0 && !self.is_empty() {
+            return Poll::Pending;
+        }
+
         Poll::Ready(Ok(()))
     }
 
     fn start_send(mut self: Pin<&mut Self>, packet: DataPacket) -> Result<(), Self::Error> {
-        self.queue.push(packet);
+        // The ordered queue and the unordered segments share the capacity of the queue. Callers
+        // that wait for `poll_ready` never send into a full buffer, otherwise the segment is
+        // dropped.
+        if self.buffer_left() == 0 {
+            return Ok(());
+        }
+
+        if self.tsbpd || matches!(packet.order_flag(), OrderFlag::InOrder) {
+            self.queue.push(packet);
+        } else {
+            self.unordered.push_back(packet.data);
+        }
+
         Ok(())
     }
 
     fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         // Drain the queue.
-        while !self.queue.is_empty() {
+        while !self.is_empty() {
             ready!(self.as_mut().poll_write(cx))?;
         }
 
         let this = self.project();
-        this.sink.poll_flush(cx)
+        this.sink.poll_flush(cx).map_err(Into::into)
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let this = self.project();
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Deliver the queued segments at their delivery time, so that the requesting streams
+        // receive the end of the stream. All segments are due within about the latency, allowing
+        // for clock drift of the peer. Anything left after twice the latency (e.g. because the
+        // underlying sink is blocked) is dropped.
+        let timeout = self.queue.latency * 2;
+        self.close_deadline
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+
+        while !self.is_empty() {
+            if self.as_mut().poll_write(cx)?.is_ready() {
+                continue;
+            }
+
+            let this = self.as_mut().project();
+            let deadline = this.close_deadline.as_mut().unwrap();
+            if deadline.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
 
-        if !this.queue.is_empty() {
             tracing::debug!("Dropping {} bytes from queue", this.queue.size);
             this.queue.clear();
+            this.unordered.clear();
         }
 
-        this.sink.poll_close(cx)
+        let this = self.project();
+        this.sink.poll_close(cx).map_err(Into::into)
     }
 }
 
@@ -137,17 +244,21 @@ struct SegmentQueue {
     queue: super::queue::SegmentQueue<Segment>,
     /// Total size of all buffers combined.
     size: usize,
-    start: Instant,
+    /// Maps the timestamps of received segments to their [`Instant`].
+    unwrapper: TimestampUnwrapper,
     latency: Duration,
+    /// Timer until the delivery time of the first segment.
+    sleep: Option<Pin<Box<Sleep>>>,
 }
 
 impl SegmentQueue {
-    pub fn new(start: Instant, latency: Duration, buffer_size: usize) -> Self {
+    pub fn new(start: Instant, latency: Duration, buffer_size: usize, kind: QueueKind) -> Self {
         Self {
-            queue: super::queue::SegmentQueue::new(buffer_size),
+            queue: super::queue::SegmentQueue::new(buffer_size, kind),
             size: 0,
-            start,
+            unwrapper: TimestampUnwrapper::new(start),
             latency,
+            sleep: None,
         }
     }
 
@@ -157,29 +268,39 @@ impl SegmentQueue {
     /// [`MessageNumber`]. The [`MessageNumber`] is only used to give the packet the correct
     /// order in the queue. If pushing multiple [`DataPacket`]s with the same [`MessageNumber`]
     /// both packets will be scheduled. Which packet comes first is unspecified.
-    pub fn push(&mut self, mut packet: DataPacket) {
+    pub fn push(&mut self, packet: DataPacket) {
         // Prevent memory exhaustion from slow receivers or attacks.
         if self.len() == self.capacity() {
             return;
         }
 
         let message_number = packet.message_number();
-        let delivery_time = self.start + packet.header.timestamp.to_duration() + self.latency;
+        let timestamp = self.unwrapper.instant(packet.header.timestamp);
 
         self.size += packet.data.len();
         self.queue.push(Segment {
             message_number,
-            delivery_time,
+            timestamp,
+            delivery_time: timestamp + self.latency,
             payload: packet.data,
         });
     }
 
     /// Returns a reference to the first [`Segment`] in the `SegmentQueue`.
     #[inline]
-    pub fn peek(&mut self) -> Option<&'_ Segment> {
+    pub fn peek(&self) -> Option<&'_ Segment> {
         self.queue.peek()
     }
 
+    /// Returns the time since the timestamp of the first [`Segment`] at `now`. Returns zero if
+    /// the queue is empty.
+    pub fn age(&self, now: Instant) -> Duration {
+        match self.peek() {
+            Some(seg) => now.saturating_duration_since(seg.timestamp),
+            None => Duration::ZERO,
+        }
+    }
+
     /// Removes and returns the first [`Segment`] from the `SegmentQueue`.
     #[inline]
     pub fn pop(&mut self) -> Option<Segment> {
@@ -188,15 +309,33 @@ impl SegmentQueue {
         Some(segment)
     }
 
-    /// Returns a future that completes once the next segment can be taken.
+    /// Removes and returns the first [`Segment`] once its delivery time is reached. Returns
+    /// `None` if the queue is empty.
     ///
     /// **Note that the queue will only keep track of the most recent waker.**
-    pub fn take(&mut self) -> Take<'_> {
-        Take {
-            queue: self,
-            sleep: None,
-            _pin: PhantomPinned,
+    pub fn poll_take(&mut self, cx: &mut Context<'_>) -> Poll<Option<Segment>> {
+        let now = Instant::now();
+
+        let deadline = match self.peek() {
+            Some(seg) if seg.delivery_time <= now => return Poll::Ready(self.pop()),
+            Some(seg) => seg.delivery_time,
+            // The queue is empty.
+            None => return Poll::Ready(None),
+        };
+
+        tracing::trace!("Ready in {:?}", deadline - now);
+
+        // The timer is kept between calls so the waker stays registered.
+        let deadline = tokio::time::Instant::from_std(deadline);
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+        if sleep.deadline() != deadline {
+            sleep.as_mut().reset(deadline);
         }
+        ready!(sleep.as_mut().poll(cx));
+
+        Poll::Ready(self.pop())
     }
 
     pub fn len(&self) -> usize {
@@ -220,6 +359,8 @@ impl SegmentQueue {
 #[derive(Clone, Debug)]
 struct Segment {
     message_number: MessageNumber,
+    /// The [`Instant`] of the timestamp of the segment.
+    timestamp: Instant,
     delivery_time: Instant,
     payload: Bytes,
 }
@@ -248,48 +389,231 @@ impl Ord for Segment {
     }
 }
 
-#[pin_project]
-struct Take<'a> {
-    queue: &'a mut SegmentQueue,
-    sleep: Option<Sleep>,
-    _pin: PhantomPinned,
-}
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::time::{Duration, Instant};
+
+    use futures::{Sink, SinkExt, StreamExt};
+
+    use crate::session::buffer::{BufferSessionManager, Config, SessionKey};
+    use crate::session::{ResourceId, SessionId, SessionManager};
+    use crate::srt::proto::Timestamp;
+    use crate::srt::queue::QueueKind;
+    use crate::srt::{DataPacket, EncryptionFlag};
+
+    use super::{OutputSink, SegmentQueue};
+
+    /// Returns a manager with the sessions `1` and `2` for the resource `1`.
+    fn manager(config: Config) -> BufferSessionManager {
+        let manager = BufferSessionManager::new(config);
+        for id in [1, 2] {
+            manager.registry.insert(SessionKey {
+                resource_id: ResourceId(1),
+                session_id: SessionId(id),
+                expires: Instant::now() + Duration::from_secs(60),
+            });
+        }
 
-impl<'a> Future for Take<'a> {
-    type Output = Option<Segment>;
+        manager
+    }
 
-    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let this = self.project();
+    /// Returns a sink publishing to the resource `1` of `manager`.
+    fn sink(
+        manager: &BufferSessionManager,
+        latency: Duration,
+        buffer_size: usize,
+        tsbpd: bool,
+    ) -> Pin<Box<OutputSink<BufferSessionManager>>> {
+        let sink = manager.publish(None, Some(ResourceId(1))).unwrap();
 
-        let now = Instant::now();
+        Box::pin(OutputSink::new(
+            sink,
+            Instant::now(),
+            latency,
+            buffer_size,
+            QueueKind::Sorted,
+            tsbpd,
+        ))
+    }
 
-        let deadline;
-        if let Some(seg) = this.queue.peek() {
-            if seg.delivery_time <= now {
-                // FIXME: pop_first == None is unreachable.
-                return Poll::Ready(this.queue.pop());
-            } else {
-                deadline = seg.delivery_time;
-            }
-        } else {
-            // The queue is empty.
-            return Poll::Ready(None);
+    fn packet(msgnum: u32) -> DataPacket {
+        DataPacket::builder()
+            .message_number(msgnum)
+            .ordered(true)
+            .body(vec![0; 16])
+            .build()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_buffer_limit() {
+        let manager = manager(Config::default());
+        let mut sink = sink(&manager, Duration::from_secs(1), 2, true);
+        assert_eq!(sink.buffer_left(), 2);
+
+        sink.as_mut().start_send(packet(1)).unwrap();
+        sink.as_mut().start_send(packet(2)).unwrap();
+        assert_eq!(sink.buffer_left(), 0);
+
+        // The queue is full until the segments are delivered after the latency.
+        let mut ready = futures::future::poll_fn(|cx| sink.as_mut().poll_ready(cx));
+        assert!(futures::poll!(&mut ready).is_pending());
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(futures::poll!(&mut ready).is_ready());
+        drop(ready);
+        assert_eq!(sink.buffer_left(), 2);
+
+        sink.feed(packet(3)).await.unwrap();
+        assert_eq!(sink.buffer_left(), 1);
+    }
+
+    #[test]
+    fn test_queue_timestamp_wrap() {
+        let start = Instant::now();
+        let latency = Duration::from_secs(1);
+        let mut queue = SegmentQueue::new(start, latency, 8, QueueKind::Sorted);
+
+        // The timestamps pass 2^31 and then wrap around at 2^32.
+        let timestamps = [1 << 30, 1 << 31, 3 << 30, u32::MAX as u64, (1 << 32) + 1000];
+        for (msgnum, micros) in timestamps.into_iter().enumerate() {
+            let mut packet = packet(msgnum as u32 + 1);
+            packet.header.timestamp = Timestamp::from_micros(micros as u32);
+            queue.push(packet);
         }
 
-        if this.sleep.is_none() {
-            let sleep = sleep_until(deadline.into());
-            *this.sleep = Some(sleep);
+        for micros in timestamps {
+            let segment = queue.pop().unwrap();
+            assert_eq!(
+                segment.delivery_time,
+                start + Duration::from_micros(micros) + latency
+            );
         }
+    }
 
-        tracing::trace!("Ready in {:?}", deadline - now);
+    #[tokio::test]
+    async fn test_queue_age() {
+        let manager = manager(Config::default());
+        let mut sink = sink(&manager, Duration::from_secs(1), 4, true);
+        assert_eq!(sink.queue_len(), 0);
+        assert_eq!(sink.queue_age(Instant::now()), Duration::ZERO);
+
+        sink.as_mut().start_send(packet(1)).unwrap();
+        sink.as_mut().start_send(packet(2)).unwrap();
+        assert_eq!(sink.queue_len(), 2);
+
+        // Both segments have the timestamp zero, i.e. they are as old as the sink.
+        let age = sink.queue_age(Instant::now() + Duration::from_millis(500));
+        assert!(age >= Duration::from_millis(500) && age < Duration::from_secs(1));
+
+        // Changing the latency does not change the age of queued segments.
+        sink.set_latency(Duration::from_secs(3));
+        let age = sink.queue_age(Instant::now() + Duration::from_millis(500));
+        assert!(age >= Duration::from_millis(500) && age < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let manager = manager(Config {
+            channel_capacity: 4,
+            backpressure: true,
+            ..Default::default()
+        });
+        let mut sink = sink(&manager, Duration::ZERO, 8, true);
+
+        // The requesting stream is stalled.
+        let mut stream = manager.request(None, Some(ResourceId(1))).unwrap();
+
+        for msgnum in 1..=3 {
+            sink.send(packet(msgnum)).await.unwrap();
+        }
+        assert_eq!(sink.buffer_left(), 8);
+
+        // The segments are retained, shrinking the buffer advertised to the sender.
+        for msgnum in 4..=6 {
+            sink.feed(packet(msgnum)).await.unwrap();
+        }
+        assert_eq!(sink.buffer_left(), 5);
+
+        for _ in 0..3 {
+            stream.next().await.unwrap();
+        }
+        let mut ready = futures::future::poll_fn(|cx| sink.as_mut().poll_ready(cx));
+        assert!(futures::poll!(&mut ready).is_ready());
+        drop(ready);
+        assert_eq!(sink.buffer_left(), 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_close_drain() {
+        let manager = manager(Config::default());
+        let mut sink = sink(&manager, Duration::from_secs(1), 8, true);
+        let mut stream = manager.request(None, Some(ResourceId(1))).unwrap();
+
+        sink.as_mut().start_send(packet(1)).unwrap();
+        sink.as_mut().start_send(packet(2)).unwrap();
+
+        // The segments are delivered after the latency before the sink is closed.
+        let mut close = futures::future::poll_fn(|cx| sink.as_mut().poll_close(cx));
+        assert!(futures::poll!(&mut close).is_pending());
+        assert!(futures::poll!(stream.next()).is_pending());
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        assert!(futures::poll!(&mut close).is_ready());
+        assert_eq!(stream.next().await.unwrap().len(), 16);
+        assert_eq!(stream.next().await.unwrap().len(), 16);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unordered() {
+        let manager = manager(Config::default());
+        let mut sink = sink(&manager, Duration::from_secs(1), 8, false);
+        let mut stream = manager.request(None, Some(ResourceId(1))).unwrap();
+
+        let unordered = DataPacket::builder()
+            .message_number(3u32)
+            .ordered(false)
+            .body(vec![0; 8])
+            .build();
+
+        sink.as_mut().start_send(packet(1)).unwrap();
+        sink.as_mut().start_send(unordered.clone()).unwrap();
+        sink.as_mut().start_send(packet(2)).unwrap();
+        assert_eq!(sink.queue_len(), 2);
+        assert_eq!(sink.buffer_left(), 5);
+
+        // The unordered segment is delivered immediately, the ordered segments after the
+        // latency.
+        let mut ready = futures::future::poll_fn(|cx| sink.as_mut().poll_ready(cx));
+        assert!(futures::poll!(&mut ready).is_ready());
+        assert_eq!(stream.next().await.unwrap().len(), 8);
+        assert!(futures::poll!(stream.next()).is_pending());
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        assert!(futures::poll!(&mut ready).is_ready());
+        drop(ready);
+        assert_eq!(stream.next().await.unwrap().len(), 16);
+        assert_eq!(stream.next().await.unwrap().len(), 16);
+
+        // The order flag is ignored if the sender uses TSBPD.
+        let manager = self::manager(Config::default());
+        let mut sink = self::sink(&manager, Duration::from_secs(1), 8, true);
+        sink.as_mut().start_send(unordered).unwrap();
+        assert_eq!(sink.queue_len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_no_key() {
+        let manager = manager(Config::default());
+        let sink = sink(&manager, Duration::from_secs(1), 8, true);
 
-        let sleep = this.sleep.as_mut().unwrap();
-        // SAFETY: `self` is `!Unpin`. It is never moved.
-        let sleep = unsafe { Pin::new_unchecked(sleep) };
-        ready!(sleep.poll(cx));
+        let mut packet = DataPacket::builder()
+            .encryption(EncryptionFlag::Even)
+            .body(vec![1; 16])
+            .build();
 
-        // Since we only ever store one waiter the segment is guaranteed to still be
-        // untouched when the timer expires.
-        Poll::Ready(this.queue.pop())
+        // The payload is kept for accounting the dropped bytes.
+        assert!(sink.decrypt(&mut packet).is_err());
+        assert_eq!(packet.data, vec![1; 16]);
     }
 }
@@ -2,12 +2,18 @@ use std::io::{ErrorKind, IoSlice, Result};
 use std::net::SocketAddr;
 use std::os::unix::io::{AsRawFd, RawFd};
 
+use bytes::BytesMut;
 use socket2::{Domain, Protocol, SockRef, Socket, Type};
+#[cfg(target_os = "linux")]
+use tokio::io::Interest;
 use tokio::net::UdpSocket;
 
 use crate::proto::Encode;
+use crate::utils::arena::Arena;
 
-use super::IsPacket;
+#[cfg(target_os = "linux")]
+use super::mmsg;
+use super::{IsPacket, Packet};
 
 /// A wrapper around a UDP socket designed to receive SRT frames.
 #[derive(Debug)]
@@ -16,6 +22,13 @@ pub struct SrtSocket {
 }
 
 impl SrtSocket {
+    /// The maximum number of datagrams sent or received in a single syscall.
+    #[cfg(target_os = "linux")]
+    pub const BATCH_SIZE: usize = mmsg::BATCH_SIZE;
+    /// The maximum number of datagrams sent or received in a single syscall.
+    #[cfg(not(target_os = "linux"))]
+    pub const BATCH_SIZE: usize = 1;
+
     /// Creates a new `SrtSocket` bound to the given `addr`.
     ///
     /// # Errors
@@ -27,23 +40,85 @@ impl SrtSocket {
         let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
         socket.set_nonblocking(true)?;
         socket.bind(&addr.into())?;
-        socket.set_recv_buffer_size(500_000_000)?;
 
         let socket = UdpSocket::from_std(socket.into())?;
 
         Ok(Self { socket })
     }
 
-    /// Receives a single datagram from the `SrtSocket` into the `buf`. Returns the number of bytes
-    /// received and the [`SocketAddr`] of the remote peer.
+    /// Receives multiple datagrams into `batch` using a single syscall where supported. Returns
+    /// the number of received datagrams, which can be taken using [`RecvBatch::drain`].
+    ///
+    /// Datagrams remaining in `batch` from a previous call are discarded.
     ///
     /// # Errors
     ///
-    /// Returns an [`Error`] if receiving a datagram fails.
+    /// Returns an [`Error`] if receiving the datagrams fails.
     ///
     /// [`Error`]: std::io::Error
-    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
-        self.socket.recv_from(buf).await
+    pub async fn recv_batch(&self, batch: &mut RecvBatch) -> Result<usize> {
+        batch.received.clear();
+
+        #[cfg(target_os = "linux")]
+        {
+            let fd = self.as_raw_fd();
+            self.socket
+                .async_io(Interest::READABLE, || {
+                    mmsg::recvmmsg(fd, &mut batch.bufs, &mut batch.received)
+                })
+                .await
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        {
+            let buf = &mut batch.bufs[0];
+            buf.resize(batch.size, 0);
+
+            let (len, addr) = self.socket.recv_from(buf).await?;
+            buf.truncate(len);
+            batch.received.push((0, len, addr));
+            Ok(1)
+        }
+    }
+
+    /// Sends all `packets` to a remote peer, using as few syscalls as possible.
+    pub async fn send_batch(
+        &self,
+        packets: &[Packet],
+        addr: SocketAddr,
+    ) -> std::result::Result<(), super::Error> {
+        let headers = packets
+            .iter()
+            .map(|packet| packet.header.encode_to_vec())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let datagrams: Vec<[IoSlice<'_>; 2]> = headers
+            .iter()
+            .zip(packets)
+            .map(|(header, packet)| [IoSlice::new(header), IoSlice::new(&packet.body)])
+            .collect();
+
+        #[cfg(target_os = "linux")]
+        {
+            let fd = self.as_raw_fd();
+
+            let mut sent = 0;
+            while sent < datagrams.len() {
+                sent += self
+                    .socket
+                    .async_io(Interest::WRITABLE, || {
+                        mmsg::sendmmsg(fd, &datagrams[sent..], addr)
+                    })
+                    .await?;
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        for datagram in &datagrams {
+            self.send_to_vectored(datagram, addr).await?;
+        }
+
+        Ok(())
     }
 
     /// Sends a SRT packet to a remote peer.
@@ -92,6 +167,24 @@ impl SrtSocket {
         self.as_socket().send_buffer_size()
     }
 
+    /// Sets the `SO_RCVBUF` value of the `SrtSocket`. Note that the kernel may clamp or adjust
+    /// the value, use [`recv_buffer_size`] to read the effective value.
+    ///
+    /// [`recv_buffer_size`]: Self::recv_buffer_size
+    #[inline]
+    pub fn set_recv_buffer_size(&self, size: usize) -> Result<()> {
+        self.as_socket().set_recv_buffer_size(size)
+    }
+
+    /// Sets the `SO_SNDBUF` value of the `SrtSocket`. Note that the kernel may clamp or adjust
+    /// the value, use [`send_buffer_size`] to read the effective value.
+    ///
+    /// [`send_buffer_size`]: Self::send_buffer_size
+    #[inline]
+    pub fn set_send_buffer_size(&self, size: usize) -> Result<()> {
+        self.as_socket().set_send_buffer_size(size)
+    }
+
     /// Returns the [`SocketAddr`] which the `SrtSocket` has been bound to.
     #[inline]
     pub fn local_addr(&self) -> Result<SocketAddr> {
@@ -104,9 +197,183 @@ impl SrtSocket {
     }
 }
 
+/// Buffers for receiving up to [`SrtSocket::BATCH_SIZE`] datagrams with
+/// [`SrtSocket::recv_batch`].
+///
+/// The buffers are taken from an [`Arena`], so receiving does not allocate for every datagram.
+#[derive(Debug)]
+pub struct RecvBatch {
+    arena: Arena,
+    /// Empty buffers with a capacity of `size` bytes.
+    bufs: Vec<BytesMut>,
+    /// The index into `bufs`, length and sender of each received datagram. Skipped datagrams
+    /// leave gaps in the indices.
+    received: Vec<(usize, usize, SocketAddr)>,
+    size: usize,
+}
+
+impl RecvBatch {
+    /// Creates a new `RecvBatch` receiving datagrams of up to `size` bytes.
+    pub fn new(size: usize) -> Self {
+        let mut arena = Arena::new();
+
+        Self {
+            bufs: (0..SrtSocket::BATCH_SIZE)
+                .map(|_| arena.get(size))
+                .collect(),
+            arena,
+            received: Vec::with_capacity(SrtSocket::BATCH_SIZE),
+            size,
+        }
+    }
+
+    /// Removes all received datagrams from the `RecvBatch`, returning the datagrams and their
+    /// senders. A datagram that fills the whole buffer may have been truncated.
+    pub fn drain(&mut self) -> impl Iterator<Item = (BytesMut, SocketAddr)> + '_ {
+        let size = self.size;
+        let arena = &mut self.arena;
+        let bufs = &mut self.bufs;
+
+        self.received.drain(..).map(move |(index, len, addr)| {
+            let buf = std::mem::replace(&mut bufs[index], arena.get(size));
+            debug_assert_eq!(buf.len(), len);
+            (buf, addr)
+        })
+    }
+}
+
 impl AsRawFd for SrtSocket {
     #[inline]
     fn as_raw_fd(&self) -> RawFd {
         self.socket.as_raw_fd()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Instant;
+
+    use crate::proto::Encode;
+    use crate::srt::{DataPacket, IsPacket, Packet};
+
+    use super::{RecvBatch, SrtSocket};
+
+    fn sockets() -> (SrtSocket, SrtSocket, SocketAddr) {
+        let addr = "127.0.0.1:0".parse().unwrap();
+        let rx = SrtSocket::new(addr).unwrap();
+        let tx = SrtSocket::new(addr).unwrap();
+        // Avoid dropping datagrams when sending faster than receiving.
+        rx.set_recv_buffer_size(1 << 22).unwrap();
+        let addr = rx.local_addr().unwrap();
+        (rx, tx, addr)
+    }
+
+    fn packet(seq: u32) -> Packet {
+        DataPacket::builder()
+            .sequence_number(seq)
+            .body(vec![seq as u8; 1316])
+            .build()
+            .upcast()
+    }
+
+    #[test]
+    fn test_batch_drain_skipped() {
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+
+        // The datagram received into the first buffer was skipped.
+        let mut batch = RecvBatch::new(1500);
+        batch.bufs[1].extend_from_slice(&[1; 100]);
+        batch.bufs[2].extend_from_slice(&[2; 200]);
+        batch.received.extend([(1, 100, addr), (2, 200, addr)]);
+
+        let drained: Vec<_> = batch.drain().collect();
+        assert_eq!(drained.len(), 2);
+        assert_eq!(drained[0].0, [1; 100][..]);
+        assert_eq!(drained[1].0, [2; 200][..]);
+        assert!(batch.bufs.iter().all(|buf| buf.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let (rx, tx, addr) = sockets();
+
+        let count = SrtSocket::BATCH_SIZE as u32 * 2 + 1;
+        let packets: Vec<_> = (0..count).map(packet).collect();
+        tx.send_batch(&packets, addr).await.unwrap();
+
+        let mut batch = RecvBatch::new(1500);
+        let mut received = Vec::new();
+        while received.len() < count as usize {
+            rx.recv_batch(&mut batch).await.unwrap();
+            for (buf, from) in batch.drain() {
+                assert_eq!(from, tx.local_addr().unwrap());
+                received.push(buf);
+            }
+        }
+
+        for (seq, buf) in received.into_iter().enumerate() {
+            let mut expected = Vec::new();
+            let packet = packet(seq as u32);
+            expected.extend(packet.header.encode_to_vec().unwrap());
+            expected.extend_from_slice(&packet.body);
+            assert_eq!(buf, expected);
+        }
+    }
+
+    /// Compares the packet rate of single and batched sends and receives over loopback.
+    ///
+    /// Run with `cargo test --release bench_batch -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn bench_batch() {
+        const COUNT: usize = 1 << 18;
+
+        let (rx, tx, addr) = sockets();
+        let recv = tokio::task::spawn(async move {
+            let mut batch = RecvBatch::new(1500);
+            let mut buf = [0; 1500];
+
+            // Single receives, then batched receives.
+            for _ in 0..COUNT {
+                rx.socket.recv_from(&mut buf).await.unwrap();
+            }
+
+            let mut count = 0;
+            while count < COUNT {
+                count += rx.recv_batch(&mut batch).await.unwrap();
+            }
+        });
+
+        let packets: Vec<_> = (0..SrtSocket::BATCH_SIZE as u32).map(packet).collect();
+
+        let now = Instant::now();
+        for index in 0..COUNT {
+            tx.send_to(packets[index % packets.len()].clone(), addr)
+                .await
+                .unwrap();
+            // Keep the receive buffer from overflowing.
+            if index % 1024 == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        let single = now.elapsed();
+
+        let now = Instant::now();
+        for index in 0..COUNT / packets.len() {
+            tx.send_batch(&packets, addr).await.unwrap();
+            if index % 32 == 0 {
+                tokio::task::yield_now().await;
+            }
+        }
+        let batched = now.elapsed();
+
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(10), recv).await;
+
+        println!(
+            "single: {:.0} packets/s, batched: {:.0} packets/s",
+            COUNT as f64 / single.as_secs_f64(),
+            COUNT as f64 / batched.as_secs_f64()
+        );
+    }
+}
@@ -3,17 +3,19 @@ use std::fmt::Display;
 use std::hash::Hash;
 use std::net::SocketAddr;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use ahash::{AHashMap, AHashSet};
 use parking_lot::{Mutex, RwLock};
-use rand::rngs::OsRng;
-use rand::RngCore;
 
 use crate::session::SessionManager;
 
-use super::config::Config;
+use super::access_log::AccessLog;
+use super::config::{Config, RuntimeConfig};
 use super::conn::ConnectionHandle;
+use super::cookie::SynCookies;
+use super::loss::LossInjector;
 use super::metrics::{ConnectionMetrics, ServerMetrics};
 
 #[derive(Debug)]
@@ -28,15 +30,44 @@ impl<S> State<S>
 where
     S: SessionManager,
 {
+    /// Creates a new `State` without an access log.
+    #[cfg(test)]
     pub fn new(session_manager: S, config: Config) -> Self {
+        Self::with_access_log(session_manager, config, None)
+    }
+
+    /// Creates a new `State` that writes closed connections to `access_log`.
+    pub fn with_access_log(
+        session_manager: S,
+        config: Config,
+        access_log: Option<AccessLog>,
+    ) -> Self {
+        let loss = match &config.loss {
+            Some(loss) if config.debug => {
+                tracing::warn!("Injecting synthetic packet loss: {:?}", loss);
+                Some(LossInjector::new(loss.clone()))
+            }
+            Some(_) => {
+                tracing::warn!("Ignoring loss injection config since debug is disabled");
+                None
+            }
+            None => None,
+        };
+
+        let syn_cookies = SynCookies::new(config.syn_cookie_lifetime);
+
         Self {
             inner: Arc::new(StateInner {
+                runtime: RwLock::new(RuntimeConfig::from(&config)),
                 config: config,
+                access_log,
+                loss,
                 pool: ConnectionPool::new(),
-                prng: Mutex::new(OsRng),
+                syn_cookies,
                 session_manager,
                 conn_metrics: Mutex::new(AHashMap::new()),
                 metrics: ServerMetrics::new(),
+                ready: AtomicBool::new(false),
             }),
         }
     }
@@ -69,22 +100,73 @@ pub struct StateInner<S>
 where
     S: SessionManager,
 {
+    /// The config the server was started with. Settings in [`runtime`] use the values of
+    /// [`runtime`] instead, which may have been reloaded since.
+    ///
+    /// [`runtime`]: Self::runtime
     pub config: Config,
+    /// The settings that can be changed while the server is running.
+    pub runtime: RwLock<RuntimeConfig>,
     pub pool: ConnectionPool,
-    /// Pseudo RNG for all non-crypto randomness
-    // NOTE: This actually is a CSPRNG but it doesn't have to be.
-    pub prng: Mutex<OsRng>,
+    pub syn_cookies: SynCookies,
     pub session_manager: S,
     pub conn_metrics: Mutex<AHashMap<ConnectionId, Arc<ConnectionMetrics>>>,
     pub metrics: ServerMetrics,
+    /// Synthetic packet loss for testing.
+    pub loss: Option<LossInjector>,
+    /// The log of closed connections, if enabled.
+    pub access_log: Option<AccessLog>,
+    /// Whether the socket is bound and the workers are running.
+    ready: AtomicBool,
 }
 
 impl<S> StateInner<S>
 where
     S: SessionManager,
 {
-    pub fn random(&self) -> u32 {
-        self.prng.lock().next_u32() >> 1
+    /// Returns `true` if the server is bound to its socket and the workers are running.
+    #[inline]
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub(super) fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    /// Applies the settings of `config` that can be changed while the server is running and
+    /// logs all changed settings. All other settings, e.g. the bind address, keep the value the
+    /// server was started with.
+    pub fn reload(&self, config: &Config) {
+        let new = RuntimeConfig::from(config);
+
+        let mut runtime = self.runtime.write();
+        let old = *runtime;
+        *runtime = new;
+        drop(runtime);
+
+        fn log_change<T>(name: &str, old: T, new: T)
+        where
+            T: PartialEq + std::fmt::Debug,
+        {
+            if old != new {
+                tracing::info!("Changed srt.{} from {:?} to {:?}", name, old, new);
+            }
+        }
+
+        log_change("latency", old.latency, new.latency);
+        log_change("max-latency", old.max_latency, new.max_latency);
+        log_change("idle-timeout", old.idle_timeout, new.idle_timeout);
+        log_change("keepalive", old.keepalive, new.keepalive);
+        log_change("ack-interval", old.ack_interval, new.ack_interval);
+
+        if config.bind != self.config.bind {
+            tracing::warn!(
+                "Ignoring change of srt.bind to {}, a restart is required",
+                config.bind
+            );
+        }
     }
 }
 
@@ -135,6 +217,18 @@ impl ConnectionPool {
 
         None
     }
+
+    pub fn find_server_id(&self, socket_id: u32) -> Option<ConnectionHandle> {
+        let inner = self.inner.read();
+
+        for handle in &*inner {
+            if handle.id.server_socket_id == socket_id {
+                return Some(handle.clone());
+            }
+        }
+
+        None
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
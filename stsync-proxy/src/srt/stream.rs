@@ -19,12 +19,19 @@ where
     S: Stream<Item = Bytes>,
 {
     initial_sequence_number: Sequence,
+    /// The sequence number of the next segment.
+    next_sequence_number: Sequence,
     // Any retransmissions MUST retain the same timestmap.
     buffer: Buffer<(Bytes, Timestamp, MessageNumber)>,
     #[pin]
     stream: S,
     next_message_number: MessageNumber,
     start_time: Instant,
+    /// The maximum payload of a single segment. Longer items of `stream` are split into
+    /// multiple segments.
+    max_payload_size: usize,
+    /// The part of the last item that did not fit into a segment yet.
+    remainder: Bytes,
 }
 
 impl<S> SrtStream<S>
@@ -36,13 +43,19 @@ where
         size: usize,
         initial_sequence_number: Sequence,
         start_time: Instant,
+        max_payload_size: usize,
     ) -> Self {
+        assert_ne!(max_payload_size, 0);
+
         Self {
             initial_sequence_number,
+            next_sequence_number: initial_sequence_number,
             stream,
             buffer: Buffer::new(size),
             next_message_number: MessageNumber::new(1),
             start_time,
+            max_payload_size,
+            remainder: Bytes::new(),
         }
     }
 
@@ -57,6 +70,17 @@ where
         }
     }
 
+    /// Returns the [`MessageNumber`] of the segment with the sequence number `seq`, even if the
+    /// segment is no longer buffered. Returns `None` if the segment was never sent.
+    pub fn message_number(&self, seq: Sequence) -> Option<MessageNumber> {
+        if seq < self.initial_sequence_number || seq >= self.next_sequence_number {
+            None
+        } else {
+            // Every segment is a single message.
+            Some(MessageNumber::new(1) + (seq - self.initial_sequence_number).get())
+        }
+    }
+
     pub fn update_start(&mut self, instant: Instant) {
         self.start_time = instant;
     }
@@ -71,18 +95,28 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = self.project();
 
-        match this.stream.poll_next(cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(Some(val)) => {
-                let msgnum = *this.next_message_number;
-                let ts = Timestamp::from_start(*this.start_time);
-
-                this.buffer.push((val.clone(), ts, msgnum));
-                *this.next_message_number += 1;
-                Poll::Ready(Some((val, ts, msgnum)))
+        let mut val = if !this.remainder.is_empty() {
+            std::mem::take(this.remainder)
+        } else {
+            match this.stream.poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Some(val)) => val,
+                Poll::Ready(None) => return Poll::Ready(None),
             }
-            Poll::Ready(None) => Poll::Ready(None),
+        };
+
+        // Every segment is sent as a single message, including the parts of a split item.
+        if val.len() > *this.max_payload_size {
+            *this.remainder = val.split_off(*this.max_payload_size);
         }
+
+        let msgnum = *this.next_message_number;
+        let ts = Timestamp::from_start(*this.start_time);
+
+        this.buffer.push((val.clone(), ts, msgnum));
+        *this.next_message_number += 1;
+        *this.next_sequence_number += 1;
+        Poll::Ready(Some((val, ts, msgnum)))
     }
 }
 
@@ -95,3 +129,44 @@ where
         &self.stream
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bytes::Bytes;
+    use futures::{stream, StreamExt};
+
+    use crate::srt::utils::{MessageNumber, Sequence};
+
+    use super::SrtStream;
+
+    #[tokio::test]
+    async fn test_split_segments() {
+        let items = [vec![0; 4], vec![1; 10], vec![2; 3]].map(Bytes::from);
+        let mut stream = SrtStream::new(
+            stream::iter(items),
+            16,
+            Sequence::new(100),
+            Instant::now(),
+            4,
+        );
+
+        let mut segments = Vec::new();
+        while let Some((buf, _, msgnum)) = stream.next().await {
+            segments.push((buf, msgnum));
+        }
+
+        let expected = [vec![0; 4], vec![1; 4], vec![1; 4], vec![1; 2], vec![2; 3]];
+        assert_eq!(segments.len(), expected.len());
+        for (index, ((buf, msgnum), expected)) in segments.iter().zip(expected).enumerate() {
+            assert_eq!(buf, &expected);
+            assert_eq!(*msgnum, MessageNumber::new(1) + index as u32);
+
+            // Retransmissions find the same segment.
+            let seq = Sequence::new(100) + index as u32;
+            assert_eq!(stream.get(seq).unwrap().0, buf);
+            assert_eq!(stream.message_number(seq), Some(*msgnum));
+        }
+    }
+}
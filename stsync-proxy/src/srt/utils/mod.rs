@@ -3,3 +3,15 @@ mod sequence;
 
 pub use message::MessageNumber;
 pub use sequence::Sequence;
+
+/// Returns the CPU time consumed by the process so far, used by the benchmarks.
+#[cfg(all(test, target_os = "linux"))]
+pub fn cpu_time() -> std::time::Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let res = unsafe { libc::clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, &mut ts) };
+    assert_eq!(res, 0);
+    std::time::Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
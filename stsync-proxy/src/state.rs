@@ -2,6 +2,7 @@ use std::ops::Deref;
 use std::sync::Arc;
 
 use crate::database::Database;
+use crate::http;
 use crate::session::buffer::BufferSessionManager;
 use crate::srt;
 
@@ -9,12 +10,23 @@ use crate::srt;
 pub struct State(Arc<StateInner>);
 
 impl State {
-    pub fn new(srt: srt::state::State<BufferSessionManager>) -> Self {
+    pub fn new(srt: srt::state::State<BufferSessionManager>, config: http::Config) -> Self {
         Self(Arc::new(StateInner {
             db: Database::new(),
             srt,
+            config,
         }))
     }
+
+    /// Creates a new `State` using the given `db` instead of the default database file.
+    #[cfg(test)]
+    pub fn with_database(
+        srt: srt::state::State<BufferSessionManager>,
+        config: http::Config,
+        db: Database,
+    ) -> Self {
+        Self(Arc::new(StateInner { db, srt, config }))
+    }
 }
 
 impl Deref for State {
@@ -29,4 +41,5 @@ impl Deref for State {
 pub struct StateInner {
     pub srt: srt::state::State<BufferSessionManager>,
     pub db: Database,
+    pub config: http::Config,
 }
@@ -0,0 +1,292 @@
+//! MPEG transport stream helpers
+//!
+//! See ISO/IEC 13818-1 section 2.4.3.
+
+/// The size of a single transport stream packet.
+pub const PACKET_SIZE: usize = 188;
+
+/// The sync byte at the start of every transport stream packet.
+pub const SYNC_BYTE: u8 = 0x47;
+
+/// The PID of the program association table.
+const PAT_PID: u16 = 0x0000;
+
+/// The PID of null packets, also used as the PCR PID if a program has no PCR.
+const NULL_PID: u16 = 0x1FFF;
+
+/// Detects keyframes of the video stream in a transport stream.
+///
+/// The PID of the video stream is learned from the program association and program map tables
+/// (ISO/IEC 13818-1 section 2.4.4). If a program has no known video stream the PCR PID is used
+/// instead. Keyframes are only detected once the tables were seen.
+#[derive(Clone, Debug, Default)]
+pub struct KeyframeDetector {
+    /// The PIDs of the program map tables from the last program association table.
+    pmt_pids: Vec<u16>,
+    video_pid: Option<u16>,
+}
+
+impl KeyframeDetector {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if any transport stream packet in `buf` starts a random access point (e.g. a
+    /// keyframe) of the video stream.
+    ///
+    /// A random access point is a packet with both the `payload_unit_start_indicator` and the
+    /// `random_access_indicator` of the adaptation field set. Trailing bytes that don't form a
+    /// complete packet are ignored.
+    pub fn is_keyframe(&mut self, buf: &[u8]) -> bool {
+        let mut keyframe = false;
+
+        for packet in buf.chunks_exact(PACKET_SIZE) {
+            if packet[0] != SYNC_BYTE {
+                continue;
+            }
+
+            let pid = pid(packet);
+            if pid == PAT_PID {
+                if let Some(section) = section(packet) {
+                    self.pmt_pids = parse_pat(section);
+                }
+            } else if self.pmt_pids.contains(&pid) {
+                if let Some(video_pid) = section(packet).and_then(parse_pmt) {
+                    self.video_pid = Some(video_pid);
+                }
+            } else if self.video_pid == Some(pid) && is_random_access_point(packet) {
+                keyframe = true;
+            }
+        }
+
+        keyframe
+    }
+}
+
+fn pid(packet: &[u8]) -> u16 {
+    u16::from_be_bytes([packet[1] & 0x1F, packet[2]])
+}
+
+fn is_random_access_point(packet: &[u8]) -> bool {
+    let payload_unit_start = packet[1] & 0x40 != 0;
+    let has_adaptation_field = packet[3] & 0x20 != 0;
+    // The adaptation field length must be at least one to contain the flags.
+    let adaptation_field_length = packet[4];
+
+    payload_unit_start
+        && has_adaptation_field
+        && adaptation_field_length > 0
+        && packet[5] & 0x40 != 0
+}
+
+/// Returns the PSI section starting in `packet`, without the CRC.
+///
+/// Sections spanning multiple packets are truncated to the first packet.
+fn section(packet: &[u8]) -> Option<&[u8]> {
+    let payload_unit_start = packet[1] & 0x40 != 0;
+    let has_payload = packet[3] & 0x10 != 0;
+    if !payload_unit_start || !has_payload {
+        return None;
+    }
+
+    let mut payload = &packet[4..];
+    if packet[3] & 0x20 != 0 {
+        payload = payload.get(1 + usize::from(packet[4])..)?;
+    }
+
+    let pointer_field = usize::from(*payload.first()?);
+    let section = payload.get(1 + pointer_field..)?;
+
+    // The section_length counts the bytes after the length field, including the 4 byte CRC.
+    let section_length = usize::from(u16::from_be_bytes([
+        *section.get(1)? & 0x0F,
+        *section.get(2)?,
+    ]));
+    let end = (3 + section_length).saturating_sub(4).min(section.len());
+    section.get(..end)
+}
+
+/// Returns the PIDs of all program map tables in a program association table.
+fn parse_pat(section: &[u8]) -> Vec<u16> {
+    if section.first() != Some(&0x00) {
+        return Vec::new();
+    }
+
+    section
+        .get(8..)
+        .unwrap_or_default()
+        .chunks_exact(4)
+        .filter(|program| program[0..2] != [0, 0])
+        .map(|program| u16::from_be_bytes([program[2] & 0x1F, program[3]]))
+        .collect()
+}
+
+/// Returns the PID of the video stream in a program map table, or the PCR PID if the program has
+/// no known video stream.
+fn parse_pmt(section: &[u8]) -> Option<u16> {
+    if section.first() != Some(&0x02) {
+        return None;
+    }
+
+    let pcr_pid = u16::from_be_bytes([*section.get(8)? & 0x1F, *section.get(9)?]);
+    let program_info_length = usize::from(u16::from_be_bytes([
+        *section.get(10)? & 0x0F,
+        *section.get(11)?,
+    ]));
+
+    let mut streams = section.get(12 + program_info_length..).unwrap_or_default();
+    while let [stream_type, pid_hi, pid_lo, len_hi, len_lo, rest @ ..] = streams {
+        if is_video(*stream_type) {
+            return Some(u16::from_be_bytes([pid_hi & 0x1F, *pid_lo]));
+        }
+
+        let es_info_length = usize::from(u16::from_be_bytes([len_hi & 0x0F, *len_lo]));
+        streams = rest.get(es_info_length..).unwrap_or_default();
+    }
+
+    (pcr_pid != NULL_PID).then_some(pcr_pid)
+}
+
+/// Returns `true` if `stream_type` is a video stream (ISO/IEC 13818-1 table 2-34).
+fn is_video(stream_type: u8) -> bool {
+    matches!(
+        stream_type,
+        // MPEG-1, MPEG-2, MPEG-4 part 2, H.264, HEVC, VVC
+        0x01 | 0x02 | 0x10 | 0x1B | 0x24 | 0x33
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{KeyframeDetector, PACKET_SIZE, SYNC_BYTE};
+
+    pub const PMT_PID: u16 = 0x1000;
+    pub const VIDEO_PID: u16 = 0x0100;
+    pub const AUDIO_PID: u16 = 0x0101;
+
+    /// Creates a packet with an adaptation field.
+    pub fn packet(pid: u16, pusi: bool, rai: bool) -> [u8; PACKET_SIZE] {
+        let mut packet = [0xFF; PACKET_SIZE];
+        packet[0] = SYNC_BYTE;
+        packet[1] = (pid >> 8) as u8 | if pusi { 0x40 } else { 0x00 };
+        packet[2] = pid as u8;
+        // Adaptation field followed by payload.
+        packet[3] = 0x30;
+        packet[4] = 7;
+        packet[5] = if rai { 0x40 } else { 0x00 };
+        packet
+    }
+
+    fn psi(pid: u16, table_id: u8, body: &[u8]) -> [u8; PACKET_SIZE] {
+        let mut packet = [0xFF; PACKET_SIZE];
+        packet[0] = SYNC_BYTE;
+        packet[1] = 0x40 | (pid >> 8) as u8;
+        packet[2] = pid as u8;
+        // Payload only.
+        packet[3] = 0x10;
+        // Pointer field
+        packet[4] = 0;
+
+        // The 5 bytes after the length field, the body and the CRC.
+        let section_length = 5 + body.len() as u16 + 4;
+        packet[5] = table_id;
+        packet[6..8].copy_from_slice(&(0xB000 | section_length).to_be_bytes());
+        packet[8..13].copy_from_slice(&[0x00, 0x01, 0xC1, 0x00, 0x00]);
+        packet[13..13 + body.len()].copy_from_slice(body);
+        // The CRC is not checked.
+        packet[13 + body.len()..17 + body.len()].copy_from_slice(&[0; 4]);
+        packet
+    }
+
+    /// Creates a program association table with a single program.
+    pub fn pat() -> [u8; PACKET_SIZE] {
+        let [hi, lo] = PMT_PID.to_be_bytes();
+        psi(0x0000, 0x00, &[0x00, 0x01, 0xE0 | hi, lo])
+    }
+
+    /// Creates a program map table with the given `(stream_type, pid)` streams.
+    pub fn pmt(pcr_pid: u16, streams: &[(u8, u16)]) -> [u8; PACKET_SIZE] {
+        let [hi, lo] = pcr_pid.to_be_bytes();
+        let mut body = vec![0xE0 | hi, lo, 0xF0, 0x00];
+        for (stream_type, pid) in streams {
+            let [hi, lo] = pid.to_be_bytes();
+            body.extend([*stream_type, 0xE0 | hi, lo, 0xF0, 0x00]);
+        }
+
+        psi(PMT_PID, 0x02, &body)
+    }
+
+    /// Creates the tables of a program with an H.264 video and an AAC audio stream.
+    pub fn tables() -> Vec<u8> {
+        let mut buf = pat().to_vec();
+        buf.extend(pmt(VIDEO_PID, &[(0x0F, AUDIO_PID), (0x1B, VIDEO_PID)]));
+        buf
+    }
+
+    #[test]
+    fn test_is_keyframe() {
+        let mut detector = KeyframeDetector::new();
+
+        // The video PID is unknown before the tables.
+        assert!(!detector.is_keyframe(&packet(VIDEO_PID, true, true)));
+        assert!(!detector.is_keyframe(&tables()));
+
+        assert!(detector.is_keyframe(&packet(VIDEO_PID, true, true)));
+        assert!(!detector.is_keyframe(&packet(VIDEO_PID, true, false)));
+        assert!(!detector.is_keyframe(&packet(VIDEO_PID, false, true)));
+
+        let mut buf = packet(VIDEO_PID, false, false).to_vec();
+        buf.extend(packet(VIDEO_PID, true, true));
+        assert!(detector.is_keyframe(&buf));
+
+        let mut buf = packet(VIDEO_PID, true, true);
+        buf[0] = 0x00;
+        assert!(!detector.is_keyframe(&buf));
+
+        // Incomplete packets are ignored.
+        assert!(!detector.is_keyframe(&packet(VIDEO_PID, true, true)[..PACKET_SIZE - 1]));
+
+        // The tables and the keyframe in a single segment.
+        let mut detector = KeyframeDetector::new();
+        let mut buf = tables();
+        buf.extend(packet(VIDEO_PID, true, true));
+        assert!(detector.is_keyframe(&buf));
+    }
+
+    #[test]
+    fn test_is_keyframe_interleaved_audio() {
+        let mut detector = KeyframeDetector::new();
+        detector.is_keyframe(&tables());
+
+        // Every audio frame is a random access point.
+        let mut buf = packet(VIDEO_PID, false, false).to_vec();
+        buf.extend(packet(AUDIO_PID, true, true));
+        buf.extend(packet(VIDEO_PID, false, false));
+        assert!(!detector.is_keyframe(&buf));
+
+        buf.extend(packet(VIDEO_PID, true, true));
+        assert!(detector.is_keyframe(&buf));
+    }
+
+    #[test]
+    fn test_is_keyframe_pcr_pid() {
+        // Without a known video stream type the PCR PID is used.
+        let mut detector = KeyframeDetector::new();
+        let mut buf = pat().to_vec();
+        buf.extend(pmt(VIDEO_PID, &[(0x0F, AUDIO_PID), (0x80, VIDEO_PID)]));
+        detector.is_keyframe(&buf);
+
+        assert!(!detector.is_keyframe(&packet(AUDIO_PID, true, true)));
+        assert!(detector.is_keyframe(&packet(VIDEO_PID, true, true)));
+
+        // No PCR either.
+        let mut detector = KeyframeDetector::new();
+        let mut buf = pat().to_vec();
+        buf.extend(pmt(0x1FFF, &[(0x0F, AUDIO_PID)]));
+        detector.is_keyframe(&buf);
+
+        assert!(!detector.is_keyframe(&packet(AUDIO_PID, true, true)));
+        assert!(!detector.is_keyframe(&packet(0x1FFF, true, true)));
+    }
+}
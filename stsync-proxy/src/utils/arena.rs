@@ -0,0 +1,91 @@
+//! Network buffer arena
+//!
+//! Receiving a datagram into a fresh allocation for every packet is expensive at high packet
+//! rates. An [`Arena`] instead hands out buffers from large chunks. The payload of a received
+//! packet is a [`Bytes`] view into the chunk, which keeps the chunk alive for as long as the
+//! packet is referenced.
+//!
+//! A chunk is only reused once every buffer taken from it, including all [`Bytes`] created from
+//! them, has been dropped. This is enforced by the reference counting of [`BytesMut`], so a
+//! buffer is never handed out twice while still referenced.
+//!
+//! [`Bytes`]: bytes::Bytes
+use bytes::BytesMut;
+
+/// An allocator for network buffers. See the [module level documentation](self) for details.
+#[derive(Debug)]
+pub struct Arena {
+    chunk: BytesMut,
+}
+
+impl Arena {
+    /// The size of a chunk in bytes. This is the largest size for which [`BytesMut`] reuses a
+    /// chunk instead of allocating a new one.
+    pub const CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            chunk: BytesMut::with_capacity(Self::CHUNK_SIZE),
+        }
+    }
+
+    /// Returns an empty buffer with a capacity of at least `size` bytes.
+    ///
+    /// The spare capacity of the buffer is not initialized.
+    pub fn get(&mut self, size: usize) -> BytesMut {
+        // Reclaims the chunk if all buffers taken from it were dropped, otherwise allocates a
+        // new chunk.
+        self.chunk.reserve(size);
+
+        let rest = self.chunk.split_off(size);
+        std::mem::replace(&mut self.chunk, rest)
+    }
+}
+
+impl Default for Arena {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::Arena;
+
+    #[test]
+    fn test_arena() {
+        let mut arena = Arena::new();
+
+        let buf = arena.get(1500);
+        assert!(buf.is_empty());
+        assert!(buf.capacity() >= 1500);
+
+        // Buffers that are still referenced never overlap.
+        let mut bufs: Vec<_> = (0..256)
+            .map(|_| {
+                let mut buf = arena.get(1500);
+                buf.resize(1500, 0);
+                buf.freeze()
+            })
+            .collect();
+        bufs.sort_by_key(|buf| buf.as_ptr());
+        for pair in bufs.windows(2) {
+            assert!(pair[0].as_ptr() as usize + 1500 <= pair[1].as_ptr() as usize);
+        }
+        drop(bufs);
+        drop(buf);
+
+        // Dropped buffers are reused.
+        let ptrs: HashSet<_> = (0..4096)
+            .map(|_| {
+                let mut buf = arena.get(1500);
+                buf.resize(1500, 0);
+                buf.as_ptr()
+            })
+            .collect();
+        assert!(ptrs.len() <= Arena::CHUNK_SIZE / 1500 * 2);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod arena;
 pub mod serial;
 
 use std::cmp::Ordering;